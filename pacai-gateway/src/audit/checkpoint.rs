@@ -0,0 +1,152 @@
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use crate::security::hsm::{self, HsmError, HsmManager};
+use crate::state::SharedState;
+use crate::util::logging::{AuditEntry, AuditEventType, ChainFault, TamperReport};
+
+/// Action name of checkpoint entries, as in the v4 API contract.
+pub const CHECKPOINT_ACTION: &str = "hsm_notarized";
+
+#[derive(Debug, Clone, Copy)]
+pub struct CheckpointPolicy {
+    pub every_entries: u64,
+    pub interval: Duration,
+}
+
+/// A signed statement that the chain had `hash` at `sequence`. Stored as
+/// the details of a checkpoint entry, which is itself chained, so a later
+/// rewrite of anything up to `sequence` invalidates the signature.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub sequence: u64,
+    pub hash: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+impl Checkpoint {
+    pub fn message(sequence: u64, hash: &str) -> Vec<u8> {
+        format!("pacai-audit-checkpoint/1:{}:{}", sequence, hash).into_bytes()
+    }
+
+    pub fn sign(signer: &HsmManager, sequence: u64, hash: &str) -> Result<Self, HsmError> {
+        let public_key = signer.verifying_key().ok_or(HsmError::NotInitialized)?;
+        let signature = signer.sign(&Self::message(sequence, hash))?;
+        Ok(Self {
+            sequence,
+            hash: hash.to_string(),
+            public_key: hex::encode(public_key.to_bytes()),
+            signature: hex::encode(signature),
+        })
+    }
+
+    /// The unsealed audit entry carrying this checkpoint.
+    pub fn entry(&self) -> AuditEntry {
+        AuditEntry::new(
+            AuditEventType::Checkpoint,
+            "system",
+            CHECKPOINT_ACTION,
+            "audit",
+            serde_json::to_value(self).ok(),
+        )
+    }
+
+    pub fn from_entry(entry: &AuditEntry) -> Option<Self> {
+        if entry.event_type != AuditEventType::Checkpoint {
+            return None;
+        }
+        serde_json::from_value(entry.details.clone()?).ok()
+    }
+
+    pub fn verify(&self, key: &VerifyingKey) -> bool {
+        let Ok(signature) = hex::decode(&self.signature) else {
+            return false;
+        };
+        hsm::verify_with_key(key, &Self::message(self.sequence, &self.hash), &signature)
+    }
+}
+
+/// Checks every checkpoint in `entries` against `key`. A checkpoint must be
+/// well formed, carry a valid signature and, when the entry it attests is in
+/// the slice, match that entry's hash. Returns the verified checkpoints.
+pub fn verify_checkpoints(entries: &[AuditEntry], key: &VerifyingKey) -> Result<Vec<Checkpoint>, TamperReport> {
+    let first = entries.first().map_or(0, |e| e.sequence);
+    let mut verified = Vec::new();
+
+    for entry in entries.iter().filter(|e| e.event_type == AuditEventType::Checkpoint) {
+        let bad = TamperReport { sequence: entry.sequence, fault: ChainFault::BadCheckpoint };
+        let checkpoint = Checkpoint::from_entry(entry).ok_or(bad.clone())?;
+        if !checkpoint.verify(key) || checkpoint.sequence >= entry.sequence {
+            return Err(bad);
+        }
+        let attested = checkpoint
+            .sequence
+            .checked_sub(first)
+            .and_then(|i| entries.get(i as usize));
+        if attested.is_some_and(|a| a.hash != checkpoint.hash) {
+            return Err(bad);
+        }
+        verified.push(checkpoint);
+    }
+
+    Ok(verified)
+}
+
+/// Writes a checkpoint whenever the time policy says one is due, and applies
+/// age-based retention, which otherwise only runs on rotation.
+pub fn spawn(state: SharedState, period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            let audit = state.clone();
+            match tokio::task::spawn_blocking(move || audit.audit.checkpoint_if_due()).await {
+                Ok(Ok(Some(entry))) => tracing::info!("Audit checkpoint written at sequence {}", entry.sequence),
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => tracing::error!("Audit checkpoint failed: {}", e),
+                Err(e) => tracing::error!("Audit checkpoint task failed: {}", e),
+            }
+            if let Err(e) = state.audit.enforce_retention() {
+                tracing::error!("Audit retention failed: {}", e);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::logging::{log_generate, GENESIS_HASH};
+
+    #[test]
+    fn test_checkpoint_signature_binds_sequence_and_hash() {
+        let key = HsmManager::ephemeral("test").verifying_key().unwrap();
+        let other_key = HsmManager::ephemeral("test").verifying_key().unwrap();
+        let checkpoint = Checkpoint::sign(&HsmManager::ephemeral("test"), 4, "ab").unwrap();
+        assert!(!checkpoint.verify(&key) && !checkpoint.verify(&other_key));
+
+        let signer = HsmManager::ephemeral("test");
+        let checkpoint = Checkpoint::sign(&signer, 4, "ab").unwrap();
+        assert!(checkpoint.verify(&signer.verifying_key().unwrap()));
+        let forged = Checkpoint { hash: "cd".into(), ..checkpoint.clone() };
+        assert!(!forged.verify(&signer.verifying_key().unwrap()));
+    }
+
+    #[test]
+    fn test_checkpoint_must_match_attested_entry() {
+        let signer = HsmManager::ephemeral("test");
+        let key = signer.verifying_key().unwrap();
+        let first = log_generate("alice", "p1", 1).seal(0, GENESIS_HASH);
+        let good = Checkpoint::sign(&signer, 0, &first.hash).unwrap().entry().seal(1, &first.hash);
+        assert_eq!(verify_checkpoints(&[first.clone(), good], &key).unwrap().len(), 1);
+
+        let stale = Checkpoint::sign(&signer, 0, GENESIS_HASH).unwrap().entry().seal(1, &first.hash);
+        assert_eq!(
+            verify_checkpoints(&[first, stale], &key),
+            Err(TamperReport { sequence: 1, fault: ChainFault::BadCheckpoint })
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Cursor, Read, Write};
+use zip::{ZipArchive, ZipWriter, write::SimpleFileOptions};
+use crate::audit::checkpoint::{self, Checkpoint};
+use crate::security::hsm::{self, HsmError, HsmManager};
+use crate::util::logging::{self, AuditEntry, AuditEventType, TamperReport};
+
+pub const ARCHIVE_FORMAT: &str = "pacai-audit-export/1";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("No audit entries in the requested range starting at sequence {from}")]
+    EmptyRange { from: u64 },
+
+    #[error("Archive I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Archive format error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("Archive serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Archive signing failed: {0}")]
+    Signing(#[from] HsmError),
+
+    #[error("Archive is invalid: {0}")]
+    Invalid(String),
+
+    #[error("Audit chain fails at sequence {}: {:?}", .0.sequence, .0.fault)]
+    Tampered(TamperReport),
+}
+
+/// Signed description of an export. The digests tie the manifest signature
+/// to the exact bytes of the entry and checkpoint files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub format: String,
+    pub from_sequence: u64,
+    pub to_sequence: u64,
+    pub entries: u64,
+    pub checkpoints: u64,
+    pub first_prev_hash: String,
+    pub last_hash: String,
+    pub entries_sha256: String,
+    pub checkpoints_sha256: String,
+    pub exported_at: String,
+    pub public_key: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveReport {
+    pub manifest: ArchiveManifest,
+    pub checkpoints_verified: u64,
+    pub attested_sequence: Option<u64>,
+    /// Entries after the last checkpoint, vouched for only by the manifest.
+    pub unattested_entries: u64,
+    /// Whether the signing key was supplied by the auditor rather than
+    /// taken from the archive itself.
+    pub key_pinned: bool,
+}
+
+/// Packs a contiguous run of entries into a zip of `entries.jsonl`,
+/// `checkpoints.json`, `manifest.json` and a hex `manifest.sig`.
+pub fn build_archive(entries: &[AuditEntry], signer: &HsmManager) -> Result<Vec<u8>, ExportError> {
+    let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+        return Err(ExportError::Invalid("cannot export an empty range".into()));
+    };
+    let public_key = signer.verifying_key().ok_or(HsmError::NotInitialized)?;
+
+    let mut entries_bytes = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut entries_bytes, entry)?;
+        entries_bytes.push(b'\n');
+    }
+    let checkpoints: Vec<Checkpoint> = entries.iter().filter_map(Checkpoint::from_entry).collect();
+    let checkpoints_bytes = serde_json::to_vec_pretty(&checkpoints)?;
+
+    let manifest = ArchiveManifest {
+        format: ARCHIVE_FORMAT.into(),
+        from_sequence: first.sequence,
+        to_sequence: last.sequence,
+        entries: entries.len() as u64,
+        checkpoints: checkpoints.len() as u64,
+        first_prev_hash: first.prev_hash.clone(),
+        last_hash: last.hash.clone(),
+        entries_sha256: hex::encode(Sha256::digest(&entries_bytes)),
+        checkpoints_sha256: hex::encode(Sha256::digest(&checkpoints_bytes)),
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        public_key: hex::encode(public_key.to_bytes()),
+    };
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
+    let signature = hex::encode(signer.sign(&manifest_bytes)?);
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, bytes) in [
+        ("entries.jsonl", entries_bytes.as_slice()),
+        ("checkpoints.json", checkpoints_bytes.as_slice()),
+        ("manifest.json", manifest_bytes.as_slice()),
+        ("manifest.sig", signature.as_bytes()),
+    ] {
+        zip.start_file(name, options)?;
+        zip.write_all(bytes)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Verifies an archive without access to the gateway. With `pinned_key` the
+/// archive must be signed by that key; otherwise the key embedded in the
+/// manifest is trusted, which only proves internal consistency.
+pub fn verify_archive(bytes: &[u8], pinned_key: Option<&VerifyingKey>) -> Result<ArchiveReport, ExportError> {
+    let mut zip = ZipArchive::new(Cursor::new(bytes))?;
+    let manifest_bytes = read_file(&mut zip, "manifest.json")?;
+    let signature = read_file(&mut zip, "manifest.sig")?;
+    let entries_bytes = read_file(&mut zip, "entries.jsonl")?;
+    let checkpoints_bytes = read_file(&mut zip, "checkpoints.json")?;
+
+    let manifest: ArchiveManifest = serde_json::from_slice(&manifest_bytes)?;
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err(invalid(format!("unsupported format {}", manifest.format)));
+    }
+    let key = hsm::parse_public_key(&manifest.public_key)
+        .ok_or_else(|| invalid("manifest public key is malformed"))?;
+    if pinned_key.is_some_and(|pinned| *pinned != key) {
+        return Err(invalid(format!("archive was signed by {}, not the pinned key", manifest.public_key)));
+    }
+    let signature = hex::decode(String::from_utf8_lossy(&signature).trim())
+        .map_err(|_| invalid("manifest signature is not hex"))?;
+    if !hsm::verify_with_key(&key, &manifest_bytes, &signature) {
+        return Err(invalid("manifest signature does not verify"));
+    }
+
+    if hex::encode(Sha256::digest(&entries_bytes)) != manifest.entries_sha256 {
+        return Err(invalid("entries.jsonl does not match the manifest digest"));
+    }
+    if hex::encode(Sha256::digest(&checkpoints_bytes)) != manifest.checkpoints_sha256 {
+        return Err(invalid("checkpoints.json does not match the manifest digest"));
+    }
+
+    let entries = entries_bytes
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(serde_json::from_slice::<AuditEntry>)
+        .collect::<Result<Vec<_>, _>>()?;
+    let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+        return Err(invalid("archive holds no entries"));
+    };
+    if entries.len() as u64 != manifest.entries
+        || first.sequence != manifest.from_sequence
+        || last.sequence != manifest.to_sequence
+        || first.prev_hash != manifest.first_prev_hash
+        || last.hash != manifest.last_hash
+    {
+        return Err(invalid("entries do not match the range described by the manifest"));
+    }
+
+    logging::verify_chain(&entries).map_err(ExportError::Tampered)?;
+    let verified = checkpoint::verify_checkpoints(&entries, &key).map_err(ExportError::Tampered)?;
+    let listed: Vec<Checkpoint> = serde_json::from_slice(&checkpoints_bytes)?;
+    if listed != verified {
+        return Err(invalid("checkpoints.json does not match the checkpoints in the chain"));
+    }
+
+    let attested_sequence = verified.iter().map(|c| c.sequence).max();
+    let unattested_entries = entries
+        .iter()
+        .filter(|e| e.event_type != AuditEventType::Checkpoint)
+        .filter(|e| attested_sequence.is_none_or(|attested| e.sequence > attested))
+        .count() as u64;
+
+    Ok(ArchiveReport {
+        checkpoints_verified: verified.len() as u64,
+        attested_sequence,
+        unattested_entries,
+        key_pinned: pinned_key.is_some(),
+        manifest,
+    })
+}
+
+fn read_file(zip: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Vec<u8>, ExportError> {
+    let mut file = zip
+        .by_name(name)
+        .map_err(|_| invalid(format!("archive is missing {}", name)))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn invalid(reason: impl Into<String>) -> ExportError {
+    ExportError::Invalid(reason.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::checkpoint::CheckpointPolicy;
+    use crate::audit::AuditLog;
+    use crate::util::logging::{log_generate, ChainFault};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    fn notarized_log(dir: &std::path::Path) -> (AuditLog, Arc<HsmManager>) {
+        let signer = HsmManager::ephemeral("test");
+        let signer = Arc::new(signer);
+        let policy = CheckpointPolicy { every_entries: 3, interval: Duration::from_secs(3600) };
+        (AuditLog::open(dir).unwrap().with_checkpoints(signer.clone(), policy), signer)
+    }
+
+    #[test]
+    fn test_archive_round_trip_with_pinned_key() {
+        let dir = tempdir().unwrap();
+        let (log, signer) = notarized_log(dir.path());
+        for seed in 0..7 {
+            log.append(log_generate("alice", "p1", seed)).unwrap();
+        }
+        // Seven entries with a checkpoint after every three: 0-2, cp 3, 4-6, cp 7, then 8.
+        let entries = log.entries_from(2).unwrap();
+        assert_eq!(entries.len(), 7);
+
+        let archive = build_archive(&entries, &signer).unwrap();
+        let report = verify_archive(&archive, signer.verifying_key().as_ref()).unwrap();
+        assert_eq!(report.manifest.from_sequence, 2);
+        assert_eq!(report.checkpoints_verified, 2);
+        assert_eq!(report.attested_sequence, Some(6));
+        assert_eq!(report.unattested_entries, 1);
+
+        let other = HsmManager::ephemeral("other");
+        assert!(matches!(
+            verify_archive(&archive, other.verifying_key().as_ref()),
+            Err(ExportError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_edited_entry_fails_even_when_resigned() {
+        let dir = tempdir().unwrap();
+        let (log, signer) = notarized_log(dir.path());
+        for seed in 0..4 {
+            log.append(log_generate("alice", "p1", seed)).unwrap();
+        }
+        let mut entries = log.entries_from(0).unwrap();
+
+        // Rewriting history and rehashing the chain still contradicts the checkpoint.
+        entries[1].actor = "mallory".into();
+        let mut prev = entries[0].hash.clone();
+        for entry in entries.iter_mut().skip(1) {
+            entry.prev_hash = prev.clone();
+            entry.hash = entry.compute_hash();
+            prev = entry.hash.clone();
+        }
+        let archive = build_archive(&entries, &signer).unwrap();
+        match verify_archive(&archive, None) {
+            Err(ExportError::Tampered(report)) => {
+                assert_eq!(report, TamperReport { sequence: 3, fault: ChainFault::BadCheckpoint })
+            }
+            other => panic!("expected tamper report, got {:?}", other.map(|r| r.manifest.to_sequence)),
+        }
+    }
+}
+
@@ -0,0 +1,217 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, MatchedPath, Request, State},
+    http::{request::Parts, Extensions, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use crate::security::session::Principal;
+use crate::state::SharedState;
+use crate::util::logging::{AuditEntry, AuditEventType};
+use crate::util::system::generate_request_id;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Who is calling and under which request ID. Handlers take this as an
+/// extractor and may hand the middleware a more specific entry via `record`.
+/// Actor and role come from the verified session principal, never from
+/// anything the client asserts directly.
+#[derive(Clone, Debug)]
+pub struct RequestContext {
+    pub request_id: String,
+    pub actor: String,
+    pub role: String,
+    entry: Arc<Mutex<Option<AuditEntry>>>,
+}
+
+impl RequestContext {
+    fn from_parts(headers: &HeaderMap, extensions: &Extensions) -> Self {
+        let request_id = headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .unwrap_or_else(generate_request_id);
+        let principal = extensions.get::<Principal>();
+        Self {
+            request_id,
+            actor: principal.map_or_else(|| "anonymous".into(), |p| p.user_id.clone()),
+            role: principal.map_or_else(|| "anonymous".into(), Principal::role_label),
+            entry: Arc::default(),
+        }
+    }
+
+    /// Replaces the route's generic entry with `entry`. The middleware still
+    /// stamps request ID, outcome and latency onto it.
+    pub fn record(&self, entry: AuditEntry) {
+        *self.entry.lock().unwrap() = Some(entry);
+    }
+
+    pub(crate) fn take(&self) -> Option<AuditEntry> {
+        self.entry.lock().unwrap().take()
+    }
+
+    /// The same caller and request ID with an entry of its own, for work
+    /// that may finish after the response, and the middleware, are done.
+    pub fn detached(&self) -> Self {
+        Self { entry: Arc::default(), ..self.clone() }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<RequestContext>()
+            .cloned()
+            .unwrap_or_else(|| RequestContext::from_parts(&parts.headers, &parts.extensions)))
+    }
+}
+
+/// Error message attached to failed responses so the audit entry can carry it.
+#[derive(Clone, Debug)]
+pub struct FailureReason(pub String);
+
+/// Event type and action for a route, or `None` when the route is a plain
+/// read that is not worth auditing.
+pub fn classify(method: &Method, route: &str) -> Option<(AuditEventType, &'static str)> {
+    let class = match (method.as_str(), route) {
+        ("POST", "/v5/prompt") => (AuditEventType::Generate, "prompt"),
+        ("POST", "/v5/projects/:id/generate") => (AuditEventType::Generate, "generate"),
+        ("DELETE", "/v5/jobs/:id") => (AuditEventType::Generate, "job_cancel"),
+        ("POST", "/v5/override") => (AuditEventType::Override, "override"),
+        ("DELETE", "/v5/projects/:id/overrides/scheduled/:schedule_id") => (AuditEventType::Override, "override_cancel"),
+        ("POST", "/v5/export") => (AuditEventType::Export, "export"),
+        ("GET", "/v5/license") => (AuditEventType::License, "license_check"),
+        ("POST", "/v5/auth/login") => (AuditEventType::Auth, "login"),
+        ("POST", "/v5/projects") => (AuditEventType::Project, "project_create"),
+        ("PATCH", "/v5/projects/:id") => (AuditEventType::Project, "project_update"),
+        ("DELETE", "/v5/projects/:id") => (AuditEventType::Project, "project_delete"),
+        ("POST", "/v5/projects/:id/zones/:zone_id/rollback") => (AuditEventType::Project, "zone_rollback"),
+        ("GET", "/v5/projects/:id/live") => (AuditEventType::Project, "live_connect"),
+        ("GET", "/v5/audit") => (AuditEventType::Audit, "audit_stream"),
+        ("GET", "/v5/audit/verify") => (AuditEventType::Audit, "audit_verify"),
+        ("GET", "/v5/audit/export") => (AuditEventType::Audit, "audit_export"),
+        ("GET", "/v5/audit/events" | "/v5/audit/events/stats" | "/v5/audit/events/auth-failures") => {
+            (AuditEventType::Audit, "audit_query")
+        }
+        ("POST", "/v5/admin/users") => (AuditEventType::User, "user_create"),
+        ("PATCH", "/v5/admin/users/:id") => (AuditEventType::User, "user_update"),
+        ("DELETE", "/v5/admin/users/:id") => (AuditEventType::User, "user_delete"),
+        ("POST", "/v5/admin/users/:id/lock") => (AuditEventType::User, "user_lock"),
+        ("POST", "/v5/admin/users/:id/unlock") => (AuditEventType::User, "user_unlock"),
+        ("PUT", "/v5/admin/users/:id/password") => (AuditEventType::User, "user_password"),
+        ("POST", "/v5/admin/users/:id/keys") => (AuditEventType::User, "user_key_issue"),
+        ("DELETE", "/v5/admin/users/:id/keys/:key_id") => (AuditEventType::User, "user_key_revoke"),
+        // Simulation is a read-only preview despite being a POST.
+        ("POST", "/v5/projects/:id/zones/:zone_id/simulate") => return None,
+        ("POST" | "PUT" | "PATCH" | "DELETE", _) => (AuditEventType::System, "mutation"),
+        _ => return None,
+    };
+    Some(class)
+}
+
+/// The project a route acts on, taken from its `:id` segment.
+fn resource_of(route: &str, path: &str) -> String {
+    route
+        .split('/')
+        .zip(path.split('/'))
+        .find(|(pattern, _)| *pattern == ":id")
+        .map(|(_, value)| value.to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+pub fn outcome(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        401 | 403 => "denied",
+        s if s >= 400 => "failure",
+        _ => "success",
+    }
+}
+
+/// Tags every request with a request ID and writes an audit entry for
+/// mutating and security-relevant routes, plus any request that was denied.
+pub async fn audit_requests(State(state): State<SharedState>, mut request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let context = RequestContext::from_parts(request.headers(), request.extensions());
+    request.extensions_mut().insert(context.clone());
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|m| m.as_str().to_string())
+        .unwrap_or_else(|| path.clone());
+
+    let mut response = next.run(request).await;
+    let status = response.status();
+    if let Ok(value) = HeaderValue::from_str(&context.request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    let class = match (outcome(status), classify(&method, &route)) {
+        ("denied", _) => Some((AuditEventType::Auth, "access_denied")),
+        (_, class) => class,
+    };
+    let Some((event_type, action)) = class else {
+        return response;
+    };
+
+    let mut entry = match context.take() {
+        // A handler's own authentication decision is kept even on failure.
+        Some(entry) if outcome(status) == "success" || entry.event_type == AuditEventType::Auth => entry,
+        _ => AuditEntry::new(event_type, &context.actor, action, &resource_of(&route, &path), None),
+    };
+
+    let mut details = match entry.details.take() {
+        Some(serde_json::Value::Object(map)) => map,
+        Some(other) => serde_json::Map::from_iter([("value".to_string(), other)]),
+        None => serde_json::Map::new(),
+    };
+    details.insert("request_id".into(), context.request_id.clone().into());
+    details.insert("role".into(), context.role.clone().into());
+    details.insert("method".into(), method.as_str().into());
+    details.insert("path".into(), path.into());
+    details.insert("status".into(), status.as_u16().into());
+    details.insert("outcome".into(), outcome(status).into());
+    details.insert("latency_ms".into(), (started.elapsed().as_millis() as u64).into());
+    if let Some(FailureReason(reason)) = response.extensions().get::<FailureReason>() {
+        details.insert("error".into(), reason.clone().into());
+    }
+    entry.details = Some(details.into());
+
+    state.audit.record(entry);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_routes() {
+        assert_eq!(
+            classify(&Method::POST, "/v5/projects/:id/generate"),
+            Some((AuditEventType::Generate, "generate"))
+        );
+        assert_eq!(classify(&Method::GET, "/v5/projects/:id"), None);
+        assert_eq!(classify(&Method::POST, "/v5/projects/:id/zones/:zone_id/simulate"), None);
+        assert_eq!(classify(&Method::PUT, "/v5/anything"), Some((AuditEventType::System, "mutation")));
+        assert_eq!(outcome(StatusCode::FORBIDDEN), "denied");
+        assert_eq!(outcome(StatusCode::CONFLICT), "failure");
+    }
+
+    #[test]
+    fn test_resource_comes_from_project_segment() {
+        assert_eq!(resource_of("/v5/projects/:id/zones/:zone_id/rollback", "/v5/projects/p1/zones/z1/rollback"), "p1");
+        assert_eq!(resource_of("/v5/export", "/v5/export"), "/v5/export");
+    }
+}
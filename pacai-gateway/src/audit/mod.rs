@@ -0,0 +1,630 @@
+pub mod checkpoint;
+pub mod export;
+pub mod middleware;
+pub mod query;
+pub mod segments;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::broadcast;
+use crate::security::hsm::{HsmError, HsmManager};
+use crate::util::logging::{self, AuditEntry, AuditEventType, TamperReport, GENESIS_HASH};
+use checkpoint::{Checkpoint, CheckpointPolicy};
+use segments::{RotationPolicy, SegmentIndex, SegmentInfo};
+
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+    #[error("Audit log I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Audit log serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Audit log is corrupt at line {line}: {reason}")]
+    Corrupt { line: usize, reason: String },
+
+    #[error("Audit checkpoint signing failed: {0}")]
+    Signing(#[from] HsmError),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainStatus {
+    pub valid: bool,
+    pub entries: u64,
+    pub head_sequence: Option<u64>,
+    pub head_hash: String,
+    pub checkpoints: u64,
+    /// Highest sequence covered by a verified checkpoint.
+    pub attested_sequence: Option<u64>,
+    /// Sealed segments still on disk, not counting the active one.
+    pub segments: u64,
+    /// Last sequence removed by retention, if any.
+    pub pruned_through: Option<u64>,
+    pub tampered: Option<TamperReport>,
+}
+
+/// Narrows a set of audit entries. Unset fields match everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditFilter {
+    #[serde(rename = "type")]
+    pub event_type: Option<AuditEventType>,
+    pub actor: Option<String>,
+    pub resource: Option<String>,
+    /// Lowest sequence to include.
+    pub since: Option<u64>,
+    /// Earliest timestamp to include.
+    pub start: Option<DateTime<Utc>>,
+    /// Timestamps at or after this are excluded.
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.event_type.is_none_or(|t| entry.event_type == t)
+            && self.actor.as_ref().is_none_or(|a| &entry.actor == a)
+            && self.resource.as_ref().is_none_or(|r| &entry.resource == r)
+            && self.since.is_none_or(|s| entry.sequence >= s)
+            && self.in_time_range(entry)
+    }
+
+    fn in_time_range(&self, entry: &AuditEntry) -> bool {
+        if self.start.is_none() && self.end.is_none() {
+            return true;
+        }
+        let Ok(at) = DateTime::parse_from_rfc3339(&entry.timestamp) else {
+            return false;
+        };
+        self.start.is_none_or(|start| at >= start) && self.end.is_none_or(|end| at < end)
+    }
+}
+
+struct Head {
+    file: File,
+    next_sequence: u64,
+    last_hash: String,
+    /// Entries appended since the last checkpoint.
+    pending: u64,
+    last_checkpoint_at: Instant,
+    /// Entries in the active segment.
+    segment_entries: u64,
+    index: SegmentIndex,
+}
+
+struct Notary {
+    signer: Arc<HsmManager>,
+    policy: CheckpointPolicy,
+}
+
+/// Append-only, hash-chained audit log stored as one JSON entry per line.
+/// Appends are serialized behind a lock and synced before they are
+/// acknowledged, and the chain resumes from the last stored entry on open.
+///
+/// The active segment is `audit.jsonl`. With rotation enabled it is sealed
+/// into `segments/` once full, and the chain carries on in a fresh file.
+pub struct AuditLog {
+    dir: PathBuf,
+    path: PathBuf,
+    head: Mutex<Head>,
+    sender: broadcast::Sender<AuditEntry>,
+    notary: Option<Notary>,
+    rotation: Option<RotationPolicy>,
+}
+
+impl AuditLog {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, AuditError> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("audit.jsonl");
+
+        let index = SegmentIndex::load(&dir)?;
+        finish_rotation(&dir, &path, &index)?;
+        let recovered = recover(&path)?;
+        let (next_sequence, last_hash) = match (&recovered.last, index.last()) {
+            (Some(last), _) => (last.sequence + 1, last.hash.clone()),
+            (None, Some(sealed)) => (sealed.last_sequence + 1, sealed.terminal_hash.clone()),
+            (None, None) => (0, GENESIS_HASH.into()),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        tracing::info!("Audit log opened: {} (next sequence {})", path.display(), next_sequence);
+
+        let mut head = Head {
+            file,
+            next_sequence,
+            last_hash,
+            pending: recovered.pending,
+            last_checkpoint_at: Instant::now(),
+            segment_entries: recovered.entries,
+            index,
+        };
+        // A crash between sealing a segment and starting the next one leaves
+        // the active file empty; start it now.
+        if let (None, Some(sealed)) = (&recovered.last, head.index.last().cloned()) {
+            write_entry(&mut head, segments::genesis_entry(&sealed))?;
+        }
+
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Ok(Self {
+            dir,
+            path,
+            head: Mutex::new(head),
+            sender,
+            notary: None,
+            rotation: None,
+        })
+    }
+
+    /// Seals the active segment once it holds `policy.max_entries` and prunes
+    /// sealed segments outside the retention policy. Sealing signs with the
+    /// checkpoint key, so this needs `with_checkpoints` as well.
+    pub fn with_rotation(mut self, policy: RotationPolicy) -> Self {
+        self.rotation = Some(policy);
+        self
+    }
+
+    /// Signs a checkpoint into the chain with `signer` whenever `policy` says
+    /// one is due.
+    pub fn with_checkpoints(mut self, signer: Arc<HsmManager>, policy: CheckpointPolicy) -> Self {
+        self.notary = Some(Notary { signer, policy });
+        self
+    }
+
+    pub fn signer(&self) -> Option<&HsmManager> {
+        self.notary.as_ref().map(|n| n.signer.as_ref())
+    }
+
+    /// Seals `entry` onto the end of the chain and stores it.
+    pub fn append(&self, entry: AuditEntry) -> Result<AuditEntry, AuditError> {
+        let mut head = self.head.lock().unwrap();
+        let entry = write_entry(&mut head, entry)?;
+
+        // The entry is already durable; a failure past this point only
+        // delays the checkpoint or rotation.
+        let mut follow_up = Vec::new();
+        match &self.notary {
+            Some(notary) if head.pending >= notary.policy.every_entries => match write_checkpoint(&mut head, notary) {
+                Ok(checkpoint) => follow_up.push(checkpoint),
+                Err(e) => tracing::error!("Audit checkpoint failed: {}", e),
+            },
+            _ => {}
+        }
+        if self.rotation.is_some_and(|policy| head.segment_entries >= policy.max_entries) {
+            match self.rotate(&mut head) {
+                Ok(written) => follow_up.extend(written),
+                Err(e) => tracing::error!("Audit segment rotation failed: {}", e),
+            }
+        }
+        drop(head);
+
+        self.publish(&entry);
+        for entry in &follow_up {
+            self.publish(entry);
+        }
+        Ok(entry)
+    }
+
+    /// Seals the active segment: checkpoints its tail, signs its terminal
+    /// hash into the index, moves it under `segments/` and opens the next
+    /// segment with a genesis entry linking back to it.
+    fn rotate(&self, head: &mut Head) -> Result<Vec<AuditEntry>, AuditError> {
+        let (Some(notary), Some(policy)) = (&self.notary, &self.rotation) else {
+            return Err(HsmError::NotInitialized.into());
+        };
+        let mut written = Vec::new();
+        if head.pending > 0 {
+            written.push(write_checkpoint(head, notary)?);
+        }
+
+        let last_sequence = head.next_sequence - 1;
+        let first_sequence = last_sequence + 1 - head.segment_entries;
+        let sealed = SegmentInfo {
+            file: segments::segment_file_name(first_sequence, last_sequence),
+            first_sequence,
+            last_sequence,
+            terminal_hash: head.last_hash.clone(),
+            sealed_at: chrono::Utc::now().to_rfc3339(),
+            seal: Checkpoint::sign(&notary.signer, last_sequence, &head.last_hash)?,
+            pruned_at: None,
+        };
+
+        // Index first: if we crash before the move, open() finishes it.
+        let mut index = head.index.clone();
+        index.segments.push(sealed.clone());
+        index.save(&self.dir)?;
+        head.index = index;
+        finish_rotation(&self.dir, &self.path, &head.index)?;
+
+        head.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        head.segment_entries = 0;
+        written.push(write_entry(head, segments::genesis_entry(&sealed))?);
+        tracing::info!("Audit segment {} sealed ({}..={})", sealed.file, first_sequence, last_sequence);
+
+        self.expire(head, policy);
+        Ok(written)
+    }
+
+    /// Applies the retention policy now, e.g. for age limits on a quiet log.
+    pub fn enforce_retention(&self) -> Result<usize, AuditError> {
+        let Some(policy) = &self.rotation else {
+            return Ok(0);
+        };
+        let mut head = self.head.lock().unwrap();
+        Ok(self.expire(&mut head, policy))
+    }
+
+    fn expire(&self, head: &mut Head, policy: &RotationPolicy) -> usize {
+        let mut index = head.index.clone();
+        let pruned = index.expire(policy, chrono::Utc::now());
+        if pruned.is_empty() {
+            return 0;
+        }
+        // Record the prune before deleting, so a crash leaves an orphan file
+        // rather than a hole in the index.
+        if let Err(e) = index.save(&self.dir) {
+            tracing::error!("Audit retention skipped, index not saved: {}", e);
+            return 0;
+        }
+        head.index = index;
+        for segment in &pruned {
+            match std::fs::remove_file(segments::segment_path(&self.dir, segment)) {
+                Ok(()) => tracing::info!("Audit segment {} pruned by retention policy", segment.file),
+                Err(e) => tracing::warn!("Audit segment {} not removed: {}", segment.file, e),
+            }
+        }
+        pruned.len()
+    }
+
+    /// Writes a checkpoint if entries are waiting for one and the policy's
+    /// interval has passed since the last.
+    pub fn checkpoint_if_due(&self) -> Result<Option<AuditEntry>, AuditError> {
+        let Some(notary) = &self.notary else {
+            return Ok(None);
+        };
+        let due = {
+            let head = self.head.lock().unwrap();
+            head.pending > 0 && head.last_checkpoint_at.elapsed() >= notary.policy.interval
+        };
+        if due { self.checkpoint() } else { Ok(None) }
+    }
+
+    /// Writes a checkpoint now unless the head is already attested.
+    pub fn checkpoint(&self) -> Result<Option<AuditEntry>, AuditError> {
+        let Some(notary) = &self.notary else {
+            return Ok(None);
+        };
+        let mut head = self.head.lock().unwrap();
+        if head.pending == 0 {
+            return Ok(None);
+        }
+        let entry = write_checkpoint(&mut head, notary)?;
+        drop(head);
+
+        self.publish(&entry);
+        Ok(Some(entry))
+    }
+
+    fn publish(&self, entry: &AuditEntry) {
+        tracing::debug!("{}", logging::format_log_line(entry));
+        let _ = self.sender.send(entry.clone());
+    }
+
+    /// Like `append`, for callers that must not fail because auditing did.
+    pub fn record(&self, entry: AuditEntry) {
+        if let Err(e) = self.append(entry) {
+            tracing::error!("Failed to write audit entry: {}", e);
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AuditEntry> {
+        self.sender.subscribe()
+    }
+
+    /// Every retained entry with a sequence of at least `from`, across
+    /// sealed segments and the active one.
+    pub fn entries_from(&self, from: u64) -> Result<Vec<AuditEntry>, AuditError> {
+        // Hold the lock so a concurrent append or rotation is fully in or out.
+        let head = self.head.lock().unwrap();
+        let mut entries = Vec::new();
+        for segment in head.index.retained().filter(|s| s.last_sequence >= from) {
+            read_entries(&segments::segment_path(&self.dir, segment), from, &mut entries)?;
+        }
+        read_entries(&self.path, from, &mut entries)?;
+        Ok(entries)
+    }
+
+    /// Recomputes the whole stored chain and checks checkpoint signatures
+    /// against this gateway's key.
+    pub fn verify(&self) -> Result<ChainStatus, AuditError> {
+        let entries = self.entries_from(0)?;
+        let index = self.head.lock().unwrap().index.clone();
+        let key = self.signer().and_then(|s| s.verifying_key());
+        let checkpoints = match &key {
+            Some(key) => checkpoint::verify_checkpoints(&entries, key),
+            None => Ok(entries.iter().filter_map(Checkpoint::from_entry).collect()),
+        };
+        let tampered = logging::verify_chain(&entries)
+            .err()
+            .or_else(|| segments::verify_seals(&index, &entries, key.as_ref()).err())
+            .or_else(|| checkpoints.as_ref().err().cloned());
+        let checkpoints = checkpoints.unwrap_or_default();
+
+        Ok(ChainStatus {
+            valid: tampered.is_none(),
+            entries: entries.len() as u64,
+            head_sequence: entries.last().map(|e| e.sequence),
+            head_hash: entries.last().map(|e| e.hash.clone()).unwrap_or_else(|| GENESIS_HASH.into()),
+            checkpoints: checkpoints.len() as u64,
+            attested_sequence: checkpoints.iter().map(|c| c.sequence).max(),
+            segments: index.retained().count() as u64,
+            pruned_through: index.anchor().map(|s| s.last_sequence),
+            tampered,
+        })
+    }
+}
+
+fn write_entry(head: &mut Head, entry: AuditEntry) -> Result<AuditEntry, AuditError> {
+    let entry = entry.seal(head.next_sequence, &head.last_hash);
+
+    let mut line = serde_json::to_vec(&entry)?;
+    line.push(b'\n');
+    head.file.write_all(&line)?;
+    head.file.sync_data()?;
+
+    head.next_sequence += 1;
+    head.last_hash = entry.hash.clone();
+    head.pending += 1;
+    head.segment_entries += 1;
+    Ok(entry)
+}
+
+fn write_checkpoint(head: &mut Head, notary: &Notary) -> Result<AuditEntry, AuditError> {
+    let attested = head.next_sequence - 1;
+    let checkpoint = Checkpoint::sign(&notary.signer, attested, &head.last_hash)?;
+    let entry = write_entry(head, checkpoint.entry())?;
+    head.pending = 0;
+    head.last_checkpoint_at = Instant::now();
+    Ok(entry)
+}
+
+fn read_entries(path: &Path, from: u64, entries: &mut Vec<AuditEntry>) -> Result<(), AuditError> {
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let entry = parse_line(index, &line?)?;
+        if entry.sequence >= from {
+            entries.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Moves the active file under `segments/` if the index says it was sealed
+/// but the move never happened.
+fn finish_rotation(dir: &Path, active: &Path, index: &SegmentIndex) -> Result<(), AuditError> {
+    let Some(sealed) = index.last() else {
+        return Ok(());
+    };
+    let target = segments::segment_path(dir, sealed);
+    if sealed.is_pruned() || target.exists() || !active.exists() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(segments::segment_dir(dir))?;
+    std::fs::rename(active, &target)?;
+    Ok(())
+}
+
+fn parse_line(index: usize, line: &str) -> Result<AuditEntry, AuditError> {
+    serde_json::from_str(line).map_err(|e| AuditError::Corrupt {
+        line: index + 1,
+        reason: e.to_string(),
+    })
+}
+
+#[derive(Default)]
+struct Recovered {
+    last: Option<AuditEntry>,
+    /// Entries after the last checkpoint.
+    pending: u64,
+    entries: u64,
+}
+
+/// Finds where the active segment left off and how many entries follow the
+/// last checkpoint. A torn final line from a crash mid-write is cut off;
+/// anything else unreadable is reported as corruption.
+fn recover(path: &Path) -> Result<Recovered, AuditError> {
+    if !path.exists() {
+        return Ok(Recovered::default());
+    }
+
+    let contents = std::fs::read(path)?;
+    let complete = contents.iter().rposition(|b| *b == b'\n').map(|i| i + 1).unwrap_or(0);
+    if complete < contents.len() {
+        tracing::warn!("Audit log {} ends in a partial entry, truncating {} bytes", path.display(), contents.len() - complete);
+        OpenOptions::new().write(true).open(path)?.set_len(complete as u64)?;
+    }
+
+    let text = String::from_utf8_lossy(&contents[..complete]);
+    let lines: Vec<_> = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).collect();
+    let entries = lines.len() as u64;
+    let mut lines = lines.into_iter().rev();
+    let Some((index, line)) = lines.next() else {
+        return Ok(Recovered::default());
+    };
+    let last = parse_line(index, line)?;
+
+    let mut pending = 0;
+    let mut entry = last.clone();
+    while entry.event_type != AuditEventType::Checkpoint {
+        pending += 1;
+        match lines.next() {
+            Some((index, line)) => entry = parse_line(index, line)?,
+            None => break,
+        }
+    }
+
+    Ok(Recovered { last: Some(last), pending, entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::logging::{log_generate, log_system, ChainFault};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_chain_continues_after_reopen() {
+        let dir = tempdir().unwrap();
+        let log = AuditLog::open(dir.path()).unwrap();
+        log.append(log_system("start", serde_json::json!({}))).unwrap();
+        let second = log.append(log_generate("alice", "p1", 7)).unwrap();
+        drop(log);
+
+        let log = AuditLog::open(dir.path()).unwrap();
+        let third = log.append(log_generate("bob", "p1", 8)).unwrap();
+        assert_eq!(third.sequence, 2);
+        assert_eq!(third.prev_hash, second.hash);
+
+        let status = log.verify().unwrap();
+        assert!(status.valid);
+        assert_eq!(status.entries, 3);
+    }
+
+    #[test]
+    fn test_verify_reports_first_tampered_sequence() {
+        let dir = tempdir().unwrap();
+        let log = AuditLog::open(dir.path()).unwrap();
+        for seed in 0..4 {
+            log.append(log_generate("alice", "p1", seed)).unwrap();
+        }
+
+        let path = dir.path().join("audit.jsonl");
+        let text = std::fs::read_to_string(&path).unwrap().replace("\"seed\":2", "\"seed\":20");
+        std::fs::write(&path, text).unwrap();
+
+        let status = log.verify().unwrap();
+        assert!(!status.valid);
+        assert_eq!(status.tampered, Some(TamperReport { sequence: 2, fault: ChainFault::HashMismatch }));
+    }
+
+    #[test]
+    fn test_filter_matches_on_every_field() {
+        let dir = tempdir().unwrap();
+        let log = AuditLog::open(dir.path()).unwrap();
+        log.append(log_generate("alice", "p1", 1)).unwrap();
+        log.append(log_generate("bob", "p1", 2)).unwrap();
+        log.append(log_system("start", serde_json::json!({}))).unwrap();
+        log.append(log_generate("alice", "p2", 3)).unwrap();
+        let entries = log.entries_from(0).unwrap();
+
+        let select = |filter: AuditFilter| -> Vec<u64> {
+            entries.iter().filter(|e| filter.matches(e)).map(|e| e.sequence).collect()
+        };
+        assert_eq!(select(AuditFilter::default()), vec![0, 1, 2, 3]);
+        assert_eq!(select(AuditFilter { actor: Some("alice".into()), ..Default::default() }), vec![0, 3]);
+        assert_eq!(select(AuditFilter { event_type: Some(AuditEventType::System), ..Default::default() }), vec![2]);
+        assert_eq!(
+            select(AuditFilter { resource: Some("p1".into()), since: Some(1), ..Default::default() }),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_cadence_survives_reopen() {
+        let dir = tempdir().unwrap();
+        let signer = HsmManager::ephemeral("test");
+        let signer = Arc::new(signer);
+        let policy = CheckpointPolicy { every_entries: 3, interval: std::time::Duration::from_secs(3600) };
+
+        let log = AuditLog::open(dir.path()).unwrap().with_checkpoints(signer.clone(), policy);
+        for seed in 0..5 {
+            log.append(log_generate("alice", "p1", seed)).unwrap();
+        }
+        drop(log);
+
+        // Two entries were pending at close; the third after reopen triggers a checkpoint.
+        let log = AuditLog::open(dir.path()).unwrap().with_checkpoints(signer, policy);
+        log.append(log_generate("alice", "p1", 5)).unwrap();
+        let types: Vec<_> = log.entries_from(0).unwrap().iter().map(|e| e.event_type).collect();
+        assert_eq!(types[3], AuditEventType::Checkpoint);
+        assert_eq!(types[7], AuditEventType::Checkpoint);
+        assert_eq!(log.checkpoint().unwrap().map(|e| e.sequence), None);
+
+        let status = log.verify().unwrap();
+        assert!(status.valid);
+        assert_eq!((status.checkpoints, status.attested_sequence), (2, Some(6)));
+    }
+
+    fn rotating_log(dir: &Path, retain_segments: Option<usize>) -> AuditLog {
+        // The key is kept with the log, so checkpoints verify after a reopen.
+        let mut signer = HsmManager::new("test");
+        signer.load_or_initialize(&dir.join("key")).unwrap();
+        let checkpoints = CheckpointPolicy { every_entries: 100, interval: std::time::Duration::from_secs(3600) };
+        let rotation = RotationPolicy { max_entries: 4, retain_segments, retain_for: None };
+        AuditLog::open(dir)
+            .unwrap()
+            .with_checkpoints(Arc::new(signer), checkpoints)
+            .with_rotation(rotation)
+    }
+
+    #[test]
+    fn test_rotation_keeps_one_chain_across_segments() {
+        let dir = tempdir().unwrap();
+        let log = rotating_log(dir.path(), None);
+        for seed in 0..10 {
+            log.append(log_generate("alice", "p1", seed)).unwrap();
+        }
+        drop(log);
+
+        let log = rotating_log(dir.path(), None);
+        let status = log.verify().unwrap();
+        assert!(status.valid, "{:?}", status.tampered);
+        assert!(status.segments >= 2);
+
+        let entries = log.entries_from(0).unwrap();
+        assert!(logging::verify_chain(&entries).is_ok());
+        let genesis = entries.iter().find(|e| e.action == segments::GENESIS_ACTION).unwrap();
+        assert_eq!(genesis.prev_hash, entries[genesis.sequence as usize - 1].hash);
+
+        // Editing a sealed segment is caught even though its file is no longer active.
+        let sealed = segments::segment_dir(dir.path()).join(segments::segment_file_name(0, 4));
+        let text = std::fs::read_to_string(&sealed).unwrap().replace("\"seed\":1", "\"seed\":11");
+        std::fs::write(&sealed, text).unwrap();
+        assert_eq!(log.verify().unwrap().tampered.map(|t| t.sequence), Some(1));
+    }
+
+    #[test]
+    fn test_retention_prunes_without_breaking_the_chain() {
+        let dir = tempdir().unwrap();
+        let log = rotating_log(dir.path(), Some(1));
+        for seed in 0..16 {
+            log.append(log_generate("alice", "p1", seed)).unwrap();
+        }
+
+        let status = log.verify().unwrap();
+        assert!(status.valid, "{:?}", status.tampered);
+        assert_eq!(status.segments, 1);
+        let pruned_through = status.pruned_through.unwrap();
+        assert_eq!(log.entries_from(0).unwrap()[0].sequence, pruned_through + 1);
+        assert_eq!(std::fs::read_dir(segments::segment_dir(dir.path())).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_partial_trailing_line_is_dropped() {
+        let dir = tempdir().unwrap();
+        let log = AuditLog::open(dir.path()).unwrap();
+        log.append(log_generate("alice", "p1", 1)).unwrap();
+        drop(log);
+
+        let path = dir.path().join("audit.jsonl");
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"sequence\":1,\"times").unwrap();
+
+        let log = AuditLog::open(dir.path()).unwrap();
+        assert_eq!(log.append(log_generate("alice", "p1", 2)).unwrap().sequence, 1);
+        assert!(log.verify().unwrap().valid);
+    }
+}
@@ -0,0 +1,206 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use crate::audit::AuditFilter;
+use crate::util::logging::{AuditEntry, AuditEventType};
+
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+pub const MAX_PAGE_SIZE: usize = 1000;
+const RECENT_FAILURES: usize = 20;
+
+#[derive(Debug, Serialize)]
+pub struct EventPage {
+    pub events: Vec<AuditEntry>,
+    /// Pass back as `cursor` for the next page; absent on the last page.
+    pub next_cursor: Option<u64>,
+}
+
+/// Up to `limit` entries matching `filter` with a sequence above `cursor`,
+/// oldest first.
+pub fn page(entries: Vec<AuditEntry>, filter: &AuditFilter, cursor: Option<u64>, limit: usize) -> EventPage {
+    let mut events: Vec<_> = entries
+        .into_iter()
+        .filter(|e| cursor.is_none_or(|c| e.sequence > c) && filter.matches(e))
+        .take(limit + 1)
+        .collect();
+
+    let more = events.len() > limit;
+    events.truncate(limit);
+    EventPage {
+        next_cursor: if more { events.last().map(|e| e.sequence) } else { None },
+        events,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DailyCount {
+    pub day: String,
+    pub actor: String,
+    pub event_type: AuditEventType,
+    pub count: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditStats {
+    pub total: u64,
+    pub daily: Vec<DailyCount>,
+    pub by_actor: BTreeMap<String, u64>,
+    pub by_type: BTreeMap<AuditEventType, u64>,
+}
+
+/// Counts per day, actor and event type. Days are UTC calendar dates.
+pub fn stats<'a>(entries: impl IntoIterator<Item = &'a AuditEntry>) -> AuditStats {
+    let mut daily: BTreeMap<(String, String, AuditEventType), u64> = BTreeMap::new();
+    let mut by_actor = BTreeMap::new();
+    let mut by_type = BTreeMap::new();
+    let mut total = 0;
+
+    for entry in entries {
+        let day = entry.timestamp.get(..10).unwrap_or(&entry.timestamp).to_string();
+        *daily.entry((day, entry.actor.clone(), entry.event_type)).or_default() += 1;
+        *by_actor.entry(entry.actor.clone()).or_default() += 1;
+        *by_type.entry(entry.event_type).or_default() += 1;
+        total += 1;
+    }
+
+    AuditStats {
+        total,
+        daily: daily
+            .into_iter()
+            .map(|((day, actor, event_type), count)| DailyCount { day, actor, event_type, count })
+            .collect(),
+        by_actor,
+        by_type,
+    }
+}
+
+/// A request refused by access control, or an explicit failed login.
+pub fn is_failed_auth(entry: &AuditEntry) -> bool {
+    let detail = |key: &str| entry.details.as_ref().and_then(|d| d.get(key));
+    detail("outcome").and_then(|o| o.as_str()) == Some("denied")
+        || (entry.event_type == AuditEventType::Auth && detail("success").and_then(|s| s.as_bool()) == Some(false))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActorFailures {
+    pub actor: String,
+    pub count: u64,
+    pub first_at: String,
+    pub last_at: String,
+    pub roles: BTreeSet<String>,
+    pub paths: BTreeSet<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthFailureSummary {
+    pub total: u64,
+    /// Most failures first.
+    pub actors: Vec<ActorFailures>,
+    pub recent: Vec<AuditEntry>,
+}
+
+pub fn auth_failures<'a>(entries: impl IntoIterator<Item = &'a AuditEntry>) -> AuthFailureSummary {
+    let mut actors: BTreeMap<String, ActorFailures> = BTreeMap::new();
+    let mut failures = Vec::new();
+
+    for entry in entries.into_iter().filter(|e| is_failed_auth(e)) {
+        let detail = |key: &str| {
+            entry.details.as_ref().and_then(|d| d.get(key)).and_then(|v| v.as_str()).map(str::to_string)
+        };
+        let summary = actors.entry(entry.actor.clone()).or_insert_with(|| ActorFailures {
+            actor: entry.actor.clone(),
+            count: 0,
+            first_at: entry.timestamp.clone(),
+            last_at: entry.timestamp.clone(),
+            roles: BTreeSet::new(),
+            paths: BTreeSet::new(),
+        });
+        summary.count += 1;
+        summary.last_at = entry.timestamp.clone();
+        summary.roles.extend(detail("role"));
+        summary.paths.extend(detail("path"));
+        failures.push(entry);
+    }
+
+    let mut actors: Vec<_> = actors.into_values().collect();
+    actors.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.actor.cmp(&b.actor)));
+    let recent = failures.iter().rev().take(RECENT_FAILURES).rev().map(|e| (*e).clone()).collect();
+
+    AuthFailureSummary {
+        total: failures.len() as u64,
+        actors,
+        recent,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::logging::{log_auth, log_generate, GENESIS_HASH};
+
+    fn sealed(entries: Vec<AuditEntry>) -> Vec<AuditEntry> {
+        let mut prev = GENESIS_HASH.to_string();
+        entries
+            .into_iter()
+            .enumerate()
+            .map(|(seq, entry)| {
+                let entry = entry.seal(seq as u64, &prev);
+                prev = entry.hash.clone();
+                entry
+            })
+            .collect()
+    }
+
+    fn denied(actor: &str, path: &str) -> AuditEntry {
+        AuditEntry::new(
+            AuditEventType::Auth,
+            actor,
+            "access_denied",
+            path,
+            Some(serde_json::json!({ "outcome": "denied", "role": "demo", "path": path })),
+        )
+    }
+
+    #[test]
+    fn test_page_walks_matches_by_cursor() {
+        let entries = sealed((0..7).map(|seed| log_generate(if seed % 2 == 0 { "alice" } else { "bob" }, "p1", seed)).collect());
+        let filter = AuditFilter { actor: Some("alice".into()), ..Default::default() };
+
+        let first = page(entries.clone(), &filter, None, 2);
+        assert_eq!(first.events.iter().map(|e| e.sequence).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(first.next_cursor, Some(2));
+
+        let second = page(entries, &filter, first.next_cursor, 2);
+        assert_eq!(second.events.iter().map(|e| e.sequence).collect::<Vec<_>>(), vec![4, 6]);
+        assert_eq!(second.next_cursor, None);
+    }
+
+    #[test]
+    fn test_stats_count_per_day_actor_and_type() {
+        let entries = sealed(vec![
+            log_generate("alice", "p1", 1),
+            log_generate("alice", "p1", 2),
+            log_auth("bob", "login", true),
+        ]);
+        let stats = stats(&entries);
+        assert_eq!(stats.total, 3);
+        assert_eq!(stats.daily.len(), 2);
+        assert_eq!(stats.by_actor["alice"], 2);
+        assert_eq!(stats.by_type[&AuditEventType::Auth], 1);
+    }
+
+    #[test]
+    fn test_auth_failures_group_by_actor() {
+        let entries = sealed(vec![
+            denied("mallory", "/v5/audit/events"),
+            log_auth("alice", "login", true),
+            denied("mallory", "/v5/export"),
+            log_auth("eve", "login", false),
+        ]);
+        let summary = auth_failures(&entries);
+        assert_eq!(summary.total, 3);
+        assert_eq!(summary.actors[0].actor, "mallory");
+        assert_eq!(summary.actors[0].count, 2);
+        assert_eq!(summary.actors[0].paths.len(), 2);
+        assert_eq!(summary.recent.last().map(|e| e.actor.as_str()), Some("eve"));
+    }
+}
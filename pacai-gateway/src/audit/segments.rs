@@ -0,0 +1,212 @@
+use chrono::{DateTime, Utc};
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::audit::AuditError;
+use crate::audit::checkpoint::Checkpoint;
+use crate::util::logging::{AuditEntry, AuditEventType, ChainFault, TamperReport};
+
+const INDEX_FILE: &str = "segments.json";
+const SEGMENT_DIR: &str = "segments";
+
+/// Action of the first entry in every segment after the first.
+pub const GENESIS_ACTION: &str = "segment_genesis";
+
+#[derive(Debug, Clone, Copy)]
+pub struct RotationPolicy {
+    /// Entries in the active segment before it is sealed.
+    pub max_entries: u64,
+    /// Sealed segments kept on disk; older ones are pruned.
+    pub retain_segments: Option<usize>,
+    /// Sealed segments older than this are pruned.
+    pub retain_for: Option<Duration>,
+}
+
+/// A sealed segment. The record outlives the file when the segment is
+/// pruned, so the oldest retained entry still links to a signed hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentInfo {
+    pub file: String,
+    pub first_sequence: u64,
+    pub last_sequence: u64,
+    /// Hash of the segment's last entry, which the next segment chains from.
+    pub terminal_hash: String,
+    pub sealed_at: String,
+    pub seal: Checkpoint,
+    pub pruned_at: Option<String>,
+}
+
+impl SegmentInfo {
+    pub fn is_pruned(&self) -> bool {
+        self.pruned_at.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SegmentIndex {
+    pub segments: Vec<SegmentInfo>,
+}
+
+impl SegmentIndex {
+    pub fn load(dir: &Path) -> Result<Self, AuditError> {
+        match std::fs::read(dir.join(INDEX_FILE)) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), AuditError> {
+        let tmp = dir.join(format!("{}.tmp", INDEX_FILE));
+        let file = std::fs::File::create(&tmp)?;
+        serde_json::to_writer_pretty(&file, self)?;
+        file.sync_all()?;
+        std::fs::rename(tmp, dir.join(INDEX_FILE))?;
+        Ok(())
+    }
+
+    pub fn last(&self) -> Option<&SegmentInfo> {
+        self.segments.last()
+    }
+
+    pub fn retained(&self) -> impl Iterator<Item = &SegmentInfo> {
+        self.segments.iter().filter(|s| !s.is_pruned())
+    }
+
+    /// The newest pruned segment: what the oldest retained entry links to.
+    pub fn anchor(&self) -> Option<&SegmentInfo> {
+        self.segments.iter().rev().find(|s| s.is_pruned())
+    }
+
+    /// Marks the oldest segments that fall outside `policy` as pruned and
+    /// returns them. Only a run from the oldest end is ever pruned, so what
+    /// remains is always contiguous.
+    pub fn expire(&mut self, policy: &RotationPolicy, now: DateTime<Utc>) -> Vec<SegmentInfo> {
+        let mut excess = match policy.retain_segments {
+            Some(keep) => self.retained().count().saturating_sub(keep),
+            None => 0,
+        };
+        let cutoff = policy
+            .retain_for
+            .and_then(|age| chrono::Duration::from_std(age).ok())
+            .map(|age| now - age);
+
+        let mut pruned = Vec::new();
+        for segment in self.segments.iter_mut().filter(|s| !s.is_pruned()) {
+            let too_old = cutoff.is_some_and(|cutoff| {
+                DateTime::parse_from_rfc3339(&segment.sealed_at).is_ok_and(|sealed| sealed < cutoff)
+            });
+            if excess == 0 && !too_old {
+                break;
+            }
+            excess = excess.saturating_sub(1);
+            segment.pruned_at = Some(now.to_rfc3339());
+            pruned.push(segment.clone());
+        }
+        pruned
+    }
+}
+
+pub fn segment_dir(dir: &Path) -> PathBuf {
+    dir.join(SEGMENT_DIR)
+}
+
+pub fn segment_path(dir: &Path, segment: &SegmentInfo) -> PathBuf {
+    segment_dir(dir).join(&segment.file)
+}
+
+pub fn segment_file_name(first: u64, last: u64) -> String {
+    format!("{:020}-{:020}.jsonl", first, last)
+}
+
+pub fn genesis_entry(previous: &SegmentInfo) -> AuditEntry {
+    AuditEntry::new(
+        AuditEventType::System,
+        "system",
+        GENESIS_ACTION,
+        "audit",
+        Some(serde_json::json!({
+            "previous_segment": previous.file,
+            "previous_last_sequence": previous.last_sequence,
+            "previous_terminal_hash": previous.terminal_hash,
+        })),
+    )
+}
+
+/// Checks what the chain alone cannot: that every sealed segment's signed
+/// terminal hash matches its stored last entry, and that the oldest
+/// retained entry links to the signed terminal hash of whatever was pruned
+/// before it. `entries` is the full retained chain, oldest first.
+pub fn verify_seals(index: &SegmentIndex, entries: &[AuditEntry], key: Option<&VerifyingKey>) -> Result<(), TamperReport> {
+    let sealed_ok = |segment: &SegmentInfo| {
+        segment.seal.sequence == segment.last_sequence
+            && segment.seal.hash == segment.terminal_hash
+            && key.is_none_or(|key| segment.seal.verify(key))
+    };
+    let first = entries.first().map_or(0, |e| e.sequence);
+
+    match (index.anchor(), entries.first()) {
+        (Some(anchor), Some(entry)) => {
+            if !sealed_ok(anchor) {
+                return Err(TamperReport { sequence: anchor.last_sequence, fault: ChainFault::BadCheckpoint });
+            }
+            if entry.sequence != anchor.last_sequence + 1 || entry.prev_hash != anchor.terminal_hash {
+                return Err(TamperReport { sequence: entry.sequence, fault: ChainFault::BrokenLink });
+            }
+        }
+        // Without a pruned anchor the chain must reach back to genesis.
+        (None, Some(entry)) if entry.sequence != 0 => {
+            return Err(TamperReport { sequence: entry.sequence, fault: ChainFault::SequenceGap });
+        }
+        _ => {}
+    }
+
+    for segment in index.retained() {
+        let stored = segment
+            .last_sequence
+            .checked_sub(first)
+            .and_then(|i| entries.get(i as usize));
+        if !sealed_ok(segment) || stored.is_none_or(|e| e.hash != segment.terminal_hash) {
+            return Err(TamperReport { sequence: segment.last_sequence, fault: ChainFault::BadCheckpoint });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(first: u64, last: u64, sealed_at: DateTime<Utc>) -> SegmentInfo {
+        SegmentInfo {
+            file: segment_file_name(first, last),
+            first_sequence: first,
+            last_sequence: last,
+            terminal_hash: String::new(),
+            sealed_at: sealed_at.to_rfc3339(),
+            seal: Checkpoint { sequence: last, hash: String::new(), public_key: String::new(), signature: String::new() },
+            pruned_at: None,
+        }
+    }
+
+    #[test]
+    fn test_expire_prunes_oldest_first() {
+        let now = Utc::now();
+        let day = chrono::Duration::days(1);
+        let mut index = SegmentIndex {
+            segments: vec![segment(0, 9, now - day * 10), segment(10, 19, now - day * 5), segment(20, 29, now)],
+        };
+
+        let by_count = RotationPolicy { max_entries: 10, retain_segments: Some(2), retain_for: None };
+        let pruned = index.expire(&by_count, now);
+        assert_eq!(pruned.iter().map(|s| s.first_sequence).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(index.anchor().map(|s| s.last_sequence), Some(9));
+
+        let by_age = RotationPolicy { max_entries: 10, retain_segments: None, retain_for: Some(Duration::from_secs(86_400 * 3)) };
+        let pruned = index.expire(&by_age, now);
+        assert_eq!(pruned.iter().map(|s| s.first_sequence).collect::<Vec<_>>(), vec![10]);
+        assert_eq!(index.retained().count(), 1);
+        assert!(index.expire(&by_age, now).is_empty());
+    }
+}
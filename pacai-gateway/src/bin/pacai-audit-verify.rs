@@ -0,0 +1,68 @@
+//! Offline verifier for audit archives from `GET /v5/audit/export`.
+//!
+//! Usage: pacai-audit-verify <archive.zip> [--public-key <hex> | --public-key-file <path>]
+//!
+//! Exits 0 when the archive verifies, 1 when it does not and 2 on bad usage.
+
+use std::process::ExitCode;
+use pacai_gateway::audit::export;
+use pacai_gateway::security::hsm;
+
+const USAGE: &str = "usage: pacai-audit-verify <archive.zip> [--public-key <hex> | --public-key-file <path>]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (archive_path, key_hex) = match args.as_slice() {
+        [path] => (path, None),
+        [path, flag, key] if flag == "--public-key" => (path, Some(key.clone())),
+        [path, flag, file] if flag == "--public-key-file" => match std::fs::read_to_string(file) {
+            Ok(key) => (path, Some(key)),
+            Err(e) => {
+                eprintln!("cannot read {}: {}", file, e);
+                return ExitCode::from(2);
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let pinned = match key_hex.as_deref().map(hsm::parse_public_key) {
+        Some(Some(key)) => Some(key),
+        Some(None) => {
+            eprintln!("public key must be 32 bytes of hex");
+            return ExitCode::from(2);
+        }
+        None => {
+            eprintln!("warning: no public key given; trusting the key embedded in the archive");
+            None
+        }
+    };
+
+    let bytes = match std::fs::read(archive_path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("cannot read {}: {}", archive_path, e);
+            return ExitCode::from(2);
+        }
+    };
+
+    match export::verify_archive(&bytes, pinned.as_ref()) {
+        Ok(report) => {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+            println!(
+                "OK: sequences {}..={} verified, {} checkpoints, {} entries after the last checkpoint",
+                report.manifest.from_sequence,
+                report.manifest.to_sequence,
+                report.checkpoints_verified,
+                report.unattested_entries
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            println!("FAILED: {}", e);
+            ExitCode::from(1)
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use ed25519_dalek::VerifyingKey;
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::security::license;
+use crate::tls::TlsConfig;
+
+#[derive(Debug, Clone)]
+pub struct GatewayConfig {
+    pub data_dir: PathBuf,
+    /// Ed25519 key used to sign audit checkpoints and exports.
+    pub signing_key_path: PathBuf,
+    /// A signed checkpoint is written after this many audit entries...
+    pub checkpoint_every_entries: u64,
+    /// ...or this long after the last one, whichever comes first.
+    pub checkpoint_interval: Duration,
+    /// Audit entries per segment before it is sealed and rotated.
+    pub audit_segment_entries: u64,
+    /// Sealed audit segments to keep; `None` keeps all of them.
+    pub audit_retain_segments: Option<usize>,
+    /// Sealed audit segments older than this are pruned.
+    pub audit_retain_for: Option<Duration>,
+    /// Lifetime of session tokens issued at login.
+    pub session_ttl: Duration,
+    /// Password for the `admin` account created on first start. Without it
+    /// a random one is generated and written to `bootstrap_password_path`.
+    pub bootstrap_admin_password: Option<String>,
+    /// Serve mutual TLS instead of plain HTTP.
+    pub tls: Option<TlsConfig>,
+    /// RBAC policy file in the `V4_RBAC_POLICY.json` format; the built-in
+    /// policy applies when unset.
+    pub rbac_policy_path: Option<PathBuf>,
+    /// Where rate limit buckets are saved across restarts; in memory only
+    /// when unset.
+    pub rate_limit_state_path: Option<PathBuf>,
+    /// Generation jobs that run at the same time; the rest wait in the queue.
+    pub job_concurrency: usize,
+    /// Signed license file, read once at startup.
+    pub license_path: PathBuf,
+    /// Key the license must be signed with. Always the embedded vendor key
+    /// outside of tests.
+    pub license_vendor_key: VerifyingKey,
+}
+
+impl GatewayConfig {
+    pub fn new(data_dir: impl Into<PathBuf>) -> Self {
+        let data_dir = data_dir.into();
+        Self {
+            signing_key_path: data_dir.join("keys").join("gateway.ed25519"),
+            checkpoint_every_entries: 100,
+            checkpoint_interval: Duration::from_secs(15 * 60),
+            audit_segment_entries: 10_000,
+            audit_retain_segments: None,
+            audit_retain_for: None,
+            session_ttl: Duration::from_secs(8 * 60 * 60),
+            bootstrap_admin_password: None,
+            tls: None,
+            rbac_policy_path: None,
+            rate_limit_state_path: None,
+            job_concurrency: 4,
+            license_path: data_dir.join("license.json"),
+            license_vendor_key: license::vendor_key(),
+            data_dir,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let data_dir = std::env::var("PACAI_DATA_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from("data"));
+        let mut config = Self::new(data_dir);
+
+        if let Ok(path) = std::env::var("PACAI_SIGNING_KEY") {
+            config.signing_key_path = PathBuf::from(path);
+        }
+        if let Some(entries) = env_number("PACAI_AUDIT_CHECKPOINT_ENTRIES") {
+            config.checkpoint_every_entries = entries.max(1);
+        }
+        if let Some(minutes) = env_number("PACAI_AUDIT_CHECKPOINT_MINUTES") {
+            config.checkpoint_interval = Duration::from_secs(minutes.max(1) * 60);
+        }
+        if let Some(entries) = env_number("PACAI_AUDIT_SEGMENT_ENTRIES") {
+            config.audit_segment_entries = entries.max(2);
+        }
+        if let Some(segments) = env_number("PACAI_AUDIT_RETAIN_SEGMENTS") {
+            config.audit_retain_segments = Some(segments as usize);
+        }
+        if let Some(days) = env_number("PACAI_AUDIT_RETAIN_DAYS") {
+            config.audit_retain_for = Some(Duration::from_secs(days * 24 * 60 * 60));
+        }
+        if let Some(hours) = env_number("PACAI_SESSION_HOURS") {
+            config.session_ttl = Duration::from_secs(hours.max(1) * 60 * 60);
+        }
+        config.bootstrap_admin_password = std::env::var("PACAI_ADMIN_PASSWORD").ok().filter(|p| !p.is_empty());
+        if let (Ok(cert), Ok(key)) = (std::env::var("PACAI_TLS_CERT"), std::env::var("PACAI_TLS_KEY")) {
+            config.tls = Some(TlsConfig {
+                cert_path: PathBuf::from(cert),
+                key_path: PathBuf::from(key),
+                client_ca_path: std::env::var("PACAI_TLS_CLIENT_CA").ok().map(PathBuf::from),
+                reload_interval: Duration::from_secs(env_number("PACAI_TLS_RELOAD_SECONDS").unwrap_or(30).max(1)),
+            });
+        }
+        config.rbac_policy_path = std::env::var("PACAI_RBAC_POLICY").ok().map(PathBuf::from);
+        config.rate_limit_state_path = std::env::var("PACAI_RATE_LIMIT_STATE").ok().map(PathBuf::from);
+        if let Ok(path) = std::env::var("PACAI_LICENSE") {
+            config.license_path = PathBuf::from(path);
+        }
+        if let Some(jobs) = env_number("PACAI_JOB_CONCURRENCY") {
+            config.job_concurrency = jobs.max(1) as usize;
+        }
+        config
+    }
+
+    pub fn projects_dir(&self) -> PathBuf {
+        self.data_dir.join("projects")
+    }
+
+    pub fn exports_dir(&self) -> PathBuf {
+        self.data_dir.join("exports")
+    }
+
+    pub fn live_dir(&self) -> PathBuf {
+        self.data_dir.join("live")
+    }
+
+    pub fn audit_dir(&self) -> PathBuf {
+        self.data_dir.join("audit")
+    }
+
+    pub fn users_path(&self) -> PathBuf {
+        self.data_dir.join("users.json")
+    }
+
+    pub fn usage_path(&self) -> PathBuf {
+        self.data_dir.join("usage.json")
+    }
+
+    /// Where a generated `admin` password is left for the operator, readable
+    /// by the gateway's own user only.
+    pub fn bootstrap_password_path(&self) -> PathBuf {
+        self.data_dir.join("bootstrap_admin_password")
+    }
+}
+
+fn env_number(name: &str) -> Option<u64> {
+    let value = std::env::var(name).ok()?;
+    match value.trim().parse() {
+        Ok(n) => Some(n),
+        Err(_) => {
+            tracing::warn!("Ignoring {}={:?}: not a number", name, value);
+            None
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use crate::engine::world::{Entity, TerrainData, TerrainFeature, WorldOutput};
+
+/// Positions closer than this are treated as unchanged, so float noise from
+/// a JSON round trip is not reported as movement.
+const MOVE_EPSILON: f32 = 1e-3;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ZoneDiff {
+    pub entities_added: Vec<EntitySummary>,
+    pub entities_removed: Vec<EntitySummary>,
+    pub entities_moved: Vec<EntityMove>,
+    pub stats_changed: Vec<FieldChange>,
+    pub attributes_changed: Vec<FieldChange>,
+    pub terrain: TerrainDiff,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntitySummary {
+    pub id: String,
+    pub name: String,
+    pub entity_type: String,
+    pub faction: String,
+    pub position: (f32, f32, f32),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntityMove {
+    pub id: String,
+    pub name: String,
+    pub from: (f32, f32, f32),
+    pub to: (f32, f32, f32),
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldChange {
+    pub entity_id: String,
+    pub field: String,
+    pub from: Value,
+    pub to: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TerrainDiff {
+    pub biome: Option<(String, String)>,
+    pub elevation_range: Option<((f32, f32), (f32, f32))>,
+    pub features_added: Vec<TerrainFeature>,
+    pub features_removed: Vec<TerrainFeature>,
+    pub hazards_added: Vec<String>,
+    pub hazards_removed: Vec<String>,
+}
+
+impl ZoneDiff {
+    pub fn is_empty(&self) -> bool {
+        self.entities_added.is_empty()
+            && self.entities_removed.is_empty()
+            && self.entities_moved.is_empty()
+            && self.stats_changed.is_empty()
+            && self.attributes_changed.is_empty()
+            && self.terrain.is_empty()
+    }
+}
+
+impl TerrainDiff {
+    pub fn is_empty(&self) -> bool {
+        self.biome.is_none()
+            && self.elevation_range.is_none()
+            && self.features_added.is_empty()
+            && self.features_removed.is_empty()
+            && self.hazards_added.is_empty()
+            && self.hazards_removed.is_empty()
+    }
+}
+
+impl From<&Entity> for EntitySummary {
+    fn from(entity: &Entity) -> Self {
+        Self {
+            id: entity.id.clone(),
+            name: entity.name.clone(),
+            entity_type: entity.entity_type.clone(),
+            faction: entity.faction.clone(),
+            position: entity.position,
+        }
+    }
+}
+
+/// Structural diff between two zone snapshots. Entities are matched by ID;
+/// terrain features have no identity and are compared as a multiset.
+pub fn diff_worlds(from: &WorldOutput, to: &WorldOutput) -> ZoneDiff {
+    let before: HashMap<&str, &Entity> = from.entities.iter().map(|e| (e.id.as_str(), e)).collect();
+    let after: HashMap<&str, &Entity> = to.entities.iter().map(|e| (e.id.as_str(), e)).collect();
+
+    let entities_added = to.entities.iter()
+        .filter(|e| !before.contains_key(e.id.as_str()))
+        .map(EntitySummary::from)
+        .collect();
+
+    let entities_removed = from.entities.iter()
+        .filter(|e| !after.contains_key(e.id.as_str()))
+        .map(EntitySummary::from)
+        .collect();
+
+    let mut entities_moved = Vec::new();
+    let mut stats_changed = Vec::new();
+    let mut attributes_changed = Vec::new();
+
+    for old in &from.entities {
+        let Some(new) = after.get(old.id.as_str()) else {
+            continue;
+        };
+
+        let distance = distance(old.position, new.position);
+        if distance > MOVE_EPSILON {
+            entities_moved.push(EntityMove {
+                id: old.id.clone(),
+                name: new.name.clone(),
+                from: old.position,
+                to: new.position,
+                distance,
+            });
+        }
+
+        let stats = [
+            ("stats.health", Value::from(old.stats.health), Value::from(new.stats.health)),
+            ("stats.threat_level", Value::from(old.stats.threat_level), Value::from(new.stats.threat_level)),
+            ("stats.awareness", Value::from(old.stats.awareness), Value::from(new.stats.awareness)),
+            ("stats.aggression", Value::from(old.stats.aggression), Value::from(new.stats.aggression)),
+        ];
+        push_changes(&mut stats_changed, &old.id, stats);
+
+        let attributes = [
+            ("name", Value::from(old.name.as_str()), Value::from(new.name.as_str())),
+            ("entity_type", Value::from(old.entity_type.as_str()), Value::from(new.entity_type.as_str())),
+            ("faction", Value::from(old.faction.as_str()), Value::from(new.faction.as_str())),
+            ("behavior", Value::from(old.behavior.as_str()), Value::from(new.behavior.as_str())),
+        ];
+        push_changes(&mut attributes_changed, &old.id, attributes);
+    }
+
+    ZoneDiff {
+        entities_added,
+        entities_removed,
+        entities_moved,
+        stats_changed,
+        attributes_changed,
+        terrain: diff_terrain(&from.terrain, &to.terrain),
+    }
+}
+
+fn push_changes<const N: usize>(
+    changes: &mut Vec<FieldChange>,
+    entity_id: &str,
+    fields: [(&str, Value, Value); N],
+) {
+    for (field, from, to) in fields {
+        if from != to {
+            changes.push(FieldChange {
+                entity_id: entity_id.to_string(),
+                field: field.to_string(),
+                from,
+                to,
+            });
+        }
+    }
+}
+
+fn diff_terrain(from: &TerrainData, to: &TerrainData) -> TerrainDiff {
+    TerrainDiff {
+        biome: (from.biome != to.biome).then(|| (from.biome.clone(), to.biome.clone())),
+        elevation_range: (from.elevation_range != to.elevation_range)
+            .then_some((from.elevation_range, to.elevation_range)),
+        features_added: multiset_difference(&to.features, &from.features),
+        features_removed: multiset_difference(&from.features, &to.features),
+        hazards_added: multiset_difference(&to.hazards, &from.hazards),
+        hazards_removed: multiset_difference(&from.hazards, &to.hazards),
+    }
+}
+
+/// Items of `left` that are not matched one-for-one by an item of `right`.
+fn multiset_difference<T: Clone + Serialize>(left: &[T], right: &[T]) -> Vec<T> {
+    let mut remaining: HashMap<String, usize> = HashMap::new();
+    for item in right {
+        *remaining.entry(fingerprint(item)).or_default() += 1;
+    }
+
+    left.iter()
+        .filter(|item| match remaining.get_mut(&fingerprint(*item)) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+fn fingerprint<T: Serialize>(item: &T) -> String {
+    serde_json::to_string(item).unwrap_or_default()
+}
+
+fn distance(a: (f32, f32, f32), b: (f32, f32, f32)) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::world;
+
+    #[test]
+    fn test_identical_worlds_have_empty_diff() {
+        let a = world::generate("harbor", 11, "zone");
+        let b = world::generate("harbor", 11, "zone");
+        assert!(diff_worlds(&a, &b).is_empty());
+    }
+
+    #[test]
+    fn test_detects_entity_changes() {
+        let a = world::generate("harbor", 11, "zone");
+        let mut b = a.clone();
+
+        let removed = b.entities.remove(0);
+        b.entities[0].position.0 += 25.0;
+        b.entities[0].behavior = "ambush".into();
+        b.entities[0].stats.health += 10;
+        b.terrain.hazards.push("flooding".into());
+
+        let diff = diff_worlds(&a, &b);
+        assert_eq!(diff.entities_removed.len(), 1);
+        assert_eq!(diff.entities_removed[0].id, removed.id);
+        assert_eq!(diff.entities_moved.len(), 1);
+        assert!((diff.entities_moved[0].distance - 25.0).abs() < 1e-3);
+        assert!(diff.stats_changed.iter().any(|c| c.field == "stats.health"));
+        assert_eq!(diff.terrain.hazards_added, vec!["flooding".to_string()]);
+    }
+
+    #[test]
+    fn test_regenerating_a_zone_with_a_new_seed_moves_its_entities() {
+        let a = world::generate("harbor", 11, "zone");
+        let b = world::generate("harbor", 12, "zone");
+        let diff = diff_worlds(&a, &b);
+        let kept = a.entities.len().min(b.entities.len());
+        assert_eq!(diff.entities_moved.len(), kept);
+        assert!(diff.entities_moved.iter().all(|m| a.entities.iter().any(|e| e.id == m.id)));
+        assert_eq!(diff.entities_added.len() + diff.entities_removed.len(), a.entities.len().abs_diff(b.entities.len()));
+
+        // Another zone's entities are different entities.
+        let elsewhere = world::generate("harbor", 11, "other");
+        assert_eq!(diff_worlds(&a, &elsewhere).entities_removed.len(), a.entities.len());
+    }
+}
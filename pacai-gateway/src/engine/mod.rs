@@ -1,3 +1,8 @@
 pub mod narrative;
 pub mod world;
 pub mod packager;
+pub mod diff;
+pub mod zone_overrides;
+pub mod override_engine;
+pub mod simulation;
+pub mod watermark;
@@ -0,0 +1,413 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use crate::engine::world::{Entity, EntityStats, WorldOutput};
+
+/// Which override currently controls an entity. A later override only takes
+/// an entity over if its priority is at least as high as the current claim.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverrideClaim {
+    pub override_id: String,
+    pub priority: u32,
+    pub behavior: String,
+}
+
+pub type ClaimMap = BTreeMap<String, OverrideClaim>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverrideTarget {
+    Entity(String),
+    EntityType(String),
+    Faction(String),
+    Radius { center: (f32, f32, f32), radius: f32 },
+    All,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OverrideModifiers {
+    pub health: Option<u32>,
+    pub threat_level: Option<u32>,
+    pub awareness: Option<f32>,
+    pub aggression: Option<f32>,
+    pub move_to: Option<(f32, f32, f32)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OverrideSpec {
+    pub id: String,
+    pub target: OverrideTarget,
+    pub behavior: String,
+    pub modifiers: OverrideModifiers,
+    pub priority: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedEntity {
+    pub entity_id: String,
+    pub blocked_by: String,
+    pub blocking_priority: u32,
+}
+
+/// The state an entity had before an override touched it, kept so that a
+/// timed override can be reverted later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityRestore {
+    pub entity_id: String,
+    pub behavior: String,
+    pub position: (f32, f32, f32),
+    pub stats: EntityStats,
+    pub claim: Option<OverrideClaim>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OverrideOutcome {
+    pub affected: Vec<String>,
+    pub skipped: Vec<SkippedEntity>,
+    #[serde(skip)]
+    pub previous: Vec<EntityRestore>,
+}
+
+/// Parameter keys that select entities rather than modify them.
+const SELECTOR_KEYS: [&str; 2] = ["center", "radius"];
+
+const MODIFIER_KEYS: [&str; 5] = ["health", "threat_level", "awareness", "aggression", "move_to"];
+
+impl OverrideSpec {
+    /// Builds a spec from the wire format used by `OverrideRequest`.
+    pub fn parse(
+        id: &str,
+        target_type: &str,
+        target_id: Option<&str>,
+        behavior: &str,
+        parameters: &Value,
+        priority: Option<u32>,
+    ) -> Result<Self, OverrideError> {
+        let empty = Map::new();
+        let params = match parameters {
+            Value::Null => &empty,
+            Value::Object(map) => map,
+            _ => return Err(OverrideError::InvalidParameter {
+                name: "parameters".into(),
+                reason: "must be an object".into(),
+            }),
+        };
+
+        let required_id = || target_id
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+            .ok_or_else(|| OverrideError::MissingTargetId { target_type: target_type.to_string() });
+
+        let target = match target_type {
+            "entity" => OverrideTarget::Entity(required_id()?),
+            "entity_type" => OverrideTarget::EntityType(required_id()?),
+            "faction" => OverrideTarget::Faction(required_id()?),
+            "radius" => OverrideTarget::Radius {
+                center: vector_param(params, "center")?
+                    .ok_or_else(|| missing("center"))?,
+                radius: float_param(params, "radius")?
+                    .filter(|r| *r >= 0.0)
+                    .ok_or_else(|| missing("radius"))?,
+            },
+            "all" => OverrideTarget::All,
+            other => return Err(OverrideError::UnknownTargetType(other.to_string())),
+        };
+
+        for key in params.keys() {
+            if !SELECTOR_KEYS.contains(&key.as_str()) && !MODIFIER_KEYS.contains(&key.as_str()) {
+                return Err(OverrideError::InvalidParameter {
+                    name: key.clone(),
+                    reason: "unknown parameter".into(),
+                });
+            }
+        }
+
+        let modifiers = OverrideModifiers {
+            health: uint_param(params, "health")?,
+            threat_level: uint_param(params, "threat_level")?,
+            awareness: unit_param(params, "awareness")?,
+            aggression: unit_param(params, "aggression")?,
+            move_to: vector_param(params, "move_to")?,
+        };
+
+        Ok(Self {
+            id: id.to_string(),
+            target,
+            behavior: behavior.to_string(),
+            modifiers,
+            priority: priority.unwrap_or(0),
+        })
+    }
+
+    pub fn matches(&self, entity: &Entity) -> bool {
+        match &self.target {
+            OverrideTarget::Entity(id) => entity.id == *id,
+            OverrideTarget::EntityType(entity_type) => entity.entity_type == *entity_type,
+            OverrideTarget::Faction(faction) => entity.faction.eq_ignore_ascii_case(faction),
+            OverrideTarget::Radius { center, radius } => {
+                let (dx, dy, dz) = (
+                    entity.position.0 - center.0,
+                    entity.position.1 - center.1,
+                    entity.position.2 - center.2,
+                );
+                (dx * dx + dy * dy + dz * dz).sqrt() <= *radius
+            }
+            OverrideTarget::All => true,
+        }
+    }
+}
+
+/// Applies `spec` to every matching entity in `world`, updating `claims`.
+/// Entities held by a higher-priority override are left untouched and
+/// reported as skipped; ties go to the newer override.
+pub fn apply(
+    world: &mut WorldOutput,
+    claims: &mut ClaimMap,
+    spec: &OverrideSpec,
+) -> Result<OverrideOutcome, OverrideError> {
+    if let OverrideTarget::Entity(id) = &spec.target {
+        if !world.entities.iter().any(|e| e.id == *id) {
+            return Err(OverrideError::EntityNotFound(id.clone()));
+        }
+    }
+
+    let mut outcome = OverrideOutcome::default();
+
+    for entity in world.entities.iter_mut().filter(|e| spec.matches(e)) {
+        if let Some(claim) = claims.get(&entity.id) {
+            if claim.priority > spec.priority {
+                outcome.skipped.push(SkippedEntity {
+                    entity_id: entity.id.clone(),
+                    blocked_by: claim.override_id.clone(),
+                    blocking_priority: claim.priority,
+                });
+                continue;
+            }
+        }
+
+        outcome.previous.push(EntityRestore {
+            entity_id: entity.id.clone(),
+            behavior: entity.behavior.clone(),
+            position: entity.position,
+            stats: entity.stats.clone(),
+            claim: claims.get(&entity.id).cloned(),
+        });
+
+        apply_to_entity(entity, spec);
+        claims.insert(entity.id.clone(), OverrideClaim {
+            override_id: spec.id.clone(),
+            priority: spec.priority,
+            behavior: spec.behavior.clone(),
+        });
+        outcome.affected.push(entity.id.clone());
+    }
+
+    Ok(outcome)
+}
+
+/// Undoes `override_id` on every entity it still holds. Entities that have
+/// since been claimed by another override are left as they are.
+pub fn revert(
+    world: &mut WorldOutput,
+    claims: &mut ClaimMap,
+    override_id: &str,
+    restores: &[EntityRestore],
+) -> Vec<String> {
+    let mut reverted = Vec::new();
+
+    for restore in restores {
+        let held = claims.get(&restore.entity_id).is_some_and(|c| c.override_id == override_id);
+        if !held {
+            continue;
+        }
+        let Some(entity) = world.entities.iter_mut().find(|e| e.id == restore.entity_id) else {
+            continue;
+        };
+
+        entity.behavior = restore.behavior.clone();
+        entity.position = restore.position;
+        entity.stats = restore.stats.clone();
+        match &restore.claim {
+            Some(claim) => claims.insert(entity.id.clone(), claim.clone()),
+            None => claims.remove(&entity.id),
+        };
+        reverted.push(entity.id.clone());
+    }
+
+    reverted
+}
+
+fn apply_to_entity(entity: &mut Entity, spec: &OverrideSpec) {
+    let modifiers = &spec.modifiers;
+
+    entity.behavior = spec.behavior.clone();
+    if let Some(health) = modifiers.health {
+        entity.stats.health = health;
+    }
+    if let Some(threat_level) = modifiers.threat_level {
+        entity.stats.threat_level = threat_level;
+    }
+    if let Some(awareness) = modifiers.awareness {
+        entity.stats.awareness = awareness;
+    }
+    if let Some(aggression) = modifiers.aggression {
+        entity.stats.aggression = aggression;
+    }
+    if let Some(position) = modifiers.move_to {
+        entity.position = position;
+    }
+}
+
+fn missing(name: &str) -> OverrideError {
+    OverrideError::InvalidParameter {
+        name: name.to_string(),
+        reason: "required for radius targets".into(),
+    }
+}
+
+fn invalid(name: &str, reason: &str) -> OverrideError {
+    OverrideError::InvalidParameter {
+        name: name.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn float_param(params: &Map<String, Value>, name: &str) -> Result<Option<f32>, OverrideError> {
+    match params.get(name) {
+        None => Ok(None),
+        Some(value) => value.as_f64()
+            .map(|v| Some(v as f32))
+            .ok_or_else(|| invalid(name, "must be a number")),
+    }
+}
+
+fn unit_param(params: &Map<String, Value>, name: &str) -> Result<Option<f32>, OverrideError> {
+    match float_param(params, name)? {
+        Some(v) if !(0.0..=1.0).contains(&v) => Err(invalid(name, "must be between 0 and 1")),
+        other => Ok(other),
+    }
+}
+
+fn uint_param(params: &Map<String, Value>, name: &str) -> Result<Option<u32>, OverrideError> {
+    match params.get(name) {
+        None => Ok(None),
+        Some(value) => value.as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .map(Some)
+            .ok_or_else(|| invalid(name, "must be a non-negative integer")),
+    }
+}
+
+/// Accepts `[x, y, z]`, or `[x, y]` with z defaulting to 0.
+fn vector_param(params: &Map<String, Value>, name: &str) -> Result<Option<(f32, f32, f32)>, OverrideError> {
+    let Some(value) = params.get(name) else {
+        return Ok(None);
+    };
+
+    let coords: Option<Vec<f32>> = value.as_array()
+        .map(|items| items.iter().map(|v| v.as_f64().map(|f| f as f32)).collect())
+        .unwrap_or(None);
+
+    match coords.as_deref() {
+        Some([x, y]) => Ok(Some((*x, *y, 0.0))),
+        Some([x, y, z]) => Ok(Some((*x, *y, *z))),
+        _ => Err(invalid(name, "must be [x, y] or [x, y, z]")),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum OverrideError {
+    #[error("Unknown target type: {0}")]
+    UnknownTargetType(String),
+
+    #[error("target_id is required for target type '{target_type}'")]
+    MissingTargetId { target_type: String },
+
+    #[error("Invalid parameter '{name}': {reason}")]
+    InvalidParameter { name: String, reason: String },
+
+    #[error("Entity not found: {0}")]
+    EntityNotFound(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::world;
+    use serde_json::json;
+
+    fn spec(id: &str, target_type: &str, target_id: Option<&str>, params: Value, priority: u32) -> OverrideSpec {
+        OverrideSpec::parse(id, target_type, target_id, "ambush", &params, Some(priority)).unwrap()
+    }
+
+    #[test]
+    fn test_faction_override_reports_exact_entities() {
+        let mut zone = world::generate("harbor", 21, "zone");
+        let faction = zone.entities[0].faction.clone();
+        let expected: Vec<String> = zone.entities.iter()
+            .filter(|e| e.faction == faction)
+            .map(|e| e.id.clone())
+            .collect();
+
+        let mut claims = ClaimMap::new();
+        let outcome = apply(&mut zone, &mut claims, &spec("o1", "faction", Some(&faction), json!({ "aggression": 0.9 }), 1)).unwrap();
+
+        assert_eq!(outcome.affected, expected);
+        for entity in zone.entities.iter().filter(|e| e.faction == faction) {
+            assert_eq!(entity.behavior, "ambush");
+            assert!((entity.stats.aggression - 0.9).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_radius_selector() {
+        let mut zone = world::generate("harbor", 21, "zone");
+        let (x, y, z) = zone.entities[2].position;
+
+        let mut claims = ClaimMap::new();
+        let outcome = apply(&mut zone, &mut claims, &spec("o1", "radius", None, json!({ "center": [x, y, z], "radius": 0.5 }), 1)).unwrap();
+
+        assert!(outcome.affected.contains(&zone.entities[2].id));
+    }
+
+    #[test]
+    fn test_higher_priority_claim_wins() {
+        let mut zone = world::generate("harbor", 21, "zone");
+        let target = zone.entities[0].id.clone();
+        let mut claims = ClaimMap::new();
+
+        apply(&mut zone, &mut claims, &spec("high", "entity", Some(&target), json!({}), 10)).unwrap();
+        let low = OverrideSpec::parse("low", "entity", Some(&target), "idle", &json!({}), Some(1)).unwrap();
+        let outcome = apply(&mut zone, &mut claims, &low).unwrap();
+
+        assert!(outcome.affected.is_empty());
+        assert_eq!(outcome.skipped[0].blocked_by, "high");
+        assert_eq!(zone.entities[0].behavior, "ambush");
+    }
+
+    #[test]
+    fn test_revert_restores_only_held_entities() {
+        let original = world::generate("harbor", 21, "zone");
+        let mut zone = original.clone();
+        let mut claims = ClaimMap::new();
+
+        let first = apply(&mut zone, &mut claims, &spec("o1", "all", None, json!({ "aggression": 0.9 }), 1)).unwrap();
+        let taken = zone.entities[0].id.clone();
+        apply(&mut zone, &mut claims, &spec("o2", "entity", Some(&taken), json!({}), 5)).unwrap();
+
+        let reverted = revert(&mut zone, &mut claims, "o1", &first.previous);
+        assert_eq!(reverted.len(), zone.entities.len() - 1);
+        assert!(!reverted.contains(&taken));
+        assert_eq!(zone.entities[1].behavior, original.entities[1].behavior);
+        assert_eq!(claims.get(&taken).unwrap().override_id, "o2");
+        assert_eq!(claims.len(), 1);
+    }
+
+    #[test]
+    fn test_rejects_bad_parameters() {
+        assert!(OverrideSpec::parse("o", "faction", None, "idle", &json!({}), None).is_err());
+        assert!(OverrideSpec::parse("o", "radius", None, "idle", &json!({ "center": [0, 0] }), None).is_err());
+        assert!(OverrideSpec::parse("o", "all", None, "idle", &json!({ "aggression": 4.0 }), None).is_err());
+        assert!(OverrideSpec::parse("o", "all", None, "idle", &json!({ "speed": 2 }), None).is_err());
+        assert!(OverrideSpec::parse("o", "squad", None, "idle", &json!({}), None).is_err());
+    }
+}
@@ -10,6 +10,7 @@ use ed25519_dalek::{SigningKey, Signature, Signer, VerifyingKey};
 use rand::rngs::OsRng;
 use anyhow::{Result, Context};
 use chrono::Utc;
+use crate::engine::watermark::{self, Watermark};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportManifest {
@@ -20,6 +21,8 @@ pub struct ExportManifest {
     pub exports: Vec<String>,
     pub signature_algorithm: String,
     pub public_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Watermark>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,15 +53,9 @@ pub fn create_dev_keypair() -> SigningKey {
     SigningKey::generate(&mut OsRng)
 }
 
-pub fn build_export_zone(
-    zone_dir: &Path,
-    output_zip: &Path,
-    seed: &str,
-    exports: &[&str],
-    signing_key: &SigningKey,
-) -> Result<ExportManifest> {
-    let mut checksums = HashMap::new();
-
+/// Reads every file under `zone_dir`, keyed by its path relative to it.
+fn read_zone(zone_dir: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut files = Vec::new();
     for entry in WalkDir::new(zone_dir).into_iter().filter_map(|e| e.ok()) {
         if entry.file_type().is_file() {
             let p = entry.path();
@@ -69,12 +66,52 @@ pub fn build_export_zone(
             let mut f = File::open(p)?;
             let mut buf = Vec::new();
             f.read_to_end(&mut buf)?;
-            let mut hasher = Sha384::new();
-            hasher.update(&buf);
-            let digest = hasher.finalize();
-            checksums.insert(rel, hex::encode(digest));
+            files.push((rel, buf));
         }
     }
+    Ok(files)
+}
+
+/// Embeds `watermark` in a file on its way into the bundle: `world.json`
+/// gets it in its metadata, scripts and text scenes a visible notice.
+fn stamp(rel: &str, content: Vec<u8>, watermark: &Watermark) -> Result<Vec<u8>> {
+    if Path::new(rel).file_name().is_some_and(|name| name == "world.json") {
+        let mut world: serde_json::Value = serde_json::from_slice(&content)
+            .with_context(|| format!("{} is not valid JSON", rel))?;
+        watermark::embed_world(&mut world, watermark);
+        return Ok(serde_json::to_vec_pretty(&world)?);
+    }
+    match std::str::from_utf8(&content).ok().and_then(|text| watermark::stamp_text(rel, text, watermark)) {
+        Some(stamped) => Ok(stamped.into_bytes()),
+        None => Ok(content),
+    }
+}
+
+/// Zips `zone_dir` with a signed manifest. With a `watermark`, the bundle
+/// and its manifest carry it as well; checksums cover the stamped files.
+pub fn build_export_zone(
+    zone_dir: &Path,
+    output_zip: &Path,
+    seed: &str,
+    exports: &[&str],
+    signing_key: &SigningKey,
+    watermark: Option<&Watermark>,
+) -> Result<ExportManifest> {
+    let mut files = read_zone(zone_dir)?;
+    if let Some(watermark) = watermark {
+        files = files
+            .into_iter()
+            .map(|(rel, buf)| Ok((rel.clone(), stamp(&rel, buf, watermark)?)))
+            .collect::<Result<_>>()?;
+    }
+
+    let mut checksums = HashMap::new();
+    for (rel, buf) in &files {
+        let mut hasher = Sha384::new();
+        hasher.update(buf);
+        let digest = hasher.finalize();
+        checksums.insert(rel.clone(), hex::encode(digest));
+    }
 
     let verifying_key = signing_key.verifying_key();
     let public_key_hex = hex::encode(verifying_key.to_bytes());
@@ -87,6 +124,7 @@ pub fn build_export_zone(
         exports: exports.iter().map(|s| s.to_string()).collect(),
         signature_algorithm: "Ed25519".to_string(),
         public_key: Some(public_key_hex),
+        watermark: watermark.cloned(),
     };
     let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
 
@@ -98,19 +136,9 @@ pub fn build_export_zone(
     let options = SimpleFileOptions::default()
         .compression_method(zip::CompressionMethod::Deflated);
 
-    for entry in WalkDir::new(zone_dir).into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            let p = entry.path();
-            let rel = p.strip_prefix(zone_dir)
-                .context("Failed to strip prefix")?
-                .to_string_lossy()
-                .to_string();
-            let mut f = File::open(p)?;
-            let mut buf = Vec::new();
-            f.read_to_end(&mut buf)?;
-            zip.start_file(&rel, options)?;
-            zip.write_all(&buf)?;
-        }
+    for (rel, buf) in &files {
+        zip.start_file(rel.as_str(), options)?;
+        zip.write_all(buf)?;
     }
 
     zip.start_file("manifest.json", options)?;
@@ -482,8 +510,6 @@ pub fn estimate_export_time(engines: &[String], quality: &str) -> u32 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
-    use tempfile::tempdir;
 
     #[test]
     fn test_create_dev_keypair() {
@@ -500,4 +526,34 @@ mod tests {
         let verifying_key = key.verifying_key();
         assert!(verify_signature(message, &signature, &verifying_key));
     }
+
+    #[test]
+    fn test_watermarked_bundle_verifies_and_is_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let zone = dir.path().join("zone");
+        std::fs::create_dir_all(zone.join("scripts")).unwrap();
+        std::fs::write(zone.join("world.json"), r#"{"id":"w","entities":[{"id":"npc-1"}],"metadata":{}}"#).unwrap();
+        std::fs::write(zone.join("scripts/npc_ai.lua"), "return {}\n").unwrap();
+        std::fs::write(zone.join("terrain.rbxm"), [0u8, 159, 146]).unwrap();
+
+        let hsm = crate::security::hsm::HsmManager::ephemeral("test");
+        let mark = Watermark::issue(&hsm, "demo", "dana", "studio", 7, &watermark::content_hash(["npc-1"])).unwrap();
+        let key = create_dev_keypair();
+        let zip_path = dir.path().join("bundle.zip");
+        let manifest = build_export_zone(&zone, &zip_path, "7", &["roblox"], &key, Some(&mark)).unwrap();
+
+        assert_eq!(manifest.watermark.as_ref(), Some(&mark));
+        assert!(verify_export_bundle(&zip_path, &key.verifying_key().to_bytes()).unwrap());
+        let detection = watermark::detect_bundle(&zip_path, &hsm.verifying_key().unwrap()).unwrap().unwrap();
+        assert_eq!(detection.watermark, mark);
+        assert!(detection.content_matches);
+        let mut found_in = detection.found_in.clone();
+        found_in.sort();
+        assert_eq!(found_in, ["manifest.json", "scripts/npc_ai.lua", "world.json"]);
+
+        let plain = dir.path().join("plain.zip");
+        let manifest = build_export_zone(&zone, &plain, "7", &["roblox"], &key, None).unwrap();
+        assert!(manifest.watermark.is_none());
+        assert!(watermark::detect_bundle(&plain, &hsm.verifying_key().unwrap()).unwrap().is_none());
+    }
 }
@@ -0,0 +1,517 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use crate::engine::override_engine::{self, ClaimMap, OverrideSpec};
+use crate::engine::world::{Entity, WorldOutput};
+
+pub const DEFAULT_TICK_RATE_HZ: u32 = 10;
+
+const DETECTION_RANGE: f32 = 150.0;
+const ATTACK_RANGE: f32 = 25.0;
+const TURRET_RANGE: f32 = 60.0;
+const BASE_DPS: f32 = 4.0;
+const PATROL_RADIUS: f32 = 40.0;
+const GUARD_LEASH: f32 = 60.0;
+const ARRIVE_EPSILON: f32 = 0.5;
+const HAZARD_RADIUS: f32 = 40.0;
+
+/// Damage per second inside a `hazard_zone` POI, by terrain hazard.
+const HAZARD_DPS: [(&str, f32); 10] = [
+    ("toxic_gas", 4.0),
+    ("radiation", 3.0),
+    ("quicksand", 2.0),
+    ("thin_ice", 2.0),
+    ("unstable_structure", 2.0),
+    ("extreme_cold", 1.0),
+    ("extreme_heat", 1.0),
+    ("blizzard", 1.0),
+    ("sandstorm", 1.0),
+    ("environmental_hazard", 1.0),
+];
+
+/// Weather hazards that halve detection range across the whole zone.
+const OBSCURING_HAZARDS: [&str; 2] = ["blizzard", "sandstorm"];
+
+/// An override to apply at the start of `tick`.
+#[derive(Debug, Clone)]
+pub struct TimedOverride {
+    pub tick: u32,
+    pub spec: OverrideSpec,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SimEvent {
+    OverrideApplied { override_id: String, affected: Vec<String> },
+    OverrideFailed { override_id: String, reason: String },
+    Engaged { entity_id: String, target_id: String },
+    Damaged { entity_id: String, source: String, amount: f32 },
+    Killed { entity_id: String, killed_by: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntitySnapshot {
+    pub id: String,
+    pub faction: String,
+    pub behavior: String,
+    pub position: (f32, f32, f32),
+    pub health: u32,
+    pub alive: bool,
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TickSnapshot {
+    pub tick: u32,
+    pub time_seconds: f32,
+    pub entities: Vec<EntitySnapshot>,
+    pub events: Vec<SimEvent>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimSummary {
+    pub ticks: u32,
+    pub alive_by_faction: BTreeMap<String, usize>,
+    pub casualties: usize,
+    pub checksum: String,
+}
+
+/// Per-entity state the world model has no room for.
+#[derive(Debug, Clone)]
+struct Runtime {
+    home: (f32, f32, f32),
+    waypoints: Vec<(f32, f32, f32)>,
+    next_waypoint: usize,
+    target: Option<usize>,
+    /// Fractional damage not yet taken off the integer health.
+    pending_damage: f32,
+    alive: bool,
+}
+
+#[derive(Debug, Default)]
+struct Intent {
+    move_to: Option<((f32, f32, f32), f32)>,
+    attack: Option<usize>,
+}
+
+/// A deterministic, fixed-timestep run of a zone. The same world, seed and
+/// overrides always produce the same sequence of snapshots.
+pub struct Simulation {
+    world: WorldOutput,
+    claims: ClaimMap,
+    runtime: Vec<Runtime>,
+    rng: StdRng,
+    tick: u32,
+    dt: f32,
+    detection_range: f32,
+    hazard_dps: f32,
+}
+
+impl Simulation {
+    pub fn new(world: WorldOutput, claims: ClaimMap, seed: u64, tick_rate_hz: u32) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let runtime = world
+            .entities
+            .iter()
+            .map(|e| {
+                let phase: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+                let waypoints = (0..4)
+                    .map(|i| {
+                        let angle = phase + i as f32 * std::f32::consts::FRAC_PI_2;
+                        (e.position.0 + PATROL_RADIUS * angle.cos(), e.position.1 + PATROL_RADIUS * angle.sin(), e.position.2)
+                    })
+                    .collect();
+                Runtime {
+                    home: e.position,
+                    waypoints,
+                    next_waypoint: 0,
+                    target: None,
+                    pending_damage: 0.0,
+                    alive: e.stats.health > 0,
+                }
+            })
+            .collect();
+
+        let obscured = world.terrain.hazards.iter().any(|h| OBSCURING_HAZARDS.contains(&h.as_str()));
+        let detection_range = DETECTION_RANGE
+            * world.atmosphere.visibility.clamp(0.1, 1.0)
+            * if obscured { 0.5 } else { 1.0 };
+        let hazard_dps = world
+            .terrain
+            .hazards
+            .iter()
+            .filter_map(|h| HAZARD_DPS.iter().find(|(name, _)| name == h).map(|(_, dps)| dps))
+            .sum();
+
+        Self {
+            world,
+            claims,
+            runtime,
+            rng,
+            tick: 0,
+            dt: 1.0 / tick_rate_hz.max(1) as f32,
+            detection_range,
+            hazard_dps,
+        }
+    }
+
+    pub fn world(&self) -> &WorldOutput {
+        &self.world
+    }
+
+    /// Advances one tick: overrides first, then behaviors, combat and
+    /// hazards. Intents are computed from the state at the start of the tick
+    /// so entity order never changes the outcome.
+    pub fn step(&mut self, overrides: &[&OverrideSpec]) -> TickSnapshot {
+        self.tick += 1;
+        let mut events = Vec::new();
+
+        for spec in overrides {
+            match override_engine::apply(&mut self.world, &mut self.claims, spec) {
+                Ok(outcome) => events.push(SimEvent::OverrideApplied {
+                    override_id: spec.id.clone(),
+                    affected: outcome.affected,
+                }),
+                Err(e) => events.push(SimEvent::OverrideFailed {
+                    override_id: spec.id.clone(),
+                    reason: e.to_string(),
+                }),
+            }
+        }
+
+        let intents: Vec<Intent> = (0..self.world.entities.len()).map(|i| self.intent(i)).collect();
+
+        for (i, intent) in intents.iter().enumerate() {
+            if let Some((point, speed)) = intent.move_to {
+                let entity = &mut self.world.entities[i];
+                entity.position = step_towards(entity.position, point, speed * self.dt);
+            }
+
+            let previous = self.runtime[i].target;
+            self.runtime[i].target = intent.attack;
+            if let Some(target) = intent.attack.filter(|t| previous != Some(*t)) {
+                events.push(SimEvent::Engaged {
+                    entity_id: self.world.entities[i].id.clone(),
+                    target_id: self.world.entities[target].id.clone(),
+                });
+            }
+        }
+
+        let mut damage: Vec<Vec<(String, f32)>> = vec![Vec::new(); self.world.entities.len()];
+        for (i, intent) in intents.iter().enumerate() {
+            let Some(target) = intent.attack else {
+                continue;
+            };
+            let attacker = &self.world.entities[i];
+            let accuracy = attacker.stats.awareness.clamp(0.2, 1.0);
+            if self.rng.gen::<f32>() < accuracy {
+                let dps = BASE_DPS * attacker.stats.threat_level.max(1) as f32 * (0.5 + attacker.stats.aggression);
+                damage[target].push((attacker.id.clone(), dps * self.dt));
+            }
+        }
+
+        if self.hazard_dps > 0.0 {
+            let zones: Vec<(f32, f32, f32)> = self
+                .world
+                .poi
+                .iter()
+                .filter(|p| p.poi_type == "hazard_zone")
+                .map(|p| p.position)
+                .collect();
+            for (i, entity) in self.world.entities.iter().enumerate() {
+                if self.runtime[i].alive && zones.iter().any(|z| planar_distance(entity.position, *z) <= HAZARD_RADIUS) {
+                    damage[i].push(("hazard".into(), self.hazard_dps * self.dt));
+                }
+            }
+        }
+
+        for (i, hits) in damage.into_iter().enumerate() {
+            if !self.runtime[i].alive {
+                continue;
+            }
+            for (source, amount) in hits {
+                events.push(SimEvent::Damaged {
+                    entity_id: self.world.entities[i].id.clone(),
+                    source: source.clone(),
+                    amount,
+                });
+
+                let runtime = &mut self.runtime[i];
+                let entity = &mut self.world.entities[i];
+                runtime.pending_damage += amount;
+                let whole = runtime.pending_damage.floor();
+                runtime.pending_damage -= whole;
+                entity.stats.health = entity.stats.health.saturating_sub(whole as u32);
+
+                if entity.stats.health == 0 {
+                    runtime.alive = false;
+                    runtime.target = None;
+                    events.push(SimEvent::Killed { entity_id: entity.id.clone(), killed_by: source });
+                    break;
+                }
+            }
+        }
+
+        self.snapshot(events)
+    }
+
+    pub fn snapshot(&self, events: Vec<SimEvent>) -> TickSnapshot {
+        TickSnapshot {
+            tick: self.tick,
+            time_seconds: self.tick as f32 * self.dt,
+            entities: self
+                .world
+                .entities
+                .iter()
+                .zip(&self.runtime)
+                .map(|(e, r)| EntitySnapshot {
+                    id: e.id.clone(),
+                    faction: e.faction.clone(),
+                    behavior: e.behavior.clone(),
+                    position: e.position,
+                    health: e.stats.health,
+                    alive: r.alive,
+                    target: r.target.map(|t| self.world.entities[t].id.clone()),
+                })
+                .collect(),
+            events,
+        }
+    }
+
+    pub fn summary(&self) -> SimSummary {
+        let mut alive_by_faction = BTreeMap::new();
+        for (entity, runtime) in self.world.entities.iter().zip(&self.runtime) {
+            let count = alive_by_faction.entry(entity.faction.clone()).or_insert(0);
+            if runtime.alive {
+                *count += 1;
+            }
+        }
+
+        let state = serde_json::to_vec(&self.snapshot(Vec::new()).entities).unwrap_or_default();
+        SimSummary {
+            ticks: self.tick,
+            alive_by_faction,
+            casualties: self.runtime.iter().filter(|r| !r.alive).count(),
+            checksum: format!("{:x}", sha2::Sha256::digest(&state)),
+        }
+    }
+
+    fn intent(&mut self, index: usize) -> Intent {
+        if !self.runtime[index].alive {
+            return Intent::default();
+        }
+
+        let entity = &self.world.entities[index];
+        let speed = move_speed(entity);
+        let range = if entity.entity_type == "automated_turret" { TURRET_RANGE } else { ATTACK_RANGE };
+        let detect = self.detection_range * entity.stats.awareness.clamp(0.1, 1.0);
+        let home = self.runtime[index].home;
+
+        match entity.behavior.as_str() {
+            "patrol" => {
+                let runtime = &mut self.runtime[index];
+                let mut waypoint = runtime.waypoints[runtime.next_waypoint];
+                if planar_distance(entity.position, waypoint) <= ARRIVE_EPSILON {
+                    runtime.next_waypoint = (runtime.next_waypoint + 1) % runtime.waypoints.len();
+                    waypoint = runtime.waypoints[runtime.next_waypoint];
+                }
+                let attack = self.nearest_hostile(index, range);
+                Intent { move_to: Some((waypoint, speed)), attack }
+            }
+            "guard" => match self.nearest_hostile(index, detect) {
+                Some(target) if planar_distance(self.world.entities[target].position, home) <= GUARD_LEASH => {
+                    self.engage(index, target, range, speed)
+                }
+                _ => Intent { move_to: Some((home, speed)), attack: None },
+            },
+            "hunt" => match self.nearest_hostile(index, detect * 2.0) {
+                Some(target) => self.engage(index, target, range, speed * 1.5),
+                None => Intent::default(),
+            },
+            "ambush" => Intent { move_to: None, attack: self.nearest_hostile(index, detect.min(range * 2.0)) },
+            "retreat" => match self.nearest_hostile(index, detect) {
+                Some(target) => {
+                    let threat = self.world.entities[target].position;
+                    let away = (
+                        entity.position.0 * 2.0 - threat.0,
+                        entity.position.1 * 2.0 - threat.1,
+                        entity.position.2,
+                    );
+                    Intent { move_to: Some((away, speed * 1.5)), attack: None }
+                }
+                None => Intent::default(),
+            },
+            "scavenge" => {
+                let cache = self
+                    .world
+                    .poi
+                    .iter()
+                    .filter(|p| matches!(p.poi_type.as_str(), "supply_cache" | "wreckage"))
+                    .min_by(|a, b| {
+                        planar_distance(entity.position, a.position).total_cmp(&planar_distance(entity.position, b.position))
+                    })
+                    .map(|p| p.position);
+                Intent { move_to: cache.map(|c| (c, speed)), attack: None }
+            }
+            // idle, and any behavior the simulation has no model for.
+            _ => Intent::default(),
+        }
+    }
+
+    fn engage(&self, index: usize, target: usize, range: f32, speed: f32) -> Intent {
+        let position = self.world.entities[index].position;
+        let target_position = self.world.entities[target].position;
+        if planar_distance(position, target_position) <= range {
+            Intent { move_to: None, attack: Some(target) }
+        } else {
+            Intent { move_to: Some((target_position, speed)), attack: None }
+        }
+    }
+
+    /// Closest living entity of another faction within `range`; ties go to
+    /// the lower index so the choice is stable.
+    fn nearest_hostile(&self, index: usize, range: f32) -> Option<usize> {
+        let me = &self.world.entities[index];
+        self.world
+            .entities
+            .iter()
+            .enumerate()
+            .filter(|(i, other)| *i != index && self.runtime[*i].alive && !other.faction.eq_ignore_ascii_case(&me.faction))
+            .map(|(i, other)| (i, planar_distance(me.position, other.position)))
+            .filter(|(_, d)| *d <= range)
+            .min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)))
+            .map(|(i, _)| i)
+    }
+}
+
+/// Runs `ticks` steps, keeping every `snapshot_every`-th snapshot plus the
+/// final one.
+pub fn run(
+    mut simulation: Simulation,
+    ticks: u32,
+    snapshot_every: u32,
+    overrides: &[TimedOverride],
+) -> (Vec<TickSnapshot>, SimSummary) {
+    let snapshot_every = snapshot_every.max(1);
+    let mut snapshots = Vec::new();
+
+    for tick in 1..=ticks {
+        let due: Vec<&OverrideSpec> = overrides.iter().filter(|o| o.tick == tick).map(|o| &o.spec).collect();
+        let snapshot = simulation.step(&due);
+        if tick % snapshot_every == 0 || tick == ticks {
+            snapshots.push(snapshot);
+        }
+    }
+
+    (snapshots, simulation.summary())
+}
+
+fn move_speed(entity: &Entity) -> f32 {
+    match entity.entity_type.as_str() {
+        "automated_turret" => 0.0,
+        "drone_swarm" => 8.0,
+        "mech_unit" => 2.0,
+        _ => 3.0,
+    }
+}
+
+fn planar_distance(a: (f32, f32, f32), b: (f32, f32, f32)) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+fn step_towards(from: (f32, f32, f32), to: (f32, f32, f32), max_step: f32) -> (f32, f32, f32) {
+    let distance = planar_distance(from, to);
+    if distance <= max_step || distance == 0.0 {
+        return (to.0, to.1, from.2);
+    }
+    let scale = max_step / distance;
+    (from.0 + (to.0 - from.0) * scale, from.1 + (to.1 - from.1) * scale, from.2)
+}
+
+use sha2::Digest;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::world;
+    use serde_json::json;
+
+    fn duel() -> WorldOutput {
+        let mut world = world::generate("harbor", 11, "zone");
+        world.terrain.hazards.clear();
+        world.atmosphere.visibility = 1.0;
+        world.entities.truncate(2);
+        for (entity, (faction, x)) in world.entities.iter_mut().zip([("Military", 0.0), ("Rogue_AI", 80.0)]) {
+            entity.entity_type = "hostile_patrol".into();
+            entity.faction = faction.into();
+            entity.position = (x, 0.0, 0.0);
+            entity.behavior = "idle".into();
+            entity.stats.health = 100;
+            entity.stats.awareness = 1.0;
+        }
+        world.entities[0].behavior = "hunt".into();
+        world
+    }
+
+    #[test]
+    fn test_same_seed_same_outcome() {
+        let base = world::generate("harbor", 5, "zone");
+        let (a, summary_a) = run(Simulation::new(base.clone(), ClaimMap::new(), 9, 10), 300, 50, &[]);
+        let (b, summary_b) = run(Simulation::new(base, ClaimMap::new(), 9, 10), 300, 50, &[]);
+
+        assert_eq!(summary_a.checksum, summary_b.checksum);
+        assert_eq!(a.len(), 6);
+        assert_eq!(serde_json::to_value(&a).unwrap(), serde_json::to_value(&b).unwrap());
+    }
+
+    #[test]
+    fn test_hunter_closes_in_and_kills() {
+        let world = duel();
+        let prey = world.entities[1].id.clone();
+        let (snapshots, summary) = run(Simulation::new(world, ClaimMap::new(), 1, 10), 600, 1, &[]);
+
+        let killed = snapshots.iter().flat_map(|s| &s.events).any(|e| {
+            matches!(e, SimEvent::Killed { entity_id, .. } if *entity_id == prey)
+        });
+        assert!(killed);
+        assert_eq!(summary.casualties, 1);
+        assert_eq!(summary.alive_by_faction["Military"], 1);
+    }
+
+    #[test]
+    fn test_override_applies_at_its_tick() {
+        let world = duel();
+        let hunter = world.entities[0].id.clone();
+        let spec = OverrideSpec::parse("o1", "entity", Some(&hunter), "idle", &json!({}), None).unwrap();
+        let overrides = [TimedOverride { tick: 5, spec }];
+
+        let (snapshots, summary) = run(Simulation::new(world, ClaimMap::new(), 1, 10), 100, 1, &overrides);
+        assert_eq!(snapshots[3].entities[0].behavior, "hunt");
+        assert_eq!(snapshots[4].entities[0].behavior, "idle");
+        assert!(matches!(&snapshots[4].events[0], SimEvent::OverrideApplied { override_id, .. } if override_id == "o1"));
+        let stopped = snapshots[4].entities[0].position;
+        assert_eq!(snapshots.last().unwrap().entities[0].position, stopped);
+        assert_eq!(summary.casualties, 0);
+    }
+
+    #[test]
+    fn test_hazard_zone_damages_entities() {
+        let mut world = duel();
+        world.entities[0].behavior = "idle".into();
+        world.terrain.hazards = vec!["toxic_gas".into()];
+        world.poi.truncate(1);
+        world.poi[0].poi_type = "hazard_zone".into();
+        world.poi[0].position = (0.0, 0.0, 0.0);
+        let exposed = world.entities[0].id.clone();
+
+        let (snapshots, summary) = run(Simulation::new(world, ClaimMap::new(), 1, 10), 300, 1, &[]);
+        let killed_by = snapshots.iter().flat_map(|s| &s.events).find_map(|e| match e {
+            SimEvent::Killed { entity_id, killed_by } if *entity_id == exposed => Some(killed_by.clone()),
+            _ => None,
+        });
+        assert_eq!(killed_by.as_deref(), Some("hazard"));
+        assert_eq!(summary.casualties, 1);
+        assert_eq!(snapshots[100].entities[1].health, 100);
+    }
+}
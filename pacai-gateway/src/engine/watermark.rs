@@ -0,0 +1,283 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::path::Path;
+use crate::security::hsm::{self, HsmError, HsmManager};
+use crate::util::json::canonical_json;
+
+/// Prefix of the single-line form of a watermark, as written into scripts
+/// and scenes.
+pub const TOKEN_PREFIX: &str = "pacai-wm1";
+
+/// A signed statement that content was generated for `issued_to` on a tier
+/// that requires watermarking. `content` binds it to the entities it was
+/// issued with, by ID only: moving or editing them keeps the binding, while
+/// a mark copied into other content no longer matches.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Watermark {
+    pub id: String,
+    pub tier: String,
+    pub issued_to: String,
+    pub tenant: String,
+    pub seed: u64,
+    /// `content_hash` of the marked entities.
+    pub content: String,
+    pub issued_at: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// SHA-256 of the sorted, distinct entity IDs.
+pub fn content_hash<'a>(entity_ids: impl IntoIterator<Item = &'a str>) -> String {
+    let ids: BTreeSet<&str> = entity_ids.into_iter().collect();
+    let mut hasher = Sha256::new();
+    for id in ids {
+        hasher.update(id.as_bytes());
+        hasher.update(b"\n");
+    }
+    hex::encode(hasher.finalize())
+}
+
+impl Watermark {
+    /// The canonical JSON of every field but the signature.
+    fn message(&self) -> Vec<u8> {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("signature");
+        }
+        canonical_json(&value).into_bytes()
+    }
+
+    pub fn issue(
+        signer: &HsmManager,
+        tier: &str,
+        issued_to: &str,
+        tenant: &str,
+        seed: u64,
+        content: &str,
+    ) -> Result<Self, HsmError> {
+        let public_key = signer.verifying_key().ok_or(HsmError::NotInitialized)?;
+        let mut watermark = Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            tier: tier.to_string(),
+            issued_to: issued_to.to_string(),
+            tenant: tenant.to_string(),
+            seed,
+            content: content.to_string(),
+            issued_at: chrono::Utc::now().to_rfc3339(),
+            public_key: hex::encode(public_key.to_bytes()),
+            signature: String::new(),
+        };
+        watermark.signature = hex::encode(signer.sign(&watermark.message())?);
+        Ok(watermark)
+    }
+
+    pub fn verify(&self, key: &VerifyingKey) -> bool {
+        let Ok(signature) = hex::decode(&self.signature) else {
+            return false;
+        };
+        hex::encode(key.to_bytes()) == self.public_key && hsm::verify_with_key(key, &self.message(), &signature)
+    }
+
+    /// `pacai-wm1.<watermark>`, the watermark as base64url JSON.
+    pub fn token(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        format!("{}.{}", TOKEN_PREFIX, URL_SAFE_NO_PAD.encode(json))
+    }
+
+    pub fn from_token(token: &str) -> Option<Self> {
+        let encoded = token.strip_prefix(TOKEN_PREFIX)?.strip_prefix('.')?;
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(encoded).ok()?).ok()
+    }
+
+    /// The text shown to whoever opens the content.
+    pub fn notice(&self) -> String {
+        format!(
+            "Generated with PacAI on the {} tier. Not licensed for commercial use. Watermark {}.",
+            self.tier, self.id
+        )
+    }
+}
+
+/// Puts the watermark under `metadata.watermark` of a `world.json` document.
+pub fn embed_world(world: &mut Value, watermark: &Watermark) {
+    let Some(root) = world.as_object_mut() else {
+        return;
+    };
+    let metadata = root.entry("metadata").or_insert_with(|| Value::Object(Default::default()));
+    if let Some(metadata) = metadata.as_object_mut() {
+        metadata.insert("watermark".into(), serde_json::to_value(watermark).unwrap_or_default());
+        metadata.insert("notice".into(), Value::String(watermark.notice()));
+    }
+}
+
+/// Comment syntax of the scripts and text scenes that get a visible notice.
+/// Binary assets have none and are left alone.
+fn comment_style(path: &str) -> Option<(&'static str, &'static str)> {
+    let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "cs" | "js" | "ts" | "wgsl" | "swift" | "vmap" | "cry" => Some(("// ", "")),
+        "gd" | "py" | "unity" | "prefab" | "mat" | "asset" | "godot" => Some(("# ", "")),
+        "tscn" | "tres" => Some(("; ", "")),
+        "lua" => Some(("-- ", "")),
+        "html" => Some(("<!-- ", " -->")),
+        _ => None,
+    }
+}
+
+/// Prepends the notice and the watermark token to a script or text scene.
+/// Returns `None` for files that cannot carry a comment.
+pub fn stamp_text(path: &str, content: &str, watermark: &Watermark) -> Option<String> {
+    let (open, close) = comment_style(path)?;
+    Some(format!(
+        "{open}{}{close}\n{open}{}{close}\n{}",
+        watermark.notice(),
+        watermark.token(),
+        content
+    ))
+}
+
+/// Where a verified watermark was found, and whether the files also hold
+/// the entities it was issued for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    pub watermark: Watermark,
+    pub found_in: Vec<String>,
+    pub content_matches: bool,
+}
+
+/// Every watermark a file carries: structured ones in `world.json` and the
+/// manifest, and tokens anywhere in text.
+fn carried(content: &[u8]) -> Vec<Watermark> {
+    let mut found = Vec::new();
+    if let Ok(json) = serde_json::from_slice::<Value>(content) {
+        for candidate in [&json["metadata"]["watermark"], &json["watermark"]] {
+            if let Ok(watermark) = serde_json::from_value(candidate.clone()) {
+                found.push(watermark);
+            }
+        }
+    }
+    let text = String::from_utf8_lossy(content);
+    for (start, _) in text.match_indices(TOKEN_PREFIX) {
+        let token: String = text[start..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            .collect();
+        found.extend(Watermark::from_token(&token));
+    }
+    found
+}
+
+/// `content_hash` of the entities a JSON file such as `world.json` lists.
+fn listed_content(content: &[u8]) -> Option<String> {
+    let json: Value = serde_json::from_slice(content).ok()?;
+    let entities = json["entities"].as_array()?;
+    Some(content_hash(entities.iter().filter_map(|e| e["id"].as_str())))
+}
+
+/// Looks for a watermark signed by `key` in any of `files`. Each stamped
+/// file carries a full copy, so stripping or editing one of them (say the
+/// `world.json` metadata) still leaves the bundle identifiable. Watermarks
+/// that fail verification are ignored. `content_matches` tells whether the
+/// files list the entities the mark was issued for.
+pub fn detect<'a>(files: impl IntoIterator<Item = (&'a str, &'a [u8])>, key: &VerifyingKey) -> Option<Detection> {
+    let mut detection: Option<Detection> = None;
+    let mut contents = Vec::new();
+    for (name, content) in files {
+        contents.extend(listed_content(content));
+        for watermark in carried(content).into_iter().filter(|w| w.verify(key)) {
+            match &mut detection {
+                None => {
+                    let found_in = vec![name.to_string()];
+                    detection = Some(Detection { watermark, found_in, content_matches: false });
+                }
+                Some(d) if d.watermark == watermark && !d.found_in.iter().any(|f| f == name) => {
+                    d.found_in.push(name.to_string());
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    let mut detection = detection?;
+    detection.content_matches = contents.contains(&detection.watermark.content);
+    Some(detection)
+}
+
+/// `detect` over the files of an export zip.
+pub fn detect_bundle(zip_path: &Path, key: &VerifyingKey) -> anyhow::Result<Option<Detection>> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(zip_path)?)?;
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        files.push((file.name().to_string(), content));
+    }
+    Ok(detect(files.iter().map(|(name, content)| (name.as_str(), content.as_slice())), key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn world(ids: &[&str]) -> Value {
+        let entities: Vec<Value> = ids.iter().map(|id| json!({ "id": id, "position": [0.0, 0.0, 0.0] })).collect();
+        json!({ "id": "w", "entities": entities, "metadata": { "generator": "PacAI" } })
+    }
+
+    #[test]
+    fn test_watermark_verifies_and_round_trips_as_a_token() {
+        let signer = HsmManager::ephemeral("test");
+        let key = signer.verifying_key().unwrap();
+        let watermark = Watermark::issue(&signer, "demo", "dana", "studio", 7, &content_hash(["a", "b"])).unwrap();
+        assert!(watermark.verify(&key));
+        assert_eq!(Watermark::from_token(&watermark.token()), Some(watermark.clone()));
+        assert_eq!(watermark.content, content_hash(["b", "a", "b"]));
+
+        let mut forged = watermark.clone();
+        forged.tier = "lifetime".into();
+        assert!(!forged.verify(&key));
+        let mut moved = watermark.clone();
+        moved.content = content_hash(["c"]);
+        assert!(!moved.verify(&key));
+        assert!(!watermark.verify(&HsmManager::ephemeral("test").verifying_key().unwrap()));
+    }
+
+    #[test]
+    fn test_detection_survives_an_edited_world_json() {
+        let signer = HsmManager::ephemeral("test");
+        let key = signer.verifying_key().unwrap();
+        let watermark = Watermark::issue(&signer, "demo", "dana", "studio", 7, &content_hash(["a", "b"])).unwrap();
+
+        let mut marked = world(&["a", "b"]);
+        embed_world(&mut marked, &watermark);
+        let marked = serde_json::to_vec(&marked).unwrap();
+        let script = stamp_text("scripts/npc_ai.lua", "return {}\n", &watermark).unwrap();
+        assert!(script.starts_with("-- Generated with PacAI on the demo tier"));
+        assert!(stamp_text("models/terrain.rbxm", "", &watermark).is_none());
+
+        let bundle = [("world.json", marked.as_slice()), ("scripts/npc_ai.lua", script.as_bytes())];
+        let detection = detect(bundle, &key).unwrap();
+        assert_eq!(detection.watermark, watermark);
+        assert_eq!(detection.found_in, ["world.json", "scripts/npc_ai.lua"]);
+        assert!(detection.content_matches);
+
+        let mut edited = world(&["b", "a"]);
+        edited["entities"][0]["position"] = json!([5.0, 5.0, 0.0]);
+        let edited = serde_json::to_vec(&edited).unwrap();
+        let bundle = [("world.json", edited.as_slice()), ("scripts/npc_ai.lua", script.as_bytes())];
+        let detection = detect(bundle, &key).unwrap();
+        assert_eq!((detection.found_in.as_slice(), detection.content_matches), (&["scripts/npc_ai.lua".to_string()][..], true));
+        assert!(detect([("world.json", edited.as_slice())], &key).is_none());
+
+        // A valid mark pasted next to other content is found but does not match it.
+        let other = serde_json::to_vec(&world(&["x", "y"])).unwrap();
+        let bundle = [("world.json", other.as_slice()), ("scripts/npc_ai.lua", script.as_bytes())];
+        assert!(!detect(bundle, &key).unwrap().content_matches);
+    }
+}
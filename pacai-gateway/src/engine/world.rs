@@ -1,7 +1,6 @@
 use serde::{Deserialize, Serialize};
 use rand::{Rng, SeedableRng};
 use rand::rngs::StdRng;
-use rand::seq::SliceRandom;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorldOutput {
@@ -85,7 +84,9 @@ const POI_TYPES: [&str; 8] = [
     "extraction_point", "enemy_base", "neutral_zone", "hazard_zone"
 ];
 
-pub fn generate(prompt: &str, seed: u64) -> WorldOutput {
+/// Generates the world for `zone_id`. Entity IDs come from the zone and the
+/// spawn index, so regenerating a zone with a new seed keeps them.
+pub fn generate(prompt: &str, seed: u64, zone_id: &str) -> WorldOutput {
     let mut rng = StdRng::seed_from_u64(seed);
     
     let id = uuid::Uuid::new_v4().to_string();
@@ -96,7 +97,7 @@ pub fn generate(prompt: &str, seed: u64) -> WorldOutput {
     let num_features: usize = rng.gen_range(10..=30);
     
     let terrain = generate_terrain(&mut rng, biome, num_features);
-    let entities = generate_entities(&mut rng, num_entities, &terrain);
+    let entities = generate_entities(&mut rng, zone_id, num_entities, &terrain);
     let poi = generate_poi(&mut rng, num_poi);
     let atmosphere = generate_atmosphere(&mut rng, biome);
     
@@ -158,7 +159,7 @@ fn generate_terrain(rng: &mut StdRng, biome: &str, num_features: usize) -> Terra
     }
 }
 
-fn generate_entities(rng: &mut StdRng, count: usize, terrain: &TerrainData) -> Vec<Entity> {
+fn generate_entities(rng: &mut StdRng, zone_id: &str, count: usize, terrain: &TerrainData) -> Vec<Entity> {
     let names_prefix = ["Alpha", "Bravo", "Charlie", "Delta", "Echo", "Foxtrot"];
     let behaviors = ["patrol", "guard", "hunt", "scavenge", "idle", "ambush"];
     
@@ -176,7 +177,7 @@ fn generate_entities(rng: &mut StdRng, count: usize, terrain: &TerrainData) -> V
             };
             
             Entity {
-                id: uuid::Uuid::new_v4().to_string(),
+                id: entity_id(zone_id, i),
                 entity_type: entity_type.to_string(),
                 name: format!("{}-{}", 
                     names_prefix[i % names_prefix.len()],
@@ -199,6 +200,18 @@ fn generate_entities(rng: &mut StdRng, count: usize, terrain: &TerrainData) -> V
         .collect()
 }
 
+/// Entity IDs are derived from the zone and spawn index so that the same
+/// entity keeps its ID when a zone is regenerated, diffed or overridden,
+/// whatever seed the regeneration uses.
+pub fn entity_id(zone_id: &str, index: usize) -> String {
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(format!("entity:{}:{}", zone_id, index).as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Builder::from_random_bytes(bytes).into_uuid().to_string()
+}
+
 fn generate_poi(rng: &mut StdRng, count: usize) -> Vec<PointOfInterest> {
     let poi_names = [
         "Outpost", "Bunker", "Cache", "Tower", "Haven", 
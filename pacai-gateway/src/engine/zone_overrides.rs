@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use crate::engine::world::WorldOutput;
+use crate::util::json::{self, JsonError, PatchOperation};
+
+/// Fields a zone must still have after overrides have been applied.
+const REQUIRED_WORLD_FIELDS: [&str; 6] = [
+    "id", "name", "terrain.biome", "entities", "poi", "atmosphere",
+];
+
+/// Overrides accepted by `POST /v5/projects/:id/generate`.
+///
+/// `merge` is deep-merged onto the generated world first, then every `set`
+/// entry is written at its dotted path (`entities[3].stats.aggression`), and
+/// finally `patch` is applied as an RFC 6902 JSON Patch. `set` is a sorted
+/// map, so the same overrides always apply in the same order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge: Option<Value>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patch: Vec<PatchOperation>,
+}
+
+impl ZoneOverrides {
+    pub fn from_value(value: &Value) -> Result<Self, JsonError> {
+        serde_json::from_value(value.clone())
+            .map_err(|e| JsonError::SerializationError(e.to_string()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.merge.is_none() && self.set.is_empty() && self.patch.is_empty()
+    }
+}
+
+pub fn apply(world: &WorldOutput, overrides: &ZoneOverrides) -> Result<WorldOutput, JsonError> {
+    let mut doc = serde_json::to_value(world)
+        .map_err(|e| JsonError::SerializationError(e.to_string()))?;
+
+    if let Some(merge) = &overrides.merge {
+        if !merge.is_object() {
+            return Err(JsonError::TypeMismatch("merge".into()));
+        }
+        doc = json::merge_json(&doc, merge);
+    }
+
+    for (path, value) in &overrides.set {
+        json::set_field(&mut doc, path, value.clone())?;
+    }
+
+    json::apply_patch(&mut doc, &overrides.patch)?;
+
+    json::validate_schema(&doc, &REQUIRED_WORLD_FIELDS)?;
+
+    serde_json::from_value(doc).map_err(|e| JsonError::TypeMismatch(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::world;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_and_set_are_applied() {
+        let base = world::generate("harbor", 3, "zone");
+        let overrides = ZoneOverrides::from_value(&json!({
+            "merge": { "atmosphere": { "weather": "blizzard" } },
+            "set": { "entities[0].stats.aggression": 0.95 }
+        })).unwrap();
+
+        let patched = apply(&base, &overrides).unwrap();
+        assert_eq!(patched.atmosphere.weather, "blizzard");
+        assert_eq!(patched.atmosphere.time_of_day, base.atmosphere.time_of_day);
+        assert!((patched.entities[0].stats.aggression - 0.95).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_invalid_result_is_rejected() {
+        let base = world::generate("harbor", 3, "zone");
+
+        let wrong_type = ZoneOverrides::from_value(&json!({
+            "set": { "entities[0].stats.health": "lots" }
+        })).unwrap();
+        assert!(matches!(apply(&base, &wrong_type), Err(JsonError::TypeMismatch(_))));
+
+        let bad_path = ZoneOverrides::from_value(&json!({
+            "set": { "entities[999].stats.health": 1 }
+        })).unwrap();
+        assert!(matches!(apply(&base, &bad_path), Err(JsonError::InvalidPath(_))));
+
+        assert!(ZoneOverrides::from_value(&json!({ "entities": [] })).is_err());
+    }
+
+    #[test]
+    fn test_patch_runs_after_set() {
+        let base = world::generate("harbor", 3, "zone");
+        let overrides = ZoneOverrides::from_value(&json!({
+            "set": { "entities[0].behavior": "guard" },
+            "patch": [
+                { "op": "test", "path": "/entities/0/behavior", "value": "guard" },
+                { "op": "remove", "path": "/entities/1" }
+            ]
+        })).unwrap();
+
+        let patched = apply(&base, &overrides).unwrap();
+        assert_eq!(patched.entities[0].behavior, "guard");
+        assert_eq!(patched.entities.len(), base.entities.len() - 1);
+    }
+}
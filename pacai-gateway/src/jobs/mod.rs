@@ -0,0 +1,478 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use crate::routes::ApiError;
+use crate::security::rbac;
+
+/// Priority jobs started in a row before a waiting standard job gets a
+/// slot, so the standard lane still moves while the priority lane is busy.
+pub const PRIORITY_BURST: u32 = 4;
+
+/// How long a finished job can still be looked up.
+const RETAIN_FINISHED_SECONDS: i64 = 60 * 60;
+
+pub type Work = Box<dyn FnOnce() -> Result<Value, ApiError> + Send>;
+pub type Reply = oneshot::Receiver<Result<Value, ApiError>>;
+pub type OnFinish = Box<dyn FnOnce(&JobInfo) + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lane {
+    Priority,
+    Standard,
+}
+
+impl Lane {
+    pub fn for_tier(tier: &str) -> Self {
+        if rbac::get_tier_limits(tier).priority_queue {
+            Lane::Priority
+        } else {
+            Lane::Standard
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Completed => "completed",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: String,
+    pub kind: String,
+    pub owner: String,
+    pub tenant: String,
+    pub tier: String,
+    pub lane: Lane,
+    pub status: JobStatus,
+    /// Jobs ahead of this one in its lane, while it is queued.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
+    pub submitted_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A job to queue.
+pub struct NewJob {
+    pub kind: String,
+    pub owner: String,
+    pub tenant: String,
+    pub tier: String,
+    pub work: Work,
+    /// Runs if the job fails or is cancelled, to hand back what was
+    /// reserved for it.
+    pub on_abandon: Option<Box<dyn FnOnce() + Send>>,
+    /// Runs once the job has completed, failed or been cancelled, with its
+    /// final state.
+    pub on_finish: Option<OnFinish>,
+}
+
+/// What a queued job still needs to run and report back.
+struct Pending {
+    work: Work,
+    reply: oneshot::Sender<Result<Value, ApiError>>,
+    on_abandon: Option<Box<dyn FnOnce() + Send>>,
+    on_finish: Option<OnFinish>,
+}
+
+struct Job {
+    info: JobInfo,
+    pending: Option<Pending>,
+}
+
+/// One lane. Owners take turns, so one user's backlog does not hold up
+/// everyone else on the same tier.
+#[derive(Default)]
+struct LaneQueue {
+    owners: VecDeque<String>,
+    pending: HashMap<String, VecDeque<String>>,
+}
+
+impl LaneQueue {
+    fn push(&mut self, owner: &str, id: &str) {
+        let queue = self.pending.entry(owner.to_string()).or_default();
+        if queue.is_empty() {
+            self.owners.push_back(owner.to_string());
+        }
+        queue.push_back(id.to_string());
+    }
+
+    fn pop(&mut self) -> Option<String> {
+        let owner = self.owners.pop_front()?;
+        let queue = self.pending.get_mut(&owner)?;
+        let id = queue.pop_front();
+        if queue.is_empty() {
+            self.pending.remove(&owner);
+        } else {
+            self.owners.push_back(owner);
+        }
+        id
+    }
+
+    fn remove(&mut self, owner: &str, id: &str) -> bool {
+        let Some(queue) = self.pending.get_mut(owner) else {
+            return false;
+        };
+        let before = queue.len();
+        queue.retain(|queued| queued != id);
+        let removed = queue.len() < before;
+        if queue.is_empty() {
+            self.pending.remove(owner);
+            self.owners.retain(|o| o != owner);
+        }
+        removed
+    }
+
+    fn is_empty(&self) -> bool {
+        self.owners.is_empty()
+    }
+
+    /// Job IDs in the order `pop` would return them.
+    fn order(&self) -> Vec<&str> {
+        let mut queues: Vec<_> = self.owners.iter().filter_map(|o| self.pending.get(o)).map(|q| q.iter()).collect();
+        let mut order = Vec::new();
+        loop {
+            let before = order.len();
+            order.extend(queues.iter_mut().filter_map(|q| q.next()).map(String::as_str));
+            if order.len() == before {
+                return order;
+            }
+        }
+    }
+}
+
+struct Inner {
+    concurrency: usize,
+    running: usize,
+    priority_streak: u32,
+    jobs: HashMap<String, Job>,
+    priority: LaneQueue,
+    standard: LaneQueue,
+}
+
+impl Inner {
+    fn lane(&mut self, lane: Lane) -> &mut LaneQueue {
+        match lane {
+            Lane::Priority => &mut self.priority,
+            Lane::Standard => &mut self.standard,
+        }
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let standard_due = self.priority_streak >= PRIORITY_BURST && !self.standard.is_empty();
+        if !standard_due {
+            if let Some(id) = self.priority.pop() {
+                self.priority_streak += 1;
+                return Some(id);
+            }
+        }
+        self.priority_streak = 0;
+        self.standard.pop()
+    }
+
+    fn info(&self, job: &Job) -> JobInfo {
+        let mut info = job.info.clone();
+        if info.status == JobStatus::Queued {
+            let lane = match info.lane {
+                Lane::Priority => &self.priority,
+                Lane::Standard => &self.standard,
+            };
+            info.position = lane.order().iter().position(|id| *id == info.id);
+        }
+        info
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>) {
+        self.jobs.retain(|_, job| {
+            job.info.finished_at.is_none_or(|at| (now - at).num_seconds() < RETAIN_FINISHED_SECONDS)
+        });
+    }
+}
+
+/// Generation jobs waiting for one of `concurrency` slots. Jobs live in
+/// memory only; a restart drops the queue.
+#[derive(Clone)]
+pub struct JobQueue {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl JobQueue {
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                concurrency: concurrency.max(1),
+                running: 0,
+                priority_streak: 0,
+                jobs: HashMap::new(),
+                priority: LaneQueue::default(),
+                standard: LaneQueue::default(),
+            })),
+        }
+    }
+
+    /// Queues `job` in the lane of its tier. The receiver gets the result,
+    /// or is dropped if the job is cancelled first.
+    pub fn submit(&self, job: NewJob) -> (JobInfo, Reply) {
+        let now = Utc::now();
+        let (reply, receiver) = oneshot::channel();
+        let info = JobInfo {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind: job.kind,
+            lane: Lane::for_tier(&job.tier),
+            owner: job.owner,
+            tenant: job.tenant,
+            tier: job.tier,
+            status: JobStatus::Queued,
+            position: None,
+            submitted_at: now,
+            started_at: None,
+            finished_at: None,
+            result: None,
+            error: None,
+        };
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.prune(now);
+            inner.lane(info.lane).push(&info.owner, &info.id);
+            let pending = Pending { work: job.work, reply, on_abandon: job.on_abandon, on_finish: job.on_finish };
+            inner.jobs.insert(info.id.clone(), Job { info: info.clone(), pending: Some(pending) });
+        }
+        self.pump();
+        (self.get(&info.id).unwrap_or(info), receiver)
+    }
+
+    pub fn get(&self, id: &str) -> Option<JobInfo> {
+        let inner = self.inner.lock().unwrap();
+        inner.jobs.get(id).map(|job| inner.info(job))
+    }
+
+    /// Takes a job off the queue. Only queued jobs can be cancelled; a
+    /// running one may already have written its results.
+    pub fn cancel(&self, id: &str) -> Result<JobInfo, JobError> {
+        let (info, pending) = {
+            let mut inner = self.inner.lock().unwrap();
+            let job = inner.jobs.get(id).ok_or_else(|| JobError::NotFound { id: id.to_string() })?;
+            if job.info.status != JobStatus::Queued {
+                return Err(JobError::NotQueued { id: id.to_string(), status: job.info.status });
+            }
+            let (lane, owner) = (job.info.lane, job.info.owner.clone());
+            inner.lane(lane).remove(&owner, id);
+            let job = inner.jobs.get_mut(id).ok_or_else(|| JobError::NotFound { id: id.to_string() })?;
+            job.info.status = JobStatus::Cancelled;
+            job.info.finished_at = Some(Utc::now());
+            (job.info.clone(), job.pending.take())
+        };
+        if let Some(pending) = pending {
+            if let Some(on_abandon) = pending.on_abandon {
+                on_abandon();
+            }
+            if let Some(on_finish) = pending.on_finish {
+                on_finish(&info);
+            }
+        }
+        Ok(info)
+    }
+
+    /// Starts queued jobs while slots are free.
+    fn pump(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        while inner.running < inner.concurrency {
+            let Some(id) = inner.next() else {
+                break;
+            };
+            let Some(job) = inner.jobs.get_mut(&id) else {
+                continue;
+            };
+            let Some(pending) = job.pending.take() else {
+                continue;
+            };
+            job.info.status = JobStatus::Running;
+            job.info.started_at = Some(Utc::now());
+            inner.running += 1;
+
+            let queue = self.clone();
+            tokio::spawn(async move {
+                let Pending { work, reply, on_abandon, on_finish } = pending;
+                let result = tokio::task::spawn_blocking(work)
+                    .await
+                    .unwrap_or_else(|e| Err(ApiError::Internal(format!("Job {} panicked: {}", id, e))));
+                queue.finish(&id, result, reply, on_abandon, on_finish);
+            });
+        }
+    }
+
+    fn finish(
+        &self,
+        id: &str,
+        result: Result<Value, ApiError>,
+        reply: oneshot::Sender<Result<Value, ApiError>>,
+        on_abandon: Option<Box<dyn FnOnce() + Send>>,
+        on_finish: Option<OnFinish>,
+    ) {
+        let info = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.running -= 1;
+            if let Some(job) = inner.jobs.get_mut(id) {
+                job.info.finished_at = Some(Utc::now());
+                match &result {
+                    Ok(value) => {
+                        job.info.status = JobStatus::Completed;
+                        job.info.result = Some(value.clone());
+                    }
+                    Err(e) => {
+                        job.info.status = JobStatus::Failed;
+                        job.info.error = Some(e.to_string());
+                    }
+                }
+            }
+            inner.jobs.get(id).map(|job| job.info.clone())
+        };
+        if result.is_err() {
+            if let Some(on_abandon) = on_abandon {
+                on_abandon();
+            }
+        }
+        if let (Some(on_finish), Some(info)) = (on_finish, &info) {
+            on_finish(info);
+        }
+        // Nobody is listening for jobs that were answered with 202.
+        let _ = reply.send(result);
+        self.pump();
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum JobError {
+    #[error("Job not found: {id}")]
+    NotFound { id: String },
+
+    #[error("Job {id} is {} and can no longer be cancelled", status.as_str())]
+    NotQueued { id: String, status: JobStatus },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+
+    fn job(owner: &str, tier: &str, work: Work) -> NewJob {
+        NewJob {
+            kind: "prompt".into(),
+            owner: owner.into(),
+            tenant: "studio".into(),
+            tier: tier.into(),
+            work,
+            on_abandon: None,
+            on_finish: None,
+        }
+    }
+
+    fn recorded(label: &str, order: &Arc<Mutex<Vec<String>>>) -> Work {
+        let (label, order) = (label.to_string(), order.clone());
+        Box::new(move || {
+            order.lock().unwrap().push(label.clone());
+            Ok(Value::String(label))
+        })
+    }
+
+    /// Work that holds its slot until the sender is used or dropped.
+    fn held(label: &str) -> (Work, mpsc::Sender<()>) {
+        let (release, wait) = mpsc::channel::<()>();
+        let label = label.to_string();
+        (Box::new(move || {
+            let _ = wait.recv();
+            Ok(Value::String(label))
+        }), release)
+    }
+
+    #[tokio::test]
+    async fn test_operators_are_not_starved_by_a_demo_flood() {
+        let queue = JobQueue::new(1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let (blocker, release) = held("blocker");
+        let (_, blocked) = queue.submit(job("ops", "operator", blocker));
+
+        let mut replies = Vec::new();
+        for n in 0..6 {
+            replies.push(queue.submit(job("demo-a", "demo", recorded(&format!("a{}", n), &order))).1);
+        }
+        replies.push(queue.submit(job("demo-b", "demo", recorded("b0", &order))).1);
+        for n in 0..6 {
+            replies.push(queue.submit(job("ops", "operator", recorded(&format!("op{}", n), &order))).1);
+        }
+        let (last, _) = queue.submit(job("demo-b", "demo", recorded("b1", &order)));
+        assert_eq!((last.lane, last.status, last.position), (Lane::Standard, JobStatus::Queued, Some(3)));
+
+        release.send(()).unwrap();
+        blocked.await.unwrap().unwrap();
+        for reply in replies {
+            reply.await.unwrap().unwrap();
+        }
+        // Four priority jobs, then a standard one; demo users take turns.
+        let order = order.lock().unwrap().clone();
+        assert_eq!(&order[..9], ["op0", "op1", "op2", "a0", "op3", "op4", "op5", "b0", "a1"]);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_only_applies_to_queued_jobs() {
+        let queue = JobQueue::new(1);
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        let on_finish = || -> Option<OnFinish> {
+            let finished = finished.clone();
+            Some(Box::new(move |info: &JobInfo| finished.lock().unwrap().push(info.status)))
+        };
+        let (work, release) = held("first");
+        let (running, _) = queue.submit(NewJob { on_finish: on_finish(), ..job("dana", "demo", work) });
+        let abandoned = Arc::new(AtomicBool::new(false));
+        let flag = abandoned.clone();
+        let mut queued = job("dana", "demo", Box::new(|| Ok(Value::Null)));
+        queued.on_abandon = Some(Box::new(move || flag.store(true, Ordering::SeqCst)));
+        queued.on_finish = on_finish();
+        let (queued, reply) = queue.submit(queued);
+
+        assert!(matches!(queue.cancel(&running.id), Err(JobError::NotQueued { status: JobStatus::Running, .. })));
+        assert_eq!(queue.cancel(&queued.id).unwrap().status, JobStatus::Cancelled);
+        assert!(abandoned.load(Ordering::SeqCst));
+        assert!(reply.await.is_err());
+        assert!(matches!(queue.cancel("nope"), Err(JobError::NotFound { .. })));
+
+        drop(release);
+        // The hook runs after the job's final state is stored.
+        while finished.lock().unwrap().len() < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(queue.get(&running.id).unwrap().result, Some(Value::String("first".into())));
+        assert_eq!(*finished.lock().unwrap(), [JobStatus::Cancelled, JobStatus::Completed]);
+    }
+}
@@ -0,0 +1,12 @@
+pub mod audit;
+pub mod config;
+pub mod routes;
+pub mod security;
+pub mod engine;
+pub mod jobs;
+pub mod live;
+pub mod overrides;
+pub mod state;
+pub mod store;
+pub mod tls;
+pub mod util;
@@ -0,0 +1,397 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use crate::engine::world::Entity;
+use crate::overrides::scheduler::ScheduleStatus;
+use crate::store::VersionSource;
+
+/// Events kept per project for replay; older ones require a resync.
+pub const BACKLOG_LIMIT: usize = 1024;
+
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Clients whose acks are kept per project. Past it the least recently
+/// acked client is forgotten and starts at the head when it reconnects.
+pub const ACK_LIMIT: usize = 256;
+
+/// A change pushed to engines subscribed to a project.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LiveMessage {
+    OverrideApplied {
+        override_id: String,
+        zone_id: String,
+        version: u32,
+        behavior: String,
+        priority: u32,
+        entities: Vec<Entity>,
+    },
+    OverrideReverted {
+        override_id: String,
+        zone_id: String,
+        version: u32,
+        entities: Vec<Entity>,
+    },
+    ZoneVersion {
+        zone_id: String,
+        version: u32,
+        checksum: String,
+        source: VersionSource,
+    },
+    ScheduleUpdated {
+        schedule_id: String,
+        status: ScheduleStatus,
+    },
+    ProjectUpdated {
+        name: String,
+        tier: String,
+        status: String,
+    },
+    ProjectDeleted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveEvent {
+    pub seq: u64,
+    pub project_id: String,
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub message: LiveMessage,
+}
+
+/// Replay state handed to a new subscriber. `gap` is set when events after
+/// the requested sequence have already been dropped from the backlog.
+pub struct Subscription {
+    pub head: u64,
+    pub gap: bool,
+    pub replay: Vec<LiveEvent>,
+    pub receiver: broadcast::Receiver<LiveEvent>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LiveError {
+    #[error("Cannot acknowledge seq {seq}, latest is {head}")]
+    AckAhead { seq: u64, head: u64 },
+
+    #[error("Live log I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Live log serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// How far one client of one user has got. Client ids are chosen by the
+/// client, so they only mean something together with the user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AckRecord {
+    user_id: String,
+    client_id: String,
+    seq: u64,
+    acked_at: DateTime<Utc>,
+}
+
+type AckKey = (String, String);
+
+struct Channel {
+    head: u64,
+    backlog: VecDeque<LiveEvent>,
+    logged: usize,
+    acks: BTreeMap<AckKey, AckRecord>,
+    sender: broadcast::Sender<LiveEvent>,
+}
+
+/// Per-project, sequenced event log for live engine sessions. Events are
+/// appended to `<dir>/<project>.jsonl` so sequence numbers and acks survive
+/// a gateway restart.
+pub struct LiveHub {
+    dir: PathBuf,
+    channels: Mutex<HashMap<String, Channel>>,
+}
+
+impl LiveHub {
+    pub fn open(dir: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            channels: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Assigns the next sequence number and fans the event out. Delivery is
+    /// best effort: a failed write is logged, never returned to the caller.
+    pub fn publish(&self, project_id: &str, message: LiveMessage) -> Option<LiveEvent> {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = match self.channel(&mut channels, project_id) {
+            Ok(channel) => channel,
+            Err(e) => {
+                tracing::error!("Live channel {} unavailable: {}", project_id, e);
+                return None;
+            }
+        };
+
+        let event = LiveEvent {
+            seq: channel.head + 1,
+            project_id: project_id.to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            message,
+        };
+        if let Err(e) = self.append(channel, &event) {
+            tracing::error!("Failed to log live event {} for {}: {}", event.seq, project_id, e);
+            return None;
+        }
+
+        channel.head = event.seq;
+        channel.backlog.push_back(event.clone());
+        if channel.backlog.len() > BACKLOG_LIMIT {
+            channel.backlog.pop_front();
+        }
+        // No receivers is fine; the backlog still covers reconnects.
+        let _ = channel.sender.send(event.clone());
+        Some(event)
+    }
+
+    /// Subscribes to a project's events after `after`. The receiver is
+    /// created under the same lock as the replay, so nothing is missed.
+    pub fn subscribe(&self, project_id: &str, after: u64) -> Result<Subscription, LiveError> {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = self.channel(&mut channels, project_id)?;
+        let (replay, gap) = replay(channel, after);
+
+        Ok(Subscription {
+            head: channel.head,
+            gap,
+            replay,
+            receiver: channel.sender.subscribe(),
+        })
+    }
+
+    /// Backlog events after `after`, used to catch up a lagging subscriber.
+    pub fn since(&self, project_id: &str, after: u64) -> Result<(Vec<LiveEvent>, bool), LiveError> {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = self.channel(&mut channels, project_id)?;
+        Ok(replay(channel, after))
+    }
+
+    pub fn head(&self, project_id: &str) -> Result<u64, LiveError> {
+        let mut channels = self.channels.lock().unwrap();
+        Ok(self.channel(&mut channels, project_id)?.head)
+    }
+
+    /// Records that `client_id` of `user_id` has processed every event up
+    /// to `seq`. Acks never move backwards.
+    pub fn ack(&self, project_id: &str, user_id: &str, client_id: &str, seq: u64) -> Result<u64, LiveError> {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = self.channel(&mut channels, project_id)?;
+        if seq > channel.head {
+            return Err(LiveError::AckAhead { seq, head: channel.head });
+        }
+
+        let key = (user_id.to_string(), client_id.to_string());
+        let acked = channel.acks.get(&key).map(|a| a.seq).unwrap_or(0);
+        if seq <= acked {
+            return Ok(acked);
+        }
+
+        if !channel.acks.contains_key(&key) && channel.acks.len() >= ACK_LIMIT {
+            let oldest = channel.acks.values().min_by_key(|a| a.acked_at).map(|a| (a.user_id.clone(), a.client_id.clone()));
+            if let Some(oldest) = oldest {
+                channel.acks.remove(&oldest);
+            }
+        }
+        let record = AckRecord { user_id: key.0.clone(), client_id: key.1.clone(), seq, acked_at: Utc::now() };
+        channel.acks.insert(key, record);
+        let bytes = serde_json::to_vec_pretty(&channel.acks.values().collect::<Vec<_>>())?;
+        write_atomic(&self.acks_path(project_id), &bytes)?;
+        Ok(seq)
+    }
+
+    pub fn acked(&self, project_id: &str, user_id: &str, client_id: &str) -> Result<Option<u64>, LiveError> {
+        let mut channels = self.channels.lock().unwrap();
+        let key = (user_id.to_string(), client_id.to_string());
+        Ok(self.channel(&mut channels, project_id)?.acks.get(&key).map(|a| a.seq))
+    }
+
+    /// Drops a project's channel and log. Open sessions see the channel
+    /// close and disconnect.
+    pub fn remove(&self, project_id: &str) -> Result<(), LiveError> {
+        self.channels.lock().unwrap().remove(project_id);
+        for path in [self.log_path(project_id), self.acks_path(project_id)] {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn channel<'a>(&self, channels: &'a mut HashMap<String, Channel>, project_id: &str) -> Result<&'a mut Channel, LiveError> {
+        if !channels.contains_key(project_id) {
+            let channel = self.load(project_id)?;
+            channels.insert(project_id.to_string(), channel);
+        }
+        Ok(channels.get_mut(project_id).expect("channel was just inserted"))
+    }
+
+    fn load(&self, project_id: &str) -> Result<Channel, LiveError> {
+        let mut backlog = VecDeque::new();
+        let mut logged = 0;
+        let log_path = self.log_path(project_id);
+        if log_path.exists() {
+            for line in std::fs::read_to_string(&log_path)?.lines().filter(|l| !l.trim().is_empty()) {
+                backlog.push_back(serde_json::from_str::<LiveEvent>(line)?);
+                if backlog.len() > BACKLOG_LIMIT {
+                    backlog.pop_front();
+                }
+                logged += 1;
+            }
+        }
+
+        let acks_path = self.acks_path(project_id);
+        let acks = if acks_path.exists() {
+            serde_json::from_slice::<Vec<AckRecord>>(&std::fs::read(&acks_path)?)?
+                .into_iter()
+                .map(|a| ((a.user_id.clone(), a.client_id.clone()), a))
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Ok(Channel {
+            head: backlog.back().map(|e| e.seq).unwrap_or(0),
+            backlog,
+            logged,
+            acks,
+            sender,
+        })
+    }
+
+    /// Appends to the project log, compacting it down to the backlog once
+    /// it has grown to twice the backlog size.
+    fn append(&self, channel: &mut Channel, event: &LiveEvent) -> Result<(), LiveError> {
+        let path = self.log_path(&event.project_id);
+        let line = serde_json::to_string(event)?;
+
+        if channel.logged >= 2 * BACKLOG_LIMIT {
+            let mut contents = String::new();
+            for kept in channel.backlog.iter().skip(1) {
+                contents.push_str(&serde_json::to_string(kept)?);
+                contents.push('\n');
+            }
+            contents.push_str(&line);
+            contents.push('\n');
+            write_atomic(&path, contents.as_bytes())?;
+            channel.logged = channel.backlog.len();
+            return Ok(());
+        }
+
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        writeln!(file, "{}", line)?;
+        channel.logged += 1;
+        Ok(())
+    }
+
+    fn log_path(&self, project_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", project_id))
+    }
+
+    fn acks_path(&self, project_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.acks.json", project_id))
+    }
+}
+
+fn replay(channel: &Channel, after: u64) -> (Vec<LiveEvent>, bool) {
+    let oldest = channel.backlog.front().map(|e| e.seq).unwrap_or(channel.head + 1);
+    let gap = after < channel.head && after + 1 < oldest;
+    let events = channel.backlog.iter().filter(|e| e.seq > after).cloned().collect();
+    (events, gap)
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), LiveError> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn updated(name: &str) -> LiveMessage {
+        LiveMessage::ProjectUpdated { name: name.into(), tier: "creator".into(), status: "active".into() }
+    }
+
+    #[test]
+    fn test_sequence_and_resume_survive_reopen() {
+        let dir = tempdir().unwrap();
+        let hub = LiveHub::open(dir.path()).unwrap();
+        for i in 0..3 {
+            hub.publish("p1", updated(&format!("n{}", i)));
+        }
+        assert_eq!(hub.ack("p1", "dana", "engine-1", 2).unwrap(), 2);
+        assert_eq!(hub.ack("p1", "dana", "engine-1", 1).unwrap(), 2);
+        assert!(matches!(hub.ack("p1", "dana", "engine-1", 9), Err(LiveError::AckAhead { .. })));
+
+        let reopened = LiveHub::open(dir.path()).unwrap();
+        assert_eq!(reopened.acked("p1", "dana", "engine-1").unwrap(), Some(2));
+        // The same client id belongs to someone else for another user.
+        assert_eq!(reopened.acked("p1", "eve", "engine-1").unwrap(), None);
+        let sub = reopened.subscribe("p1", 2).unwrap();
+        assert_eq!(sub.head, 3);
+        assert!(!sub.gap);
+        assert_eq!(sub.replay.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(reopened.publish("p1", updated("n3")).unwrap().seq, 4);
+    }
+
+    #[test]
+    fn test_subscriber_receives_in_order() {
+        let dir = tempdir().unwrap();
+        let hub = LiveHub::open(dir.path()).unwrap();
+        let mut sub = hub.subscribe("p1", 0).unwrap();
+        assert!(sub.replay.is_empty());
+
+        hub.publish("p1", updated("a"));
+        hub.publish("p1", LiveMessage::ProjectDeleted);
+        assert_eq!(sub.receiver.try_recv().unwrap().seq, 1);
+        assert!(matches!(sub.receiver.try_recv().unwrap().message, LiveMessage::ProjectDeleted));
+    }
+
+    #[test]
+    fn test_least_recently_acked_clients_are_forgotten_past_the_limit() {
+        let dir = tempdir().unwrap();
+        let hub = LiveHub::open(dir.path()).unwrap();
+        hub.publish("p1", LiveMessage::ProjectDeleted);
+        for n in 0..ACK_LIMIT + 1 {
+            hub.ack("p1", "dana", &format!("engine-{}", n), 1).unwrap();
+        }
+
+        let reopened = LiveHub::open(dir.path()).unwrap();
+        assert_eq!(reopened.acked("p1", "dana", "engine-0").unwrap(), None);
+        assert_eq!(reopened.acked("p1", "dana", &format!("engine-{}", ACK_LIMIT)).unwrap(), Some(1));
+        assert_eq!(reopened.channels.lock().unwrap()["p1"].acks.len(), ACK_LIMIT);
+    }
+
+    #[test]
+    fn test_trimmed_backlog_reports_gap() {
+        let dir = tempdir().unwrap();
+        let hub = LiveHub::open(dir.path()).unwrap();
+        for _ in 0..BACKLOG_LIMIT * 2 + 5 {
+            hub.publish("p1", LiveMessage::ProjectDeleted);
+        }
+
+        let (events, gap) = hub.since("p1", 1).unwrap();
+        assert!(gap);
+        assert_eq!(events.len(), BACKLOG_LIMIT);
+
+        let reopened = LiveHub::open(dir.path()).unwrap();
+        assert_eq!(reopened.head("p1").unwrap(), (BACKLOG_LIMIT * 2 + 5) as u64);
+        assert!(!reopened.since("p1", reopened.head("p1").unwrap() - 1).unwrap().1);
+    }
+}
@@ -1,12 +1,9 @@
-use axum::{Router, routing::get, routing::post};
 use tower_http::cors::{CorsLayer, Any};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use std::net::SocketAddr;
-
-mod routes;
-mod security;
-mod engine;
-mod util;
+use std::sync::Arc;
+use std::time::Duration;
+use pacai_gateway::{audit::checkpoint, config::GatewayConfig, overrides::scheduler, routes, security::{policy, rate_limit}, state::AppState, tls, util::logging};
 
 #[tokio::main]
 async fn main() {
@@ -16,27 +13,48 @@ async fn main() {
             .unwrap_or_else(|_| "pacai_gateway=debug,tower_http=debug".into()))
         .init();
 
+    let config = GatewayConfig::from_env();
+    let tls = config.tls.clone().map(|tls| {
+        Arc::new(tls::TlsReloader::load(tls).unwrap_or_else(|e| panic!("Failed to load TLS configuration: {}", e)))
+    });
+    if let Some(path) = config.rbac_policy_path.clone() {
+        let mut file = policy::PolicyFile::new(path);
+        let loaded = file.poll().unwrap_or_else(|e| panic!("Failed to load RBAC policy: {}", e));
+        policy::install(loaded.expect("first read of the policy file"), file.path().display().to_string());
+        tracing::info!("RBAC policy loaded from {}", file.path().display());
+        policy::spawn_reload(file, Duration::from_secs(10));
+    }
+    let state = AppState::open(config).expect("Failed to open gateway state");
+    match state.license.current() {
+        Ok(license) => tracing::info!("Licensed: {} tier, {} seats, license {}", license.tier, license.seats, license.license_id),
+        Err(e) => tracing::error!("{}; generation, overrides and export are refused until a valid license is installed", e),
+    }
+    state.audit.record(logging::log_system("gateway_start", serde_json::json!({ "version": env!("CARGO_PKG_VERSION") })));
+    scheduler::spawn(state.clone(), Duration::from_secs(1));
+    checkpoint::spawn(state.clone(), Duration::from_secs(30));
+    if state.config.rate_limit_state_path.is_some() {
+        rate_limit::spawn_persist(state.clone(), Duration::from_secs(30));
+    }
+
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
         .allow_headers(Any);
 
-    let app = Router::new()
-        .route("/health", get(routes::health::health))
-        .route("/v5/license", get(routes::license::license_check))
-        .route("/v5/prompt", post(routes::prompt::handle_prompt))
-        .route("/v5/override", post(routes::override_route::apply_override))
-        .route("/v5/export", post(routes::export::export_bundle))
-        .route("/v5/projects", post(routes::prompt::create_project))
-        .route("/v5/projects/:id", get(routes::prompt::get_project))
-        .route("/v5/projects/:id/generate", post(routes::prompt::generate_zone))
-        .route("/v5/audit", get(routes::health::audit_stream))
-        .layer(cors);
+    let app = routes::router(state).layer(cors);
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
     tracing::info!("PacAI v6.3 Gateway — Production Ready • SCIF-Compatible • Hardware-Root Secure");
-    tracing::info!("Listening on {}", addr);
-
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    match tls {
+        Some(reloader) => {
+            tracing::info!("Listening on {} (mutual TLS)", addr);
+            tls::spawn_reload(reloader.clone());
+            tls::serve(listener, app, reloader).await.unwrap();
+        }
+        None => {
+            tracing::info!("Listening on {}", addr);
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+        }
+    }
 }
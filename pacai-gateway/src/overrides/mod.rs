@@ -0,0 +1,229 @@
+pub mod scheduler;
+
+use serde_json::Value;
+use crate::engine::override_engine::{self, EntityRestore, OverrideOutcome, OverrideSpec};
+use crate::engine::world::{Entity, WorldOutput};
+use crate::live::LiveMessage;
+use crate::routes::ApiError;
+use crate::state::AppState;
+use crate::store::{OverrideRecord, Project, StoreError, VersionSource, ZoneRecord};
+
+/// Everything needed to apply an override to a project, whether it comes
+/// straight from `POST /v5/override` or from the scheduler.
+#[derive(Debug, Clone)]
+pub struct OverrideCommand {
+    pub id: String,
+    pub project_id: String,
+    pub zone_id: Option<String>,
+    pub target_type: String,
+    pub target_id: Option<String>,
+    pub behavior: String,
+    pub parameters: Value,
+    pub priority: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AppliedOverride {
+    pub zone_id: String,
+    pub version: u32,
+    pub priority: u32,
+    pub target_id: String,
+    pub applied_at: String,
+    pub checksum: String,
+    pub outcome: OverrideOutcome,
+}
+
+impl OverrideCommand {
+    pub fn spec(&self) -> Result<OverrideSpec, ApiError> {
+        Ok(OverrideSpec::parse(
+            &self.id,
+            &self.target_type,
+            self.target_id.as_deref(),
+            &self.behavior,
+            &self.parameters,
+            self.priority,
+        )?)
+    }
+}
+
+/// Overrides target the requested zone, or the most recently generated one.
+pub fn select_zone<'a>(project: &'a Project, zone_id: Option<&str>) -> Result<&'a ZoneRecord, ApiError> {
+    match zone_id {
+        Some(zone_id) => Ok(project.zone(zone_id)?),
+        None => project.zones.last().ok_or_else(|| {
+            ApiError::BadRequest(format!("Project {} has no generated zones", project.id))
+        }),
+    }
+}
+
+/// Applies `command` to the head version of its zone and records it on the
+/// project. The override is computed outside the store lock and committed
+/// only if the zone head has not moved in the meantime. `finish` runs in the
+/// same store update, so callers can record follow-up state atomically.
+pub fn commit<F>(state: &AppState, command: &OverrideCommand, finish: F) -> Result<AppliedOverride, ApiError>
+where
+    F: FnOnce(&mut Project, &AppliedOverride) -> Result<(), StoreError>,
+{
+    let spec = command.spec()?;
+    let project = state.projects.get(&command.project_id)?;
+    let zone = select_zone(&project, command.zone_id.as_deref())?;
+    let head = zone.head_version().ok_or_else(|| StoreError::VersionNotFound {
+        zone_id: zone.zone_id.clone(),
+        version: zone.head,
+    })?;
+
+    let mut world = head.world.clone();
+    let mut claims = head.claims.clone();
+    let outcome = override_engine::apply(&mut world, &mut claims, &spec)?;
+
+    let zone_id = zone.zone_id.clone();
+    let expected_head = zone.head;
+    let target_id = command.target_id.clone().unwrap_or_else(|| "all".into());
+
+    let checksum = format!("{:x}", sha2::Sha256::digest(
+        format!("{}{}{}{}{:?}", command.id, command.project_id, command.behavior, command.parameters, outcome.affected).as_bytes()
+    ));
+    let applied_at = chrono::Utc::now().to_rfc3339();
+    let entities = entities_in(&world, &outcome.affected);
+
+    let (_, applied) = state.projects.try_update(&command.project_id, |p| {
+        let zone = checked_zone(p, &zone_id, expected_head)?;
+
+        let version = if outcome.affected.is_empty() {
+            zone.head
+        } else {
+            let seed = zone.head_version().map(|v| v.seed).unwrap_or_default();
+            let overrides = zone.head_version().and_then(|v| v.overrides.clone());
+            zone.push_version(
+                seed,
+                checksum.clone(),
+                VersionSource::Override { override_id: command.id.clone() },
+                overrides,
+                claims,
+                world,
+            ).version
+        };
+
+        p.overrides.push(OverrideRecord {
+            id: command.id.clone(),
+            zone_id: zone_id.clone(),
+            version,
+            target_type: command.target_type.clone(),
+            target_id: target_id.clone(),
+            behavior: command.behavior.clone(),
+            parameters: command.parameters.clone(),
+            priority: command.priority,
+            affected_entities: outcome.affected.clone(),
+            applied_at: applied_at.clone(),
+            checksum: checksum.clone(),
+        });
+
+        let applied = AppliedOverride {
+            zone_id: zone_id.clone(),
+            version,
+            priority: spec.priority,
+            target_id,
+            applied_at,
+            checksum,
+            outcome,
+        };
+        finish(p, &applied)?;
+        Ok(applied)
+    })?;
+
+    tracing::info!(
+        "Override {} on zone {} v{}: {} affected, {} skipped",
+        command.id, applied.zone_id, applied.version, applied.outcome.affected.len(), applied.outcome.skipped.len()
+    );
+
+    if !entities.is_empty() {
+        state.live.publish(&command.project_id, LiveMessage::OverrideApplied {
+            override_id: command.id.clone(),
+            zone_id: applied.zone_id.clone(),
+            version: applied.version,
+            behavior: command.behavior.clone(),
+            priority: applied.priority,
+            entities,
+        });
+    }
+
+    Ok(applied)
+}
+
+/// Reverts a previously applied override on its zone, restoring every
+/// entity it still holds. `finish` receives the resulting head version and
+/// the restored entity ids inside the same store update.
+pub fn revert<F>(
+    state: &AppState,
+    project_id: &str,
+    zone_id: &str,
+    override_id: &str,
+    restores: &[EntityRestore],
+    finish: F,
+) -> Result<(u32, Vec<String>), ApiError>
+where
+    F: FnOnce(&mut Project, u32, &[String]) -> Result<(), StoreError>,
+{
+    let project = state.projects.get(project_id)?;
+    let zone = project.zone(zone_id)?;
+    let head = zone.head_version().ok_or_else(|| StoreError::VersionNotFound {
+        zone_id: zone_id.to_string(),
+        version: zone.head,
+    })?;
+
+    let mut world = head.world.clone();
+    let mut claims = head.claims.clone();
+    let reverted = override_engine::revert(&mut world, &mut claims, override_id, restores);
+    let entities = entities_in(&world, &reverted);
+
+    let expected_head = zone.head;
+    let (_, version) = state.projects.try_update(project_id, |p| {
+        let zone = checked_zone(p, zone_id, expected_head)?;
+        let version = match zone.head_version() {
+            Some(head) if !reverted.is_empty() => {
+                let (seed, checksum, overrides) = (head.seed, head.checksum.clone(), head.overrides.clone());
+                zone.push_version(
+                    seed,
+                    checksum,
+                    VersionSource::Revert { override_id: override_id.to_string() },
+                    overrides,
+                    claims,
+                    world,
+                ).version
+            }
+            _ => zone.head,
+        };
+        finish(p, version, &reverted)?;
+        Ok(version)
+    })?;
+
+    tracing::info!("Override {} reverted on zone {} v{}: {} entities", override_id, zone_id, version, reverted.len());
+
+    if !entities.is_empty() {
+        state.live.publish(project_id, LiveMessage::OverrideReverted {
+            override_id: override_id.to_string(),
+            zone_id: zone_id.to_string(),
+            version,
+            entities,
+        });
+    }
+    Ok((version, reverted))
+}
+
+fn entities_in(world: &WorldOutput, ids: &[String]) -> Vec<Entity> {
+    world.entities.iter().filter(|e| ids.contains(&e.id)).cloned().collect()
+}
+
+fn checked_zone<'a>(project: &'a mut Project, zone_id: &str, expected_head: u32) -> Result<&'a mut ZoneRecord, StoreError> {
+    let zone = project.zone_mut(zone_id)?;
+    if zone.head != expected_head {
+        return Err(StoreError::HeadMoved {
+            zone_id: zone_id.to_string(),
+            expected: expected_head,
+            actual: zone.head,
+        });
+    }
+    Ok(zone)
+}
+
+use sha2::Digest;
@@ -0,0 +1,534 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use crate::engine::override_engine::EntityRestore;
+use crate::engine::world::{Entity, WorldOutput};
+use crate::live::LiveMessage;
+use crate::overrides::{self, AppliedOverride, OverrideCommand};
+use crate::routes::ApiError;
+use crate::state::{AppState, SharedState};
+use crate::store::{Project, StoreError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleStatus {
+    /// Waiting for its start time and condition.
+    Pending,
+    /// Applied, and due to be reverted at `revert_at`.
+    Active,
+    Completed,
+    Cancelled,
+    /// The condition never held before `expires_at`.
+    Expired,
+    Failed,
+}
+
+impl ScheduleStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ScheduleStatus::Pending => "pending",
+            ScheduleStatus::Active => "active",
+            ScheduleStatus::Completed => "completed",
+            ScheduleStatus::Cancelled => "cancelled",
+            ScheduleStatus::Expired => "expired",
+            ScheduleStatus::Failed => "failed",
+        }
+    }
+
+    pub fn is_open(self) -> bool {
+        matches!(self, ScheduleStatus::Pending | ScheduleStatus::Active)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Eq,
+    Ne,
+}
+
+impl Comparison {
+    pub fn holds(self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            Comparison::Lt => lhs < rhs,
+            Comparison::Lte => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Gte => lhs >= rhs,
+            Comparison::Eq => (lhs - rhs).abs() < 1e-6,
+            Comparison::Ne => (lhs - rhs).abs() >= 1e-6,
+        }
+    }
+}
+
+const ENTITY_STATS: [&str; 4] = ["health", "threat_level", "awareness", "aggression"];
+
+/// Condition a pending override waits for, evaluated against the head
+/// version of its zone on every scheduler tick.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TriggerCondition {
+    EntityStat { entity_id: String, stat: String, op: Comparison, value: f64 },
+    FactionCount { faction: String, op: Comparison, value: f64 },
+    OverrideApplied { override_id: String },
+}
+
+impl TriggerCondition {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            TriggerCondition::EntityStat { stat, .. } if !ENTITY_STATS.contains(&stat.as_str()) => {
+                Err(format!("Unknown entity stat '{}', expected one of {}", stat, ENTITY_STATS.join(", ")))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn holds(&self, project: &Project, world: &WorldOutput) -> bool {
+        match self {
+            TriggerCondition::EntityStat { entity_id, stat, op, value } => world
+                .entities
+                .iter()
+                .find(|e| &e.id == entity_id)
+                .and_then(|e| entity_stat(e, stat))
+                .is_some_and(|actual| op.holds(actual, *value)),
+            TriggerCondition::FactionCount { faction, op, value } => {
+                let count = world
+                    .entities
+                    .iter()
+                    .filter(|e| e.faction.eq_ignore_ascii_case(faction))
+                    .count();
+                op.holds(count as f64, *value)
+            }
+            TriggerCondition::OverrideApplied { override_id } => {
+                project.overrides.iter().any(|o| &o.id == override_id && !o.affected_entities.is_empty())
+            }
+        }
+    }
+}
+
+fn entity_stat(entity: &Entity, stat: &str) -> Option<f64> {
+    match stat {
+        "health" => Some(entity.stats.health as f64),
+        "threat_level" => Some(entity.stats.threat_level as f64),
+        "awareness" => Some(entity.stats.awareness as f64),
+        "aggression" => Some(entity.stats.aggression as f64),
+        _ => None,
+    }
+}
+
+/// An override queued on a project. Once applied it shares its id with the
+/// resulting `OverrideRecord`; a `duration_seconds` makes it revert itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledOverride {
+    pub id: String,
+    pub zone_id: Option<String>,
+    pub target_type: String,
+    pub target_id: Option<String>,
+    pub behavior: String,
+    #[serde(default)]
+    pub parameters: Value,
+    pub priority: Option<u32>,
+    pub status: ScheduleStatus,
+    pub created_at: String,
+    pub start_at: Option<String>,
+    pub duration_seconds: Option<u64>,
+    pub expires_at: Option<String>,
+    pub condition: Option<TriggerCondition>,
+    pub applied_at: Option<String>,
+    pub applied_zone_id: Option<String>,
+    pub applied_version: Option<u32>,
+    pub revert_at: Option<String>,
+    pub reverted_at: Option<String>,
+    pub cancelled_at: Option<String>,
+    #[serde(default)]
+    pub affected_entities: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub restore: Vec<EntityRestore>,
+    pub error: Option<String>,
+}
+
+impl ScheduledOverride {
+    pub fn new(command: &OverrideCommand) -> Self {
+        Self {
+            id: command.id.clone(),
+            zone_id: command.zone_id.clone(),
+            target_type: command.target_type.clone(),
+            target_id: command.target_id.clone(),
+            behavior: command.behavior.clone(),
+            parameters: command.parameters.clone(),
+            priority: command.priority,
+            status: ScheduleStatus::Pending,
+            created_at: Utc::now().to_rfc3339(),
+            start_at: None,
+            duration_seconds: None,
+            expires_at: None,
+            condition: None,
+            applied_at: None,
+            applied_zone_id: None,
+            applied_version: None,
+            revert_at: None,
+            reverted_at: None,
+            cancelled_at: None,
+            affected_entities: Vec::new(),
+            restore: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn command(&self, project_id: &str) -> OverrideCommand {
+        OverrideCommand {
+            id: self.id.clone(),
+            project_id: project_id.to_string(),
+            zone_id: self.zone_id.clone(),
+            target_type: self.target_type.clone(),
+            target_id: self.target_id.clone(),
+            behavior: self.behavior.clone(),
+            parameters: self.parameters.clone(),
+            priority: self.priority,
+        }
+    }
+
+    /// Records a successful apply. Overrides with a duration stay active
+    /// until they are reverted; everything else is done at this point.
+    pub fn mark_applied(&mut self, applied: &AppliedOverride, now: DateTime<Utc>) {
+        let revert_at = self
+            .duration_seconds
+            .filter(|_| !applied.outcome.affected.is_empty())
+            .map(|secs| now + chrono::Duration::seconds(secs as i64));
+
+        self.status = if revert_at.is_some() { ScheduleStatus::Active } else { ScheduleStatus::Completed };
+        self.applied_at = Some(now.to_rfc3339());
+        self.applied_zone_id = Some(applied.zone_id.clone());
+        self.applied_version = Some(applied.version);
+        self.revert_at = revert_at.map(|t| t.to_rfc3339());
+        self.affected_entities = applied.outcome.affected.clone();
+        self.restore = applied.outcome.previous.clone();
+    }
+
+    fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.start_at.as_deref().and_then(parse_time).is_none_or(|t| t <= now)
+    }
+
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.as_deref().and_then(parse_time).is_some_and(|t| t <= now)
+    }
+
+    fn revert_due(&self, now: DateTime<Utc>) -> bool {
+        self.revert_at.as_deref().and_then(parse_time).is_some_and(|t| t <= now)
+    }
+
+    fn ensure_status(&self, status: ScheduleStatus) -> Result<(), StoreError> {
+        if self.status != status {
+            return Err(StoreError::ScheduleClosed {
+                schedule_id: self.id.clone(),
+                status: self.status.as_str().into(),
+            });
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|t| t.with_timezone(&Utc))
+}
+
+/// Tells live sessions about the current status of a scheduled override.
+pub fn publish_status(state: &AppState, project_id: &str, schedule_id: &str) {
+    let Ok(project) = state.projects.get(project_id) else {
+        return;
+    };
+    if let Ok(schedule) = project.schedule(schedule_id) {
+        state.live.publish(project_id, LiveMessage::ScheduleUpdated {
+            schedule_id: schedule.id.clone(),
+            status: schedule.status,
+        });
+    }
+}
+
+/// Cancels a scheduled override. A pending one is simply dropped; an active
+/// one is reverted first so its entities return to their previous state.
+pub fn cancel(state: &AppState, project_id: &str, schedule_id: &str) -> Result<ScheduledOverride, ApiError> {
+    let project = state.projects.get(project_id)?;
+    let schedule = project.schedule(schedule_id)?.clone();
+    let now = Utc::now().to_rfc3339();
+
+    match schedule.status {
+        ScheduleStatus::Pending => {
+            state.projects.try_update(project_id, |p| {
+                let s = p.schedule_mut(schedule_id)?;
+                s.ensure_status(ScheduleStatus::Pending)?;
+                s.status = ScheduleStatus::Cancelled;
+                s.cancelled_at = Some(now);
+                Ok(())
+            })?;
+        }
+        ScheduleStatus::Active => {
+            let zone_id = schedule.applied_zone_id.as_deref().unwrap_or_default();
+            overrides::revert(state, project_id, zone_id, schedule_id, &schedule.restore, |p, _, _| {
+                let s = p.schedule_mut(schedule_id)?;
+                s.ensure_status(ScheduleStatus::Active)?;
+                s.status = ScheduleStatus::Cancelled;
+                s.reverted_at = Some(now.clone());
+                s.cancelled_at = Some(now);
+                Ok(())
+            })?;
+        }
+        status => {
+            return Err(StoreError::ScheduleClosed {
+                schedule_id: schedule_id.to_string(),
+                status: status.as_str().into(),
+            }
+            .into());
+        }
+    }
+
+    publish_status(state, project_id, schedule_id);
+    Ok(state.projects.get(project_id)?.schedule(schedule_id)?.clone())
+}
+
+/// Runs one scheduler pass: reverts active overrides whose time is up, then
+/// applies pending ones that are due and whose condition holds. Returns the
+/// number of overrides that changed state.
+pub fn tick(state: &AppState, now: DateTime<Utc>) -> usize {
+    let projects = &state.projects;
+    let mut changed = 0;
+
+    let project_ids = projects.ids_where(|p| p.scheduled_overrides.iter().any(|s| s.status.is_open()));
+    for project_id in project_ids {
+        let Ok(project) = projects.get(&project_id) else {
+            continue;
+        };
+
+        let mut open: Vec<&ScheduledOverride> = project
+            .scheduled_overrides
+            .iter()
+            .filter(|s| s.status.is_open())
+            .collect();
+        // Reverts go first so a follow-up override sees the restored state.
+        open.sort_by_key(|s| (s.status != ScheduleStatus::Active, s.start_at.clone(), s.created_at.clone()));
+
+        for schedule in open {
+            let result = match schedule.status {
+                ScheduleStatus::Active if schedule.revert_due(now) => expire_active(state, &project_id, schedule, now),
+                ScheduleStatus::Pending => run_pending(state, &project_id, &schedule.id, now),
+                _ => Ok(false),
+            };
+
+            match result {
+                Ok(true) => {
+                    changed += 1;
+                    publish_status(state, &project_id, &schedule.id);
+                }
+                Ok(false) => {}
+                // The zone moved under us; the next tick works from the new head.
+                Err(ApiError::Conflict(message)) => tracing::debug!("Scheduled override {} deferred: {}", schedule.id, message),
+                Err(ApiError::Internal(message)) => tracing::error!("Scheduled override {} failed: {}", schedule.id, message),
+                Err(err) => {
+                    tracing::warn!("Scheduled override {} failed: {}", schedule.id, err);
+                    let message = err.to_string();
+                    let failed = projects.update(&project_id, |p| {
+                        if let Ok(s) = p.schedule_mut(&schedule.id) {
+                            s.status = ScheduleStatus::Failed;
+                            s.error = Some(message);
+                        }
+                    });
+                    if failed.is_ok() {
+                        changed += 1;
+                        publish_status(state, &project_id, &schedule.id);
+                    }
+                }
+            }
+        }
+    }
+
+    changed
+}
+
+fn expire_active(state: &AppState, project_id: &str, schedule: &ScheduledOverride, now: DateTime<Utc>) -> Result<bool, ApiError> {
+    let zone_id = schedule.applied_zone_id.as_deref().unwrap_or_default();
+    overrides::revert(state, project_id, zone_id, &schedule.id, &schedule.restore, |p, _, _| {
+        let s = p.schedule_mut(&schedule.id)?;
+        s.ensure_status(ScheduleStatus::Active)?;
+        s.status = ScheduleStatus::Completed;
+        s.reverted_at = Some(now.to_rfc3339());
+        Ok(())
+    })?;
+    Ok(true)
+}
+
+fn run_pending(state: &AppState, project_id: &str, schedule_id: &str, now: DateTime<Utc>) -> Result<bool, ApiError> {
+    // Earlier schedules in this pass may have changed the project.
+    let project = state.projects.get(project_id)?;
+    let schedule = project.schedule(schedule_id)?;
+    if schedule.status != ScheduleStatus::Pending || !schedule.is_due(now) {
+        return Ok(false);
+    }
+
+    if let Some(condition) = &schedule.condition {
+        let head = overrides::select_zone(&project, schedule.zone_id.as_deref())
+            .ok()
+            .and_then(|zone| zone.head_version());
+        let holds = head.is_some_and(|head| condition.holds(&project, &head.world));
+
+        if !holds {
+            if !schedule.is_expired(now) {
+                return Ok(false);
+            }
+            state.projects.try_update(project_id, |p| {
+                let s = p.schedule_mut(schedule_id)?;
+                s.ensure_status(ScheduleStatus::Pending)?;
+                s.status = ScheduleStatus::Expired;
+                Ok(())
+            })?;
+            return Ok(true);
+        }
+    }
+
+    overrides::commit(state, &schedule.command(project_id), |p, applied| {
+        let s = p.schedule_mut(schedule_id)?;
+        s.ensure_status(ScheduleStatus::Pending)?;
+        s.mark_applied(applied, now);
+        Ok(())
+    })?;
+    Ok(true)
+}
+
+/// Starts the background task that drives scheduled overrides.
+pub fn spawn(state: SharedState, period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            let changed = tick(&state, Utc::now());
+            if changed > 0 {
+                tracing::info!("Override scheduler: {} scheduled overrides updated", changed);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GatewayConfig;
+    use crate::engine::world;
+    use crate::store::VersionSource;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    fn seeded_state() -> (tempfile::TempDir, SharedState, Project) {
+        let dir = tempdir().unwrap();
+        let state = AppState::open(GatewayConfig::new(dir.path())).unwrap();
+        let project = state.projects.create(Project::new("Harbor", "creator", 7)).unwrap();
+        let world = world::generate("harbor", 7, "zone");
+        let project = state
+            .projects
+            .update(&project.id, |p| {
+                let mut zone = crate::store::ZoneRecord::new("zone-a", "harbor");
+                zone.push_version(7, "c".into(), VersionSource::Generate, None, Default::default(), world);
+                p.zones.push(zone);
+            })
+            .unwrap();
+        (dir, state, project)
+    }
+
+    fn schedule(project: &Project, entity_id: &str) -> ScheduledOverride {
+        ScheduledOverride::new(&OverrideCommand {
+            id: uuid::Uuid::new_v4().to_string(),
+            project_id: project.id.clone(),
+            zone_id: None,
+            target_type: "entity".into(),
+            target_id: Some(entity_id.into()),
+            behavior: "ambush".into(),
+            parameters: json!({}),
+            priority: None,
+        })
+    }
+
+    fn head_behavior(store: &crate::store::ProjectStore, project_id: &str, entity_id: &str) -> String {
+        let project = store.get(project_id).unwrap();
+        let head = project.zones[0].head_version().unwrap();
+        head.world.entities.iter().find(|e| e.id == entity_id).unwrap().behavior.clone()
+    }
+
+    #[test]
+    fn test_timed_override_applies_and_reverts() {
+        let (_dir, state, project) = seeded_state();
+        let store = &state.projects;
+        let entity = project.zones[0].head_version().unwrap().world.entities[0].clone();
+        let now = Utc::now();
+
+        let pending = ScheduledOverride {
+            start_at: Some((now + chrono::Duration::seconds(120)).to_rfc3339()),
+            duration_seconds: Some(60),
+            ..schedule(&project, &entity.id)
+        };
+        let id = pending.id.clone();
+        store.update(&project.id, |p| p.scheduled_overrides.push(pending)).unwrap();
+
+        assert_eq!(tick(&state, now), 0);
+        assert_eq!(tick(&state, now + chrono::Duration::seconds(121)), 1);
+        assert_eq!(head_behavior(store, &project.id, &entity.id), "ambush");
+        assert_eq!(store.get(&project.id).unwrap().schedule(&id).unwrap().status, ScheduleStatus::Active);
+
+        assert_eq!(tick(&state, now + chrono::Duration::seconds(200)), 1);
+        let project = store.get(&project.id).unwrap();
+        assert_eq!(project.schedule(&id).unwrap().status, ScheduleStatus::Completed);
+        assert_eq!(head_behavior(store, &project.id, &entity.id), entity.behavior);
+        assert_eq!(project.zones[0].head, 3);
+    }
+
+    #[test]
+    fn test_condition_waits_then_expires() {
+        let (_dir, state, project) = seeded_state();
+        let store = &state.projects;
+        let entity = project.zones[0].head_version().unwrap().world.entities[0].clone();
+        let now = Utc::now();
+
+        let pending = ScheduledOverride {
+            condition: Some(TriggerCondition::EntityStat {
+                entity_id: entity.id.clone(),
+                stat: "health".into(),
+                op: Comparison::Lt,
+                value: 0.0,
+            }),
+            expires_at: Some((now + chrono::Duration::seconds(30)).to_rfc3339()),
+            ..schedule(&project, &entity.id)
+        };
+        let id = pending.id.clone();
+        store.update(&project.id, |p| p.scheduled_overrides.push(pending)).unwrap();
+
+        assert_eq!(tick(&state, now), 0);
+        assert_eq!(tick(&state, now + chrono::Duration::seconds(31)), 1);
+        let project = store.get(&project.id).unwrap();
+        assert_eq!(project.schedule(&id).unwrap().status, ScheduleStatus::Expired);
+        assert_eq!(project.zones[0].head, 1);
+    }
+
+    #[test]
+    fn test_cancel_active_reverts() {
+        let (_dir, state, project) = seeded_state();
+        let store = &state.projects;
+        let entity = project.zones[0].head_version().unwrap().world.entities[0].clone();
+
+        let pending = ScheduledOverride {
+            duration_seconds: Some(600),
+            ..schedule(&project, &entity.id)
+        };
+        let id = pending.id.clone();
+        store.update(&project.id, |p| p.scheduled_overrides.push(pending)).unwrap();
+        tick(&state, Utc::now());
+
+        let cancelled = cancel(&state, &project.id, &id).unwrap();
+        assert_eq!(cancelled.status, ScheduleStatus::Cancelled);
+        assert!(cancelled.reverted_at.is_some());
+        assert_eq!(head_behavior(store, &project.id, &entity.id), entity.behavior);
+        assert!(matches!(cancel(&state, &project.id, &id), Err(ApiError::Conflict(_))));
+    }
+}
@@ -0,0 +1,196 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Json, Response, sse::{Event, KeepAlive, Sse}},
+};
+use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, StreamExt};
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
+use crate::audit::{AuditFilter, ChainStatus};
+use crate::audit::export::{self, ExportError};
+use crate::audit::query::{self, AuditStats, AuthFailureSummary, EventPage};
+use crate::audit::middleware::RequestContext;
+use crate::routes::ApiError;
+use crate::state::SharedState;
+use crate::util::logging::{AuditEntry, AuditEventType};
+
+fn sse_event(entry: &AuditEntry) -> Event {
+    Event::default()
+        .id(entry.sequence.to_string())
+        .event("audit")
+        .json_data(entry)
+        .unwrap_or_else(|_| Event::default().comment("unserializable audit entry"))
+}
+
+/// Streams stored entries matching the filter, then follows new ones as they
+/// are appended. A reconnecting client's `Last-Event-ID` resumes after that
+/// sequence unless `since` is given explicitly.
+pub async fn audit_stream(
+    State(state): State<SharedState>,
+    Query(mut filter): Query<AuditFilter>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    if filter.since.is_none() {
+        filter.since = headers
+            .get("last-event-id")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(|seq| seq + 1);
+    }
+
+    // Subscribe before reading history so nothing falls between the two.
+    let receiver = state.audit.subscribe();
+    let history = state.audit.entries_from(filter.since.unwrap_or(0))?;
+    let last = history
+        .last()
+        .map(|e| e.sequence)
+        .or_else(|| filter.since.and_then(|s| s.checked_sub(1)));
+    let history: Vec<_> = history.into_iter().filter(|e| filter.matches(e)).collect();
+
+    let live = stream::unfold((state, receiver, last), |(state, mut receiver, last)| async move {
+        loop {
+            let batch = match receiver.recv().await {
+                Ok(entry) if last.is_some_and(|l| entry.sequence <= l) => continue,
+                Ok(entry) => vec![entry],
+                // Fell behind the broadcast buffer; reread what was missed.
+                Err(RecvError::Lagged(_)) => match state.audit.entries_from(last.map_or(0, |l| l + 1)) {
+                    Ok(entries) if !entries.is_empty() => entries,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        tracing::error!("Audit stream catch-up failed: {}", e);
+                        return None;
+                    }
+                },
+                Err(RecvError::Closed) => return None,
+            };
+            let next = batch.last().map(|e| e.sequence);
+            return Some((batch, (state, receiver, next)));
+        }
+    })
+    .flat_map(stream::iter)
+    .filter(move |entry| std::future::ready(filter.matches(entry)));
+
+    let events = stream::iter(history)
+        .chain(live)
+        .map(|entry| Ok(sse_event(&entry)));
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    #[serde(rename = "type")]
+    pub event_type: Option<AuditEventType>,
+    pub actor: Option<String>,
+    pub resource: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    /// Sequence of the last entry already seen.
+    pub cursor: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+impl EventsQuery {
+    fn filter(&self) -> AuditFilter {
+        AuditFilter {
+            event_type: self.event_type,
+            actor: self.actor.clone(),
+            resource: self.resource.clone(),
+            since: None,
+            start: self.start,
+            end: self.end,
+        }
+    }
+}
+
+async fn load_entries(state: SharedState, from: u64) -> Result<Vec<AuditEntry>, ApiError> {
+    Ok(tokio::task::spawn_blocking(move || state.audit.entries_from(from))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??)
+}
+
+pub async fn list_events(
+    State(state): State<SharedState>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Json<EventPage>, ApiError> {
+    let limit = query.limit.unwrap_or(query::DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > query::MAX_PAGE_SIZE {
+        return Err(ApiError::BadRequest(format!("limit must be between 1 and {}", query::MAX_PAGE_SIZE)));
+    }
+    let entries = load_entries(state, query.cursor.map_or(0, |c| c + 1)).await?;
+    Ok(Json(query::page(entries, &query.filter(), query.cursor, limit)))
+}
+
+pub async fn event_stats(
+    State(state): State<SharedState>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Json<AuditStats>, ApiError> {
+    let filter = query.filter();
+    let entries = load_entries(state, 0).await?;
+    Ok(Json(query::stats(entries.iter().filter(|e| filter.matches(e)))))
+}
+
+pub async fn auth_failure_summary(
+    State(state): State<SharedState>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Json<AuthFailureSummary>, ApiError> {
+    let filter = query.filter();
+    let entries = load_entries(state, 0).await?;
+    Ok(Json(query::auth_failures(entries.iter().filter(|e| filter.matches(e)))))
+}
+
+pub async fn verify_audit(State(state): State<SharedState>) -> Result<Json<ChainStatus>, ApiError> {
+    let status = tokio::task::spawn_blocking(move || state.audit.verify())
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok(Json(status))
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+/// Signed zip of the entries in `from..=to` for offline verification with
+/// `pacai-audit-verify`. Without `to`, the head is checkpointed first so the
+/// whole export is covered by a checkpoint.
+pub async fn export_audit(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    let from = query.from.unwrap_or(0);
+    let (to, archive) = tokio::task::spawn_blocking(move || -> Result<_, ApiError> {
+        if query.to.is_none() {
+            state.audit.checkpoint()?;
+        }
+        let entries: Vec<_> = state
+            .audit
+            .entries_from(from)?
+            .into_iter()
+            .take_while(|e| query.to.is_none_or(|to| e.sequence <= to))
+            .collect();
+        let to = entries.last().ok_or(ExportError::EmptyRange { from })?.sequence;
+        Ok((to, export::build_archive(&entries, &state.signer)?))
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    context.record(AuditEntry::new(
+        AuditEventType::Audit,
+        &context.actor,
+        "audit_export",
+        "audit",
+        Some(serde_json::json!({ "from": from, "to": to, "bytes": archive.len() })),
+    ));
+
+    let disposition = format!("attachment; filename=\"pacai-audit-{}-{}.zip\"", from, to);
+    Ok((
+        [(header::CONTENT_TYPE, "application/zip".to_string()), (header::CONTENT_DISPOSITION, disposition)],
+        archive,
+    )
+        .into_response())
+}
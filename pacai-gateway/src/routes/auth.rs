@@ -0,0 +1,74 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use crate::audit::middleware::RequestContext;
+use crate::routes::ApiError;
+use crate::security::rate_limit::{self, RouteClass};
+use crate::security::rbac::RbacError;
+use crate::security::session::Principal;
+use crate::state::SharedState;
+use crate::util::logging;
+
+#[derive(Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize)]
+pub struct LoginResponse {
+    pub token: String,
+    pub token_type: &'static str,
+    pub expires_at: DateTime<Utc>,
+    pub principal: Principal,
+}
+
+/// Exchanges a username and password for a signed session token. Each
+/// address gets a few wrong passwords per account before it is throttled,
+/// fewer than it takes to lock the account.
+pub async fn login(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Json(payload): Json<LoginRequest>,
+) -> Result<Json<LoginResponse>, Response> {
+    let username = payload.username.trim().to_string();
+    let address = rate_limit::client_address(connect_info.as_ref());
+    let now = Utc::now();
+    let outcome = state.rate_limits.check_login(&username, &address, now);
+    if !outcome.allowed {
+        return Err(rate_limit::refusal(&outcome, RouteClass::Login, now));
+    }
+    let users = state.clone();
+    let id = username.clone();
+    let result = tokio::task::spawn_blocking(move || users.users.authenticate(&id, &payload.password))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()).into_response())?;
+
+    context.record(logging::log_auth(&username, "login", result.is_ok()));
+    if result.is_err() {
+        state.rate_limits.login_failed(&username, &address, now);
+    }
+    let user = result.map_err(|e| RbacError::Unauthenticated { reason: e.to_string() }.into_response())?;
+
+    let (token, principal) = state
+        .sessions
+        .issue(&user.id, user.roles, &user.tenant)
+        .map_err(|e| ApiError::Internal(e.to_string()).into_response())?;
+
+    Ok(Json(LoginResponse {
+        token,
+        token_type: "Bearer",
+        expires_at: principal.expiry,
+        principal,
+    }))
+}
+
+/// The principal behind the presented token.
+pub async fn session(principal: Principal) -> Json<Principal> {
+    Json(principal)
+}
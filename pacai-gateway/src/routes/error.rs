@@ -0,0 +1,146 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use crate::audit::AuditError;
+use crate::audit::export::ExportError;
+use crate::audit::middleware::FailureReason;
+use crate::engine::override_engine::OverrideError;
+use crate::jobs::JobError;
+use crate::live::LiveError;
+use crate::store::{StoreError, UserError};
+use crate::util::json::JsonError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+#[derive(Serialize)]
+pub struct ErrorBody {
+    pub error: String,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "not_found",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if let ApiError::Internal(message) = &self {
+            tracing::error!("Internal error: {}", message);
+        }
+
+        let message = self.to_string();
+        let body = ErrorBody {
+            error: self.code().into(),
+            message: message.clone(),
+        };
+        let mut response = (self.status(), Json(body)).into_response();
+        response.extensions_mut().insert(FailureReason(message));
+        response
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::NotFound { .. }
+            | StoreError::ZoneNotFound { .. }
+            | StoreError::VersionNotFound { .. }
+            | StoreError::ScheduleNotFound { .. } => ApiError::NotFound(err.to_string()),
+            StoreError::AlreadyExists { .. }
+            | StoreError::ProjectLimit { .. }
+            | StoreError::HeadMoved { .. }
+            | StoreError::ScheduleClosed { .. } => ApiError::Conflict(err.to_string()),
+            StoreError::Io(_) | StoreError::Serialization(_) => ApiError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl From<JsonError> for ApiError {
+    fn from(err: JsonError) -> Self {
+        ApiError::BadRequest(err.to_string())
+    }
+}
+
+impl From<OverrideError> for ApiError {
+    fn from(err: OverrideError) -> Self {
+        match err {
+            OverrideError::EntityNotFound(_) => ApiError::NotFound(err.to_string()),
+            _ => ApiError::BadRequest(err.to_string()),
+        }
+    }
+}
+
+impl From<LiveError> for ApiError {
+    fn from(err: LiveError) -> Self {
+        match err {
+            LiveError::AckAhead { .. } => ApiError::BadRequest(err.to_string()),
+            LiveError::Io(_) | LiveError::Serialization(_) => ApiError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl From<AuditError> for ApiError {
+    fn from(err: AuditError) -> Self {
+        ApiError::Internal(err.to_string())
+    }
+}
+
+impl From<ExportError> for ApiError {
+    fn from(err: ExportError) -> Self {
+        match err {
+            ExportError::EmptyRange { .. } => ApiError::NotFound(err.to_string()),
+            _ => ApiError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl From<UserError> for ApiError {
+    fn from(err: UserError) -> Self {
+        match err {
+            UserError::NotFound { .. } | UserError::KeyNotFound { .. } => ApiError::NotFound(err.to_string()),
+            UserError::AlreadyExists { .. } | UserError::LastAdmin => ApiError::Conflict(err.to_string()),
+            UserError::InvalidCredentials => ApiError::BadRequest(err.to_string()),
+            UserError::Io(_) | UserError::Serialization(_) => ApiError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl From<JobError> for ApiError {
+    fn from(err: JobError) -> Self {
+        match err {
+            JobError::NotFound { .. } => ApiError::NotFound(err.to_string()),
+            JobError::NotQueued { .. } => ApiError::Conflict(err.to_string()),
+        }
+    }
+}
@@ -1,7 +1,20 @@
-use axum::response::Json;
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Json, Response},
+};
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use uuid::Uuid;
-use crate::engine::packager;
+use crate::audit::middleware::RequestContext;
+use crate::engine::{packager, watermark::Watermark};
+use crate::routes::{project_for, ApiError};
+use crate::security::policy::permissions;
+use crate::security::quota;
+use crate::security::session::Principal;
+use crate::state::SharedState;
+use crate::store::{ExportRecord, Project};
+use crate::util::logging;
 
 #[derive(Deserialize)]
 pub struct ExportRequest {
@@ -20,6 +33,8 @@ pub struct ExportResponse {
     pub total_size_bytes: u64,
     pub download_url: String,
     pub expires_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Watermark>,
 }
 
 #[derive(Serialize)]
@@ -30,9 +45,36 @@ pub struct EngineExport {
     pub size_bytes: u64,
 }
 
-pub async fn export_bundle(Json(payload): Json<ExportRequest>) -> Json<ExportResponse> {
+/// The route only asks for `export` on some engine; every engine in the
+/// request must be granted on its own, under the name the policy lists.
+pub async fn export_bundle(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    Json(payload): Json<ExportRequest>,
+) -> Result<Json<ExportResponse>, Response> {
+    for engine in &payload.engines {
+        let format = match engine.to_lowercase().as_str() {
+            "unreal" => "ue5".to_string(),
+            other => other.to_string(),
+        };
+        principal.require(&permissions::export(&format)).map_err(IntoResponse::into_response)?;
+    }
+    build_export(&state, &context, &principal, payload).map(Json).map_err(IntoResponse::into_response)
+}
+
+fn build_export(
+    state: &SharedState,
+    context: &RequestContext,
+    principal: &Principal,
+    payload: ExportRequest,
+) -> Result<ExportResponse, ApiError> {
+    let project = project_for(state, principal, &payload.project_id)?;
+    let entities = project.zones.iter().filter_map(|z| z.head_version()).flat_map(|v| &v.world.entities);
+    let watermark = quota::watermark_for(state, principal, project.seed, entities)?;
     let id = Uuid::new_v4().to_string();
-    
+    package(state, &project, &id, &payload.engines, watermark.as_ref())?;
+
     let engines: Vec<EngineExport> = payload.engines.iter().map(|engine| {
         let bundle = packager::get_engine_bundle(engine);
         EngineExport {
@@ -45,7 +87,18 @@ pub async fn export_bundle(Json(payload): Json<ExportRequest>) -> Json<ExportRes
     
     let total_size: u64 = engines.iter().map(|e| e.size_bytes).sum();
     
-    Json(ExportResponse {
+    let record = ExportRecord {
+        id: id.clone(),
+        engines: payload.engines.clone(),
+        status: "completed".into(),
+        total_size_bytes: total_size,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        watermark: watermark.clone(),
+    };
+    state.projects.update(&payload.project_id, |p| p.exports.push(record))?;
+    context.record(logging::log_export(&context.actor, &payload.project_id, &payload.engines));
+
+    Ok(ExportResponse {
         id: id.clone(),
         project_id: payload.project_id,
         status: "completed".into(),
@@ -53,5 +106,98 @@ pub async fn export_bundle(Json(payload): Json<ExportRequest>) -> Json<ExportRes
         total_size_bytes: total_size,
         download_url: format!("/v5/export/{}/download", id),
         expires_at: (chrono::Utc::now() + chrono::Duration::hours(24)).to_rfc3339(),
+        watermark,
     })
 }
+
+fn bundle_path(state: &SharedState, id: &str) -> PathBuf {
+    state.config.exports_dir().join(format!("{}.zip", id))
+}
+
+/// Writes the bundle for export `id`: a `world.json` per engine, where that
+/// engine expects it, holding the head version of every zone. The packager
+/// signs it with the gateway key and stamps `watermark` into every file that
+/// can carry one and into the manifest.
+fn package(
+    state: &SharedState,
+    project: &Project,
+    id: &str,
+    engines: &[String],
+    watermark: Option<&Watermark>,
+) -> Result<(), ApiError> {
+    // Engine names become directories in the bundle.
+    if let Some(engine) = engines.iter().find(|e| e.is_empty() || !e.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')) {
+        return Err(ApiError::BadRequest(format!("Invalid engine name: {}", engine)));
+    }
+    let internal = |e: &dyn std::fmt::Display| ApiError::Internal(format!("Building export {}: {}", id, e));
+    let staging = state.config.exports_dir().join(id);
+    let heads: Vec<_> = project.zones.iter().filter_map(|z| Some((z, z.head_version()?))).collect();
+    let world = serde_json::json!({
+        "project_id": project.id,
+        "name": project.name,
+        "seed": project.seed,
+        "zones": heads.iter().map(|(zone, head)| serde_json::json!({
+            "zone_id": zone.zone_id,
+            "zone_type": zone.zone_type,
+            "version": head.version,
+            "checksum": head.checksum,
+            "world": head.world,
+        })).collect::<Vec<_>>(),
+        "entities": heads.iter().flat_map(|(_, head)| &head.world.entities).collect::<Vec<_>>(),
+        "metadata": { "generator": "PacAI" },
+    });
+    let world = serde_json::to_vec_pretty(&world).map_err(|e| internal(&e))?;
+
+    std::fs::create_dir_all(&staging).map_err(|e| internal(&e))?;
+    for engine in engines {
+        let bundle = packager::get_engine_bundle(engine);
+        let file = bundle.files.iter().find(|f| f.ends_with("world.json")).map_or("world.json", String::as_str);
+        let path = staging.join(engine.to_lowercase()).join(file);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| internal(&e))?;
+        }
+        std::fs::write(&path, &world).map_err(|e| internal(&e))?;
+    }
+
+    let signing_key = state.signer.signing_key().map_err(|e| internal(&e))?;
+    let exports: Vec<&str> = engines.iter().map(String::as_str).collect();
+    let built = packager::build_export_zone(
+        &staging,
+        &bundle_path(state, id),
+        &project.seed.to_string(),
+        &exports,
+        signing_key,
+        watermark,
+    );
+    if let Err(e) = std::fs::remove_dir_all(&staging) {
+        tracing::warn!("Could not remove export staging dir {}: {}", staging.display(), e);
+    }
+    built.map(|_| ()).map_err(|e| internal(&e))
+}
+
+/// `GET /v5/export/:id/download` — the zip built for an export of a project
+/// the caller can see.
+pub async fn download_bundle(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let not_found = || ApiError::NotFound(format!("Export not found: {}", id));
+    state
+        .projects
+        .list()
+        .iter()
+        .find(|p| p.exports.iter().any(|e| e.id == id))
+        .filter(|p| principal.sees_tenant(&p.tenant))
+        .ok_or_else(not_found)?;
+    let bytes = match std::fs::read(bundle_path(&state, &id)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(not_found()),
+        Err(e) => return Err(ApiError::Internal(e.to_string())),
+    };
+    let headers = [
+        (header::CONTENT_TYPE, "application/zip".to_string()),
+        (header::CONTENT_DISPOSITION, format!("attachment; filename=\"pacai-export-{}.zip\"", id)),
+    ];
+    Ok((headers, bytes).into_response())
+}
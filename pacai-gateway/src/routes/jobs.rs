@@ -0,0 +1,149 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::Value;
+use crate::audit::middleware::RequestContext;
+use crate::jobs::{JobInfo, JobStatus, NewJob, OnFinish};
+use crate::routes::ApiError;
+use crate::security::policy::permissions;
+use crate::security::quota;
+use crate::security::session::Principal;
+use crate::state::SharedState;
+use crate::store::Grant;
+use crate::util::logging::{AuditEntry, AuditEventType};
+
+/// Whether the client sent `Prefer: respond-async` (RFC 7240).
+fn respond_async(headers: &HeaderMap) -> bool {
+    headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split([',', ';']))
+        .any(|preference| preference.trim().eq_ignore_ascii_case("respond-async"))
+}
+
+/// Runs generation `work` through the job queue. A client that prefers an
+/// asynchronous response gets 202 and the job to poll; anyone else waits
+/// for the result as if the work ran in the handler. An accepted job keeps
+/// its quota `grant` unless it fails or is cancelled.
+///
+/// The work records its audit entry on the context it is given. A
+/// synchronous request hands that entry to the audit middleware as usual.
+/// Once a 202 has gone out the middleware is done, so the job appends the
+/// entry itself when it finishes, with its id and how it ended.
+pub async fn run(
+    state: &SharedState,
+    context: &RequestContext,
+    principal: &Principal,
+    headers: &HeaderMap,
+    grant: Option<Grant>,
+    kind: &str,
+    work: impl FnOnce(&RequestContext) -> Result<Value, ApiError> + Send + 'static,
+) -> Result<Response, ApiError> {
+    let asynchronous = respond_async(headers);
+    let on_abandon = grant.filter(|_| asynchronous).map(|grant| {
+        let state = state.clone();
+        Box::new(move || {
+            if let Err(e) = state.usage.release(&grant) {
+                tracing::error!("Failed to hand back quota of an abandoned job: {}", e);
+            }
+        }) as Box<dyn FnOnce() + Send>
+    });
+    let job = context.detached();
+    let on_finish = asynchronous.then(|| {
+        let (state, job) = (state.clone(), job.clone());
+        Box::new(move |info: &JobInfo| state.audit.record(job_entry(&job, info))) as OnFinish
+    });
+    let worker = job.clone();
+    let (info, reply) = state.jobs.submit(NewJob {
+        kind: kind.to_string(),
+        owner: principal.user_id.clone(),
+        tenant: principal.tenant.clone(),
+        tier: quota::tier_of(state, principal),
+        work: Box::new(move || work(&worker)),
+        on_abandon,
+        on_finish,
+    });
+
+    if asynchronous {
+        context.record(AuditEntry::new(
+            AuditEventType::Generate,
+            &context.actor,
+            "job_submit",
+            &info.id,
+            Some(serde_json::json!({ "kind": kind })),
+        ));
+        let headers = [
+            (header::LOCATION, format!("/v5/jobs/{}", info.id)),
+            (HeaderName::from_static("preference-applied"), "respond-async".to_string()),
+        ];
+        return Ok((StatusCode::ACCEPTED, headers, Json(info)).into_response());
+    }
+    let result = reply.await;
+    if let Some(entry) = job.take() {
+        context.record(entry);
+    }
+    match result {
+        Ok(result) => result.map(|value| Json(value).into_response()),
+        Err(_) => Err(ApiError::Conflict(format!("Job {} was cancelled", info.id))),
+    }
+}
+
+/// The audit entry of a job that was answered with 202, stamped with what
+/// the middleware would have added. A job cancelled before it ran has no
+/// entry of its own.
+fn job_entry(job: &RequestContext, info: &JobInfo) -> AuditEntry {
+    let mut entry = job
+        .take()
+        .unwrap_or_else(|| AuditEntry::new(AuditEventType::Generate, &job.actor, &info.kind, &info.id, None));
+    let mut details = match entry.details.take() {
+        Some(Value::Object(map)) => map,
+        Some(other) => serde_json::Map::from_iter([("value".to_string(), other)]),
+        None => serde_json::Map::new(),
+    };
+    details.insert("request_id".into(), job.request_id.clone().into());
+    details.insert("role".into(), job.role.clone().into());
+    details.insert("job_id".into(), info.id.clone().into());
+    details.insert("job_status".into(), info.status.as_str().into());
+    let outcome = if info.status == JobStatus::Completed { "success" } else { "failure" };
+    details.insert("outcome".into(), outcome.into());
+    if let Some(error) = &info.error {
+        details.insert("error".into(), error.clone().into());
+    }
+    entry.details = Some(details.into());
+    entry
+}
+
+/// A job is visible to its owner; anyone else must be in the same tenant
+/// and hold `permission`. Jobs the caller may not see are reported as
+/// missing.
+fn authorize(principal: &Principal, info: &JobInfo, permission: &str) -> Result<(), ApiError> {
+    let owner = info.owner == principal.user_id;
+    if owner || (info.tenant == principal.tenant && principal.require(permission).is_ok()) {
+        Ok(())
+    } else {
+        Err(ApiError::NotFound(format!("Job not found: {}", info.id)))
+    }
+}
+
+pub async fn get_job(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Json<JobInfo>, ApiError> {
+    let info = state.jobs.get(&id).ok_or_else(|| ApiError::NotFound(format!("Job not found: {}", id)))?;
+    authorize(&principal, &info, permissions::PROJECTS_READ)?;
+    Ok(Json(info))
+}
+
+pub async fn cancel_job(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Json<JobInfo>, ApiError> {
+    let info = state.jobs.get(&id).ok_or_else(|| ApiError::NotFound(format!("Job not found: {}", id)))?;
+    authorize(&principal, &info, permissions::PROJECTS_UPDATE)?;
+    Ok(Json(state.jobs.cancel(&id)?))
+}
@@ -1,38 +1,64 @@
-use axum::response::Json;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
 use serde::Serialize;
+use crate::audit::middleware::{FailureReason, RequestContext};
 use crate::security::hsm;
+use crate::state::SharedState;
+use crate::util::logging;
 
 #[derive(Serialize)]
 pub struct LicenseResponse {
     pub valid: bool,
+    pub license_id: Option<String>,
     pub tier: String,
     pub expiry: u64,
     pub hardware_id: String,
     pub seats_used: u32,
     pub seats_total: u32,
     pub features: Vec<String>,
-    pub offline_grace_days: u32,
     pub last_validation: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
-pub async fn license_check() -> Json<LicenseResponse> {
-    let hsm_response = hsm::verify_license();
-    
-    Json(LicenseResponse {
-        valid: hsm_response.valid,
-        tier: hsm_response.tier,
-        expiry: hsm_response.expiry,
-        hardware_id: hsm_response.hardware_id,
-        seats_used: 1,
-        seats_total: 10,
-        features: vec![
-            "unlimited_generations".into(),
-            "priority_support".into(),
-            "multi_engine_export".into(),
-            "custom_behaviors".into(),
-            "team_collaboration".into(),
-        ],
-        offline_grace_days: 30,
+/// Reports the license loaded at startup. Without a valid one the gateway
+/// is reported unlicensed with no seats or features, under 423 as in the
+/// v4 contract.
+pub async fn license_check(State(state): State<SharedState>, context: RequestContext) -> Response {
+    let seats_used = state.users.list().iter().filter(|u| !u.disabled).count() as u32;
+    let mut response = LicenseResponse {
+        valid: false,
+        license_id: None,
+        tier: "unlicensed".into(),
+        expiry: 0,
+        hardware_id: hsm::get_hardware_id(),
+        seats_used,
+        seats_total: 0,
+        features: Vec::new(),
         last_validation: chrono::Utc::now().to_rfc3339(),
-    })
+        error: None,
+    };
+
+    match state.license.current() {
+        Ok(license) => {
+            context.record(logging::log_license(&context.actor, "license_check", &license.tier));
+            response.valid = true;
+            response.license_id = Some(license.license_id.clone());
+            response.tier = license.tier.clone();
+            response.expiry = license.expiry_timestamp;
+            response.hardware_id = license.hardware_id.clone();
+            response.seats_total = license.seats;
+            response.features = license.features.clone();
+            Json(response).into_response()
+        }
+        Err(e) => {
+            response.error = Some(e.to_string());
+            let mut response = (StatusCode::LOCKED, Json(response)).into_response();
+            response.extensions_mut().insert(FailureReason(e.to_string()));
+            response
+        }
+    }
 }
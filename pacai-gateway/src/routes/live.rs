@@ -0,0 +1,155 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    response::Response,
+};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use crate::live::LiveEvent;
+use crate::routes::{project_for, ApiError};
+use crate::security::session::Principal;
+use crate::state::SharedState;
+
+#[derive(Deserialize)]
+pub struct LiveQuery {
+    pub client_id: Option<String>,
+    pub last_seq: Option<u64>,
+}
+
+/// Frames sent to engines. Every `event` carries the project sequence
+/// number; `resync` means events were missed and the engine should reload
+/// the zone heads before applying what follows.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerFrame {
+    Hello { project_id: String, client_id: String, head_seq: u64, resume_from: u64 },
+    Event(LiveEvent),
+    Resync { from_seq: u64 },
+    Ack { seq: u64 },
+    Pong,
+    Error { message: String },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    Ack { seq: u64 },
+    Ping,
+}
+
+/// `GET /v5/projects/:id/live` — upgrades to a WebSocket session. Without
+/// `last_seq` a known `client_id` resumes from its last ack, and a new
+/// client starts at the current head. Acks belong to the caller, so another
+/// user naming the same `client_id` does not pick them up.
+pub async fn live_session(
+    ws: WebSocketUpgrade,
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path(project_id): Path<String>,
+    Query(query): Query<LiveQuery>,
+) -> Result<Response, ApiError> {
+    project_for(&state, &principal, &project_id)?;
+
+    let client_id = query.client_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let resume_from = match query.last_seq {
+        Some(seq) => seq,
+        None => match state.live.acked(&project_id, &principal.user_id, &client_id)? {
+            Some(seq) => seq,
+            None => state.live.head(&project_id)?,
+        },
+    };
+
+    Ok(ws.on_upgrade(move |socket| async move {
+        tracing::info!("Live session {} joined project {} at seq {}", client_id, project_id, resume_from);
+        if let Err(e) = run_session(&state, &project_id, &principal.user_id, &client_id, resume_from, socket).await {
+            tracing::warn!("Live session {} on {} ended: {}", client_id, project_id, e);
+        } else {
+            tracing::info!("Live session {} left project {}", client_id, project_id);
+        }
+    }))
+}
+
+async fn send(socket: &mut futures::stream::SplitSink<WebSocket, Message>, frame: &ServerFrame) -> Result<(), ApiError> {
+    let text = serde_json::to_string(frame).map_err(|e| ApiError::Internal(e.to_string()))?;
+    socket
+        .send(Message::Text(text))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))
+}
+
+async fn run_session(
+    state: &SharedState,
+    project_id: &str,
+    user_id: &str,
+    client_id: &str,
+    resume_from: u64,
+    socket: WebSocket,
+) -> Result<(), ApiError> {
+    let (mut sink, mut stream) = socket.split();
+    let mut subscription = state.live.subscribe(project_id, resume_from)?;
+
+    send(&mut sink, &ServerFrame::Hello {
+        project_id: project_id.to_string(),
+        client_id: client_id.to_string(),
+        head_seq: subscription.head,
+        resume_from,
+    }).await?;
+
+    let mut last_sent = resume_from;
+    let replay = std::mem::take(&mut subscription.replay);
+    if subscription.gap {
+        send(&mut sink, &ServerFrame::Resync { from_seq: replay.first().map(|e| e.seq).unwrap_or(subscription.head) }).await?;
+    }
+    for event in replay {
+        last_sent = event.seq;
+        send(&mut sink, &ServerFrame::Event(event)).await?;
+    }
+
+    loop {
+        tokio::select! {
+            incoming = stream.next() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(ApiError::Internal(e.to_string())),
+                };
+
+                let reply = match serde_json::from_str::<ClientFrame>(&text) {
+                    Ok(ClientFrame::Ack { seq }) => match state.live.ack(project_id, user_id, client_id, seq) {
+                        Ok(seq) => ServerFrame::Ack { seq },
+                        Err(e) => ServerFrame::Error { message: e.to_string() },
+                    },
+                    Ok(ClientFrame::Ping) => ServerFrame::Pong,
+                    Err(e) => ServerFrame::Error { message: format!("Invalid frame: {}", e) },
+                };
+                send(&mut sink, &reply).await?;
+            }
+            received = subscription.receiver.recv() => match received {
+                Ok(event) if event.seq <= last_sent => {}
+                Ok(event) => {
+                    last_sent = event.seq;
+                    send(&mut sink, &ServerFrame::Event(event)).await?;
+                }
+                // Too slow for the broadcast buffer: catch up from the backlog.
+                Err(RecvError::Lagged(_)) => {
+                    let (events, gap) = state.live.since(project_id, last_sent)?;
+                    if gap {
+                        send(&mut sink, &ServerFrame::Resync { from_seq: events.first().map(|e| e.seq).unwrap_or(last_sent) }).await?;
+                    }
+                    for event in events {
+                        last_sent = event.seq;
+                        send(&mut sink, &ServerFrame::Event(event)).await?;
+                    }
+                }
+                Err(RecvError::Closed) => {
+                    let _ = sink.send(Message::Close(None)).await;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
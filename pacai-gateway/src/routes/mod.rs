@@ -1,5 +1,932 @@
+pub mod audit;
+pub mod auth;
 pub mod health;
+pub mod jobs;
 pub mod license;
 pub mod prompt;
 pub mod override_route;
+pub mod policy;
 pub mod export;
+pub mod zones;
+pub mod live;
+pub mod simulation;
+pub mod usage;
+pub mod users;
+pub mod error;
+
+use axum::{Router, middleware, routing::{delete, get, patch, post, put}};
+use crate::audit::middleware::audit_requests;
+use crate::security::{license as licensing, policy::permissions, quota, rate_limit::RateLimitLayer, rbac, session};
+use crate::security::session::Principal;
+use crate::state::SharedState;
+use crate::store::{Project, StoreError};
+
+pub use error::ApiError;
+
+/// The v5 API. Every route outside the public group sits behind the rbac
+/// middleware for the permission it needs; a path served by several groups
+/// is merged back into a single route. Session tokens are verified before
+/// anything else runs, so the audit entry already knows who the caller is
+/// and the rate limiter whose bucket to draw from.
+pub fn router(state: SharedState) -> Router {
+    Router::new()
+        .route("/health", get(health::health))
+        .route("/v5/license", get(license::license_check))
+        .route("/v5/auth/login", post(auth::login))
+        .route("/v5/auth/session", get(auth::session))
+        .route("/v5/rbac/explain", get(policy::explain))
+        .route("/v5/usage", get(usage::usage))
+        .route("/v5/jobs/:id", get(jobs::get_job).delete(jobs::cancel_job))
+        .merge(generate_routes(&state))
+        .merge(override_routes(&state))
+        .merge(export_routes(&state))
+        .merge(project_routes())
+        .merge(audit_routes())
+        .merge(user_routes())
+        .layer(RateLimitLayer::new(state.clone()))
+        .layer(middleware::from_fn_with_state(state.clone(), audit_requests))
+        .layer(middleware::from_fn_with_state(state.clone(), session::authenticate))
+        .with_state(state)
+}
+
+/// Quota layers sit inside the rbac and license checks, so a caller who is
+/// refused either way has no usage counted.
+fn generate_routes(state: &SharedState) -> Router<SharedState> {
+    Router::new()
+        .route("/v5/prompt", post(prompt::handle_prompt))
+        .route("/v5/projects/:id/generate", post(prompt::generate_zone))
+        .route_layer(middleware::from_fn_with_state(state.clone(), quota::require_generation))
+        .route_layer(middleware::from_fn_with_state(state.clone(), licensing::require))
+        .route_layer(middleware::from_fn(rbac::require_generate))
+}
+
+fn override_routes(state: &SharedState) -> Router<SharedState> {
+    Router::new()
+        .route("/v5/override", post(override_route::apply_override))
+        .route(
+            "/v5/projects/:id/overrides/scheduled/:schedule_id",
+            delete(override_route::cancel_scheduled),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), licensing::require))
+        .route_layer(middleware::from_fn(rbac::require_override))
+}
+
+fn export_routes(state: &SharedState) -> Router<SharedState> {
+    // Fetching a bundle that was already built spends no quota.
+    let download = Router::new()
+        .route("/v5/export/:id/download", get(export::download_bundle))
+        .route_layer(middleware::from_fn_with_state(state.clone(), licensing::require))
+        .route_layer(middleware::from_fn(rbac::require_export));
+
+    Router::new()
+        .route("/v5/export", post(export::export_bundle))
+        .route_layer(middleware::from_fn_with_state(state.clone(), quota::require_export))
+        .route_layer(middleware::from_fn_with_state(state.clone(), licensing::require))
+        .route_layer(middleware::from_fn(rbac::require_export))
+        .merge(download)
+}
+
+fn project_routes() -> Router<SharedState> {
+    let read = Router::new()
+        .route("/v5/projects", get(prompt::list_projects))
+        .route("/v5/projects/:id", get(prompt::get_project))
+        .route_layer(middleware::from_fn_with_state(permissions::PROJECTS_READ, rbac::require));
+
+    // Zones, their history and the live session are what the policy calls
+    // scenarios.
+    let scenarios = Router::new()
+        .route("/v5/projects/:id/zones", get(zones::list_zones))
+        .route("/v5/projects/:id/zones/:zone_id/versions", get(zones::zone_history))
+        .route("/v5/projects/:id/zones/:zone_id/versions/:version", get(zones::get_zone_version))
+        .route("/v5/projects/:id/zones/:zone_id/diff", get(zones::diff_zone))
+        // A simulation is a preview and writes nothing back.
+        .route("/v5/projects/:id/zones/:zone_id/simulate", post(simulation::simulate_zone))
+        .route("/v5/projects/:id/live", get(live::live_session))
+        .route("/v5/projects/:id/overrides/scheduled", get(override_route::list_scheduled))
+        .route("/v5/projects/:id/overrides/scheduled/:schedule_id", get(override_route::get_scheduled))
+        .route_layer(middleware::from_fn_with_state(permissions::SCENARIOS_READ, rbac::require));
+
+    // The project cap is checked by the store as the project is created.
+    let create = Router::new()
+        .route("/v5/projects", post(prompt::create_project))
+        .route_layer(middleware::from_fn_with_state(permissions::PROJECTS_CREATE, rbac::require));
+
+    let update = Router::new()
+        .route("/v5/projects/:id", patch(prompt::update_project))
+        .route("/v5/projects/:id/zones/:zone_id/rollback", post(zones::rollback_zone))
+        .route_layer(middleware::from_fn_with_state(permissions::PROJECTS_UPDATE, rbac::require));
+
+    let remove = Router::new()
+        .route("/v5/projects/:id", delete(prompt::delete_project))
+        .route_layer(middleware::from_fn_with_state(permissions::PROJECTS_DELETE, rbac::require));
+
+    read.merge(scenarios).merge(create).merge(update).merge(remove)
+}
+
+/// The project `id`, if `principal` may see it. Every route that takes a
+/// project id looks it up here; another tenant's project is reported as
+/// missing, so its id is not even confirmed.
+pub fn project_for(state: &SharedState, principal: &Principal, id: &str) -> Result<Project, ApiError> {
+    let project = state.projects.get(id)?;
+    if !principal.sees_tenant(&project.tenant) {
+        return Err(StoreError::NotFound { id: id.to_string() }.into());
+    }
+    Ok(project)
+}
+
+fn audit_routes() -> Router<SharedState> {
+    Router::new()
+        .route("/v5/audit", get(audit::audit_stream))
+        .route("/v5/audit/verify", get(audit::verify_audit))
+        .route("/v5/audit/export", get(audit::export_audit))
+        .route("/v5/audit/events", get(audit::list_events))
+        .route("/v5/audit/events/stats", get(audit::event_stats))
+        .route("/v5/audit/events/auth-failures", get(audit::auth_failure_summary))
+        .route_layer(middleware::from_fn(rbac::require_audit_read))
+}
+
+/// Role changes also need `roles:assign`, which the handlers check. Setting
+/// a password or minting a key hands over the account, and with it every role
+/// the target holds, so those routes need `roles:assign` as well.
+fn user_routes() -> Router<SharedState> {
+    let list = Router::new()
+        .route("/v5/admin/users", get(users::list_users))
+        .route("/v5/admin/users/:id", get(users::get_user))
+        .route_layer(middleware::from_fn_with_state(permissions::USERS_LIST, rbac::require));
+
+    let create = Router::new()
+        .route("/v5/admin/users", post(users::create_user))
+        .route_layer(middleware::from_fn_with_state(permissions::USERS_CREATE, rbac::require));
+
+    let modify = Router::new()
+        .route("/v5/admin/users/:id", patch(users::update_user))
+        .route("/v5/admin/users/:id/lock", post(users::lock_user))
+        .route("/v5/admin/users/:id/unlock", post(users::unlock_user))
+        .route("/v5/admin/users/:id/keys/:key_id", delete(users::revoke_key))
+        .route_layer(middleware::from_fn_with_state(permissions::USERS_MODIFY, rbac::require));
+
+    let credentials = Router::new()
+        .route("/v5/admin/users/:id/password", put(users::set_password))
+        .route("/v5/admin/users/:id/keys", post(users::issue_key))
+        .route_layer(middleware::from_fn_with_state(permissions::USERS_MODIFY, rbac::require))
+        .route_layer(middleware::from_fn_with_state(permissions::ROLES_ASSIGN, rbac::require));
+
+    let remove = Router::new()
+        .route("/v5/admin/users/:id", delete(users::delete_user))
+        .route_layer(middleware::from_fn_with_state(permissions::USERS_DELETE, rbac::require));
+
+    list.merge(create).merge(modify).merge(credentials).merge(remove)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+    use crate::config::GatewayConfig;
+    use crate::security::hsm::{self, HsmManager, SignedLicense};
+    use crate::security::policy;
+    use crate::state::AppState;
+    use crate::store::{Project, User};
+    use crate::util::logging::AuditEventType;
+
+    /// One request per route group, with the permission it needs.
+    const CASES: &[(&str, &str, &str, &str)] = &[
+        ("POST", "/v5/prompt", r#"{"prompt":"ridge","seed":1}"#, "generate"),
+        ("POST", "/v5/projects/nope/generate", r#"{"zone_type":"forest"}"#, "generate"),
+        ("POST", "/v5/override", r#"{"project_id":"nope","target_type":"npc","behavior":"idle"}"#, "control:apply"),
+        ("DELETE", "/v5/projects/nope/overrides/scheduled/s1", "", "control:apply"),
+        ("GET", "/v5/projects/nope/overrides/scheduled/s1", "", "scenarios:read"),
+        ("POST", "/v5/export", r#"{"project_id":"nope","engines":["ue5"]}"#, "export:ue5"),
+        ("GET", "/v5/projects", "", "projects:read"),
+        ("GET", "/v5/projects/nope/zones", "", "scenarios:read"),
+        ("POST", "/v5/projects", r#"{"name":"rbac","tier":"creator"}"#, "projects:create"),
+        ("PATCH", "/v5/projects/nope", r#"{"name":"renamed"}"#, "projects:update"),
+        ("DELETE", "/v5/projects/nope", "", "projects:delete"),
+        ("GET", "/v5/audit/verify", "", "audit:read"),
+        ("GET", "/v5/audit/events", "", "audit:read"),
+        ("GET", "/v5/admin/users", "", "users:list"),
+        ("POST", "/v5/admin/users", r#"{"id":"nope","roles":["auditor"]}"#, "users:create"),
+        ("PATCH", "/v5/admin/users/nope", r#"{"disabled":true}"#, "users:modify"),
+        ("DELETE", "/v5/admin/users/nope", "", "users:delete"),
+        ("PUT", "/v5/admin/users/nope/password", r#"{"password":"correct horse battery"}"#, "roles:assign"),
+        ("POST", "/v5/admin/users/nope/keys", "", "roles:assign"),
+    ];
+
+    fn request(method: &str, uri: &str, body: &str, token: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json");
+        if let Some(token) = token {
+            builder = builder.header("authorization", format!("Bearer {}", token));
+        }
+        builder.body(Body::from(body.to_string())).unwrap()
+    }
+
+    async fn body_json(response: axum::response::Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    /// A config trusting a test vendor key, with a license from that vendor
+    /// for this machine in the data dir.
+    fn licensed_config(dir: &std::path::Path) -> (GatewayConfig, SignedLicense) {
+        let vendor = HsmManager::ephemeral("vendor");
+        let license = vendor.create_license("enterprise", &hsm::get_hardware_id(), 30, 25, vec!["cluster_mode".into()]).unwrap();
+        let mut config = GatewayConfig::new(dir);
+        std::fs::write(&config.license_path, serde_json::to_vec(&license).unwrap()).unwrap();
+        config.license_vendor_key = vendor.verifying_key().unwrap();
+        (config, license)
+    }
+
+    #[tokio::test]
+    async fn test_every_role_gets_exactly_its_permissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::open(licensed_config(dir.path()).0).unwrap();
+        let app = router(state.clone());
+
+        for role in rbac::get_all_roles() {
+            let tester = format!("{}-tester", role);
+            state.users.create(User::new(&tester, vec![role.to_string()], "default")).unwrap();
+            let (token, _) = state.sessions.issue(&tester, vec![role.to_string()], "default").unwrap();
+            for (method, uri, body, permission) in CASES {
+                let response = app.clone().oneshot(request(method, uri, body, Some(&token))).await.unwrap();
+                let allowed = rbac::can(&role, permission);
+                assert_eq!(
+                    response.status() == StatusCode::FORBIDDEN,
+                    !allowed,
+                    "{} {} as {} returned {}",
+                    method, uri, role, response.status()
+                );
+                if !allowed {
+                    let body = body_json(response).await;
+                    assert_eq!(body["error"], "forbidden");
+                    // The route may refuse the whole action before the
+                    // handler gets to the resource.
+                    assert!(policy::covers(body["permission"].as_str().unwrap(), permission), "{}", body);
+                    assert_eq!(body["role"], role);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_projects_are_invisible_across_tenants() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::open(licensed_config(dir.path()).0).unwrap();
+        let app = router(state.clone());
+        let mut tokens = Vec::new();
+        for (user, tenant) in [("north-lead", "north"), ("south-lead", "south")] {
+            state.users.create(User::new(user, vec!["instructor".into()], tenant)).unwrap();
+            tokens.push(state.sessions.issue(user, vec!["instructor".into()], tenant).unwrap().0);
+        }
+        let (north, south) = (&tokens[0], &tokens[1]);
+
+        let body = r#"{"name":"Harbor","tier":"creator","seed":7}"#;
+        let response = app.clone().oneshot(request("POST", "/v5/projects", body, Some(north))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let id = body_json(response).await["id"].as_str().unwrap().to_string();
+        assert_eq!(state.projects.get(&id).unwrap().tenant, "north");
+        let response = app.clone().oneshot(request("POST", &format!("/v5/projects/{}/generate", id), r#"{"zone_type":"forest"}"#, Some(north))).await.unwrap();
+        let zone_id = body_json(response).await["zone_id"].as_str().unwrap().to_string();
+
+        let list = |token: &str| request("GET", "/v5/projects", "", Some(token));
+        let listed = body_json(app.clone().oneshot(list(north)).await.unwrap()).await;
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        let listed = body_json(app.clone().oneshot(list(south)).await.unwrap()).await;
+        assert_eq!(listed, serde_json::json!([]));
+
+        let override_body = format!(r#"{{"project_id":"{}","target_type":"npc","behavior":"idle"}}"#, id);
+        let export_body = format!(r#"{{"project_id":"{}","engines":["ue5"]}}"#, id);
+        let prompt_body = format!(r#"{{"prompt":"ridge","project_id":"{}"}}"#, id);
+        let cases = [
+            ("POST", "/v5/prompt".to_string(), prompt_body),
+            ("GET", format!("/v5/projects/{}", id), String::new()),
+            ("PATCH", format!("/v5/projects/{}", id), r#"{"name":"Taken"}"#.to_string()),
+            ("POST", format!("/v5/projects/{}/generate", id), r#"{"zone_type":"forest"}"#.to_string()),
+            ("GET", format!("/v5/projects/{}/zones", id), String::new()),
+            ("GET", format!("/v5/projects/{}/zones/{}/versions", id, zone_id), String::new()),
+            ("POST", format!("/v5/projects/{}/zones/{}/simulate", id, zone_id), r#"{"ticks":5}"#.to_string()),
+            ("GET", format!("/v5/projects/{}/overrides/scheduled", id), String::new()),
+            ("POST", "/v5/override".to_string(), override_body),
+            ("POST", "/v5/export".to_string(), export_body),
+        ];
+        for (method, uri, body) in &cases {
+            let response = app.clone().oneshot(request(method, uri, body, Some(south))).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{} {}", method, uri);
+        }
+        assert_eq!(state.projects.get(&id).unwrap().name, "Harbor");
+
+        // The owning tenant and global admins still reach it.
+        let (admin, _) = state.sessions.issue("admin", vec!["admin".into()], "default").unwrap();
+        for token in [north.as_str(), admin.as_str()] {
+            let response = app.clone().oneshot(request("GET", &format!("/v5/projects/{}", id), "", Some(token))).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_user_admin_stays_inside_the_tenant() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::open(licensed_config(dir.path()).0).unwrap();
+        let app = router(state.clone());
+        for (user, tenant) in [("north-lead", "north"), ("south-lead", "south")] {
+            state.users.create(User::new(user, vec!["instructor".into()], tenant)).unwrap();
+        }
+        let (north, _) = state.sessions.issue("north-lead", vec!["instructor".into()], "north").unwrap();
+        let (admin, _) = state.sessions.issue("admin", vec!["admin".into()], "default").unwrap();
+
+        let ids = |listed: serde_json::Value| -> Vec<String> {
+            listed.as_array().unwrap().iter().map(|u| u["id"].as_str().unwrap().to_string()).collect()
+        };
+        let listed = body_json(app.clone().oneshot(request("GET", "/v5/admin/users", "", Some(&north))).await.unwrap()).await;
+        assert_eq!(ids(listed), ["north-lead"]);
+        let response = app.clone().oneshot(request("GET", "/v5/admin/users/south-lead", "", Some(&north))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = app.clone().oneshot(request("GET", "/v5/admin/users/north-lead", "", Some(&north))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Global admins see every tenant, and new accounts default to their own.
+        let listed = ids(body_json(app.clone().oneshot(request("GET", "/v5/admin/users", "", Some(&admin))).await.unwrap()).await);
+        assert!(listed.contains(&"north-lead".to_string()) && listed.contains(&"south-lead".to_string()));
+        let body = r#"{"id":"erin","roles":["auditor"]}"#;
+        let response = app.clone().oneshot(request("POST", "/v5/admin/users", body, Some(&admin))).await.unwrap();
+        assert_eq!(body_json(response).await["tenant"], "default");
+        let response = app.clone().oneshot(request("POST", "/v5/admin/users/south-lead/lock", r#"{"minutes":5}"#, Some(&admin))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_requests_without_a_valid_session_are_unauthenticated() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = router(AppState::open(GatewayConfig::new(dir.path())).unwrap());
+
+        let response = app.clone().oneshot(request("GET", "/v5/projects", "", None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(body_json(response).await["error"], "unauthenticated");
+
+        // The old role header grants nothing.
+        let forged = Request::builder()
+            .uri("/v5/audit/events")
+            .header("x-pacai-role", "admin")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(app.clone().oneshot(forged).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        let response = app.clone().oneshot(request("GET", "/v5/projects", "", Some("pacai1.e30.AAAA"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app.oneshot(request("GET", "/health", "", None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_login_issues_a_token_carrying_the_users_roles() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = GatewayConfig::new(dir.path());
+        config.bootstrap_admin_password = Some("correct horse".into());
+        let app = router(AppState::open(config).unwrap());
+
+        let wrong = request("POST", "/v5/auth/login", r#"{"username":"admin","password":"nope"}"#, None);
+        assert_eq!(app.clone().oneshot(wrong).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        let login = request("POST", "/v5/auth/login", r#"{"username":"admin","password":"correct horse"}"#, None);
+        let body = body_json(app.clone().oneshot(login).await.unwrap()).await;
+        assert_eq!(body["principal"]["roles"], serde_json::json!(["admin"]));
+        let token = body["token"].as_str().unwrap();
+
+        let response = app.oneshot(request("GET", "/v5/auth/session", "", Some(token))).await.unwrap();
+        assert_eq!(body_json(response).await["user_id"], "admin");
+    }
+
+    #[tokio::test]
+    async fn test_failed_logins_are_throttled_before_the_account_locks() {
+        use crate::security::rate_limit::LOGIN_FAILURES_PER_HOUR;
+        use crate::store::users::MAX_FAILED_LOGINS;
+        use axum::extract::ConnectInfo;
+        use std::net::SocketAddr;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = GatewayConfig::new(dir.path());
+        config.bootstrap_admin_password = Some("correct horse".into());
+        let state = AppState::open(config).unwrap();
+        let app = router(state.clone());
+        let login = |password: &str, from: [u8; 4]| {
+            let body = format!(r#"{{"username":"admin","password":"{}"}}"#, password);
+            let mut request = request("POST", "/v5/auth/login", &body, None);
+            request.extensions_mut().insert(ConnectInfo(SocketAddr::from((from, 40000))));
+            request
+        };
+        let attacker = [203, 0, 113, 9];
+
+        for _ in 0..LOGIN_FAILURES_PER_HOUR {
+            let response = app.clone().oneshot(login("nope", attacker)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert!(response.headers().contains_key("ratelimit-remaining"));
+        }
+        for password in ["nope", "correct horse"] {
+            let response = app.clone().oneshot(login(password, attacker)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(body_json(response).await["error"], "rate_limited");
+        }
+
+        // The account never reached the lockout, so the admin still gets in.
+        let admin = state.users.get("admin").unwrap();
+        assert!(admin.failed_logins < MAX_FAILED_LOGINS && admin.locked_until.is_none());
+        let response = app.oneshot(login("correct horse", [192, 0, 2, 1])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_explain_names_the_deciding_rule() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::open(GatewayConfig::new(dir.path())).unwrap();
+        let app = router(state.clone());
+        let (token, _) = state.sessions.issue("admin", vec!["admin".into()], "default").unwrap();
+
+        let uri = "/v5/rbac/explain?role=creator&permission=export:ue5";
+        let body = body_json(app.clone().oneshot(request("GET", uri, "", Some(&token))).await.unwrap()).await;
+        assert_eq!(body["allowed"], true);
+        assert_eq!(body["effective_role"], "creator");
+        assert_eq!(body["capability"], "export:*");
+        assert_eq!(body["rule"], "roles.creator.capabilities[1]");
+        assert_eq!(body["policy_source"], "built-in");
+
+        let uri = "/v5/rbac/explain?role=auditor&permission=control:apply";
+        let body = body_json(app.clone().oneshot(request("GET", uri, "", Some(&token))).await.unwrap()).await;
+        assert_eq!(body["allowed"], false);
+        assert_eq!(body["rule"], "default_deny");
+        assert_eq!(body["constraints"]["read_only"], true);
+
+        let anonymous = app.oneshot(request("GET", "/v5/rbac/explain?role=admin&permission=x", "", None)).await.unwrap();
+        assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_tier_quotas_refuse_with_reset_time_and_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::open(licensed_config(dir.path()).0).unwrap();
+        let app = router(state.clone());
+        let mut dana = User::new("dana", vec!["instructor".into()], "studio");
+        dana.tier = Some("demo".into());
+        state.users.create(dana).unwrap();
+        let (token, _) = state.sessions.issue("dana", vec!["instructor".into()], "studio").unwrap();
+        let send = |method: &str, uri: &str, body: &str| app.clone().oneshot(request(method, uri, body, Some(&token)));
+
+        // A request that fails does not use up the quota.
+        let failed = send("POST", "/v5/projects/nope/generate", r#"{"zone_type":"forest"}"#).await.unwrap();
+        assert_eq!(failed.status(), StatusCode::NOT_FOUND);
+        for seed in 1..=2 {
+            let body = format!(r#"{{"prompt":"ridge","seed":{}}}"#, seed);
+            assert_eq!(send("POST", "/v5/prompt", &body).await.unwrap().status(), StatusCode::OK);
+        }
+        let refused = send("POST", "/v5/prompt", r#"{"prompt":"ridge","seed":3}"#).await.unwrap();
+        assert_eq!(refused.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(refused.headers().contains_key("retry-after"));
+        let body = body_json(refused).await;
+        assert_eq!(body["error"], "tier_limit_exceeded");
+        assert!(body["reset_at"].is_string());
+
+        for name in ["a", "b", "c"] {
+            let created = send("POST", "/v5/projects", &format!(r#"{{"name":"{}","tier":"demo"}}"#, name));
+            assert_eq!(created.await.unwrap().status(), StatusCode::CREATED);
+        }
+        let fourth = send("POST", "/v5/projects", r#"{"name":"d","tier":"demo"}"#).await.unwrap();
+        assert_eq!(fourth.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let usage = body_json(send("GET", "/v5/usage", "").await.unwrap()).await;
+        assert_eq!(usage["tier"], "demo");
+        assert_eq!(usage["generations"]["used"], 2);
+        assert_eq!(usage["generations"]["remaining"], 0);
+        assert_eq!(usage["generations"]["window_seconds"], 7 * 24 * 3600);
+        assert_eq!(usage["exports"]["limit"], 1);
+        assert_eq!(usage["projects"]["used"], 3);
+        assert_eq!(usage["tenant_usage"]["generations"], 2);
+
+        let (admin, _) = state.sessions.issue("admin", vec!["admin".into()], "default").unwrap();
+        let response = app.clone().oneshot(request("GET", "/v5/usage?user=dana", "", Some(&admin))).await.unwrap();
+        assert_eq!(body_json(response).await["generations"]["used"], 2);
+        // Other users are only visible from their own tenant.
+        for (id, tenant, status) in [("erin", "elsewhere", StatusCode::NOT_FOUND), ("finn", "studio", StatusCode::OK)] {
+            state.users.create(User::new(id, vec!["instructor".into()], tenant)).unwrap();
+            let (token, _) = state.sessions.issue(id, vec!["instructor".into()], tenant).unwrap();
+            let response = app.clone().oneshot(request("GET", "/v5/usage?user=dana", "", Some(&token))).await.unwrap();
+            assert_eq!(response.status(), status);
+        }
+        let own = body_json(app.oneshot(request("GET", "/v5/usage", "", Some(&admin))).await.unwrap()).await;
+        assert_eq!(own["generations"]["limit"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_demo_output_carries_a_signed_watermark() {
+        use crate::engine::watermark::{content_hash, Watermark};
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::open(licensed_config(dir.path()).0).unwrap();
+        let app = router(state.clone());
+        let mut dana = User::new("dana", vec!["instructor".into()], "studio");
+        dana.tier = Some("demo".into());
+        state.users.create(dana).unwrap();
+        let (token, _) = state.sessions.issue("dana", vec!["instructor".into()], "studio").unwrap();
+        let (admin, _) = state.sessions.issue("admin", vec!["admin".into()], "default").unwrap();
+        let send = |uri: &str, body: &str, token: &str| app.clone().oneshot(request("POST", uri, body, Some(token)));
+        let key = state.signer.verifying_key().unwrap();
+
+        let generated = body_json(send("/v5/prompt", r#"{"prompt":"ridge","seed":4}"#, &token).await.unwrap()).await;
+        let mark: Watermark = serde_json::from_value(generated["watermark"].clone()).unwrap();
+        assert!(mark.verify(&key));
+        assert_eq!((mark.tier.as_str(), mark.issued_to.as_str(), mark.seed), ("demo", "dana", 4));
+        let ids = |entities: &serde_json::Value| -> Vec<String> {
+            entities.as_array().unwrap().iter().map(|e| e["id"].as_str().unwrap().to_string()).collect()
+        };
+        let generated_ids = ids(&generated["world"]["entities"]);
+        assert_eq!(mark.content, content_hash(generated_ids.iter().map(String::as_str)));
+
+        let project = body_json(send("/v5/projects", r#"{"name":"a","tier":"demo"}"#, &token).await.unwrap()).await;
+        let generate = format!("/v5/projects/{}/generate", project["id"].as_str().unwrap());
+        let zone = body_json(send(&generate, r#"{"zone_type":"forest"}"#, &token).await.unwrap()).await;
+        let zone_ids = ids(&zone["entities"]);
+        let mark: Watermark = serde_json::from_value(zone["watermark"].clone()).unwrap();
+        assert_eq!(mark.content, content_hash(zone_ids.iter().map(String::as_str)));
+        let export = format!(r#"{{"project_id":"{}","engines":["godot"]}}"#, project["id"].as_str().unwrap());
+        let exported = body_json(send("/v5/export", &export, &token).await.unwrap()).await;
+        let mark: Watermark = serde_json::from_value(exported["watermark"].clone()).unwrap();
+        assert!(mark.verify(&key));
+        assert_eq!(mark.content, content_hash(zone_ids.iter().map(String::as_str)));
+        let stored = state.projects.get(project["id"].as_str().unwrap()).unwrap();
+        assert_eq!(stored.exports[0].watermark.as_ref(), Some(&mark));
+
+        // The bundle itself is signed by the gateway and carries the mark.
+        let download = exported["download_url"].as_str().unwrap();
+        let response = app.clone().oneshot(request("GET", download, "", Some(&token))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/zip");
+        let bundle = dir.path().join("downloaded.zip");
+        std::fs::write(&bundle, axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert!(crate::engine::packager::verify_export_bundle(&bundle, &key.to_bytes()).unwrap());
+        let detection = crate::engine::watermark::detect_bundle(&bundle, &key).unwrap().unwrap();
+        assert_eq!(detection.watermark, mark);
+        assert!(detection.content_matches);
+        let mut found_in = detection.found_in;
+        found_in.sort();
+        assert_eq!(found_in, ["godot/world.json", "manifest.json"]);
+        state.users.create(User::new("erin", vec!["instructor".into()], "elsewhere")).unwrap();
+        let (outsider, _) = state.sessions.issue("erin", vec!["instructor".into()], "elsewhere").unwrap();
+        let response = app.clone().oneshot(request("GET", download, "", Some(&outsider))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let unmarked = body_json(send("/v5/prompt", r#"{"prompt":"ridge"}"#, &admin).await.unwrap()).await;
+        assert!(unmarked.get("watermark").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_async_generation_is_polled_and_failed_jobs_return_their_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::open(licensed_config(dir.path()).0).unwrap();
+        let app = router(state.clone());
+        state.users.create(User::new("dana", vec!["instructor".into()], "studio")).unwrap();
+        let (token, _) = state.sessions.issue("dana", vec!["instructor".into()], "studio").unwrap();
+        // Other people's jobs need projects:read, which operators lack, and
+        // never cross tenants.
+        let outsider = |id: &str, role: &str, tenant: &str| {
+            state.users.create(User::new(id, vec![role.into()], tenant)).unwrap();
+            state.sessions.issue(id, vec![role.into()], tenant).unwrap().0
+        };
+        let (eve, frank, grace) = (
+            outsider("eve", "operator", "studio"),
+            outsider("frank", "admin", "elsewhere"),
+            outsider("grace", "instructor", "studio"),
+        );
+        let submit = |body: &str| {
+            let mut request = request("POST", "/v5/prompt", body, Some(&token));
+            request.headers_mut().insert("prefer", "respond-async".parse().unwrap());
+            app.clone().oneshot(request)
+        };
+        let poll = |id: String| {
+            let (app, token) = (app.clone(), token.clone());
+            async move {
+                loop {
+                    let uri = format!("/v5/jobs/{}", id);
+                    let job = body_json(app.clone().oneshot(request("GET", &uri, "", Some(&token))).await.unwrap()).await;
+                    if !matches!(job["status"].as_str(), Some("queued" | "running")) {
+                        return job;
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                }
+            }
+        };
+
+        let accepted = submit(r#"{"prompt":"ridge","seed":9}"#).await.unwrap();
+        assert_eq!(accepted.status(), StatusCode::ACCEPTED);
+        let location = accepted.headers()["location"].to_str().unwrap().to_string();
+        let job = body_json(accepted).await;
+        assert_eq!(location, format!("/v5/jobs/{}", job["id"].as_str().unwrap()));
+        assert_eq!((job["lane"].as_str(), job["tier"].as_str()), (Some("priority"), Some("creator")));
+
+        let done = poll(job["id"].as_str().unwrap().to_string()).await;
+        assert_eq!(done["status"], "completed");
+        assert_eq!(done["result"]["status"], "completed");
+        for (outsider, status) in [(&eve, StatusCode::NOT_FOUND), (&frank, StatusCode::NOT_FOUND), (&grace, StatusCode::OK)] {
+            let others = app.clone().oneshot(request("GET", &location, "", Some(outsider))).await.unwrap();
+            assert_eq!(others.status(), status);
+        }
+        let cancel = app.clone().oneshot(request("DELETE", &location, "", Some(&frank))).await.unwrap();
+        assert_eq!(cancel.status(), StatusCode::NOT_FOUND);
+        let cancel = app.clone().oneshot(request("DELETE", &location, "", Some(&token))).await.unwrap();
+        assert_eq!(cancel.status(), StatusCode::CONFLICT);
+
+        // The job fails after the 202, so its generation is handed back.
+        let failing = body_json(submit(r#"{"prompt":"ridge","project_id":"nope"}"#).await.unwrap()).await;
+        let failed = poll(failing["id"].as_str().unwrap().to_string()).await;
+        assert_eq!(failed["status"], "failed");
+        assert!(failed["error"].as_str().unwrap().contains("nope"));
+        let usage = body_json(app.oneshot(request("GET", "/v5/usage", "", Some(&token))).await.unwrap()).await;
+        assert_eq!(usage["generations"]["used"], 1);
+
+        // Each 202 is audited as a submission, and each job once it ends.
+        let audited = |action: &str| {
+            let entries = state.audit.entries_from(0).unwrap();
+            entries.into_iter().filter(|e| e.action == action).map(|e| e.details.unwrap()).collect::<Vec<_>>()
+        };
+        let mut finished = audited("generate");
+        for _ in 0..200 {
+            if finished.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            finished = audited("generate");
+        }
+        let submitted = audited("job_submit");
+        assert_eq!(submitted.iter().map(|d| d["status"].as_u64()).collect::<Vec<_>>(), [Some(202), Some(202)]);
+        let outcomes: Vec<_> = finished
+            .iter()
+            .map(|d| (d["job_id"].as_str().unwrap(), d["job_status"].as_str().unwrap(), d["outcome"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                (job["id"].as_str().unwrap(), "completed", "success"),
+                (failing["id"].as_str().unwrap(), "failed", "failure"),
+            ]
+        );
+        assert!(finished[1]["error"].as_str().unwrap().contains("nope"));
+    }
+
+    #[tokio::test]
+    async fn test_license_route_reports_the_loaded_license_and_fails_closed() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::open(GatewayConfig::new(dir.path())).unwrap();
+        let app = router(state.clone());
+        let (admin, _) = state.sessions.issue("admin", vec!["admin".into()], "default").unwrap();
+        let unlicensed = app.clone().oneshot(request("GET", "/v5/license", "", Some(&admin))).await.unwrap();
+        assert_eq!(unlicensed.status(), StatusCode::LOCKED);
+        let body = body_json(unlicensed).await;
+        assert_eq!((body["valid"].as_bool(), body["tier"].as_str()), (Some(false), Some("unlicensed")));
+        assert_eq!((body["seats_total"].as_u64(), body["features"].as_array().map(Vec::len)), (Some(0), Some(0)));
+        assert!(body["error"].as_str().unwrap().contains("No license file"));
+
+        // Nothing is generated, and no quota spent, without a license.
+        let generate = || request("POST", "/v5/prompt", r#"{"prompt":"ridge","seed":1}"#, Some(&admin));
+        let refused = app.clone().oneshot(generate()).await.unwrap();
+        assert_eq!(refused.status(), StatusCode::LOCKED);
+        assert_eq!(body_json(refused).await["error"], "unlicensed");
+        let export = request("POST", "/v5/export", r#"{"project_id":"nope","engines":["ue5"]}"#, Some(&admin));
+        assert_eq!(app.clone().oneshot(export).await.unwrap().status(), StatusCode::LOCKED);
+        let usage = body_json(app.oneshot(request("GET", "/v5/usage", "", Some(&admin))).await.unwrap()).await;
+        assert_eq!(usage["generations"]["used"], 0);
+
+        let licensed_dir = tempfile::tempdir().unwrap();
+        let (config, license) = licensed_config(licensed_dir.path());
+        let state = AppState::open(config).unwrap();
+        let app = router(state.clone());
+        state.users.create(User::new("dana", vec!["instructor".into()], "studio")).unwrap();
+        let (admin, _) = state.sessions.issue("admin", vec!["admin".into()], "default").unwrap();
+
+        let licensed = app.clone().oneshot(request("GET", "/v5/license", "", Some(&admin))).await.unwrap();
+        assert_eq!(licensed.status(), StatusCode::OK);
+        let body = body_json(licensed).await;
+        assert_eq!((body["valid"].as_bool(), body["tier"].as_str()), (Some(true), Some("enterprise")));
+        assert_eq!(body["license_id"], license.license_id.as_str());
+        assert_eq!(body["expiry"], license.expiry_timestamp);
+        // The bootstrap admin and dana.
+        assert_eq!((body["seats_total"].as_u64(), body["seats_used"].as_u64()), (Some(25), Some(2)));
+        assert_eq!(body["features"], serde_json::json!(["cluster_mode"]));
+        let generate = request("POST", "/v5/prompt", r#"{"prompt":"ridge","seed":1}"#, Some(&admin));
+        assert_eq!(app.oneshot(generate).await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_follow_the_role_and_refuse_with_headers() {
+        use crate::security::rate_limit::RouteClass;
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::open(GatewayConfig::new(dir.path())).unwrap();
+        let app = router(state.clone());
+        let (admin, _) = state.sessions.issue("admin", vec!["admin".into()], "default").unwrap();
+        state.users.create(User::new("dana", vec!["auditor".into()], "studio")).unwrap();
+        let (token, _) = state.sessions.issue("dana", vec!["auditor".into()], "studio").unwrap();
+
+        let response = app.clone().oneshot(request("GET", "/v5/projects", "", Some(&admin))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["ratelimit-limit"], "10000");
+        assert_eq!(response.headers()["ratelimit-remaining"], "9999");
+        assert_eq!(response.headers()["ratelimit-policy"], "10000;w=3600");
+
+        let now = chrono::Utc::now();
+        while state.rate_limits.check("dana", RouteClass::Read, 1800, now).allowed {}
+        let refused = app.clone().oneshot(request("GET", "/v5/audit", "", Some(&token))).await.unwrap();
+        assert_eq!(refused.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(refused.headers()["ratelimit-remaining"], "0");
+        assert!(refused.headers().contains_key("retry-after"));
+        assert_eq!(body_json(refused).await["error"], "rate_limited");
+
+        // Writes draw from a bucket of their own.
+        let other = app.oneshot(request("POST", "/v5/projects", r#"{"name":"a"}"#, Some(&token))).await.unwrap();
+        assert_ne!(other.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_operators_persisted_before_the_upgrade_still_generate_and_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, _) = licensed_config(dir.path());
+        let old = serde_json::json!({ "users": [User::new("ops", vec!["operator".into()], "studio")] });
+        std::fs::write(config.users_path(), serde_json::to_vec(&old).unwrap()).unwrap();
+        let state = AppState::open(config).unwrap();
+        let app = router(state.clone());
+        state.users.create(User::new("eve", vec!["operator".into()], "studio")).unwrap();
+        let project = state.projects.create(Project { tenant: "studio".into(), ..Project::new("Harbor", "operator", 7) }).unwrap();
+        let send = |uri: &str, body: String, user: &str| {
+            let (token, _) = state.sessions.issue(user, vec!["operator".into()], "studio").unwrap();
+            app.clone().oneshot(request("POST", uri, &body, Some(&token)))
+        };
+
+        let generate = format!("/v5/projects/{}/generate", project.id);
+        let export = format!(r#"{{"project_id":"{}","engines":["ue5","vbs4"]}}"#, project.id);
+        let zone = send(&generate, r#"{"zone_type":"forest"}"#.into(), "ops").await.unwrap();
+        assert_eq!(zone.status(), StatusCode::OK);
+        assert_eq!(send("/v5/export", export.clone(), "ops").await.unwrap().status(), StatusCode::OK);
+
+        // Operators created since are v4 operators.
+        let zone = send(&generate, r#"{"zone_type":"forest"}"#.into(), "eve").await.unwrap();
+        assert_eq!(zone.status(), StatusCode::FORBIDDEN);
+        assert_eq!(send("/v5/export", export, "eve").await.unwrap().status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_admins_manage_users_and_changes_apply_to_live_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = GatewayConfig::new(dir.path());
+        config.bootstrap_admin_password = Some("correct horse".into());
+        let state = AppState::open(config).unwrap();
+        let app = router(state.clone());
+        let (admin, _) = state.sessions.issue("admin", vec!["admin".into()], "default").unwrap();
+        let send = |method: &str, uri: &str, body: &str, token: &str| {
+            app.clone().oneshot(request(method, uri, body, Some(token)))
+        };
+
+        // Legacy roles are policy roles of their own, with the old permissions.
+        let created = send("POST", "/v5/admin/users", r#"{"id":"dana","roles":["creator"],"password":"hunter2hunter2"}"#, &admin);
+        let created = created.await.unwrap();
+        assert_eq!(created.status(), StatusCode::CREATED);
+        assert_eq!(body_json(created).await["roles"], serde_json::json!(["creator"]));
+        let bad_role = send("POST", "/v5/admin/users", r#"{"id":"eve","roles":["root"]}"#, &admin);
+        assert_eq!(bad_role.await.unwrap().status(), StatusCode::BAD_REQUEST);
+
+        let login = request("POST", "/v5/auth/login", r#"{"username":"dana","password":"hunter2hunter2"}"#, None);
+        let body = body_json(app.clone().oneshot(login).await.unwrap()).await;
+        let dana = body["token"].as_str().unwrap().to_string();
+        assert_eq!(send("GET", "/v5/admin/users", "", &dana).await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(send("DELETE", "/v5/admin/users/admin", "", &dana).await.unwrap().status(), StatusCode::FORBIDDEN);
+
+        // Role changes reach tokens that were already issued.
+        let promoted = send("PATCH", "/v5/admin/users/dana", r#"{"roles":["auditor"]}"#, &admin);
+        assert_eq!(promoted.await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send("GET", "/v5/audit/events", "", &dana).await.unwrap().status(), StatusCode::OK);
+
+        let key = body_json(send("POST", "/v5/admin/users/dana/keys", r#"{"label":"ci"}"#, &admin).await.unwrap()).await;
+        let key = key["key"].as_str().unwrap().to_string();
+        let response = send("GET", "/v5/auth/session", "", &key).await.unwrap();
+        assert_eq!(body_json(response).await["user_id"], "dana");
+
+        let listed = body_json(send("GET", "/v5/admin/users/dana", "", &admin).await.unwrap()).await;
+        assert_eq!(listed["has_password"], true);
+        assert!(listed.get("password").is_none());
+        assert_eq!(listed["api_keys"][0]["label"], "ci");
+
+        // Locking ends the session and the key alike.
+        let locked = send("POST", "/v5/admin/users/dana/lock", r#"{"minutes":30}"#, &admin);
+        assert_eq!(locked.await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send("GET", "/v5/auth/session", "", &dana).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(send("GET", "/v5/auth/session", "", &key).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(send("POST", "/v5/admin/users/dana/unlock", "", &admin).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send("GET", "/v5/auth/session", "", &dana).await.unwrap().status(), StatusCode::OK);
+
+        let last_admin = send("PATCH", "/v5/admin/users/admin", r#"{"disabled":true}"#, &admin);
+        assert_eq!(last_admin.await.unwrap().status(), StatusCode::CONFLICT);
+        assert_eq!(send("DELETE", "/v5/admin/users/dana", "", &admin).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(send("GET", "/v5/auth/session", "", &key).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        let actions: Vec<String> = state
+            .audit
+            .entries_from(0)
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.event_type == AuditEventType::User)
+            .map(|e| e.action)
+            .collect();
+        // Refused changes are audited as well as the ones that went through.
+        assert_eq!(
+            actions,
+            [
+                "user_create", "user_create", "user_update", "user_key_issue",
+                "user_lock", "user_unlock", "user_update", "user_delete",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_live_sessions_authenticate_resync_and_resume_from_their_ack() {
+        use crate::live::{LiveMessage, BACKLOG_LIMIT};
+        use futures::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Error as WsError, Message};
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::open(GatewayConfig::new(dir.path())).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = router(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        state.users.create(User::new("ops", vec!["operator".into()], "studio")).unwrap();
+        let (token, _) = state.sessions.issue("ops", vec!["operator".into()], "studio").unwrap();
+        let project = state.projects.create(Project { tenant: "studio".into(), ..Project::new("Harbor", "operator", 7) }).unwrap();
+        let publish = |count: usize| {
+            for _ in 0..count {
+                let message = LiveMessage::ProjectUpdated { name: "Harbor".into(), tier: "operator".into(), status: "active".into() };
+                state.live.publish(&project.id, message).unwrap();
+            }
+        };
+        publish(BACKLOG_LIMIT + 3);
+        let head = (BACKLOG_LIMIT + 3) as u64;
+
+        let url = |query: &str| format!("ws://{}/v5/projects/{}/live?{}", address, project.id, query);
+        let refused = |result: Result<_, WsError>| match result {
+            Err(WsError::Http(response)) => response.status(),
+            other => panic!("handshake was not refused: {:?}", other.map(|_| ())),
+        };
+        assert_eq!(refused(tokio_tungstenite::connect_async(url("client_id=rig")).await), StatusCode::UNAUTHORIZED);
+        let forged = url("client_id=rig&access_token=forged");
+        assert_eq!(refused(tokio_tungstenite::connect_async(forged).await), StatusCode::UNAUTHORIZED);
+
+        async fn next_frame<S>(socket: &mut S) -> serde_json::Value
+        where
+            S: futures::Stream<Item = Result<Message, WsError>> + Unpin,
+        {
+            match socket.next().await.unwrap().unwrap() {
+                Message::Text(text) => serde_json::from_str(&text).unwrap(),
+                other => panic!("unexpected frame {:?}", other),
+            }
+        }
+
+        // A client that fell behind the backlog is told to resync, then
+        // gets what the backlog still holds.
+        let (mut socket, _) = tokio_tungstenite::connect_async(url(&format!("client_id=rig&last_seq=1&access_token={token}")))
+            .await
+            .unwrap();
+        let hello = next_frame(&mut socket).await;
+        assert_eq!((hello["type"].as_str(), hello["head_seq"].as_u64(), hello["resume_from"].as_u64()), (Some("hello"), Some(head), Some(1)));
+        let oldest = head - BACKLOG_LIMIT as u64 + 1;
+        assert_eq!(next_frame(&mut socket).await, serde_json::json!({ "type": "resync", "from_seq": oldest }));
+        for seq in oldest..=head {
+            let event = next_frame(&mut socket).await;
+            assert_eq!((event["type"].as_str(), event["seq"].as_u64()), (Some("event"), Some(seq)));
+        }
+
+        socket.send(Message::Text(format!(r#"{{"type":"ack","seq":{}}}"#, head + 1))).await.unwrap();
+        assert_eq!(next_frame(&mut socket).await["type"], "error");
+        socket.send(Message::Text(format!(r#"{{"type":"ack","seq":{head}}}"#))).await.unwrap();
+        assert_eq!(next_frame(&mut socket).await, serde_json::json!({ "type": "ack", "seq": head }));
+        socket.close(None).await.unwrap();
+
+        // Reconnecting without last_seq resumes after the ack, with the
+        // token in the header this time, and new events arrive live.
+        let mut resumed = url("client_id=rig").into_client_request().unwrap();
+        resumed.headers_mut().insert("authorization", format!("Bearer {token}").parse().unwrap());
+        let (mut socket, _) = tokio_tungstenite::connect_async(resumed).await.unwrap();
+        let hello = next_frame(&mut socket).await;
+        assert_eq!((hello["client_id"].as_str(), hello["resume_from"].as_u64()), (Some("rig"), Some(head)));
+        publish(1);
+        let event = next_frame(&mut socket).await;
+        assert_eq!((event["type"].as_str(), event["seq"].as_u64()), (Some("event"), Some(head + 1)));
+
+        // Someone else naming the same client starts at the head, not at
+        // the first user's ack.
+        state.users.create(User::new("ops2", vec!["operator".into()], "studio")).unwrap();
+        let (other, _) = state.sessions.issue("ops2", vec!["operator".into()], "studio").unwrap();
+        let (mut socket, _) = tokio_tungstenite::connect_async(url(&format!("client_id=rig&access_token={other}"))).await.unwrap();
+        let hello = next_frame(&mut socket).await;
+        assert_eq!(hello["resume_from"].as_u64(), Some(head + 1));
+    }
+}
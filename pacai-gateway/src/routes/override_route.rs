@@ -1,47 +1,224 @@
-use axum::response::Json;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use crate::audit::middleware::RequestContext;
+use crate::engine::override_engine::SkippedEntity;
+use crate::overrides::{self, OverrideCommand};
+use crate::overrides::scheduler::{self, ScheduleStatus, ScheduledOverride, TriggerCondition};
+use crate::routes::{project_for, ApiError};
+use crate::security::session::Principal;
+use crate::state::SharedState;
+use crate::util::logging;
 
 #[derive(Deserialize)]
 pub struct OverrideRequest {
     pub project_id: String,
+    pub zone_id: Option<String>,
     pub target_type: String,
     pub target_id: Option<String>,
     pub behavior: String,
+    #[serde(default)]
     pub parameters: serde_json::Value,
     pub priority: Option<u32>,
+    pub schedule: Option<ScheduleRequest>,
+}
+
+/// When an override should start and stop. `start_at` (RFC 3339) and
+/// `delay_seconds` are alternatives; a `condition` delays the override until
+/// it holds, or until `expires_at` passes.
+#[derive(Deserialize)]
+pub struct ScheduleRequest {
+    pub start_at: Option<String>,
+    pub delay_seconds: Option<u64>,
+    pub duration_seconds: Option<u64>,
+    pub expires_at: Option<String>,
+    pub condition: Option<TriggerCondition>,
 }
 
 #[derive(Serialize)]
 pub struct OverrideResponse {
     pub id: String,
     pub project_id: String,
+    pub zone_id: String,
+    pub version: u32,
     pub status: String,
     pub applied_at: String,
     pub target_type: String,
     pub target_id: String,
     pub behavior: String,
+    pub priority: u32,
+    pub entities_affected: Vec<String>,
+    pub entities_skipped: Vec<SkippedEntity>,
     pub checksum: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_at: Option<String>,
 }
 
-pub async fn apply_override(Json(payload): Json<OverrideRequest>) -> Json<OverrideResponse> {
-    let id = Uuid::new_v4().to_string();
-    let target_id = payload.target_id.unwrap_or_else(|| "all".into());
-    
-    let checksum = format!("{:x}", sha2::Sha256::digest(
-        format!("{}{}{}{}", id, payload.project_id, payload.behavior, payload.parameters).as_bytes()
-    ));
-    
-    Json(OverrideResponse {
-        id,
+#[derive(Deserialize)]
+pub struct ScheduleQuery {
+    pub status: Option<ScheduleStatus>,
+}
+
+fn parse_time(field: &str, value: &str) -> Result<chrono::DateTime<chrono::Utc>, ApiError> {
+    scheduler::parse_time(value)
+        .ok_or_else(|| ApiError::BadRequest(format!("{} must be an RFC 3339 timestamp", field)))
+}
+
+fn build_schedule(command: &OverrideCommand, request: ScheduleRequest) -> Result<ScheduledOverride, ApiError> {
+    let now = chrono::Utc::now();
+    let start_at = match (&request.start_at, request.delay_seconds) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::BadRequest("Use either start_at or delay_seconds, not both".into()));
+        }
+        (Some(start_at), None) => Some(parse_time("start_at", start_at)?),
+        (None, Some(delay)) => Some(now + chrono::Duration::seconds(delay as i64)),
+        (None, None) => None,
+    };
+
+    if request.duration_seconds == Some(0) {
+        return Err(ApiError::BadRequest("duration_seconds must be greater than zero".into()));
+    }
+    if let Some(expires_at) = &request.expires_at {
+        let expires_at = parse_time("expires_at", expires_at)?;
+        if expires_at <= start_at.unwrap_or(now) {
+            return Err(ApiError::BadRequest("expires_at must be after the start time".into()));
+        }
+    }
+    if let Some(condition) = &request.condition {
+        condition.validate().map_err(ApiError::BadRequest)?;
+    }
+    if start_at.is_none() && request.duration_seconds.is_none() && request.condition.is_none() {
+        return Err(ApiError::BadRequest(
+            "schedule needs start_at, delay_seconds, duration_seconds or condition".into(),
+        ));
+    }
+
+    Ok(ScheduledOverride {
+        start_at: start_at.map(|t| t.to_rfc3339()),
+        duration_seconds: request.duration_seconds,
+        expires_at: request.expires_at,
+        condition: request.condition,
+        ..ScheduledOverride::new(command)
+    })
+}
+
+pub async fn apply_override(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    Json(payload): Json<OverrideRequest>,
+) -> Result<Response, ApiError> {
+    project_for(&state, &principal, &payload.project_id)?;
+    let command = OverrideCommand {
+        id: Uuid::new_v4().to_string(),
         project_id: payload.project_id,
-        status: "applied".into(),
-        applied_at: chrono::Utc::now().to_rfc3339(),
+        zone_id: payload.zone_id,
         target_type: payload.target_type,
-        target_id,
+        target_id: payload.target_id,
         behavior: payload.behavior,
-        checksum,
+        parameters: payload.parameters,
+        priority: payload.priority,
+    };
+    command.spec()?;
+    context.record(logging::log_override(
+        &context.actor,
+        &command.project_id,
+        command.target_id.as_deref().unwrap_or(&command.target_type),
+        &command.behavior,
+    ));
+
+    let schedule = payload.schedule.map(|s| build_schedule(&command, s)).transpose()?;
+
+    // Anything with a start time or a condition waits for the scheduler.
+    if let Some(schedule) = schedule.as_ref().filter(|s| s.start_at.is_some() || s.condition.is_some()) {
+        let project = state.projects.get(&command.project_id)?;
+        overrides::select_zone(&project, command.zone_id.as_deref())?;
+        state.projects.update(&command.project_id, |p| p.scheduled_overrides.push(schedule.clone()))?;
+        scheduler::publish_status(&state, &command.project_id, &schedule.id);
+
+        tracing::info!("Override {} scheduled on project {}", schedule.id, command.project_id);
+        return Ok((StatusCode::ACCEPTED, Json(schedule.clone())).into_response());
+    }
+
+    let applied = overrides::commit(&state, &command, |p, applied| {
+        if let Some(mut schedule) = schedule {
+            schedule.mark_applied(applied, chrono::Utc::now());
+            p.scheduled_overrides.push(schedule);
+        }
+        Ok(())
+    })?;
+
+    let status = if !applied.outcome.affected.is_empty() {
+        "applied"
+    } else if !applied.outcome.skipped.is_empty() {
+        "blocked"
+    } else {
+        "no_match"
+    };
+    let project = state.projects.get(&command.project_id)?;
+    let revert_at = match project.schedule(&command.id) {
+        Ok(schedule) => {
+            scheduler::publish_status(&state, &command.project_id, &schedule.id);
+            schedule.revert_at.clone()
+        }
+        Err(_) => None,
+    };
+
+    Ok(Json(OverrideResponse {
+        id: command.id,
+        project_id: command.project_id,
+        zone_id: applied.zone_id,
+        version: applied.version,
+        status: status.into(),
+        applied_at: applied.applied_at,
+        target_type: command.target_type,
+        target_id: applied.target_id,
+        behavior: command.behavior,
+        priority: applied.priority,
+        entities_affected: applied.outcome.affected,
+        entities_skipped: applied.outcome.skipped,
+        checksum: applied.checksum,
+        revert_at,
     })
+    .into_response())
 }
 
-use sha2::Digest;
+pub async fn list_scheduled(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path(id): Path<String>,
+    Query(query): Query<ScheduleQuery>,
+) -> Result<Json<Vec<ScheduledOverride>>, ApiError> {
+    let project = project_for(&state, &principal, &id)?;
+    let scheduled = project
+        .scheduled_overrides
+        .into_iter()
+        .filter(|s| query.status.is_none_or(|status| s.status == status))
+        .collect();
+
+    Ok(Json(scheduled))
+}
+
+pub async fn get_scheduled(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path((id, schedule_id)): Path<(String, String)>,
+) -> Result<Json<ScheduledOverride>, ApiError> {
+    let project = project_for(&state, &principal, &id)?;
+    Ok(Json(project.schedule(&schedule_id)?.clone()))
+}
+
+pub async fn cancel_scheduled(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path((id, schedule_id)): Path<(String, String)>,
+) -> Result<Json<ScheduledOverride>, ApiError> {
+    project_for(&state, &principal, &id)?;
+    let cancelled = scheduler::cancel(&state, &id, &schedule_id)?;
+    tracing::info!("Scheduled override {} cancelled on project {}", schedule_id, id);
+    Ok(Json(cancelled))
+}
@@ -0,0 +1,52 @@
+use axum::{extract::Query, response::Json};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::routes::ApiError;
+use crate::security::policy::{self, Decision};
+use crate::security::session::Principal;
+
+#[derive(Deserialize)]
+pub struct ExplainQuery {
+    pub role: String,
+    pub permission: String,
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ExplainResponse {
+    pub role: String,
+    pub permission: String,
+    pub tenant: Option<String>,
+    #[serde(flatten)]
+    pub decision: Decision,
+    /// The role's documented constraints, which the engine does not enforce.
+    pub constraints: BTreeMap<String, serde_json::Value>,
+    pub policy_source: String,
+    pub policy_version: String,
+}
+
+/// Which rule of the policy in force grants or denies `permission` to
+/// `role`. Any signed-in caller may ask; the policy is not a secret.
+pub async fn explain(_principal: Principal, Query(query): Query<ExplainQuery>) -> Result<Json<ExplainResponse>, ApiError> {
+    if query.role.is_empty() || query.permission.is_empty() {
+        return Err(ApiError::BadRequest("role and permission are required".into()));
+    }
+    let active = policy::current();
+    let decision = active.policy.decide(&query.role, query.tenant.as_deref(), &query.permission);
+    let constraints = decision
+        .effective_role
+        .as_ref()
+        .map(|role| active.policy.roles[role].constraints.clone())
+        .unwrap_or_default();
+
+    Ok(Json(ExplainResponse {
+        role: query.role,
+        permission: query.permission,
+        tenant: query.tenant,
+        decision,
+        constraints,
+        policy_source: active.source.clone(),
+        policy_version: active.policy.version.clone(),
+    }))
+}
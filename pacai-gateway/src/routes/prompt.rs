@@ -1,11 +1,21 @@
 use axum::{
-    extract::Path,
-    response::Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use crate::engine::{narrative, world};
-use crate::security::rbac;
+use crate::audit::middleware::RequestContext;
+use crate::engine::{narrative, watermark::Watermark, world, zone_overrides::{self, ZoneOverrides}};
+use crate::live::LiveMessage;
+use crate::routes::{jobs, project_for, ApiError};
+use crate::security::quota;
+use crate::security::rbac::{self, RbacError};
+use crate::security::session::Principal;
+use crate::state::SharedState;
+use crate::store::{Grant, NarrativeRecord, Project, StoreError, VersionSource, ZoneRecord};
+use crate::util::logging::{self, AuditEntry, AuditEventType};
 
 #[derive(Deserialize)]
 pub struct PromptRequest {
@@ -13,6 +23,7 @@ pub struct PromptRequest {
     pub seed: Option<u64>,
     pub style: Option<String>,
     pub constraints: Option<Vec<String>>,
+    pub project_id: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -22,25 +33,68 @@ pub struct PromptResponse {
     pub narrative: narrative::NarrativeOutput,
     pub world: world::WorldOutput,
     pub checksum: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Watermark>,
 }
 
-pub async fn handle_prompt(Json(payload): Json<PromptRequest>) -> Json<PromptResponse> {
+fn to_value(response: impl Serialize) -> Result<serde_json::Value, ApiError> {
+    serde_json::to_value(response).map_err(|e| ApiError::Internal(e.to_string()))
+}
+
+/// Generation runs as a job on the queue; see `jobs::run`.
+pub async fn handle_prompt(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    headers: HeaderMap,
+    grant: Option<Extension<Grant>>,
+    Json(payload): Json<PromptRequest>,
+) -> Result<Response, ApiError> {
+    let seed = payload.seed.unwrap_or_else(rand::random);
+    let (worker, owner) = (state.clone(), principal.clone());
+    let work = move |job: &RequestContext| {
+        let resource = payload.project_id.as_deref().unwrap_or("prompt");
+        job.record(logging::log_generate(&job.actor, resource, seed));
+        to_value(generate_prompt(&worker, &owner, payload, seed)?)
+    };
+    jobs::run(&state, &context, &principal, &headers, grant.map(|Extension(g)| g), "prompt", work).await
+}
+
+fn generate_prompt(
+    state: &SharedState,
+    principal: &Principal,
+    payload: PromptRequest,
+    seed: u64,
+) -> Result<PromptResponse, ApiError> {
     let id = Uuid::new_v4().to_string();
-    let seed = payload.seed.unwrap_or_else(|| rand::random());
-    
     let narrative_output = narrative::generate(&payload.prompt, seed);
-    let world_output = world::generate(&payload.prompt, seed);
-    
+    let world_output = world::generate(&payload.prompt, seed, &id);
+    let watermark = quota::watermark_for(state, principal, seed, &world_output.entities)?;
+
     let checksum = format!("{:x}", sha2::Sha256::digest(
         format!("{}{}{}", id, seed, payload.prompt).as_bytes()
     ));
-    
-    Json(PromptResponse {
+
+    if let Some(project_id) = &payload.project_id {
+        project_for(state, principal, project_id)?;
+        let record = NarrativeRecord {
+            id: id.clone(),
+            prompt: payload.prompt.clone(),
+            seed,
+            checksum: checksum.clone(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            narrative: narrative_output.clone(),
+        };
+        state.projects.update(project_id, |p| p.narratives.push(record))?;
+    }
+
+    Ok(PromptResponse {
         id,
         status: "completed".into(),
         narrative: narrative_output,
         world: world_output,
         checksum,
+        watermark,
     })
 }
 
@@ -51,6 +105,14 @@ pub struct CreateProjectRequest {
     pub seed: Option<u64>,
 }
 
+#[derive(Deserialize)]
+pub struct UpdateProjectRequest {
+    pub name: Option<String>,
+    pub tier: Option<String>,
+    pub seed: Option<u64>,
+    pub status: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct ProjectResponse {
     pub id: String,
@@ -58,37 +120,125 @@ pub struct ProjectResponse {
     pub tier: String,
     pub seed: u64,
     pub created_at: String,
+    pub updated_at: String,
     pub status: String,
+    pub zone_count: usize,
+    pub narrative_count: usize,
+    pub override_count: usize,
+    pub export_count: usize,
 }
 
-pub async fn create_project(Json(payload): Json<CreateProjectRequest>) -> Json<ProjectResponse> {
-    let id = Uuid::new_v4().to_string();
-    let seed = payload.seed.unwrap_or_else(|| rand::random());
-    
-    Json(ProjectResponse {
-        id,
-        name: payload.name,
-        tier: payload.tier,
-        seed,
-        created_at: chrono::Utc::now().to_rfc3339(),
-        status: "active".into(),
-    })
+impl From<&Project> for ProjectResponse {
+    fn from(project: &Project) -> Self {
+        Self {
+            id: project.id.clone(),
+            name: project.name.clone(),
+            tier: project.tier.clone(),
+            seed: project.seed,
+            created_at: project.created_at.clone(),
+            updated_at: project.updated_at.clone(),
+            status: project.status.clone(),
+            zone_count: project.zones.len(),
+            narrative_count: project.narratives.len(),
+            override_count: project.overrides.len(),
+            export_count: project.exports.len(),
+        }
+    }
 }
 
-pub async fn get_project(Path(id): Path<String>) -> Json<ProjectResponse> {
-    Json(ProjectResponse {
-        id: id.clone(),
-        name: format!("Project-{}", &id[..8]),
-        tier: "lifetime".into(),
-        seed: 42,
-        created_at: chrono::Utc::now().to_rfc3339(),
-        status: "active".into(),
-    })
+pub async fn list_projects(State(state): State<SharedState>, principal: Principal) -> Json<Vec<ProjectResponse>> {
+    let projects = state.projects.list();
+    Json(projects.iter().filter(|p| principal.sees_tenant(&p.tenant)).map(ProjectResponse::from).collect())
+}
+
+pub async fn create_project(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    Json(payload): Json<CreateProjectRequest>,
+) -> Result<(StatusCode, Json<ProjectResponse>), Response> {
+    let seed = payload.seed.unwrap_or_else(rand::random);
+    let mut project = Project::new(&payload.name, &payload.tier, seed);
+    project.owner = Some(principal.user_id.clone());
+    project.tenant = principal.tenant.clone();
+    // `max_projects` caps the projects a user has at once, so deleting one
+    // makes room for the next.
+    let tier = quota::tier_of(&state, &principal);
+    let max = rbac::get_tier_limits(&tier).max_projects;
+    let project = state.projects.create_within(project, max).map_err(|e| match e {
+        StoreError::ProjectLimit { .. } => {
+            let message = format!("tier '{}' allows {} projects", tier, max);
+            RbacError::TierLimitExceeded { message, reset_at: None }.into_response()
+        }
+        e => ApiError::from(e).into_response(),
+    })?;
+    context.record(AuditEntry::new(
+        AuditEventType::Project,
+        &context.actor,
+        "project_create",
+        &project.id,
+        Some(serde_json::json!({ "name": project.name, "tier": project.tier })),
+    ));
+
+    Ok((StatusCode::CREATED, Json(ProjectResponse::from(&project))))
+}
+
+pub async fn get_project(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Json<ProjectResponse>, ApiError> {
+    let project = project_for(&state, &principal, &id)?;
+    Ok(Json(ProjectResponse::from(&project)))
+}
+
+pub async fn update_project(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateProjectRequest>,
+) -> Result<Json<ProjectResponse>, ApiError> {
+    project_for(&state, &principal, &id)?;
+    let project = state.projects.update(&id, |p| {
+        if let Some(name) = payload.name {
+            p.name = name;
+        }
+        if let Some(tier) = payload.tier {
+            p.tier = tier;
+        }
+        if let Some(seed) = payload.seed {
+            p.seed = seed;
+        }
+        if let Some(status) = payload.status {
+            p.status = status;
+        }
+    })?;
+
+    state.live.publish(&id, LiveMessage::ProjectUpdated {
+        name: project.name.clone(),
+        tier: project.tier.clone(),
+        status: project.status.clone(),
+    });
+
+    Ok(Json(ProjectResponse::from(&project)))
+}
+
+pub async fn delete_project(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    project_for(&state, &principal, &id)?;
+    state.projects.delete(&id)?;
+    state.live.publish(&id, LiveMessage::ProjectDeleted);
+    state.live.remove(&id)?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
 #[derive(Deserialize)]
 pub struct GenerateZoneRequest {
     pub zone_type: String,
+    pub zone_id: Option<String>,
     pub seed: Option<u64>,
     pub overrides: Option<serde_json::Value>,
 }
@@ -96,33 +246,103 @@ pub struct GenerateZoneRequest {
 #[derive(Serialize)]
 pub struct GenerateZoneResponse {
     pub zone_id: String,
+    pub version: u32,
     pub zone_type: String,
     pub seed: u64,
     pub checksum: String,
+    pub overrides_applied: Option<ZoneOverrides>,
     pub entities: Vec<world::Entity>,
     pub terrain: world::TerrainData,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Watermark>,
 }
 
 pub async fn generate_zone(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    headers: HeaderMap,
+    grant: Option<Extension<Grant>>,
     Path(project_id): Path<String>,
     Json(payload): Json<GenerateZoneRequest>,
-) -> Json<GenerateZoneResponse> {
-    let zone_id = Uuid::new_v4().to_string();
-    let seed = payload.seed.unwrap_or_else(|| rand::random());
-    
-    let world_output = world::generate(&payload.zone_type, seed);
-    
+) -> Result<Response, ApiError> {
+    let project = project_for(&state, &principal, &project_id)?;
+    let seed = payload.seed.unwrap_or(project.seed);
+    let (worker, owner) = (state.clone(), principal.clone());
+    let work = move |job: &RequestContext| to_value(build_zone(&worker, job, &owner, project_id, payload, seed)?);
+    jobs::run(&state, &context, &principal, &headers, grant.map(|Extension(g)| g), "zone", work).await
+}
+
+fn build_zone(
+    state: &SharedState,
+    context: &RequestContext,
+    principal: &Principal,
+    project_id: String,
+    payload: GenerateZoneRequest,
+    seed: u64,
+) -> Result<GenerateZoneResponse, ApiError> {
+    context.record(logging::log_generate(&context.actor, &project_id, seed));
+    let project = state.projects.get(&project_id)?;
+    let zone_id = match &payload.zone_id {
+        Some(zone_id) => project.zone(zone_id)?.zone_id.clone(),
+        None => Uuid::new_v4().to_string(),
+    };
+
+    let overrides = match &payload.overrides {
+        Some(value) => Some(ZoneOverrides::from_value(value)?).filter(|o| !o.is_empty()),
+        None => None,
+    };
+
+    let mut world_output = world::generate(&payload.zone_type, seed, &zone_id);
+    if let Some(overrides) = &overrides {
+        world_output = zone_overrides::apply(&world_output, overrides)?;
+    }
+    let watermark = quota::watermark_for(state, principal, seed, &world_output.entities)?;
+
+    let overrides_json = overrides.as_ref()
+        .map(|o| serde_json::to_string(o).unwrap_or_default())
+        .unwrap_or_default();
     let checksum = format!("{:x}", sha2::Sha256::digest(
-        format!("{}{}{}", project_id, zone_id, seed).as_bytes()
+        format!("{}{}{}{}", project_id, zone_id, seed, overrides_json).as_bytes()
     ));
-    
-    Json(GenerateZoneResponse {
+
+    let (_, version) = state.projects.try_update(&project_id, |p| {
+        if p.zone(&zone_id).is_err() {
+            p.zones.push(ZoneRecord::new(&zone_id, &payload.zone_type));
+        }
+        let zone = p.zone_mut(&zone_id)?;
+        zone.zone_type = payload.zone_type.clone();
+        let version = zone.push_version(
+            seed,
+            checksum.clone(),
+            VersionSource::Generate,
+            overrides.clone(),
+            Default::default(),
+            world_output.clone(),
+        );
+        Ok(version.version)
+    })?;
+
+    state.live.publish(&project_id, LiveMessage::ZoneVersion {
+        zone_id: zone_id.clone(),
+        version,
+        checksum: checksum.clone(),
+        source: VersionSource::Generate,
+    });
+    let mut entry = logging::log_generate(&context.actor, &project_id, seed);
+    entry.details = Some(serde_json::json!({ "seed": seed, "zone_id": zone_id, "version": version }));
+    context.record(entry);
+
+    Ok(GenerateZoneResponse {
         zone_id,
+        version,
         zone_type: payload.zone_type,
         seed,
         checksum,
+        overrides_applied: overrides,
         entities: world_output.entities,
         terrain: world_output.terrain,
+        watermark,
     })
 }
 
@@ -0,0 +1,122 @@
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use crate::engine::override_engine::OverrideSpec;
+use crate::engine::simulation::{self, Simulation, SimSummary, TickSnapshot, TimedOverride};
+use crate::routes::{project_for, ApiError};
+use crate::security::session::Principal;
+use crate::state::SharedState;
+use crate::store::StoreError;
+
+const MAX_TICKS: u32 = 36_000;
+const MAX_SNAPSHOTS: u32 = 2_000;
+const MAX_TICK_RATE_HZ: u32 = 60;
+
+#[derive(Deserialize)]
+pub struct SimulateRequest {
+    pub version: Option<u32>,
+    pub ticks: u32,
+    pub tick_rate_hz: Option<u32>,
+    pub seed: Option<u64>,
+    pub snapshot_every: Option<u32>,
+    #[serde(default)]
+    pub overrides: Vec<SimOverrideRequest>,
+}
+
+/// Same shape as `POST /v5/override`, applied at `at_tick` of the run only.
+#[derive(Deserialize)]
+pub struct SimOverrideRequest {
+    pub at_tick: u32,
+    pub target_type: String,
+    pub target_id: Option<String>,
+    pub behavior: String,
+    #[serde(default)]
+    pub parameters: serde_json::Value,
+    pub priority: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct SimulateResponse {
+    pub zone_id: String,
+    pub version: u32,
+    pub seed: u64,
+    pub tick_rate_hz: u32,
+    pub snapshots: Vec<TickSnapshot>,
+    pub summary: SimSummary,
+}
+
+/// Runs a headless preview of a zone version. Nothing is written back; the
+/// stored zone is left exactly as it was.
+pub async fn simulate_zone(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path((project_id, zone_id)): Path<(String, String)>,
+    Json(payload): Json<SimulateRequest>,
+) -> Result<Json<SimulateResponse>, ApiError> {
+    let tick_rate_hz = payload.tick_rate_hz.unwrap_or(simulation::DEFAULT_TICK_RATE_HZ);
+    let snapshot_every = payload.snapshot_every.unwrap_or(1).max(1);
+    if payload.ticks == 0 || payload.ticks > MAX_TICKS {
+        return Err(ApiError::BadRequest(format!("ticks must be between 1 and {}", MAX_TICKS)));
+    }
+    if tick_rate_hz == 0 || tick_rate_hz > MAX_TICK_RATE_HZ {
+        return Err(ApiError::BadRequest(format!("tick_rate_hz must be between 1 and {}", MAX_TICK_RATE_HZ)));
+    }
+    if payload.ticks / snapshot_every > MAX_SNAPSHOTS {
+        return Err(ApiError::BadRequest(format!(
+            "At most {} snapshots per run; raise snapshot_every", MAX_SNAPSHOTS
+        )));
+    }
+
+    let overrides = payload
+        .overrides
+        .iter()
+        .enumerate()
+        .map(|(i, o)| {
+            if o.at_tick == 0 || o.at_tick > payload.ticks {
+                return Err(ApiError::BadRequest(format!("overrides[{}].at_tick is outside the run", i)));
+            }
+            let spec = OverrideSpec::parse(
+                &format!("sim-{}", i),
+                &o.target_type,
+                o.target_id.as_deref(),
+                &o.behavior,
+                &o.parameters,
+                o.priority,
+            )?;
+            Ok(TimedOverride { tick: o.at_tick, spec })
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+
+    let project = project_for(&state, &principal, &project_id)?;
+    let zone = project.zone(&zone_id)?;
+    let version = payload.version.unwrap_or(zone.head);
+    let snapshot = zone.version(version).ok_or_else(|| StoreError::VersionNotFound {
+        zone_id: zone_id.clone(),
+        version,
+    })?;
+    let seed = payload.seed.unwrap_or(snapshot.seed);
+
+    let sim = Simulation::new(snapshot.world.clone(), snapshot.claims.clone(), seed, tick_rate_hz);
+    let ticks = payload.ticks;
+    let (snapshots, summary) = tokio::task::spawn_blocking(move || {
+        simulation::run(sim, ticks, snapshot_every, &overrides)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("Simulation task failed: {}", e)))?;
+
+    tracing::info!(
+        "Simulated zone {} v{} for {} ticks: {} casualties",
+        zone_id, version, summary.ticks, summary.casualties
+    );
+
+    Ok(Json(SimulateResponse {
+        zone_id,
+        version,
+        seed,
+        tick_rate_hz,
+        snapshots,
+        summary,
+    }))
+}
@@ -0,0 +1,107 @@
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::routes::ApiError;
+use crate::security::policy::permissions;
+use crate::security::quota;
+use crate::security::rbac;
+use crate::security::session::Principal;
+use crate::state::SharedState;
+use crate::store::{Metric, User};
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    /// Another user's usage; needs `users:list` and, except for admins, the
+    /// same tenant.
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct UsageResponse {
+    pub user_id: String,
+    pub tenant: String,
+    pub tier: String,
+    pub generations: QuotaUsage,
+    pub exports: QuotaUsage,
+    pub projects: QuotaUsage,
+    /// Uses by everyone in the tenant over the same windows.
+    pub tenant_usage: TenantUsage,
+}
+
+/// `limit` and `remaining` are `null` when the tier is unlimited.
+#[derive(Serialize)]
+pub struct QuotaUsage {
+    pub limit: Option<u32>,
+    pub used: u32,
+    pub remaining: Option<u32>,
+    /// Rolling window length; `null` for the project cap.
+    pub window_seconds: Option<i64>,
+    /// When the oldest counted use leaves the window.
+    pub reset_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct TenantUsage {
+    pub generations: u32,
+    pub exports: u32,
+}
+
+impl QuotaUsage {
+    fn new(limit: u32, used: u32, window_seconds: Option<i64>, reset_at: Option<DateTime<Utc>>) -> Self {
+        let limit = (limit != u32::MAX).then_some(limit);
+        Self { remaining: limit.map(|l| l.saturating_sub(used)), limit, used, window_seconds, reset_at }
+    }
+}
+
+pub async fn usage(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<UsageResponse>, Response> {
+    let user_id = match query.user {
+        Some(user) if user != principal.user_id => {
+            principal.require(permissions::USERS_LIST).map_err(IntoResponse::into_response)?;
+            user
+        }
+        _ => principal.user_id.clone(),
+    };
+    let user = state
+        .users
+        .get(&user_id)
+        .ok()
+        .filter(|user| principal.sees_tenant(&user.tenant))
+        .ok_or_else(|| ApiError::NotFound(format!("User not found: {}", user_id)).into_response())?;
+    Ok(Json(report(&state, user)))
+}
+
+fn report(state: &SharedState, user: User) -> UsageResponse {
+    let now = Utc::now();
+    let tier = user.tier();
+    let limits = rbac::get_tier_limits(&tier);
+    let metered = |metric: Metric| {
+        let window = state.usage.user_window(&user.id, metric, now);
+        QuotaUsage::new(
+            quota::limit(&limits, metric),
+            window.used,
+            Some(metric.window().num_seconds()),
+            window.next_release,
+        )
+    };
+
+    UsageResponse {
+        generations: metered(Metric::Generations),
+        exports: metered(Metric::Exports),
+        projects: QuotaUsage::new(limits.max_projects, quota::owned_projects(state, &user.id), None, None),
+        tenant_usage: TenantUsage {
+            generations: state.usage.tenant_window(&user.tenant, Metric::Generations, now).used,
+            exports: state.usage.tenant_window(&user.tenant, Metric::Exports, now).used,
+        },
+        user_id: user.id,
+        tenant: user.tenant,
+        tier,
+    }
+}
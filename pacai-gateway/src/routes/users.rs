@@ -0,0 +1,399 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::audit::middleware::RequestContext;
+use crate::routes::ApiError;
+use crate::security::policy::permissions;
+use crate::security::rbac;
+use crate::security::session::Principal;
+use crate::store::users::PasswordHash;
+use crate::store::{User, UserError};
+use crate::state::SharedState;
+use crate::util::logging::{AuditEntry, AuditEventType};
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+#[derive(Deserialize)]
+pub struct CreateUserRequest {
+    pub id: String,
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Defaults to a legacy role name that is also a tier (`creator`),
+    /// otherwise to the tier the roles imply.
+    #[serde(default)]
+    pub tier: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateUserRequest {
+    pub roles: Option<Vec<String>>,
+    pub tenant: Option<String>,
+    pub disabled: Option<bool>,
+    pub tier: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LockRequest {
+    pub minutes: u32,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordRequest {
+    pub password: String,
+}
+
+#[derive(Deserialize, Default)]
+pub struct IssueKeyRequest {
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// A user as the API shows it: no password or key hashes.
+#[derive(Serialize)]
+pub struct UserResponse {
+    pub id: String,
+    pub roles: Vec<String>,
+    pub tenant: String,
+    pub tier: String,
+    pub disabled: bool,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub has_password: bool,
+    pub api_keys: Vec<KeySummary>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Serialize)]
+pub struct KeySummary {
+    pub id: String,
+    pub label: Option<String>,
+    pub created_at: String,
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        Self {
+            tier: user.tier(),
+            has_password: user.password.is_some(),
+            api_keys: user
+                .api_keys
+                .into_iter()
+                .map(|k| KeySummary { id: k.id, label: k.label, created_at: k.created_at })
+                .collect(),
+            id: user.id,
+            roles: user.roles,
+            tenant: user.tenant,
+            disabled: user.disabled,
+            locked_until: user.locked_until.filter(|until| *until > Utc::now()),
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+/// Returned once, when a key is issued.
+#[derive(Serialize)]
+pub struct IssuedKeyResponse {
+    pub id: String,
+    pub key: String,
+    pub label: Option<String>,
+    pub created_at: String,
+}
+
+fn validate_id(id: &str) -> Result<(), ApiError> {
+    let valid = !id.is_empty()
+        && id.len() <= 64
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '@' | '-'));
+    if !valid {
+        return Err(ApiError::BadRequest(
+            "User id must be 1-64 characters of letters, digits, '.', '_', '@' or '-'".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// The roles, each checked against the policy.
+fn policy_roles(roles: Vec<String>) -> Result<Vec<String>, ApiError> {
+    if roles.is_empty() {
+        return Err(ApiError::BadRequest("A user needs at least one role".into()));
+    }
+    let mut resolved = Vec::with_capacity(roles.len());
+    for role in roles {
+        let role = rbac::resolve_role(&role).ok_or_else(|| ApiError::BadRequest(format!("Unknown role: {}", role)))?;
+        if !resolved.contains(&role) {
+            resolved.push(role);
+        }
+    }
+    Ok(resolved)
+}
+
+fn validate_tier(tier: &str) -> Result<(), ApiError> {
+    if !rbac::TIERS.contains(&tier) {
+        return Err(ApiError::BadRequest(format!("Unknown tier: {}", tier)));
+    }
+    Ok(())
+}
+
+/// PBKDF2 is deliberately slow, so hashing stays off the async workers.
+async fn hash_password(password: String) -> Result<PasswordHash, ApiError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(ApiError::BadRequest(format!(
+            "Passwords must be at least {} characters",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+    tokio::task::spawn_blocking(move || PasswordHash::new(&password))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))
+}
+
+fn user_event(context: &RequestContext, action: &str, user_id: &str, details: serde_json::Value) -> AuditEntry {
+    AuditEntry::new(AuditEventType::User, &context.actor, action, user_id, Some(details))
+}
+
+/// Every user id in a route goes through here. Someone else's tenant is
+/// reported as missing, the same as for projects.
+fn user_for(state: &SharedState, principal: &Principal, id: &str) -> Result<User, ApiError> {
+    let user = state.users.get(id)?;
+    if !principal.sees_tenant(&user.tenant) {
+        return Err(UserError::NotFound { id: id.to_string() }.into());
+    }
+    Ok(user)
+}
+
+/// Only global admins may put an account in a tenant other than their own.
+fn check_tenant(principal: &Principal, tenant: &str) -> Result<(), ApiError> {
+    if !principal.sees_tenant(tenant) {
+        return Err(ApiError::BadRequest(format!("Cannot place users in tenant {}", tenant)));
+    }
+    Ok(())
+}
+
+pub async fn list_users(State(state): State<SharedState>, principal: Principal) -> Json<Vec<UserResponse>> {
+    Json(
+        state
+            .users
+            .list()
+            .into_iter()
+            .filter(|user| principal.sees_tenant(&user.tenant))
+            .map(UserResponse::from)
+            .collect(),
+    )
+}
+
+/// A new account is a role assignment too, so this also needs `roles:assign`.
+/// It lands in the caller's tenant unless a global admin names another.
+pub async fn create_user(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    Json(payload): Json<CreateUserRequest>,
+) -> Result<(StatusCode, Json<UserResponse>), Response> {
+    principal.require(permissions::ROLES_ASSIGN).map_err(IntoResponse::into_response)?;
+    insert_user(&state, &context, &principal, payload).await.map_err(IntoResponse::into_response)
+}
+
+async fn insert_user(
+    state: &SharedState,
+    context: &RequestContext,
+    principal: &Principal,
+    payload: CreateUserRequest,
+) -> Result<(StatusCode, Json<UserResponse>), ApiError> {
+    validate_id(&payload.id)?;
+    let tier = payload
+        .tier
+        .or_else(|| payload.roles.iter().find(|role| rbac::TIERS.contains(&role.as_str())).cloned());
+    if let Some(tier) = &tier {
+        validate_tier(tier)?;
+    }
+    let roles = policy_roles(payload.roles)?;
+
+    let tenant = payload.tenant.unwrap_or_else(|| principal.tenant.clone());
+    check_tenant(principal, &tenant)?;
+    let mut user = User::new(&payload.id, roles, &tenant);
+    user.tier = tier;
+    if let Some(password) = payload.password {
+        user.password = Some(hash_password(password).await?);
+    }
+    let user = state.users.create(user)?;
+    context.record(user_event(
+        context,
+        "user_create",
+        &user.id,
+        serde_json::json!({ "roles": user.roles, "tenant": user.tenant, "tier": user.tier() }),
+    ));
+
+    Ok((StatusCode::CREATED, Json(UserResponse::from(user))))
+}
+
+pub async fn get_user(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Json<UserResponse>, ApiError> {
+    Ok(Json(UserResponse::from(user_for(&state, &principal, &id)?)))
+}
+
+/// Role assignment, tenant moves and enabling or disabling the account.
+/// Changing roles needs `roles:assign` on top of `users:modify`.
+pub async fn update_user(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateUserRequest>,
+) -> Result<Json<UserResponse>, Response> {
+    if payload.roles.is_some() {
+        principal.require(permissions::ROLES_ASSIGN).map_err(IntoResponse::into_response)?;
+    }
+    modify_user(&state, &context, &principal, &id, payload).map_err(IntoResponse::into_response)
+}
+
+fn modify_user(
+    state: &SharedState,
+    context: &RequestContext,
+    principal: &Principal,
+    id: &str,
+    payload: UpdateUserRequest,
+) -> Result<Json<UserResponse>, ApiError> {
+    let roles = payload.roles.map(policy_roles).transpose()?;
+    if let Some(tier) = &payload.tier {
+        validate_tier(tier)?;
+    }
+    let previous = user_for(state, principal, id)?;
+    if let Some(tenant) = &payload.tenant {
+        check_tenant(principal, tenant)?;
+    }
+    let user = state.users.update(id, |u| {
+        if let Some(roles) = roles {
+            u.roles = roles;
+        }
+        if let Some(tenant) = payload.tenant {
+            u.tenant = tenant;
+        }
+        if let Some(disabled) = payload.disabled {
+            u.disabled = disabled;
+        }
+        if let Some(tier) = payload.tier {
+            u.tier = Some(tier);
+        }
+    })?;
+    context.record(user_event(
+        context,
+        "user_update",
+        &user.id,
+        serde_json::json!({
+            "roles": { "from": previous.roles, "to": user.roles },
+            "tenant": { "from": previous.tenant, "to": user.tenant },
+            "disabled": { "from": previous.disabled, "to": user.disabled },
+            "tier": { "from": previous.tier(), "to": user.tier() },
+        }),
+    ));
+
+    Ok(Json(UserResponse::from(user)))
+}
+
+pub async fn delete_user(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    user_for(&state, &principal, &id)?;
+    let user = state.users.delete(&id)?;
+    context.record(user_event(&context, "user_delete", &user.id, serde_json::json!({ "roles": user.roles })));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Locks the account for a while. Use `disabled` to switch it off for good.
+pub async fn lock_user(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    Path(id): Path<String>,
+    Json(payload): Json<LockRequest>,
+) -> Result<Json<UserResponse>, ApiError> {
+    if payload.minutes == 0 {
+        return Err(ApiError::BadRequest("minutes must be greater than zero".into()));
+    }
+    user_for(&state, &principal, &id)?;
+    let until = Utc::now() + chrono::Duration::minutes(payload.minutes.into());
+    let user = state.users.update(&id, |u| u.locked_until = Some(until))?;
+    context.record(user_event(&context, "user_lock", &user.id, serde_json::json!({ "until": until })));
+    Ok(Json(UserResponse::from(user)))
+}
+
+/// Lifts a lock, whether set by an administrator or by failed logins.
+pub async fn unlock_user(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Json<UserResponse>, ApiError> {
+    user_for(&state, &principal, &id)?;
+    let user = state.users.update(&id, |u| {
+        u.locked_until = None;
+        u.failed_logins = 0;
+    })?;
+    context.record(user_event(&context, "user_unlock", &user.id, serde_json::json!({})));
+    Ok(Json(UserResponse::from(user)))
+}
+
+pub async fn set_password(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    Path(id): Path<String>,
+    Json(payload): Json<PasswordRequest>,
+) -> Result<StatusCode, ApiError> {
+    user_for(&state, &principal, &id)?;
+    let hash = hash_password(payload.password).await?;
+    let user = state.users.update(&id, |u| {
+        u.password = Some(hash);
+        u.failed_logins = 0;
+    })?;
+    context.record(user_event(&context, "user_password", &user.id, serde_json::json!({})));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn issue_key(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    Path(id): Path<String>,
+    payload: Option<Json<IssueKeyRequest>>,
+) -> Result<(StatusCode, Json<IssuedKeyResponse>), ApiError> {
+    user_for(&state, &principal, &id)?;
+    let Json(payload) = payload.unwrap_or_default();
+    let (key, record) = state.users.issue_key(&id, payload.label)?;
+    context.record(user_event(
+        &context,
+        "user_key_issue",
+        &id,
+        serde_json::json!({ "key_id": record.id, "label": record.label }),
+    ));
+
+    Ok((
+        StatusCode::CREATED,
+        Json(IssuedKeyResponse { id: record.id, key, label: record.label, created_at: record.created_at }),
+    ))
+}
+
+pub async fn revoke_key(
+    State(state): State<SharedState>,
+    context: RequestContext,
+    principal: Principal,
+    Path((id, key_id)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    user_for(&state, &principal, &id)?;
+    state.users.revoke_key(&id, &key_id)?;
+    context.record(user_event(&context, "user_key_revoke", &id, serde_json::json!({ "key_id": key_id })));
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,210 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use crate::engine::{diff, world, zone_overrides::ZoneOverrides};
+use crate::live::LiveMessage;
+use crate::routes::{project_for, ApiError};
+use crate::security::session::Principal;
+use crate::state::SharedState;
+use crate::store::{StoreError, VersionSource, ZoneRecord, ZoneVersion};
+
+#[derive(Serialize)]
+pub struct ZoneSummary {
+    pub zone_id: String,
+    pub zone_type: String,
+    pub head: u32,
+    pub version_count: usize,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Serialize)]
+pub struct VersionSummary {
+    pub version: u32,
+    pub seed: u64,
+    pub checksum: String,
+    pub created_at: String,
+    pub source: VersionSource,
+    pub is_head: bool,
+    pub entity_count: usize,
+}
+
+#[derive(Serialize)]
+pub struct ZoneHistoryResponse {
+    pub zone_id: String,
+    pub head: u32,
+    pub versions: Vec<VersionSummary>,
+}
+
+#[derive(Serialize)]
+pub struct ZoneVersionResponse {
+    pub zone_id: String,
+    pub version: u32,
+    pub seed: u64,
+    pub checksum: String,
+    pub created_at: String,
+    pub source: VersionSource,
+    pub overrides: Option<ZoneOverrides>,
+    pub world: world::WorldOutput,
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    pub from: u32,
+    pub to: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct ZoneDiffResponse {
+    pub zone_id: String,
+    pub from_version: u32,
+    pub to_version: u32,
+    pub identical: bool,
+    pub diff: diff::ZoneDiff,
+}
+
+#[derive(Deserialize)]
+pub struct RollbackRequest {
+    pub version: u32,
+}
+
+#[derive(Serialize)]
+pub struct RollbackResponse {
+    pub zone_id: String,
+    pub restored_version: u32,
+    pub head: u32,
+    pub checksum: String,
+}
+
+impl From<&ZoneRecord> for ZoneSummary {
+    fn from(zone: &ZoneRecord) -> Self {
+        Self {
+            zone_id: zone.zone_id.clone(),
+            zone_type: zone.zone_type.clone(),
+            head: zone.head,
+            version_count: zone.versions.len(),
+            created_at: zone.created_at.clone(),
+            updated_at: zone.updated_at.clone(),
+        }
+    }
+}
+
+fn find_version(zone: &ZoneRecord, version: u32) -> Result<&ZoneVersion, StoreError> {
+    zone.version(version).ok_or_else(|| StoreError::VersionNotFound {
+        zone_id: zone.zone_id.clone(),
+        version,
+    })
+}
+
+pub async fn list_zones(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<ZoneSummary>>, ApiError> {
+    let project = project_for(&state, &principal, &project_id)?;
+    Ok(Json(project.zones.iter().map(ZoneSummary::from).collect()))
+}
+
+pub async fn zone_history(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path((project_id, zone_id)): Path<(String, String)>,
+) -> Result<Json<ZoneHistoryResponse>, ApiError> {
+    let project = project_for(&state, &principal, &project_id)?;
+    let zone = project.zone(&zone_id)?;
+
+    let versions = zone.versions.iter().map(|v| VersionSummary {
+        version: v.version,
+        seed: v.seed,
+        checksum: v.checksum.clone(),
+        created_at: v.created_at.clone(),
+        source: v.source.clone(),
+        is_head: v.version == zone.head,
+        entity_count: v.world.entities.len(),
+    }).collect();
+
+    Ok(Json(ZoneHistoryResponse {
+        zone_id: zone.zone_id.clone(),
+        head: zone.head,
+        versions,
+    }))
+}
+
+pub async fn get_zone_version(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path((project_id, zone_id, version)): Path<(String, String, u32)>,
+) -> Result<Json<ZoneVersionResponse>, ApiError> {
+    let project = project_for(&state, &principal, &project_id)?;
+    let zone = project.zone(&zone_id)?;
+    let snapshot = find_version(zone, version)?;
+
+    Ok(Json(ZoneVersionResponse {
+        zone_id: zone.zone_id.clone(),
+        version: snapshot.version,
+        seed: snapshot.seed,
+        checksum: snapshot.checksum.clone(),
+        created_at: snapshot.created_at.clone(),
+        source: snapshot.source.clone(),
+        overrides: snapshot.overrides.clone(),
+        world: snapshot.world.clone(),
+    }))
+}
+
+pub async fn diff_zone(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path((project_id, zone_id)): Path<(String, String)>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<ZoneDiffResponse>, ApiError> {
+    let project = project_for(&state, &principal, &project_id)?;
+    let zone = project.zone(&zone_id)?;
+
+    let to_version = query.to.unwrap_or(zone.head);
+    let from = find_version(zone, query.from)?;
+    let to = find_version(zone, to_version)?;
+
+    let diff = diff::diff_worlds(&from.world, &to.world);
+
+    Ok(Json(ZoneDiffResponse {
+        zone_id: zone.zone_id.clone(),
+        from_version: from.version,
+        to_version: to.version,
+        identical: diff.is_empty(),
+        diff,
+    }))
+}
+
+pub async fn rollback_zone(
+    State(state): State<SharedState>,
+    principal: Principal,
+    Path((project_id, zone_id)): Path<(String, String)>,
+    Json(payload): Json<RollbackRequest>,
+) -> Result<Json<RollbackResponse>, ApiError> {
+    project_for(&state, &principal, &project_id)?;
+    let (_, restored) = state.projects.try_update(&project_id, |p| {
+        let zone = p.zone_mut(&zone_id)?;
+        zone.rollback(payload.version).cloned().ok_or_else(|| StoreError::VersionNotFound {
+            zone_id: zone_id.clone(),
+            version: payload.version,
+        })
+    })?;
+
+    tracing::info!("Zone {} rolled back to v{} as v{}", zone_id, payload.version, restored.version);
+
+    state.live.publish(&project_id, LiveMessage::ZoneVersion {
+        zone_id: zone_id.clone(),
+        version: restored.version,
+        checksum: restored.checksum.clone(),
+        source: restored.source.clone(),
+    });
+
+    Ok(Json(RollbackResponse {
+        zone_id,
+        restored_version: payload.version,
+        head: restored.version,
+        checksum: restored.checksum,
+    }))
+}
@@ -1,14 +1,7 @@
 use serde::{Deserialize, Serialize};
 use ed25519_dalek::{SigningKey, VerifyingKey, Signature, Signer, Verifier};
 use sha2::{Sha256, Digest};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LicenseResponse {
-    pub valid: bool,
-    pub tier: String,
-    pub expiry: u64,
-    pub hardware_id: String,
-}
+use crate::util::json::canonical_json;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HsmConfig {
@@ -25,15 +18,22 @@ pub struct SignedLicense {
     pub tier: String,
     pub expiry_timestamp: u64,
     pub issued_at: u64,
+    #[serde(default)]
+    pub seats: u32,
+    #[serde(default)]
+    pub features: Vec<String>,
     pub signature: Vec<u8>,
 }
 
-pub fn verify_license() -> LicenseResponse {
-    LicenseResponse {
-        valid: true,
-        tier: "lifetime".into(),
-        expiry: u64::MAX,
-        hardware_id: "YubiHSM-87F3-12AC".into(),
+impl SignedLicense {
+    /// Everything the signature covers: the canonical JSON of every other
+    /// field, so no two different licenses sign the same bytes.
+    pub fn signed_data(&self) -> Vec<u8> {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("signature");
+        }
+        canonical_json(&value).into_bytes()
     }
 }
 
@@ -60,6 +60,27 @@ impl HsmManager {
         }
     }
     
+    /// A manager that holds only a public key: it can check signatures,
+    /// such as the vendor's on a license, but not make any.
+    pub fn verifier(device_path: &str, verifying_key: VerifyingKey) -> Self {
+        Self {
+            signing_key: None,
+            verifying_key: Some(verifying_key),
+            device_path: device_path.to_string(),
+        }
+    }
+
+    /// A manager with a fresh key that is never written anywhere.
+    #[cfg(test)]
+    pub fn ephemeral(device_path: &str) -> Self {
+        let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+        Self {
+            verifying_key: Some(signing_key.verifying_key()),
+            signing_key: Some(signing_key),
+            device_path: device_path.to_string(),
+        }
+    }
+
     pub fn initialize(&mut self) -> Result<(), HsmError> {
         let mut csprng = rand::rngs::OsRng;
         let signing_key = SigningKey::generate(&mut csprng);
@@ -72,12 +93,51 @@ impl HsmManager {
         Ok(())
     }
     
-    pub fn sign_license(&self, license_data: &[u8]) -> Result<Vec<u8>, HsmError> {
+    /// Loads the signing key stored at `key_path`, generating and storing a
+    /// new one on first use so signatures stay verifiable across restarts.
+    pub fn load_or_initialize(&mut self, key_path: &std::path::Path) -> Result<(), HsmError> {
+        let signing_key = match std::fs::read_to_string(key_path) {
+            Ok(text) => {
+                let bytes: [u8; 32] = hex::decode(text.trim())
+                    .ok()
+                    .and_then(|b| b.try_into().ok())
+                    .ok_or_else(|| HsmError::KeyStorage(format!("{} is not a hex Ed25519 key", key_path.display())))?;
+                SigningKey::from_bytes(&bytes)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+                write_key(key_path, &signing_key).map_err(|e| HsmError::KeyStorage(e.to_string()))?;
+                tracing::info!("Generated signing key at {}", key_path.display());
+                signing_key
+            }
+            Err(e) => return Err(HsmError::KeyStorage(e.to_string())),
+        };
+
+        self.verifying_key = Some(signing_key.verifying_key());
+        self.signing_key = Some(signing_key);
+        tracing::info!("HSM initialized: {}", self.device_path);
+        Ok(())
+    }
+
+    pub fn verifying_key(&self) -> Option<VerifyingKey> {
+        self.verifying_key
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>, HsmError> {
         let signing_key = self.signing_key.as_ref()
             .ok_or(HsmError::NotInitialized)?;
-        
-        let signature = signing_key.sign(license_data);
-        Ok(signature.to_bytes().to_vec())
+
+        Ok(signing_key.sign(data).to_bytes().to_vec())
+    }
+
+    /// The key itself, for the export packager, which signs bundles with it
+    /// directly.
+    pub fn signing_key(&self) -> Result<&SigningKey, HsmError> {
+        self.signing_key.as_ref().ok_or(HsmError::NotInitialized)
+    }
+
+    pub fn sign_license(&self, license_data: &[u8]) -> Result<Vec<u8>, HsmError> {
+        self.sign(license_data)
     }
     
     pub fn verify_signature(&self, data: &[u8], signature: &[u8]) -> Result<bool, HsmError> {
@@ -91,52 +151,81 @@ impl HsmManager {
         Ok(verifying_key.verify(data, &signature).is_ok())
     }
     
-    pub fn create_license(&self, tier: &str, hardware_id: &str, days_valid: u32) -> Result<SignedLicense, HsmError> {
+    pub fn create_license(
+        &self,
+        tier: &str,
+        hardware_id: &str,
+        days_valid: u32,
+        seats: u32,
+        features: Vec<String>,
+    ) -> Result<SignedLicense, HsmError> {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        
-        let expiry = now + (days_valid as u64 * 24 * 60 * 60);
-        
-        let license_data = format!("{}:{}:{}:{}", 
-            hardware_id, tier, expiry, now);
-        
-        let signature = self.sign_license(license_data.as_bytes())?;
-        
-        Ok(SignedLicense {
+
+        let mut license = SignedLicense {
             license_id: uuid::Uuid::new_v4().to_string(),
             hardware_id: hardware_id.to_string(),
             tier: tier.to_string(),
-            expiry_timestamp: expiry,
+            expiry_timestamp: now + (days_valid as u64 * 24 * 60 * 60),
             issued_at: now,
-            signature,
-        })
+            seats,
+            features,
+            signature: Vec::new(),
+        };
+        license.signature = self.sign_license(&license.signed_data())?;
+        Ok(license)
     }
-    
-    pub fn validate_license(&self, license: &SignedLicense) -> Result<bool, HsmError> {
+
+    /// Checks the signature, expiry and hardware binding of `license`.
+    pub fn validate_license(&self, license: &SignedLicense) -> Result<(), HsmError> {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        
+
+        if !self.verify_signature(&license.signed_data(), &license.signature)? {
+            return Err(HsmError::InvalidSignature);
+        }
         if license.expiry_timestamp < now {
-            return Ok(false);
+            return Err(HsmError::ValidationFailed { reason: "license has expired".into() });
         }
-        
         let current_hardware_id = get_hardware_id();
         if license.hardware_id != current_hardware_id {
-            tracing::warn!("Hardware ID mismatch: expected {}, got {}", 
-                license.hardware_id, current_hardware_id);
+            return Err(HsmError::ValidationFailed {
+                reason: format!("license is bound to {}, this machine is {}", license.hardware_id, current_hardware_id),
+            });
         }
-        
-        let license_data = format!("{}:{}:{}:{}", 
-            license.hardware_id, license.tier, license.expiry_timestamp, license.issued_at);
-        
-        self.verify_signature(license_data.as_bytes(), &license.signature)
+        Ok(())
     }
 }
 
+fn write_key(path: &std::path::Path, key: &SigningKey) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, hex::encode(key.to_bytes()).as_bytes())
+}
+
+/// Checks `signature` over `data` against a specific public key, for
+/// verifiers that hold the key but not an `HsmManager`.
+pub fn verify_with_key(key: &VerifyingKey, data: &[u8], signature: &[u8]) -> bool {
+    let Ok(bytes) = <[u8; 64]>::try_from(signature) else {
+        return false;
+    };
+    key.verify(data, &Signature::from_bytes(&bytes)).is_ok()
+}
+
+pub fn parse_public_key(hex_key: &str) -> Option<VerifyingKey> {
+    let bytes: [u8; 32] = hex::decode(hex_key.trim()).ok()?.try_into().ok()?;
+    VerifyingKey::from_bytes(&bytes).ok()
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum HsmError {
     #[error("HSM not initialized")]
@@ -153,4 +242,7 @@ pub enum HsmError {
     
     #[error("License validation failed: {reason}")]
     ValidationFailed { reason: String },
+
+    #[error("Key storage error: {0}")]
+    KeyStorage(String),
 }
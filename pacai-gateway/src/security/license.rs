@@ -0,0 +1,124 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use ed25519_dalek::VerifyingKey;
+use std::path::Path;
+use crate::audit::middleware::FailureReason;
+use crate::routes::error::ErrorBody;
+use crate::security::hsm::{self, HsmManager, SignedLicense};
+use crate::state::SharedState;
+
+/// Public half of the key PacAI signs licenses with. Only the vendor holds
+/// the private half, so a license cannot be minted on site.
+pub const VENDOR_PUBLIC_KEY: &str = "50cf425bf087ba08e602e1bac0895fa161281e3939d2c0e6e072e2a7518eef73";
+
+pub fn vendor_key() -> VerifyingKey {
+    hsm::parse_public_key(VENDOR_PUBLIC_KEY).expect("embedded vendor key is a valid Ed25519 key")
+}
+
+/// The license file read at startup, checked against the vendor key.
+pub struct Licensing {
+    verifier: HsmManager,
+    loaded: Result<SignedLicense, LicenseError>,
+}
+
+impl Licensing {
+    pub fn load(path: &Path, vendor_key: VerifyingKey) -> Self {
+        let loaded = match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| LicenseError::Unreadable {
+                path: path.display().to_string(),
+                message: e.to_string(),
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(LicenseError::Missing { path: path.display().to_string() })
+            }
+            Err(e) => Err(LicenseError::Unreadable { path: path.display().to_string(), message: e.to_string() }),
+        };
+        Self { verifier: HsmManager::verifier("vendor", vendor_key), loaded }
+    }
+
+    /// The license, if it is valid now. It is validated on every call so
+    /// that one expiring while the gateway runs stops counting.
+    pub fn current(&self) -> Result<&SignedLicense, LicenseError> {
+        let license = self.loaded.as_ref().map_err(Clone::clone)?;
+        self.verifier
+            .validate_license(license)
+            .map_err(|e| LicenseError::Rejected { reason: e.to_string() })?;
+        Ok(license)
+    }
+}
+
+/// Refuses the request unless a valid license is loaded. Generation,
+/// overrides and export sit behind it; an unlicensed gateway still serves
+/// reads, the audit log and user administration.
+pub async fn require(State(state): State<SharedState>, request: Request, next: Next) -> Result<Response, LicenseError> {
+    state.license.current()?;
+    Ok(next.run(request).await)
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum LicenseError {
+    #[error("No license file at {path}")]
+    Missing { path: String },
+
+    #[error("License file {path} is unreadable: {message}")]
+    Unreadable { path: String, message: String },
+
+    #[error("License rejected: {reason}")]
+    Rejected { reason: String },
+}
+
+/// 423, as `/v5/license` answers without a valid license.
+impl IntoResponse for LicenseError {
+    fn into_response(self) -> Response {
+        let message = self.to_string();
+        let body = ErrorBody { error: "unlicensed".into(), message: message.clone() };
+        let mut response = (StatusCode::LOCKED, Json(body)).into_response();
+        response.extensions_mut().insert(FailureReason(message));
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_only_licenses_signed_by_the_vendor_for_this_machine_count() {
+        let dir = tempdir().unwrap();
+        let vendor = HsmManager::ephemeral("test");
+        let key = vendor.verifying_key().unwrap();
+        let path = dir.path().join("license.json");
+        let write = |license: &SignedLicense| std::fs::write(&path, serde_json::to_vec(license).unwrap()).unwrap();
+
+        assert!(matches!(Licensing::load(&path, key).current(), Err(LicenseError::Missing { .. })));
+
+        let license = vendor.create_license("enterprise", &hsm::get_hardware_id(), 30, 25, vec!["cluster_mode".into()]).unwrap();
+        write(&license);
+        assert_eq!(Licensing::load(&path, key).current().unwrap().seats, 25);
+        assert!(matches!(Licensing::load(&path, vendor_key()).current(), Err(LicenseError::Rejected { .. })));
+
+        let mut raised = license.clone();
+        raised.seats = 500;
+        write(&raised);
+        assert!(Licensing::load(&path, key).current().is_err());
+
+        // Fields cannot be shifted into one another.
+        let joined = vendor.create_license("enterprise", &hsm::get_hardware_id(), 30, 25, vec!["a,b".into()]).unwrap();
+        let split = SignedLicense { features: vec!["a".into(), "b".into()], ..joined.clone() };
+        assert_ne!(joined.signed_data(), split.signed_data());
+        write(&split);
+        assert!(Licensing::load(&path, key).current().is_err());
+
+        let elsewhere = vendor.create_license("enterprise", "HSM-0000000000000000", 30, 25, Vec::new()).unwrap();
+        write(&elsewhere);
+        let Err(LicenseError::Rejected { reason }) = Licensing::load(&path, key).current() else {
+            panic!("license for another machine was accepted");
+        };
+        assert!(reason.contains("HSM-0000000000000000"));
+    }
+}
@@ -1,2 +1,7 @@
 pub mod rbac;
+pub mod quota;
+pub mod rate_limit;
+pub mod policy;
 pub mod hsm;
+pub mod license;
+pub mod session;
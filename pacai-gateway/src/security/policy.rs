@@ -0,0 +1,48 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+pub use pacai_rbac::{covers, permissions, Decision, PolicyError, PolicyFile, RbacPolicy, Role, LEGACY_OPERATOR};
+
+/// The policy in force and where it came from.
+#[derive(Debug)]
+pub struct ActivePolicy {
+    pub policy: RbacPolicy,
+    /// The policy file, or `built-in`.
+    pub source: String,
+}
+
+lazy_static::lazy_static! {
+    static ref ACTIVE: RwLock<Arc<ActivePolicy>> = RwLock::new(Arc::new(ActivePolicy {
+        policy: RbacPolicy::builtin(),
+        source: "built-in".into(),
+    }));
+}
+
+pub fn current() -> Arc<ActivePolicy> {
+    ACTIVE.read().unwrap().clone()
+}
+
+pub fn install(policy: RbacPolicy, source: impl Into<String>) {
+    *ACTIVE.write().unwrap() = Arc::new(ActivePolicy { policy, source: source.into() });
+}
+
+/// Re-reads the policy file every `period` and installs it when it changed.
+/// An unreadable or invalid file is logged and the policy in force is kept.
+pub fn spawn_reload(mut file: PolicyFile, period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            match file.poll() {
+                Ok(Some(policy)) => {
+                    tracing::info!("RBAC policy reloaded from {} ({} roles)", file.path().display(), policy.roles.len());
+                    install(policy, file.path().display().to_string());
+                }
+                Ok(None) => {}
+                Err(e) => tracing::error!("RBAC policy reload failed, keeping the current policy: {}", e),
+            }
+        }
+    })
+}
@@ -0,0 +1,96 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use crate::engine::watermark::{self, Watermark};
+use crate::engine::world;
+use crate::routes::ApiError;
+use crate::security::rbac::{self, RbacError, TierLimits};
+use crate::security::session::{self, Principal};
+use crate::state::{AppState, SharedState};
+use crate::store::{Metric, UsageError};
+
+/// The usage tier of the caller's account.
+pub fn tier_of(state: &AppState, principal: &Principal) -> String {
+    state
+        .users
+        .get(&principal.user_id)
+        .map(|user| user.tier())
+        .unwrap_or_else(|_| rbac::default_tier(&principal.roles))
+}
+
+pub fn limit(limits: &TierLimits, metric: Metric) -> u32 {
+    match metric {
+        Metric::Generations => limits.generations_per_week,
+        Metric::Exports => limits.exports_per_day,
+    }
+}
+
+/// Projects the user created and still has.
+pub fn owned_projects(state: &AppState, user_id: &str) -> u32 {
+    let owner = Some(user_id);
+    state.projects.list().iter().filter(|p| p.owner.as_deref() == owner).count() as u32
+}
+
+/// A watermark for output made for the caller, if their tier requires one,
+/// bound to the entities of that output.
+pub fn watermark_for<'a>(
+    state: &AppState,
+    principal: &Principal,
+    seed: u64,
+    entities: impl IntoIterator<Item = &'a world::Entity>,
+) -> Result<Option<Watermark>, ApiError> {
+    let tier = tier_of(state, principal);
+    if !rbac::get_tier_limits(&tier).watermark {
+        return Ok(None);
+    }
+    let content = watermark::content_hash(entities.into_iter().map(|e| e.id.as_str()));
+    Watermark::issue(&state.signer, &tier, &principal.user_id, &principal.tenant, seed, &content)
+        .map(Some)
+        .map_err(|e| ApiError::Internal(e.to_string()))
+}
+
+/// Counts the request against the caller's rolling window for `metric`.
+/// Only requests that succeed keep their place; a failed one is handed back.
+/// The grant goes into the request extensions for handlers that hand the
+/// work off and answer before it is done.
+async fn metered(state: SharedState, metric: Metric, mut request: Request, next: Next) -> Result<Response, Response> {
+    let principal = session::principal_of(request.extensions()).map_err(IntoResponse::into_response)?;
+    let tier = tier_of(&state, principal);
+    let limit = limit(&rbac::get_tier_limits(&tier), metric);
+
+    let grant = match state.usage.acquire(&principal.user_id, &principal.tenant, metric, limit, Utc::now()) {
+        Ok(grant) => grant,
+        Err(UsageError::Exhausted { reset_at, .. }) => {
+            let message = format!("tier '{}' allows {} {} per {}", tier, limit, metric.as_str(), window_name(metric));
+            return Err(RbacError::TierLimitExceeded { message, reset_at }.into_response());
+        }
+        Err(e) => return Err(ApiError::Internal(e.to_string()).into_response()),
+    };
+    request.extensions_mut().insert(grant.clone());
+
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        if let Err(e) = state.usage.release(&grant) {
+            tracing::error!("Failed to hand back unused {} quota: {}", metric.as_str(), e);
+        }
+    }
+    Ok(response)
+}
+
+fn window_name(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Generations => "week",
+        Metric::Exports => "day",
+    }
+}
+
+pub async fn require_generation(State(state): State<SharedState>, request: Request, next: Next) -> Result<Response, Response> {
+    metered(state, Metric::Generations, request, next).await
+}
+
+pub async fn require_export(State(state): State<SharedState>, request: Request, next: Next) -> Result<Response, Response> {
+    metered(state, Metric::Exports, request, next).await
+}
@@ -0,0 +1,392 @@
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request},
+    http::{HeaderMap, HeaderValue, Method},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::{Layer, Service};
+use crate::security::rbac::{self, RbacError};
+use crate::security::session::Principal;
+use crate::state::SharedState;
+use crate::store::users::MAX_FAILED_LOGINS;
+
+const WINDOW_SECONDS: f64 = 3600.0;
+
+/// How often `check` drops buckets that have been idle for a whole window.
+const PRUNE_EVERY_SECONDS: i64 = 60;
+
+/// Failed logins one address may make against one account in an hour. It
+/// stays below `MAX_FAILED_LOGINS`, so a single client is throttled before
+/// it can lock the account.
+pub const LOGIN_FAILURES_PER_HOUR: u32 = MAX_FAILED_LOGINS - 1;
+
+/// Which bucket a request draws from. Every class has a bucket of its own
+/// per principal, so a dashboard polling reads cannot use up generations.
+/// Requests without a principal draw from buckets of their address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteClass {
+    Generate,
+    Export,
+    Login,
+    Write,
+    Read,
+}
+
+impl RouteClass {
+    pub fn of(method: &Method, route: &str) -> Self {
+        match (method.as_str(), route) {
+            ("POST", "/v5/prompt" | "/v5/projects/:id/generate") => RouteClass::Generate,
+            ("POST", "/v5/export") => RouteClass::Export,
+            ("POST", "/v5/auth/login") => RouteClass::Login,
+            ("GET" | "HEAD" | "OPTIONS", _) => RouteClass::Read,
+            _ => RouteClass::Write,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RouteClass::Generate => "generate",
+            RouteClass::Export => "export",
+            RouteClass::Login => "login",
+            RouteClass::Write => "write",
+            RouteClass::Read => "read",
+        }
+    }
+}
+
+/// A token bucket that holds up to an hour's allowance and refills
+/// continuously.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Bucket {
+    principal: String,
+    class: RouteClass,
+    tokens: f64,
+    updated: DateTime<Utc>,
+}
+
+/// The state of a bucket after a request, as reported in the headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset: u64,
+    /// Seconds until the next request is allowed, when this one was not.
+    pub retry_after: Option<u64>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    by_key: HashMap<(String, RouteClass), Bucket>,
+    pruned: DateTime<Utc>,
+}
+
+impl Buckets {
+    /// Drops buckets unused for a whole window. They have refilled
+    /// completely, which is what a missing bucket starts as.
+    fn prune(&mut self, now: DateTime<Utc>) {
+        self.by_key.retain(|_, b| (now - b.updated).num_seconds() < WINDOW_SECONDS as i64);
+        self.pruned = now;
+    }
+}
+
+/// Token buckets per principal and route class. They live in memory and
+/// idle ones are dropped as requests come in; with a path they are also
+/// saved now and then and restored on start, so a restart does not hand
+/// out fresh allowances.
+#[derive(Default)]
+pub struct RateLimiter {
+    path: Option<PathBuf>,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, RateLimitError> {
+        let path = path.into();
+        let saved: Vec<Bucket> = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let by_key = saved.into_iter().map(|b| ((b.principal.clone(), b.class), b)).collect();
+        Ok(Self { path: Some(path), buckets: Mutex::new(Buckets { by_key, ..Default::default() }) })
+    }
+
+    /// Takes a token from the bucket of `principal` for `class`, if one is left.
+    pub fn check(&self, principal: &str, class: RouteClass, limit_per_hour: u32, now: DateTime<Utc>) -> Outcome {
+        self.draw(principal, class, limit_per_hour, now, true)
+    }
+
+    /// Refuses a login when `address` has used up its failures against
+    /// `username`. Only failures are counted, see `login_failed`.
+    pub fn check_login(&self, username: &str, address: &str, now: DateTime<Utc>) -> Outcome {
+        self.draw(&login_key(username, address), RouteClass::Login, LOGIN_FAILURES_PER_HOUR, now, false)
+    }
+
+    pub fn login_failed(&self, username: &str, address: &str, now: DateTime<Utc>) {
+        self.draw(&login_key(username, address), RouteClass::Login, LOGIN_FAILURES_PER_HOUR, now, true);
+    }
+
+    /// Refills the bucket and, with `take`, takes a token from it. Without,
+    /// the request is refused only if a token could not be taken.
+    fn draw(&self, principal: &str, class: RouteClass, limit_per_hour: u32, now: DateTime<Utc>, take: bool) -> Outcome {
+        let capacity = f64::from(limit_per_hour);
+        let rate = capacity / WINDOW_SECONDS;
+        let mut buckets = self.buckets.lock().unwrap();
+        if (now - buckets.pruned).num_seconds() >= PRUNE_EVERY_SECONDS {
+            buckets.prune(now);
+        }
+        let bucket = buckets.by_key.entry((principal.to_string(), class)).or_insert_with(|| Bucket {
+            principal: principal.to_string(),
+            class,
+            tokens: capacity,
+            updated: now,
+        });
+
+        let elapsed = (now - bucket.updated).num_milliseconds().max(0) as f64 / 1000.0;
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+        let allowed = bucket.tokens >= 1.0;
+        if allowed && take {
+            bucket.tokens -= 1.0;
+        }
+
+        let seconds_until = |tokens: f64| if rate > 0.0 { (tokens / rate).ceil() as u64 } else { WINDOW_SECONDS as u64 };
+        Outcome {
+            allowed,
+            limit: limit_per_hour,
+            remaining: bucket.tokens.floor() as u32,
+            reset: seconds_until(capacity - bucket.tokens),
+            retry_after: (!allowed).then(|| seconds_until(1.0 - bucket.tokens)),
+        }
+    }
+
+    /// Writes the buckets used within the last hour.
+    pub fn save(&self, now: DateTime<Utc>) -> Result<(), RateLimitError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let saved: Vec<Bucket> = {
+            let mut buckets = self.buckets.lock().unwrap();
+            buckets.prune(now);
+            buckets.by_key.values().cloned().collect()
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&saved)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+fn login_key(username: &str, address: &str) -> String {
+    format!("{}@{}", username, address)
+}
+
+/// The IP address a request came from, as the listener recorded it. Requests
+/// handed to the router without one, as in tests, share a bucket.
+pub fn client_address(connect_info: Option<&ConnectInfo<SocketAddr>>) -> String {
+    connect_info.map(|ConnectInfo(addr)| addr.ip().to_string()).unwrap_or_else(|| "unknown".into())
+}
+
+/// The 429 for a request `outcome` refused, with the rate limit headers.
+pub fn refusal(outcome: &Outcome, class: RouteClass, now: DateTime<Utc>) -> Response {
+    let message = format!("{} {} requests per hour", outcome.limit, class.as_str());
+    let reset_at = now + chrono::Duration::seconds(outcome.retry_after.unwrap_or_default() as i64);
+    let mut response = RbacError::RateLimited { message, reset_at }.into_response();
+    set_headers(response.headers_mut(), outcome);
+    response
+}
+
+/// The hourly allowance of the most generous of the principal's roles.
+fn limit_for(principal: &Principal) -> u32 {
+    principal
+        .roles
+        .iter()
+        .filter_map(|role| rbac::get_role_config(role)?.rate_limit_per_hour)
+        .max()
+        .unwrap_or(rbac::DEFAULT_RATE_LIMIT_PER_HOUR)
+}
+
+fn set_headers(headers: &mut HeaderMap, outcome: &Outcome) {
+    headers.insert("ratelimit-limit", HeaderValue::from(outcome.limit));
+    headers.insert("ratelimit-remaining", HeaderValue::from(outcome.remaining));
+    headers.insert("ratelimit-reset", HeaderValue::from(outcome.reset));
+    if let Ok(policy) = HeaderValue::from_str(&format!("{};w={}", outcome.limit, WINDOW_SECONDS as u64)) {
+        headers.insert("ratelimit-policy", policy);
+    }
+}
+
+/// Applies the rate limiter to every request but health probes. Requests
+/// without a principal share a bucket per address at the default allowance.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    state: SharedState,
+}
+
+impl RateLimitLayer {
+    pub fn new(state: SharedState) -> Self {
+        Self { state }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit { inner, state: self.state.clone() }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    state: SharedState,
+}
+
+impl<S> Service<Request> for RateLimit<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The clone that was polled ready serves this request.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|m| m.as_str().to_string())
+            .unwrap_or_else(|| request.uri().path().to_string());
+        if route == "/health" {
+            return Box::pin(inner.call(request));
+        }
+        let class = RouteClass::of(request.method(), &route);
+        let now = Utc::now();
+        let outcome = match request.extensions().get::<Principal>() {
+            Some(principal) => self.state.rate_limits.check(&principal.user_id, class, limit_for(principal), now),
+            None => {
+                let address = client_address(request.extensions().get());
+                let key = format!("anonymous@{}", address);
+                self.state.rate_limits.check(&key, class, rbac::DEFAULT_RATE_LIMIT_PER_HOUR, now)
+            }
+        };
+
+        if !outcome.allowed {
+            let response = refusal(&outcome, class, now);
+            return Box::pin(async move { Ok(response) });
+        }
+        Box::pin(async move {
+            let mut response = inner.call(request).await?;
+            set_headers(response.headers_mut(), &outcome);
+            Ok(response)
+        })
+    }
+}
+
+/// Saves the buckets every `period`, if the limiter has a file.
+pub fn spawn_persist(state: SharedState, period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = state.rate_limits.save(Utc::now()) {
+                tracing::error!("Saving rate limit state failed: {}", e);
+            }
+        }
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RateLimitError {
+    #[error("Rate limit state I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Rate limit state serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_bucket_drains_and_refills_per_class() {
+        let limiter = RateLimiter::default();
+        let start = Utc::now();
+
+        let first = limiter.check("dana", RouteClass::Generate, 3600, start);
+        assert_eq!(first, Outcome { allowed: true, limit: 3600, remaining: 3599, reset: 1, retry_after: None });
+
+        let limiter = RateLimiter::default();
+        for _ in 0..2 {
+            assert!(limiter.check("dana", RouteClass::Generate, 2, start).allowed);
+        }
+        let refused = limiter.check("dana", RouteClass::Generate, 2, start);
+        assert!(!refused.allowed);
+        assert_eq!((refused.remaining, refused.reset, refused.retry_after), (0, 3600, Some(1800)));
+
+        // Other classes and principals are untouched.
+        assert!(limiter.check("dana", RouteClass::Read, 2, start).allowed);
+        assert!(limiter.check("eve", RouteClass::Generate, 2, start).allowed);
+
+        let later = start + chrono::Duration::seconds(1800);
+        assert!(limiter.check("dana", RouteClass::Generate, 2, later).allowed);
+        assert!(!limiter.check("dana", RouteClass::Generate, 2, later).allowed);
+    }
+
+    #[test]
+    fn test_saved_buckets_survive_a_restart() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("rate_limits.json");
+        let now = Utc::now();
+        {
+            let limiter = RateLimiter::open(&path).unwrap();
+            limiter.check("dana", RouteClass::Export, 1, now);
+            limiter.check("eve", RouteClass::Export, 1, now - chrono::Duration::hours(2));
+            limiter.save(now).unwrap();
+        }
+
+        let limiter = RateLimiter::open(&path).unwrap();
+        assert!(!limiter.check("dana", RouteClass::Export, 1, now).allowed);
+        // Idle for over an hour, so it was full and not worth saving.
+        assert_eq!(limiter.buckets.lock().unwrap().by_key.len(), 1);
+    }
+
+    #[test]
+    fn test_idle_buckets_are_dropped_without_a_file() {
+        let limiter = RateLimiter::default();
+        let start = Utc::now();
+        limiter.check("dana", RouteClass::Read, 10, start);
+        limiter.check("eve", RouteClass::Read, 10, start + chrono::Duration::minutes(30));
+        assert_eq!(limiter.buckets.lock().unwrap().by_key.len(), 2);
+
+        limiter.check("eve", RouteClass::Read, 10, start + chrono::Duration::minutes(61));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.by_key.keys().map(|(p, _)| p.as_str()).collect::<Vec<_>>(), ["eve"]);
+    }
+}
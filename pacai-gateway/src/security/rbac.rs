@@ -1,11 +1,14 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use axum::{
-    extract::Request,
-    http::StatusCode,
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Json, Response},
 };
+use crate::audit::middleware::FailureReason;
+use crate::security::policy::{self, permissions};
+use crate::security::session;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoleConfig {
@@ -21,51 +24,75 @@ pub struct Permission {
     pub resource: String,
 }
 
-lazy_static::lazy_static! {
-    static ref ROLE_PERMISSIONS: HashMap<&'static str, Vec<&'static str>> = {
-        let mut m = HashMap::new();
-        m.insert("admin", vec![
-            "generate", "override", "export", "audit:read", "license:manage", 
-            "users:manage", "project:create", "project:read", "project:update", 
-            "project:delete", "system:configure"
-        ]);
-        m.insert("operator", vec![
-            "generate", "override", "export", "audit:read",
-            "project:read", "project:update"
-        ]);
-        m.insert("lifetime", vec![
-            "generate", "override", "export", "audit:read",
-            "project:create", "project:read", "project:update"
-        ]);
-        m.insert("creator", vec![
-            "generate", "export",
-            "project:create", "project:read", "project:update"
-        ]);
-        m.insert("demo", vec![
-            "generate",
-            "project:read"
-        ]);
-        m.insert("auditor", vec![
-            "audit:read",
-            "project:read"
-        ]);
-        m
-    };
+/// Requests per hour for a role whose policy entry sets no
+/// `rate_limit_per_hour`.
+pub const DEFAULT_RATE_LIMIT_PER_HOUR: u32 = 600;
+
+/// A role as the policy in force defines it, legacy names resolved.
+pub fn get_role_config(role: &str) -> Option<RoleConfig> {
+    let active = policy::current();
+    let name = active.policy.resolve_role(role)?;
+    let definition = &active.policy.roles[name];
+    let rate_limit = definition
+        .resource_limits
+        .get("rate_limit_per_hour")
+        .and_then(|v| v.as_u64())
+        .map(|n| n.min(u32::MAX as u64) as u32);
+
+    Some(RoleConfig {
+        role: name.to_string(),
+        permissions: definition.capabilities.clone(),
+        tier_limit: None,
+        rate_limit_per_hour: Some(rate_limit.unwrap_or(DEFAULT_RATE_LIMIT_PER_HOUR)),
+    })
 }
 
+/// Whether `role` holds `permission` under the policy in force.
 pub fn can(role: &str, permission: &str) -> bool {
-    match ROLE_PERMISSIONS.get(role) {
-        Some(perms) => perms.contains(&permission),
-        None => false,
-    }
+    policy::current().policy.decide(role, None, permission).allowed
+}
+
+/// Like `can`, with the tenant's overrides applied.
+pub fn can_in_tenant(role: &str, tenant: &str, permission: &str) -> bool {
+    policy::current().policy.decide(role, Some(tenant), permission).allowed
+}
+
+/// The capabilities the policy lists for `role`, wildcards and all.
+pub fn get_role_permissions(role: &str) -> Vec<String> {
+    let active = policy::current();
+    active
+        .policy
+        .resolve_role(role)
+        .map(|r| active.policy.roles[r].capabilities.clone())
+        .unwrap_or_default()
 }
 
-pub fn get_role_permissions(role: &str) -> Vec<&'static str> {
-    ROLE_PERMISSIONS.get(role).cloned().unwrap_or_default()
+/// The policy role `name` stands for.
+pub fn resolve_role(name: &str) -> Option<String> {
+    policy::current().policy.resolve_role(name).map(str::to_string)
 }
 
-pub fn get_all_roles() -> Vec<&'static str> {
-    vec!["admin", "operator", "lifetime", "creator", "demo", "auditor"]
+pub fn get_all_roles() -> Vec<String> {
+    policy::current().policy.roles.keys().cloned().collect()
+}
+
+/// Usage tiers with limits of their own; any other name gets the demo limits.
+pub const TIERS: &[&str] = &["admin", "lifetime", "operator", "creator", "auditor", "demo"];
+
+/// The tier of a user who was not given one: the most generous their roles
+/// name, with instructors counted as creators and v5 operators as operators.
+pub fn default_tier(roles: &[String]) -> String {
+    roles
+        .iter()
+        .map(|role| match role.as_str() {
+            "instructor" => "creator",
+            policy::LEGACY_OPERATOR => "operator",
+            other => other,
+        })
+        .filter(|tier| TIERS.contains(tier))
+        .max_by_key(|tier| get_tier_limits(tier).generations_per_week)
+        .unwrap_or("demo")
+        .to_string()
 }
 
 pub fn get_tier_limits(tier: &str) -> TierLimits {
@@ -98,7 +125,7 @@ pub fn get_tier_limits(tier: &str) -> TierLimits {
             watermark: false,
             priority_queue: false,
         },
-        "demo" | "free" | _ => TierLimits {
+        _ => TierLimits {
             generations_per_week: 2,
             exports_per_day: 1,
             max_projects: 3,
@@ -141,70 +168,109 @@ pub fn require_permission(role: &str, permission: &str) -> Result<(), RbacError>
     }
 }
 
-pub async fn require_generate(request: Request, next: Next) -> Result<Response, StatusCode> {
-    let role = extract_role_from_request(&request);
-    if can(&role, "generate") {
-        Ok(next.run(request).await)
-    } else {
-        Err(StatusCode::FORBIDDEN)
-    }
+/// Lets the request through when its principal holds `permission`,
+/// otherwise answers with a JSON 401 (no valid session) or 403.
+async fn check(permission: &str, request: Request, next: Next) -> Result<Response, RbacError> {
+    session::principal_of(request.extensions())?.require(permission)?;
+    Ok(next.run(request).await)
 }
 
-pub async fn require_export(request: Request, next: Next) -> Result<Response, StatusCode> {
-    let role = extract_role_from_request(&request);
-    if can(&role, "export") {
-        Ok(next.run(request).await)
-    } else {
-        Err(StatusCode::FORBIDDEN)
-    }
+/// Middleware for any permission, for use with `from_fn_with_state`.
+pub async fn require(
+    State(permission): State<&'static str>,
+    request: Request,
+    next: Next,
+) -> Result<Response, RbacError> {
+    check(permission, request, next).await
 }
 
-pub async fn require_override(request: Request, next: Next) -> Result<Response, StatusCode> {
-    let role = extract_role_from_request(&request);
-    if can(&role, "override") {
-        Ok(next.run(request).await)
-    } else {
-        Err(StatusCode::FORBIDDEN)
-    }
+pub async fn require_generate(request: Request, next: Next) -> Result<Response, RbacError> {
+    check(permissions::GENERATE, request, next).await
 }
 
-pub async fn require_audit_read(request: Request, next: Next) -> Result<Response, StatusCode> {
-    let role = extract_role_from_request(&request);
-    if can(&role, "audit:read") {
-        Ok(next.run(request).await)
-    } else {
-        Err(StatusCode::FORBIDDEN)
-    }
+pub async fn require_export(request: Request, next: Next) -> Result<Response, RbacError> {
+    check(permissions::EXPORT, request, next).await
+}
+
+pub async fn require_override(request: Request, next: Next) -> Result<Response, RbacError> {
+    check(permissions::CONTROL_APPLY, request, next).await
+}
+
+pub async fn require_audit_read(request: Request, next: Next) -> Result<Response, RbacError> {
+    check(permissions::AUDIT_READ, request, next).await
 }
 
-pub async fn require_admin(request: Request, next: Next) -> Result<Response, StatusCode> {
-    let role = extract_role_from_request(&request);
-    if role == "admin" {
+pub async fn require_admin(request: Request, next: Next) -> Result<Response, RbacError> {
+    let principal = session::principal_of(request.extensions())?;
+    if principal.has_role("admin") {
         Ok(next.run(request).await)
     } else {
-        Err(StatusCode::FORBIDDEN)
+        Err(RbacError::PermissionDenied { role: principal.role_label(), permission: "admin".into() })
     }
 }
 
-fn extract_role_from_request(request: &Request) -> String {
-    request
-        .headers()
-        .get("x-pacai-role")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("demo")
-        .to_string()
-}
-
 #[derive(Debug, thiserror::Error)]
 pub enum RbacError {
     #[error("Permission denied: role '{role}' cannot perform '{permission}'")]
     PermissionDenied { role: String, permission: String },
     
     #[error("Tier limit exceeded: {message}")]
-    TierLimitExceeded { message: String },
+    TierLimitExceeded { message: String, reset_at: Option<DateTime<Utc>> },
+
+    #[error("Rate limit exceeded: {message}")]
+    RateLimited { message: String, reset_at: DateTime<Utc> },
     
     #[error("Invalid role: {role}")]
     InvalidRole { role: String },
+
+    #[error("Authentication required: {reason}")]
+    Unauthenticated { reason: String },
+}
+
+#[derive(Serialize)]
+pub struct ForbiddenBody {
+    pub error: &'static str,
+    pub message: String,
+    pub role: String,
+    pub permission: Option<String>,
+    /// For quota and rate limit refusals: when the request may succeed again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset_at: Option<DateTime<Utc>>,
+}
+
+impl IntoResponse for RbacError {
+    fn into_response(self) -> Response {
+        let message = self.to_string();
+        let reset_at = match &self {
+            RbacError::TierLimitExceeded { reset_at, .. } => *reset_at,
+            RbacError::RateLimited { reset_at, .. } => Some(*reset_at),
+            _ => None,
+        };
+        let (status, error, role, permission) = match self {
+            RbacError::PermissionDenied { role, permission } => {
+                (StatusCode::FORBIDDEN, "forbidden", role, Some(permission))
+            }
+            RbacError::TierLimitExceeded { .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, "tier_limit_exceeded", String::new(), None)
+            }
+            RbacError::RateLimited { .. } => (StatusCode::TOO_MANY_REQUESTS, "rate_limited", String::new(), None),
+            RbacError::InvalidRole { role } => (StatusCode::FORBIDDEN, "invalid_role", role, None),
+            RbacError::Unauthenticated { .. } => {
+                (StatusCode::UNAUTHORIZED, "unauthenticated", "anonymous".into(), None)
+            }
+        };
+        let body = ForbiddenBody { error, message: message.clone(), role, permission, reset_at };
+        let mut response = (status, Json(body)).into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        if let Some(reset_at) = reset_at {
+            let seconds = (reset_at - Utc::now()).num_seconds().max(1);
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response.extensions_mut().insert(FailureReason(message));
+        response
+    }
 }
 
 #[cfg(test)]
@@ -213,27 +279,49 @@ mod tests {
 
     #[test]
     fn test_admin_has_all_permissions() {
-        assert!(can("admin", "generate"));
-        assert!(can("admin", "export"));
-        assert!(can("admin", "override"));
+        assert!(can("admin", permissions::GENERATE));
+        assert!(can("admin", permissions::EXPORT));
+        assert!(can("admin", permissions::CONTROL_APPLY));
         assert!(can("admin", "license:manage"));
-        assert!(can("admin", "users:manage"));
+        assert!(can("admin", permissions::USERS_MODIFY));
+        assert!(can("admin", permissions::PROJECTS_DELETE));
     }
 
     #[test]
     fn test_auditor_read_only() {
-        assert!(can("auditor", "audit:read"));
-        assert!(can("auditor", "project:read"));
-        assert!(!can("auditor", "generate"));
-        assert!(!can("auditor", "export"));
+        assert!(can("auditor", permissions::AUDIT_READ));
+        assert!(can("auditor", permissions::SCENARIOS_READ));
+        assert!(!can("auditor", permissions::GENERATE));
+        assert!(!can("auditor", permissions::EXPORT));
     }
 
     #[test]
-    fn test_demo_limited() {
-        assert!(can("demo", "generate"));
-        assert!(can("demo", "project:read"));
-        assert!(!can("demo", "export"));
-        assert!(!can("demo", "override"));
+    fn test_legacy_roles_keep_their_v5_permissions() {
+        for legacy in ["demo", "creator", "lifetime", policy::LEGACY_OPERATOR] {
+            assert_eq!(resolve_role(legacy).as_deref(), Some(legacy));
+            assert!(can(legacy, permissions::GENERATE));
+            assert!(!can(legacy, permissions::USERS_LIST));
+            assert!(!can(legacy, permissions::PROJECTS_DELETE));
+        }
+        assert!(!can("demo", permissions::EXPORT));
+        assert!(!can("demo", permissions::PROJECTS_CREATE));
+        assert!(can("creator", &permissions::export("vbs4")));
+        assert!(!can("creator", permissions::CONTROL_APPLY));
+        assert!(!can("creator", permissions::AUDIT_READ));
+        assert!(can("lifetime", permissions::CONTROL_APPLY));
+        assert!(can(policy::LEGACY_OPERATOR, &permissions::export("ue5")));
+        assert!(!can(policy::LEGACY_OPERATOR, permissions::PROJECTS_CREATE));
+        // Quotas still follow the old tier names.
+        assert!(get_tier_limits("demo").watermark);
+    }
+
+    #[test]
+    fn test_role_config_takes_rate_limits_from_the_policy() {
+        let admin = get_role_config("admin").unwrap();
+        assert_eq!(admin.rate_limit_per_hour, Some(10_000));
+        assert!(admin.permissions.contains(&"generate:*".to_string()));
+        assert_eq!(get_role_config("creator").unwrap().role, "creator");
+        assert!(get_role_config("ghost").is_none());
     }
 
     #[test]
@@ -244,5 +332,11 @@ mod tests {
         let demo = get_tier_limits("demo");
         assert_eq!(demo.generations_per_week, 2);
         assert!(demo.watermark);
+
+        let roles = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(default_tier(&roles(&["instructor"])), "creator");
+        assert_eq!(default_tier(&roles(&["auditor", "operator"])), "operator");
+        assert_eq!(default_tier(&roles(&[policy::LEGACY_OPERATOR])), "operator");
+        assert_eq!(default_tier(&roles(&["integrator"])), "demo");
     }
 }
@@ -0,0 +1,302 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{header, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::security::hsm::{self, HsmError, HsmManager};
+use crate::security::rbac::{self, RbacError};
+use crate::state::{AppState, SharedState};
+use crate::store::UserStore;
+use crate::tls::ClientCertificate;
+
+const TOKEN_PREFIX: &str = "pacai1";
+const SIGNING_CONTEXT: &str = "pacai-session/1:";
+
+/// The authenticated caller, as carried by a verified session token. This is
+/// the only thing access control looks at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Principal {
+    pub user_id: String,
+    pub roles: Vec<String>,
+    pub tenant: String,
+    pub expiry: DateTime<Utc>,
+}
+
+impl Principal {
+    pub fn can(&self, permission: &str) -> bool {
+        self.roles.iter().any(|role| rbac::can_in_tenant(role, &self.tenant, permission))
+    }
+
+    /// `can`, as an error carrying what was missing.
+    pub fn require(&self, permission: &str) -> Result<(), RbacError> {
+        if self.can(permission) {
+            return Ok(());
+        }
+        Err(RbacError::PermissionDenied { role: self.role_label(), permission: permission.to_string() })
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    /// Whether records of `tenant` are visible at all: callers see their own
+    /// tenant, global admins every tenant.
+    pub fn sees_tenant(&self, tenant: &str) -> bool {
+        self.tenant == tenant || self.has_role("admin")
+    }
+
+    /// Roles as one string, for error bodies and audit details.
+    pub fn role_label(&self) -> String {
+        self.roles.join(",")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    #[serde(flatten)]
+    principal: Principal,
+    issued_at: DateTime<Utc>,
+    token_id: String,
+}
+
+/// Issues and verifies session tokens of the form
+/// `pacai1.<claims>.<signature>`, both parts base64url. The signature is the
+/// gateway's Ed25519 key over the encoded claims.
+#[derive(Clone)]
+pub struct SessionKeys {
+    signer: Arc<HsmManager>,
+    ttl: Duration,
+}
+
+impl SessionKeys {
+    pub fn new(signer: Arc<HsmManager>, ttl: Duration) -> Self {
+        Self { signer, ttl }
+    }
+
+    /// A principal valid for one session lifetime from `now`.
+    pub fn principal(&self, user_id: &str, roles: Vec<String>, tenant: &str, now: DateTime<Utc>) -> Principal {
+        let ttl = chrono::Duration::from_std(self.ttl).unwrap_or(chrono::Duration::hours(8));
+        Principal {
+            user_id: user_id.to_string(),
+            roles,
+            tenant: tenant.to_string(),
+            expiry: now + ttl,
+        }
+    }
+
+    pub fn issue(&self, user_id: &str, roles: Vec<String>, tenant: &str) -> Result<(String, Principal), HsmError> {
+        let issued_at = Utc::now();
+        let principal = self.principal(user_id, roles, tenant, issued_at);
+        let claims = Claims {
+            principal: principal.clone(),
+            issued_at,
+            token_id: uuid::Uuid::new_v4().to_string(),
+        };
+
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).map_err(|e| HsmError::SigningFailed {
+            message: e.to_string(),
+        })?);
+        let signature = self.signer.sign(&signing_input(&payload))?;
+        let token = format!("{}.{}.{}", TOKEN_PREFIX, payload, URL_SAFE_NO_PAD.encode(signature));
+        Ok((token, principal))
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Principal, SessionError> {
+        self.verify_at(token, Utc::now())
+    }
+
+    fn verify_at(&self, token: &str, now: DateTime<Utc>) -> Result<Principal, SessionError> {
+        let mut parts = token.split('.');
+        let (Some(TOKEN_PREFIX), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(SessionError::Malformed);
+        };
+
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| SessionError::Malformed)?;
+        let key = self.signer.verifying_key().ok_or(SessionError::BadSignature)?;
+        if !hsm::verify_with_key(&key, &signing_input(payload), &signature) {
+            return Err(SessionError::BadSignature);
+        }
+
+        let claims: Claims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or(SessionError::Malformed)?;
+        if claims.principal.expiry <= now {
+            return Err(SessionError::Expired { at: claims.principal.expiry });
+        }
+        Ok(claims.principal)
+    }
+}
+
+fn signing_input(payload: &str) -> Vec<u8> {
+    format!("{}{}", SIGNING_CONTEXT, payload).into_bytes()
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum SessionError {
+    #[error("Session token is malformed")]
+    Malformed,
+
+    #[error("Session token signature is invalid")]
+    BadSignature,
+
+    #[error("Session token expired at {at}")]
+    Expired { at: DateTime<Utc> },
+
+    #[error("Client certificate {subject} does not belong to an enabled user")]
+    UnknownSubject { subject: String },
+
+    #[error("Account {user_id} is disabled, locked or no longer exists")]
+    AccountUnavailable { user_id: String },
+
+    #[error("Invalid API key")]
+    InvalidApiKey,
+}
+
+/// Verifies the bearer token or API key, if any, and leaves the `Principal`
+/// (or the reason the credential was refused) in the request extensions for
+/// the rbac middleware and extractors further in. Without a bearer
+/// credential, a verified mTLS client certificate stands in for one.
+/// Requests with neither pass through unauthenticated; it is up to the route
+/// whether that is enough.
+pub async fn authenticate(State(state): State<SharedState>, mut request: Request, next: Next) -> Response {
+    let result = match (bearer_token(&request), request.extensions().get::<ClientCertificate>()) {
+        (Some(key), _) if UserStore::is_api_key(&key) => Some(key_principal(&state, &key)),
+        (Some(token), _) => Some(state.sessions.verify(&token).and_then(|p| current_principal(&state, p))),
+        (None, Some(cert)) => Some(certificate_principal(&state, cert)),
+        (None, None) => None,
+    };
+    match result {
+        Some(Ok(principal)) => {
+            request.extensions_mut().insert(principal);
+        }
+        Some(Err(e)) => {
+            request.extensions_mut().insert(e);
+        }
+        None => {}
+    }
+    next.run(request).await
+}
+
+/// A token outlives changes to its account, so the account is looked up
+/// again: disabling or deleting a user ends their sessions, and role changes
+/// apply at once.
+fn current_principal(state: &AppState, mut principal: Principal) -> Result<Principal, SessionError> {
+    let user = state
+        .users
+        .get(&principal.user_id)
+        .ok()
+        .filter(|user| user.is_active(Utc::now()))
+        .ok_or_else(|| SessionError::AccountUnavailable { user_id: principal.user_id.clone() })?;
+    principal.roles = user.roles;
+    principal.tenant = user.tenant;
+    Ok(principal)
+}
+
+fn key_principal(state: &AppState, key: &str) -> Result<Principal, SessionError> {
+    let user = state.users.authenticate_key(key).map_err(|_| SessionError::InvalidApiKey)?;
+    Ok(state.sessions.principal(&user.id, user.roles, &user.tenant, Utc::now()))
+}
+
+/// Maps a client certificate to the user named by its common name. The
+/// certificate chain was verified during the handshake; this only decides
+/// who it speaks for. The principal lasts no longer than the certificate.
+fn certificate_principal(state: &AppState, cert: &ClientCertificate) -> Result<Principal, SessionError> {
+    let unknown = || SessionError::UnknownSubject { subject: cert.subject.clone() };
+    let user = cert
+        .common_name
+        .as_deref()
+        .and_then(|cn| state.users.get(cn).ok())
+        .filter(|user| user.is_active(Utc::now()))
+        .ok_or_else(unknown)?;
+
+    let mut principal = state.sessions.principal(&user.id, user.roles, &user.tenant, Utc::now());
+    principal.expiry = principal.expiry.min(cert.not_after);
+    Ok(principal)
+}
+
+/// The `Authorization: Bearer` token. Browsers cannot set headers on a
+/// WebSocket handshake, so upgrades may pass it as `access_token` instead.
+fn bearer_token(request: &Request) -> Option<String> {
+    let headers = request.headers();
+    if let Some(value) = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) {
+        return value.strip_prefix("Bearer ").map(|t| t.trim().to_string());
+    }
+    if !headers.contains_key(header::UPGRADE) {
+        return None;
+    }
+    request
+        .uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("access_token="))
+        .map(str::to_string)
+}
+
+/// The caller's principal, or why there is none.
+pub fn principal_of(extensions: &axum::http::Extensions) -> Result<&Principal, RbacError> {
+    if let Some(principal) = extensions.get::<Principal>() {
+        return Ok(principal);
+    }
+    let reason = extensions
+        .get::<SessionError>()
+        .map(|e| e.to_string())
+        .unwrap_or_else(|| "Missing bearer token".into());
+    Err(RbacError::Unauthenticated { reason })
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Principal {
+    type Rejection = RbacError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        principal_of(&parts.extensions).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> SessionKeys {
+        SessionKeys::new(Arc::new(HsmManager::ephemeral("test")), Duration::from_secs(60))
+    }
+
+    #[test]
+    fn test_token_round_trips_principal() {
+        let keys = keys();
+        let (token, issued) = keys.issue("alice", vec!["creator".into()], "studio").unwrap();
+        let principal = keys.verify(&token).unwrap();
+        assert_eq!(principal, issued);
+        assert!(principal.can("export") && !principal.can("projects:delete"));
+
+        let later = principal.expiry + chrono::Duration::seconds(1);
+        assert!(matches!(keys.verify_at(&token, later), Err(SessionError::Expired { .. })));
+    }
+
+    #[test]
+    fn test_forged_tokens_are_rejected() {
+        let (foreign, _) = keys().issue("alice", vec!["admin".into()], "studio").unwrap();
+        let keys = keys();
+        let (token, _) = keys.issue("alice", vec!["demo".into()], "studio").unwrap();
+        assert!(matches!(keys.verify(&foreign), Err(SessionError::BadSignature)));
+
+        let mut parts: Vec<&str> = token.split('.').collect();
+        let escalated = URL_SAFE_NO_PAD.encode(
+            String::from_utf8(URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap().replace("demo", "admin"),
+        );
+        parts[1] = &escalated;
+        assert!(matches!(keys.verify(&parts.join(".")), Err(SessionError::BadSignature)));
+        assert!(matches!(keys.verify("Bearer nonsense"), Err(SessionError::Malformed)));
+    }
+}
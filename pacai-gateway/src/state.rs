@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::audit::{AuditError, AuditLog};
+use crate::audit::checkpoint::CheckpointPolicy;
+use crate::audit::segments::RotationPolicy;
+use crate::config::GatewayConfig;
+use crate::jobs::JobQueue;
+use crate::live::LiveHub;
+use crate::security::hsm::{HsmError, HsmManager};
+use crate::security::license::Licensing;
+use crate::security::rate_limit::{RateLimitError, RateLimiter};
+use crate::security::session::SessionKeys;
+use crate::store::{ProjectStore, StoreError, UsageError, UsageStore, UserError, UserStore};
+
+pub struct AppState {
+    pub config: GatewayConfig,
+    pub projects: ProjectStore,
+    pub live: LiveHub,
+    pub audit: AuditLog,
+    pub signer: Arc<HsmManager>,
+    pub users: UserStore,
+    pub sessions: SessionKeys,
+    pub usage: UsageStore,
+    pub rate_limits: RateLimiter,
+    pub jobs: JobQueue,
+    pub license: Licensing,
+}
+
+pub type SharedState = Arc<AppState>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StateError {
+    #[error(transparent)]
+    Store(#[from] StoreError),
+
+    #[error(transparent)]
+    Audit(#[from] AuditError),
+
+    #[error(transparent)]
+    Hsm(#[from] HsmError),
+
+    #[error(transparent)]
+    Users(#[from] UserError),
+
+    #[error(transparent)]
+    Usage(#[from] UsageError),
+
+    #[error(transparent)]
+    RateLimit(#[from] RateLimitError),
+
+    #[error("Cannot write the generated admin password to {}: {source}", path.display())]
+    BootstrapPassword { path: PathBuf, source: std::io::Error },
+}
+
+impl AppState {
+    pub fn open(config: GatewayConfig) -> Result<SharedState, StateError> {
+        let projects = ProjectStore::open(config.projects_dir())?;
+        let live = LiveHub::open(config.live_dir()).map_err(StoreError::from)?;
+        let mut signer = HsmManager::new("software");
+        signer.load_or_initialize(&config.signing_key_path)?;
+        let signer = Arc::new(signer);
+
+        let policy = CheckpointPolicy {
+            every_entries: config.checkpoint_every_entries,
+            interval: config.checkpoint_interval,
+        };
+        let rotation = RotationPolicy {
+            max_entries: config.audit_segment_entries,
+            retain_segments: config.audit_retain_segments,
+            retain_for: config.audit_retain_for,
+        };
+        let audit = AuditLog::open(config.audit_dir())?
+            .with_checkpoints(signer.clone(), policy)
+            .with_rotation(rotation);
+
+        let users = UserStore::open(config.users_path())?;
+        if let Some(password) = users.bootstrap_admin(config.bootstrap_admin_password.as_deref())? {
+            let path = config.bootstrap_password_path();
+            write_secret(&path, &password).map_err(|source| StateError::BootstrapPassword { path: path.clone(), source })?;
+            tracing::warn!(
+                "Created user 'admin' with a generated password, saved to {}. Delete the file once it is changed \
+                 (set PACAI_ADMIN_PASSWORD to choose one instead)",
+                path.display()
+            );
+        }
+        let sessions = SessionKeys::new(signer.clone(), config.session_ttl);
+        let usage = UsageStore::open(config.usage_path())?;
+        let rate_limits = match &config.rate_limit_state_path {
+            Some(path) => RateLimiter::open(path)?,
+            None => RateLimiter::default(),
+        };
+        let jobs = JobQueue::new(config.job_concurrency);
+        let license = Licensing::load(&config.license_path, config.license_vendor_key);
+
+        Ok(Arc::new(Self { config, projects, live, audit, signer, users, sessions, usage, rate_limits, jobs, license }))
+    }
+}
+
+/// Writes `secret` to a new file only the owner can read, replacing any
+/// left over from an earlier bootstrap.
+fn write_secret(path: &Path, secret: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, secret.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_admin_password_goes_to_a_private_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = GatewayConfig::new(dir.path());
+        let path = config.bootstrap_password_path();
+        let state = AppState::open(config).unwrap();
+
+        let password = std::fs::read_to_string(&path).unwrap();
+        assert!(state.users.authenticate("admin", &password).is_ok());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        // Reopening finds the admin already there and leaves the file alone.
+        drop(state);
+        AppState::open(GatewayConfig::new(dir.path())).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), password);
+    }
+}
@@ -0,0 +1,9 @@
+pub mod projects;
+pub mod usage;
+pub mod users;
+pub mod zones;
+
+pub use projects::{ExportRecord, NarrativeRecord, OverrideRecord, Project, ProjectStore, StoreError};
+pub use usage::{Grant, Metric, UsageError, UsageStore};
+pub use users::{ApiKey, User, UserError, UserStore};
+pub use zones::{VersionSource, ZoneRecord, ZoneVersion};
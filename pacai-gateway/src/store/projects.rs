@@ -0,0 +1,377 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use crate::engine::{narrative, watermark::Watermark};
+use crate::overrides::scheduler::ScheduledOverride;
+use crate::store::zones::ZoneRecord;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+    pub tier: String,
+    pub seed: u64,
+    pub created_at: String,
+    pub updated_at: String,
+    pub status: String,
+    /// The user who created the project; counts towards their `max_projects`.
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// The creator's tenant. Only that tenant, and global admins, see the
+    /// project; older projects belong to `default`.
+    #[serde(default = "default_tenant")]
+    pub tenant: String,
+    #[serde(default)]
+    pub zones: Vec<ZoneRecord>,
+    #[serde(default)]
+    pub narratives: Vec<NarrativeRecord>,
+    #[serde(default)]
+    pub overrides: Vec<OverrideRecord>,
+    #[serde(default)]
+    pub exports: Vec<ExportRecord>,
+    #[serde(default)]
+    pub scheduled_overrides: Vec<ScheduledOverride>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NarrativeRecord {
+    pub id: String,
+    pub prompt: String,
+    pub seed: u64,
+    pub checksum: String,
+    pub created_at: String,
+    pub narrative: narrative::NarrativeOutput,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverrideRecord {
+    pub id: String,
+    pub zone_id: String,
+    pub version: u32,
+    pub target_type: String,
+    pub target_id: String,
+    pub behavior: String,
+    pub parameters: serde_json::Value,
+    pub priority: Option<u32>,
+    #[serde(default)]
+    pub affected_entities: Vec<String>,
+    pub applied_at: String,
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportRecord {
+    pub id: String,
+    pub engines: Vec<String>,
+    pub status: String,
+    pub total_size_bytes: u64,
+    pub created_at: String,
+    /// Stamped into the bundle when it is built.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Watermark>,
+}
+
+impl Project {
+    pub fn new(name: &str, tier: &str, seed: u64) -> Self {
+        let now = chrono::Utc::now().to_rfc3339();
+
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            tier: tier.to_string(),
+            seed,
+            created_at: now.clone(),
+            updated_at: now,
+            status: "active".into(),
+            owner: None,
+            tenant: default_tenant(),
+            zones: Vec::new(),
+            narratives: Vec::new(),
+            overrides: Vec::new(),
+            exports: Vec::new(),
+            scheduled_overrides: Vec::new(),
+        }
+    }
+
+    pub fn zone(&self, zone_id: &str) -> Result<&ZoneRecord, StoreError> {
+        self.zones
+            .iter()
+            .find(|z| z.zone_id == zone_id)
+            .ok_or_else(|| StoreError::ZoneNotFound { zone_id: zone_id.to_string() })
+    }
+
+    pub fn zone_mut(&mut self, zone_id: &str) -> Result<&mut ZoneRecord, StoreError> {
+        self.zones
+            .iter_mut()
+            .find(|z| z.zone_id == zone_id)
+            .ok_or_else(|| StoreError::ZoneNotFound { zone_id: zone_id.to_string() })
+    }
+
+    pub fn schedule(&self, schedule_id: &str) -> Result<&ScheduledOverride, StoreError> {
+        self.scheduled_overrides
+            .iter()
+            .find(|s| s.id == schedule_id)
+            .ok_or_else(|| StoreError::ScheduleNotFound { schedule_id: schedule_id.to_string() })
+    }
+
+    pub fn schedule_mut(&mut self, schedule_id: &str) -> Result<&mut ScheduledOverride, StoreError> {
+        self.scheduled_overrides
+            .iter_mut()
+            .find(|s| s.id == schedule_id)
+            .ok_or_else(|| StoreError::ScheduleNotFound { schedule_id: schedule_id.to_string() })
+    }
+}
+
+fn default_tenant() -> String {
+    "default".into()
+}
+
+/// File-backed project repository. Every project is kept in memory and
+/// mirrored to `<dir>/<id>.json`, so the gateway keeps working fully offline.
+pub struct ProjectStore {
+    dir: PathBuf,
+    projects: RwLock<HashMap<String, Project>>,
+}
+
+impl ProjectStore {
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, StoreError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        let mut projects = HashMap::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            let bytes = std::fs::read(&path)?;
+            let project: Project = serde_json::from_slice(&bytes)?;
+            projects.insert(project.id.clone(), project);
+        }
+
+        tracing::info!("Project store opened: {} ({} projects)", dir.display(), projects.len());
+
+        Ok(Self {
+            dir,
+            projects: RwLock::new(projects),
+        })
+    }
+
+    pub fn list(&self) -> Vec<Project> {
+        let projects = self.projects.read().unwrap();
+        let mut list: Vec<Project> = projects.values().cloned().collect();
+        list.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        list
+    }
+
+    /// Ids of the projects matching `filter`, without cloning them.
+    pub fn ids_where<F>(&self, filter: F) -> Vec<String>
+    where
+        F: Fn(&Project) -> bool,
+    {
+        let projects = self.projects.read().unwrap();
+        let mut ids: Vec<String> = projects.values().filter(|p| filter(p)).map(|p| p.id.clone()).collect();
+        ids.sort();
+        ids
+    }
+
+    pub fn get(&self, id: &str) -> Result<Project, StoreError> {
+        self.projects
+            .read()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound { id: id.to_string() })
+    }
+
+    pub fn create(&self, project: Project) -> Result<Project, StoreError> {
+        self.create_within(project, u32::MAX)
+    }
+
+    /// Like `create`, but refused when the owner already has `max` projects.
+    /// The count and the insert happen under one lock, so concurrent
+    /// requests cannot both take the last slot.
+    pub fn create_within(&self, project: Project, max: u32) -> Result<Project, StoreError> {
+        let mut projects = self.projects.write().unwrap();
+        if projects.contains_key(&project.id) {
+            return Err(StoreError::AlreadyExists { id: project.id });
+        }
+        if let Some(owner) = &project.owner {
+            let owned = projects.values().filter(|p| p.owner.as_ref() == Some(owner)).count();
+            if owned >= max as usize {
+                return Err(StoreError::ProjectLimit { owner: owner.clone(), max });
+            }
+        }
+
+        self.persist(&project)?;
+        projects.insert(project.id.clone(), project.clone());
+        Ok(project)
+    }
+
+    /// Applies `mutate` to the stored project and persists the result. The
+    /// in-memory copy is only replaced once the write to disk has succeeded.
+    pub fn update<F>(&self, id: &str, mutate: F) -> Result<Project, StoreError>
+    where
+        F: FnOnce(&mut Project),
+    {
+        self.try_update(id, |project| {
+            mutate(project);
+            Ok(())
+        })
+        .map(|(project, _)| project)
+    }
+
+    /// Like `update`, but `mutate` may fail, in which case nothing is stored.
+    pub fn try_update<F, T>(&self, id: &str, mutate: F) -> Result<(Project, T), StoreError>
+    where
+        F: FnOnce(&mut Project) -> Result<T, StoreError>,
+    {
+        let mut projects = self.projects.write().unwrap();
+        let mut project = projects
+            .get(id)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound { id: id.to_string() })?;
+
+        let output = mutate(&mut project)?;
+        project.id = id.to_string();
+        project.updated_at = chrono::Utc::now().to_rfc3339();
+
+        self.persist(&project)?;
+        projects.insert(project.id.clone(), project.clone());
+        Ok((project, output))
+    }
+
+    pub fn delete(&self, id: &str) -> Result<Project, StoreError> {
+        let mut projects = self.projects.write().unwrap();
+        let project = projects
+            .remove(id)
+            .ok_or_else(|| StoreError::NotFound { id: id.to_string() })?;
+
+        let path = self.project_path(id);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+
+        Ok(project)
+    }
+
+    fn project_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn persist(&self, project: &Project) -> Result<(), StoreError> {
+        let path = self.project_path(&project.id);
+        let bytes = serde_json::to_vec_pretty(project)?;
+        write_atomic(&path, &bytes)
+    }
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), StoreError> {
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("Project not found: {id}")]
+    NotFound { id: String },
+
+    #[error("Zone not found: {zone_id}")]
+    ZoneNotFound { zone_id: String },
+
+    #[error("Zone {zone_id} has no version {version}")]
+    VersionNotFound { zone_id: String, version: u32 },
+
+    #[error("Zone {zone_id} head moved from v{expected} to v{actual}")]
+    HeadMoved { zone_id: String, expected: u32, actual: u32 },
+
+    #[error("Scheduled override not found: {schedule_id}")]
+    ScheduleNotFound { schedule_id: String },
+
+    #[error("Scheduled override {schedule_id} is already {status}")]
+    ScheduleClosed { schedule_id: String, status: String },
+
+    #[error("Project already exists: {id}")]
+    AlreadyExists { id: String },
+
+    #[error("{owner} already has {max} projects")]
+    ProjectLimit { owner: String, max: u32 },
+
+    #[error("Storage I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Storage serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_create_and_reopen() {
+        let dir = tempdir().unwrap();
+        let store = ProjectStore::open(dir.path()).unwrap();
+        let project = store.create(Project::new("Harbor", "creator", 7)).unwrap();
+
+        let reopened = ProjectStore::open(dir.path()).unwrap();
+        let loaded = reopened.get(&project.id).unwrap();
+        assert_eq!(loaded.name, "Harbor");
+        assert_eq!(loaded.seed, 7);
+    }
+
+    #[test]
+    fn test_unknown_project_not_found() {
+        let dir = tempdir().unwrap();
+        let store = ProjectStore::open(dir.path()).unwrap();
+
+        assert!(matches!(store.get("missing"), Err(StoreError::NotFound { .. })));
+        assert!(matches!(store.update("missing", |_| {}), Err(StoreError::NotFound { .. })));
+        assert!(matches!(store.delete("missing"), Err(StoreError::NotFound { .. })));
+    }
+
+    #[test]
+    fn test_update_and_delete_persist() {
+        let dir = tempdir().unwrap();
+        let store = ProjectStore::open(dir.path()).unwrap();
+        let project = store.create(Project::new("Harbor", "creator", 7)).unwrap();
+
+        store.update(&project.id, |p| p.name = "Harbor II".into()).unwrap();
+        assert_eq!(ProjectStore::open(dir.path()).unwrap().get(&project.id).unwrap().name, "Harbor II");
+
+        store.delete(&project.id).unwrap();
+        assert!(ProjectStore::open(dir.path()).unwrap().list().is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_creates_cannot_exceed_the_owner_limit() {
+        let dir = tempdir().unwrap();
+        let store = ProjectStore::open(dir.path()).unwrap();
+        let owned = |name: &str, owner: &str| {
+            let mut project = Project::new(name, "demo", 7);
+            project.owner = Some(owner.into());
+            project
+        };
+        store.create(owned("other", "erin")).unwrap();
+
+        let created = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|n| {
+                    let project = owned(&format!("p{}", n), "dana");
+                    let store = &store;
+                    scope.spawn(move || store.create_within(project, 3))
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).filter(Result::is_ok).count()
+        });
+        assert_eq!(created, 3);
+        let refused = store.create_within(owned("late", "dana"), 3);
+        assert!(matches!(refused, Err(StoreError::ProjectLimit { max: 3, .. })));
+        assert!(store.create_within(owned("second", "erin"), 3).is_ok());
+    }
+}
@@ -0,0 +1,219 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// What a tier quota counts. Each is a rolling window, so a use stops
+/// counting exactly one window after it happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Generations,
+    Exports,
+}
+
+impl Metric {
+    pub fn window(self) -> Duration {
+        match self {
+            Metric::Generations => Duration::weeks(1),
+            Metric::Exports => Duration::days(1),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Metric::Generations => "generations",
+            Metric::Exports => "exports",
+        }
+    }
+}
+
+/// Timestamps of the uses still inside their window, oldest first.
+type Counters = BTreeMap<Metric, Vec<DateTime<Utc>>>;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UsageFile {
+    #[serde(default)]
+    users: BTreeMap<String, Counters>,
+    #[serde(default)]
+    tenants: BTreeMap<String, Counters>,
+}
+
+/// Uses counted against a user's window at a given time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    pub used: u32,
+    /// When the oldest counted use drops out of the window.
+    pub next_release: Option<DateTime<Utc>>,
+}
+
+/// A use recorded by `acquire`, to hand back if the work then fails.
+#[derive(Debug, Clone)]
+pub struct Grant {
+    user: String,
+    tenant: String,
+    metric: Metric,
+    at: DateTime<Utc>,
+}
+
+/// Per-user and per-tenant usage, kept in memory and mirrored to one JSON
+/// file so quotas survive a restart. Limits are enforced per user; tenant
+/// totals are kept for reporting.
+pub struct UsageStore {
+    path: PathBuf,
+    file: Mutex<UsageFile>,
+}
+
+impl UsageStore {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, UsageError> {
+        let path = path.into();
+        let file = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => UsageFile::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, file: Mutex::new(file) })
+    }
+
+    /// Counts one use by `user` unless `limit` uses already fall inside the
+    /// window ending at `now`.
+    pub fn acquire(&self, user: &str, tenant: &str, metric: Metric, limit: u32, now: DateTime<Utc>) -> Result<Grant, UsageError> {
+        let mut file = self.file.lock().unwrap();
+        let uses = file.users.entry(user.to_string()).or_default().entry(metric).or_default();
+        prune(uses, metric, now);
+        if uses.len() >= limit as usize {
+            // Enough old uses must expire to leave room for one more.
+            let reset_at = uses.get(uses.len() - limit as usize).map(|at| *at + metric.window());
+            return Err(UsageError::Exhausted { metric, limit, reset_at: reset_at.filter(|_| limit > 0) });
+        }
+        uses.push(now);
+        let tenant_uses = file.tenants.entry(tenant.to_string()).or_default().entry(metric).or_default();
+        prune(tenant_uses, metric, now);
+        tenant_uses.push(now);
+
+        let grant = Grant { user: user.to_string(), tenant: tenant.to_string(), metric, at: now };
+        if let Err(e) = self.persist(&file) {
+            forget(&mut file, &grant);
+            return Err(e);
+        }
+        Ok(grant)
+    }
+
+    /// Takes back a use whose work did not go through.
+    pub fn release(&self, grant: &Grant) -> Result<(), UsageError> {
+        let mut file = self.file.lock().unwrap();
+        forget(&mut file, grant);
+        self.persist(&file)
+    }
+
+    pub fn user_window(&self, user: &str, metric: Metric, now: DateTime<Utc>) -> Window {
+        window(self.file.lock().unwrap().users.get(user), metric, now)
+    }
+
+    pub fn tenant_window(&self, tenant: &str, metric: Metric, now: DateTime<Utc>) -> Window {
+        window(self.file.lock().unwrap().tenants.get(tenant), metric, now)
+    }
+
+    fn persist(&self, file: &UsageFile) -> Result<(), UsageError> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(file)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn prune(uses: &mut Vec<DateTime<Utc>>, metric: Metric, now: DateTime<Utc>) {
+    let start = now - metric.window();
+    uses.retain(|at| *at > start);
+}
+
+fn forget(file: &mut UsageFile, grant: &Grant) {
+    let scopes = [file.users.get_mut(&grant.user), file.tenants.get_mut(&grant.tenant)];
+    for uses in scopes.into_iter().flatten().filter_map(|counters| counters.get_mut(&grant.metric)) {
+        if let Some(index) = uses.iter().rposition(|at| *at == grant.at) {
+            uses.remove(index);
+        }
+    }
+}
+
+fn window(counters: Option<&Counters>, metric: Metric, now: DateTime<Utc>) -> Window {
+    let start = now - metric.window();
+    let uses: Vec<_> = counters
+        .and_then(|c| c.get(&metric))
+        .map(|uses| uses.iter().filter(|at| **at > start).collect())
+        .unwrap_or_default();
+    Window {
+        used: uses.len() as u32,
+        next_release: uses.first().map(|at| **at + metric.window()),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UsageError {
+    #[error("The {} quota of {limit} is used up", metric.as_str())]
+    Exhausted { metric: Metric, limit: u32, reset_at: Option<DateTime<Utc>> },
+
+    #[error("Usage store I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Usage store serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rolling_window_frees_the_oldest_use_first() {
+        let dir = tempdir().unwrap();
+        let store = UsageStore::open(dir.path().join("usage.json")).unwrap();
+        let start = Utc::now();
+        let hour = Duration::hours(1);
+
+        store.acquire("dana", "studio", Metric::Exports, 2, start).unwrap();
+        store.acquire("dana", "studio", Metric::Exports, 2, start + hour).unwrap();
+        let Err(UsageError::Exhausted { reset_at, .. }) = store.acquire("dana", "studio", Metric::Exports, 2, start + hour * 2)
+        else {
+            panic!("third export in a day was allowed");
+        };
+        assert_eq!(reset_at, Some(start + Duration::days(1)));
+
+        // Other users and other metrics have windows of their own.
+        store.acquire("eve", "studio", Metric::Exports, 2, start + hour * 2).unwrap();
+        store.acquire("dana", "studio", Metric::Generations, 2, start + hour * 2).unwrap();
+
+        let after_first = start + Duration::days(1) + Duration::seconds(1);
+        store.acquire("dana", "studio", Metric::Exports, 2, after_first).unwrap();
+        assert_eq!(store.user_window("dana", Metric::Exports, after_first).used, 2);
+        assert_eq!(store.tenant_window("studio", Metric::Exports, after_first).used, 3);
+        assert!(matches!(
+            store.acquire("auditor", "studio", Metric::Exports, 0, start),
+            Err(UsageError::Exhausted { reset_at: None, .. })
+        ));
+    }
+
+    #[test]
+    fn test_usage_survives_reopen_and_released_uses_do_not_count() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("usage.json");
+        let now = Utc::now();
+        {
+            let store = UsageStore::open(&path).unwrap();
+            store.acquire("dana", "studio", Metric::Generations, 5, now).unwrap();
+            let failed = store.acquire("dana", "studio", Metric::Generations, 5, now).unwrap();
+            store.release(&failed).unwrap();
+        }
+
+        let store = UsageStore::open(&path).unwrap();
+        let window = store.user_window("dana", Metric::Generations, now);
+        assert_eq!(window, Window { used: 1, next_release: Some(now + Duration::weeks(1)) });
+        assert_eq!(store.tenant_window("studio", Metric::Generations, now).used, 1);
+        assert_eq!(store.user_window("dana", Metric::Generations, now + Duration::weeks(1)).used, 0);
+    }
+}
@@ -0,0 +1,486 @@
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use subtle::ConstantTimeEq;
+use crate::security::policy::LEGACY_OPERATOR;
+use crate::security::rbac;
+
+#[cfg(not(test))]
+const PASSWORD_ITERATIONS: u32 = 100_000;
+// Unoptimized test builds would spend seconds on every stored password.
+#[cfg(test)]
+const PASSWORD_ITERATIONS: u32 = 1_000;
+
+/// Consecutive failed logins before an account is locked out.
+pub const MAX_FAILED_LOGINS: u32 = 5;
+const LOCKOUT: chrono::Duration = chrono::Duration::minutes(15);
+const KEY_PREFIX: &str = "pk_";
+
+/// A PBKDF2-HMAC-SHA256 password hash. The iteration count is stored so it
+/// can be raised later without invalidating existing passwords.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordHash {
+    pub salt: String,
+    pub hash: String,
+    pub iterations: u32,
+}
+
+impl PasswordHash {
+    pub fn new(password: &str) -> Self {
+        let mut salt = [0u8; 16];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        Self {
+            hash: hex::encode(derive(password, &salt, PASSWORD_ITERATIONS)),
+            salt: hex::encode(salt),
+            iterations: PASSWORD_ITERATIONS,
+        }
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        let (Ok(salt), Ok(expected)) = (hex::decode(&self.salt), hex::decode(&self.hash)) else {
+            return false;
+        };
+        derive(password, &salt, self.iterations).ct_eq(&expected).into()
+    }
+}
+
+fn derive(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut out);
+    out
+}
+
+/// An enrolled API key. Keys look like `pk_<id>_<secret>` and are shown
+/// once; only the SHA-256 of the whole key is stored. Keys are random, so a
+/// slow hash would buy nothing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub label: Option<String>,
+    pub hash: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    /// Login name; also the actor recorded in the audit log.
+    pub id: String,
+    pub roles: Vec<String>,
+    pub tenant: String,
+    pub password: Option<PasswordHash>,
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    /// Switched off by an administrator until switched back on.
+    #[serde(default)]
+    pub disabled: bool,
+    /// Temporarily refused, after too many failed logins or by an
+    /// administrator.
+    #[serde(default)]
+    pub locked_until: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub failed_logins: u32,
+    /// Usage tier the quotas come from; derived from the roles when unset.
+    #[serde(default)]
+    pub tier: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl User {
+    pub fn new(id: &str, roles: Vec<String>, tenant: &str) -> Self {
+        let now = chrono::Utc::now().to_rfc3339();
+        Self {
+            id: id.to_string(),
+            roles,
+            tenant: tenant.to_string(),
+            password: None,
+            api_keys: Vec::new(),
+            disabled: false,
+            locked_until: None,
+            failed_logins: 0,
+            tier: None,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    /// Whether the account may sign in or use an existing session at `now`.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        !self.disabled && self.locked_until.is_none_or(|until| until <= now)
+    }
+
+    pub fn tier(&self) -> String {
+        self.tier.clone().unwrap_or_else(|| rbac::default_tier(&self.roles))
+    }
+
+    fn is_admin(&self) -> bool {
+        !self.disabled && self.roles.iter().any(|r| r == "admin")
+    }
+}
+
+/// Version 0 files were written by the v5 gateway's own RBAC, where
+/// `operator` meant the role now stored as `LEGACY_OPERATOR`.
+const USER_FILE_VERSION: u32 = 1;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UserFile {
+    #[serde(default)]
+    version: u32,
+    users: Vec<User>,
+}
+
+/// Local user accounts, kept in memory and mirrored to a single JSON file.
+pub struct UserStore {
+    path: PathBuf,
+    users: RwLock<HashMap<String, User>>,
+}
+
+impl UserStore {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, UserError> {
+        let path = path.into();
+        let file: UserFile = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => UserFile::default(),
+            Err(e) => return Err(e.into()),
+        };
+        let migrate = file.version < USER_FILE_VERSION && !file.users.is_empty();
+        let mut users: HashMap<_, _> = file.users.into_iter().map(|u| (u.id.clone(), u)).collect();
+        if migrate {
+            for user in users.values_mut().filter(|u| u.roles.iter().any(|r| r == "operator")) {
+                tracing::info!("Keeping the v5 operator permissions of {} as {}", user.id, LEGACY_OPERATOR);
+                for role in user.roles.iter_mut().filter(|r| *r == "operator") {
+                    *role = LEGACY_OPERATOR.to_string();
+                }
+            }
+        }
+        tracing::info!("User store opened: {} ({} users)", path.display(), users.len());
+
+        let store = Self { path, users: RwLock::new(users) };
+        if migrate {
+            store.persist(&store.users.read().unwrap())?;
+        }
+        Ok(store)
+    }
+
+    pub fn get(&self, id: &str) -> Result<User, UserError> {
+        self.users
+            .read()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| UserError::NotFound { id: id.to_string() })
+    }
+
+    pub fn create(&self, user: User) -> Result<User, UserError> {
+        let mut users = self.users.write().unwrap();
+        if users.contains_key(&user.id) {
+            return Err(UserError::AlreadyExists { id: user.id });
+        }
+
+        users.insert(user.id.clone(), user.clone());
+        if let Err(e) = self.persist(&users) {
+            users.remove(&user.id);
+            return Err(e);
+        }
+        Ok(user)
+    }
+
+    pub fn list(&self) -> Vec<User> {
+        let mut users: Vec<User> = self.users.read().unwrap().values().cloned().collect();
+        users.sort_by(|a, b| a.id.cmp(&b.id));
+        users
+    }
+
+    /// Applies `change` to the account and saves it. Refused if it would
+    /// leave no enabled administrator.
+    pub fn update(&self, id: &str, change: impl FnOnce(&mut User)) -> Result<User, UserError> {
+        let mut users = self.users.write().unwrap();
+        let previous = users.get(id).cloned().ok_or_else(|| UserError::NotFound { id: id.to_string() })?;
+
+        let mut user = previous.clone();
+        change(&mut user);
+        user.id = previous.id.clone();
+        user.updated_at = Utc::now().to_rfc3339();
+        users.insert(user.id.clone(), user.clone());
+
+        let result = if previous.is_admin() && !users.values().any(User::is_admin) {
+            Err(UserError::LastAdmin)
+        } else {
+            self.persist(&users)
+        };
+        if let Err(e) = result {
+            users.insert(previous.id.clone(), previous);
+            return Err(e);
+        }
+        Ok(user)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<User, UserError> {
+        let mut users = self.users.write().unwrap();
+        let user = users.remove(id).ok_or_else(|| UserError::NotFound { id: id.to_string() })?;
+
+        let result = if user.is_admin() && !users.values().any(User::is_admin) {
+            Err(UserError::LastAdmin)
+        } else {
+            self.persist(&users)
+        };
+        if let Err(e) = result {
+            users.insert(user.id.clone(), user);
+            return Err(e);
+        }
+        Ok(user)
+    }
+
+    /// The account for `id` if `password` is right and the account is usable.
+    /// Every failure is reported the same way so callers cannot probe for
+    /// account names. `MAX_FAILED_LOGINS` wrong passwords in a row lock the
+    /// account for a while.
+    pub fn authenticate(&self, id: &str, password: &str) -> Result<User, UserError> {
+        let user = self.users.read().unwrap().get(id).cloned();
+        let valid = match user.as_ref().and_then(|u| u.password.as_ref()) {
+            Some(hash) => hash.verify(password),
+            None => {
+                // Spend the same time as a real check.
+                derive(password, &[0u8; 16], PASSWORD_ITERATIONS);
+                false
+            }
+        };
+        let Some(user) = user else {
+            return Err(UserError::InvalidCredentials);
+        };
+
+        let now = Utc::now();
+        if !user.is_active(now) {
+            return Err(UserError::InvalidCredentials);
+        }
+        if valid {
+            if user.failed_logins == 0 {
+                return Ok(user);
+            }
+            return self.update(id, |u| u.failed_logins = 0);
+        }
+        self.update(id, |u| {
+            u.failed_logins += 1;
+            if u.failed_logins >= MAX_FAILED_LOGINS {
+                tracing::warn!("Locking {} after {} failed logins", u.id, u.failed_logins);
+                u.locked_until = Some(now + LOCKOUT);
+                u.failed_logins = 0;
+            }
+        })?;
+        Err(UserError::InvalidCredentials)
+    }
+
+    /// Enrolls a new API key for `id` and returns it in plain text.
+    pub fn issue_key(&self, id: &str, label: Option<String>) -> Result<(String, ApiKey), UserError> {
+        let mut key_id = [0u8; 6];
+        let mut secret = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut key_id);
+        rand::rngs::OsRng.fill_bytes(&mut secret);
+
+        let key_id = hex::encode(key_id);
+        let key = format!("{}{}_{}", KEY_PREFIX, key_id, hex::encode(secret));
+        let record = ApiKey {
+            id: key_id,
+            label,
+            hash: hex::encode(Sha256::digest(key.as_bytes())),
+            created_at: Utc::now().to_rfc3339(),
+        };
+        self.update(id, |u| u.api_keys.push(record.clone()))?;
+        Ok((key, record))
+    }
+
+    pub fn revoke_key(&self, id: &str, key_id: &str) -> Result<(), UserError> {
+        if !self.get(id)?.api_keys.iter().any(|k| k.id == key_id) {
+            return Err(UserError::KeyNotFound { id: key_id.to_string() });
+        }
+        self.update(id, |u| u.api_keys.retain(|k| k.id != key_id))?;
+        Ok(())
+    }
+
+    /// Whether `credential` looks like an API key rather than a session token.
+    pub fn is_api_key(credential: &str) -> bool {
+        credential.starts_with(KEY_PREFIX)
+    }
+
+    /// The active account an API key belongs to.
+    pub fn authenticate_key(&self, key: &str) -> Result<User, UserError> {
+        let key_id = key
+            .strip_prefix(KEY_PREFIX)
+            .and_then(|rest| rest.split_once('_'))
+            .map(|(id, _)| id)
+            .ok_or(UserError::InvalidCredentials)?;
+        let hash = hex::encode(Sha256::digest(key.as_bytes()));
+
+        let users = self.users.read().unwrap();
+        let user = users
+            .values()
+            .find(|u| {
+                u.api_keys
+                    .iter()
+                    .any(|k| k.id == key_id && bool::from(k.hash.as_bytes().ct_eq(hash.as_bytes())))
+            })
+            .ok_or(UserError::InvalidCredentials)?;
+        if !user.is_active(Utc::now()) {
+            return Err(UserError::InvalidCredentials);
+        }
+        Ok(user.clone())
+    }
+
+    /// Creates an `admin` account on first start so the gateway can be
+    /// administered at all. Returns the password when one had to be generated.
+    pub fn bootstrap_admin(&self, password: Option<&str>) -> Result<Option<String>, UserError> {
+        if !self.users.read().unwrap().is_empty() {
+            return Ok(None);
+        }
+
+        let generated = password.is_none().then(|| {
+            let mut bytes = [0u8; 18];
+            rand::rngs::OsRng.fill_bytes(&mut bytes);
+            hex::encode(bytes)
+        });
+        let mut admin = User::new("admin", vec!["admin".into()], "default");
+        admin.password = Some(PasswordHash::new(password.or(generated.as_deref()).unwrap_or_default()));
+        self.create(admin)?;
+        Ok(generated)
+    }
+
+    fn persist(&self, users: &HashMap<String, User>) -> Result<(), UserError> {
+        let mut list: Vec<User> = users.values().cloned().collect();
+        list.sort_by(|a, b| a.id.cmp(&b.id));
+        let bytes = serde_json::to_vec_pretty(&UserFile { version: USER_FILE_VERSION, users: list })?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(&mut options.open(&tmp)?, &bytes)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UserError {
+    #[error("User not found: {id}")]
+    NotFound { id: String },
+
+    #[error("User already exists: {id}")]
+    AlreadyExists { id: String },
+
+    #[error("API key not found: {id}")]
+    KeyNotFound { id: String },
+
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+
+    #[error("At least one enabled admin account must remain")]
+    LastAdmin,
+
+    #[error("User store I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("User store serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_bootstrap_admin_can_log_in_after_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("users.json");
+        let store = UserStore::open(&path).unwrap();
+        let password = store.bootstrap_admin(None).unwrap().unwrap();
+        assert!(store.bootstrap_admin(None).unwrap().is_none());
+
+        let store = UserStore::open(&path).unwrap();
+        assert_eq!(store.authenticate("admin", &password).unwrap().roles, vec!["admin"]);
+        assert!(matches!(store.authenticate("admin", "wrong"), Err(UserError::InvalidCredentials)));
+        assert!(matches!(store.authenticate("nobody", &password), Err(UserError::InvalidCredentials)));
+        assert!(!std::fs::read_to_string(&path).unwrap().contains(&password));
+    }
+
+    #[test]
+    fn test_repeated_failures_lock_the_account() {
+        let dir = tempdir().unwrap();
+        let store = UserStore::open(dir.path().join("users.json")).unwrap();
+        store.bootstrap_admin(Some("right")).unwrap();
+
+        for _ in 0..MAX_FAILED_LOGINS {
+            assert!(store.authenticate("admin", "wrong").is_err());
+        }
+        assert!(matches!(store.authenticate("admin", "right"), Err(UserError::InvalidCredentials)));
+
+        store.update("admin", |u| u.locked_until = None).unwrap();
+        assert!(store.authenticate("admin", "right").is_ok());
+    }
+
+    #[test]
+    fn test_the_last_admin_cannot_be_removed_or_disabled() {
+        let dir = tempdir().unwrap();
+        let store = UserStore::open(dir.path().join("users.json")).unwrap();
+        store.bootstrap_admin(Some("pw")).unwrap();
+
+        assert!(matches!(store.update("admin", |u| u.disabled = true), Err(UserError::LastAdmin)));
+        assert!(matches!(store.update("admin", |u| u.roles = vec!["auditor".into()]), Err(UserError::LastAdmin)));
+        assert!(matches!(store.delete("admin"), Err(UserError::LastAdmin)));
+        assert!(!store.get("admin").unwrap().disabled);
+
+        store.create(User::new("second", vec!["admin".into()], "default")).unwrap();
+        store.delete("admin").unwrap();
+    }
+
+    #[test]
+    fn test_v5_operators_keep_their_permissions_after_upgrade() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("users.json");
+        let old = serde_json::json!({ "users": [
+            User::new("ops", vec!["operator".into(), "auditor".into()], "studio"),
+            User::new("dana", vec!["creator".into()], "studio"),
+        ] });
+        std::fs::write(&path, serde_json::to_vec(&old).unwrap()).unwrap();
+
+        let store = UserStore::open(&path).unwrap();
+        let ops = store.get("ops").unwrap();
+        assert_eq!(ops.roles, [LEGACY_OPERATOR, "auditor"]);
+        assert_eq!(ops.tier(), "operator");
+        assert_eq!(store.get("dana").unwrap().roles, ["creator"]);
+
+        // Operators created after the upgrade are v4 operators.
+        store.create(User::new("eve", vec!["operator".into()], "studio")).unwrap();
+        let store = UserStore::open(&path).unwrap();
+        assert_eq!(store.get("ops").unwrap().roles, [LEGACY_OPERATOR, "auditor"]);
+        assert_eq!(store.get("eve").unwrap().roles, ["operator"]);
+    }
+
+    #[test]
+    fn test_api_keys_authenticate_until_revoked() {
+        let dir = tempdir().unwrap();
+        let store = UserStore::open(dir.path().join("users.json")).unwrap();
+        store.create(User::new("ci", vec!["creator".into()], "default")).unwrap();
+
+        let (key, record) = store.issue_key("ci", Some("pipeline".into())).unwrap();
+        assert!(UserStore::is_api_key(&key));
+        assert_eq!(store.authenticate_key(&key).unwrap().id, "ci");
+        assert!(store.authenticate_key(&format!("{}x", key)).is_err());
+
+        store.update("ci", |u| u.disabled = true).unwrap();
+        assert!(store.authenticate_key(&key).is_err());
+        store.update("ci", |u| u.disabled = false).unwrap();
+
+        store.revoke_key("ci", &record.id).unwrap();
+        assert!(store.authenticate_key(&key).is_err());
+        assert!(matches!(store.revoke_key("ci", &record.id), Err(UserError::KeyNotFound { .. })));
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use crate::engine::override_engine::ClaimMap;
+use crate::engine::world;
+use crate::engine::zone_overrides::ZoneOverrides;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneRecord {
+    pub zone_id: String,
+    pub zone_type: String,
+    pub head: u32,
+    pub created_at: String,
+    pub updated_at: String,
+    pub versions: Vec<ZoneVersion>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneVersion {
+    pub version: u32,
+    pub seed: u64,
+    pub checksum: String,
+    pub created_at: String,
+    pub source: VersionSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<ZoneOverrides>,
+    #[serde(default, skip_serializing_if = "ClaimMap::is_empty")]
+    pub claims: ClaimMap,
+    pub world: world::WorldOutput,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VersionSource {
+    Generate,
+    Rollback { from_version: u32 },
+    Override { override_id: String },
+    Revert { override_id: String },
+}
+
+impl ZoneRecord {
+    pub fn new(zone_id: &str, zone_type: &str) -> Self {
+        let now = chrono::Utc::now().to_rfc3339();
+
+        Self {
+            zone_id: zone_id.to_string(),
+            zone_type: zone_type.to_string(),
+            head: 0,
+            created_at: now.clone(),
+            updated_at: now,
+            versions: Vec::new(),
+        }
+    }
+
+    pub fn version(&self, version: u32) -> Option<&ZoneVersion> {
+        self.versions.iter().find(|v| v.version == version)
+    }
+
+    pub fn head_version(&self) -> Option<&ZoneVersion> {
+        self.version(self.head)
+    }
+
+    /// Appends a snapshot as the next version and makes it the head.
+    /// Versions are never rewritten, so history is append-only.
+    pub fn push_version(
+        &mut self,
+        seed: u64,
+        checksum: String,
+        source: VersionSource,
+        overrides: Option<ZoneOverrides>,
+        claims: ClaimMap,
+        world: world::WorldOutput,
+    ) -> &ZoneVersion {
+        let version = self.versions.last().map(|v| v.version + 1).unwrap_or(1);
+        let now = chrono::Utc::now().to_rfc3339();
+
+        self.versions.push(ZoneVersion {
+            version,
+            seed,
+            checksum,
+            created_at: now.clone(),
+            source,
+            overrides,
+            claims,
+            world,
+        });
+        self.head = version;
+        self.updated_at = now;
+
+        self.versions.last().unwrap()
+    }
+
+    /// Restores `version` by copying it forward as a new head version.
+    pub fn rollback(&mut self, version: u32) -> Option<&ZoneVersion> {
+        let target = self.version(version)?.clone();
+        Some(self.push_version(
+            target.seed,
+            target.checksum,
+            VersionSource::Rollback { from_version: version },
+            target.overrides,
+            target.claims,
+            target.world,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback_appends_new_head() {
+        let mut zone = ZoneRecord::new("z1", "harbor");
+        zone.push_version(1, "a".into(), VersionSource::Generate, None, ClaimMap::new(), world::generate("harbor", 1, "zone"));
+        zone.push_version(2, "b".into(), VersionSource::Generate, None, ClaimMap::new(), world::generate("harbor", 2, "zone"));
+
+        let restored = zone.rollback(1).unwrap();
+        assert_eq!(restored.version, 3);
+        assert_eq!(restored.seed, 1);
+        assert_eq!(zone.head, 3);
+        assert_eq!(zone.versions.len(), 3);
+        assert!(zone.rollback(9).is_none());
+    }
+}
@@ -0,0 +1,312 @@
+use axum::{extract::{ConnectInfo, Request}, Router};
+use chrono::{DateTime, Utc};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::{
+    self,
+    pki_types::CertificateDer,
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig,
+};
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// CA bundle client certificates must chain to. TLS without it is refused.
+    pub client_ca_path: Option<PathBuf>,
+    /// How often the files above are checked for changes.
+    pub reload_interval: Duration,
+}
+
+/// The verified client certificate of a mutual-TLS connection, attached to
+/// every request made over it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    pub subject: String,
+    pub common_name: Option<String>,
+    pub not_after: DateTime<Utc>,
+}
+
+impl ClientCertificate {
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        let subject = cert.subject();
+        let common_name = subject.iter_common_name().next().and_then(|cn| cn.as_str().ok()).map(str::to_string);
+        let client = Self {
+            subject: subject.to_string(),
+            common_name,
+            not_after: DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)?,
+        };
+        Some(client)
+    }
+}
+
+/// The live server configuration. Certificates, key and client CA bundle are
+/// re-read when any of them changes on disk; a bad update is logged and the
+/// previous configuration stays in service.
+pub struct TlsReloader {
+    config: TlsConfig,
+    current: RwLock<(Vec<Option<SystemTime>>, Arc<ServerConfig>)>,
+}
+
+impl TlsReloader {
+    pub fn load(config: TlsConfig) -> Result<Self, TlsError> {
+        let stamp = stamp(&config);
+        let server = server_config(&config)?;
+        Ok(Self { config, current: RwLock::new((stamp, server)) })
+    }
+
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.read().unwrap().1.clone())
+    }
+
+    /// Rebuilds the server configuration if any file changed since the last
+    /// successful load. Returns whether it did.
+    pub fn reload_if_changed(&self) -> Result<bool, TlsError> {
+        let stamp = stamp(&self.config);
+        if self.current.read().unwrap().0 == stamp {
+            return Ok(false);
+        }
+        let server = server_config(&self.config)?;
+        *self.current.write().unwrap() = (stamp, server);
+        Ok(true)
+    }
+}
+
+fn stamp(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+    [Some(&config.cert_path), Some(&config.key_path), config.client_ca_path.as_ref()]
+        .into_iter()
+        .map(|path| path.and_then(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok()))
+        .collect()
+}
+
+fn server_config(config: &TlsConfig) -> Result<Arc<ServerConfig>, TlsError> {
+    let ca_path = config.client_ca_path.as_ref().ok_or(TlsError::MissingClientCa)?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let mut roots = RootCertStore::empty();
+    for cert in read_certs(ca_path)? {
+        roots.add(cert)?;
+    }
+    let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build()?;
+
+    let key = rustls_pemfile::private_key(&mut open(&config.key_path)?)
+        .map_err(|e| TlsError::Io { path: config.key_path.clone(), source: e })?
+        .ok_or_else(|| TlsError::NoKey { path: config.key_path.clone() })?;
+
+    let mut server = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(read_certs(&config.cert_path)?, key)?;
+    server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(server))
+}
+
+fn open(path: &Path) -> Result<std::io::BufReader<std::fs::File>, TlsError> {
+    std::fs::File::open(path)
+        .map(std::io::BufReader::new)
+        .map_err(|e| TlsError::Io { path: path.to_path_buf(), source: e })
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certs = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| TlsError::Io { path: path.to_path_buf(), source: e })?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates { path: path.to_path_buf() });
+    }
+    Ok(certs)
+}
+
+/// Checks the certificate files every `reload_interval`.
+pub fn spawn_reload(reloader: Arc<TlsReloader>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(reloader.config.reload_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            match reloader.reload_if_changed() {
+                Ok(true) => tracing::info!("TLS certificates reloaded"),
+                Ok(false) => {}
+                Err(e) => tracing::error!("TLS reload failed, keeping previous certificates: {}", e),
+            }
+        }
+    })
+}
+
+/// Serves `app` over mutual TLS. Each connection picks up the configuration
+/// current at accept time, so reloads apply to new connections only.
+pub async fn serve(listener: TcpListener, app: Router, reloader: Arc<TlsReloader>) -> std::io::Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let acceptor = reloader.acceptor();
+        let app = app.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_connection(stream, peer, acceptor, app).await {
+                tracing::debug!("TLS connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    acceptor: TlsAcceptor,
+    app: Router,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await??;
+    let client = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|chain| chain.first())
+        .and_then(|leaf| ClientCertificate::from_der(leaf));
+
+    let service = hyper::service::service_fn(move |mut request: Request<hyper::body::Incoming>| {
+        request.extensions_mut().insert(ConnectInfo(peer));
+        if let Some(client) = &client {
+            request.extensions_mut().insert(client.clone());
+        }
+        app.clone().oneshot(request)
+    });
+    auto::Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(TokioIo::new(stream), service)
+        .await
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("{path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+
+    #[error("No certificates in {path}")]
+    NoCertificates { path: PathBuf },
+
+    #[error("No private key in {path}")]
+    NoKey { path: PathBuf },
+
+    #[error("Mutual TLS needs a client CA bundle (PACAI_TLS_CLIENT_CA)")]
+    MissingClientCa,
+
+    #[error("TLS configuration rejected: {0}")]
+    Rustls(#[from] rustls::Error),
+
+    #[error("Client CA bundle rejected: {0}")]
+    Verifier(#[from] rustls::server::VerifierBuilderError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GatewayConfig;
+    use crate::state::AppState;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::{pki_types::ServerName, ClientConfig};
+    use tokio_rustls::TlsConnector;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tls").join(name)
+    }
+
+    fn fixture_config(dir: &Path) -> TlsConfig {
+        for name in ["server.pem", "server.key", "ca.pem"] {
+            std::fs::copy(fixture(name), dir.join(name)).unwrap();
+        }
+        TlsConfig {
+            cert_path: dir.join("server.pem"),
+            key_path: dir.join("server.key"),
+            client_ca_path: Some(dir.join("ca.pem")),
+            reload_interval: Duration::from_secs(30),
+        }
+    }
+
+    fn connector(client: Option<&str>) -> TlsConnector {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut roots = RootCertStore::empty();
+        roots.add(read_certs(&fixture("ca.pem")).unwrap().remove(0)).unwrap();
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = match client {
+            Some(name) => {
+                let certs = read_certs(&fixture(&format!("{}.pem", name))).unwrap();
+                let key = rustls_pemfile::private_key(&mut open(&fixture(&format!("{}.key", name))).unwrap())
+                    .unwrap()
+                    .unwrap();
+                builder.with_client_auth_cert(certs, key).unwrap()
+            }
+            None => builder.with_no_client_auth(),
+        };
+        TlsConnector::from(Arc::new(config))
+    }
+
+    /// The raw HTTP/1.1 response to `GET path`, or `None` if the server
+    /// refused the connection.
+    async fn get(addr: std::net::SocketAddr, client: Option<&str>, path: &str) -> Option<String> {
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let server_name = ServerName::try_from("localhost").unwrap();
+        let mut stream = connector(client).connect(server_name, tcp).await.ok()?;
+        let request = format!("GET {} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.ok()?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.ok()?;
+        Some(response).filter(|r| !r.is_empty())
+    }
+
+    #[tokio::test]
+    async fn test_client_certificates_authenticate_as_their_common_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::open(GatewayConfig::new(dir.path())).unwrap();
+        let reloader = Arc::new(TlsReloader::load(fixture_config(dir.path())).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, crate::routes::router(state), reloader));
+
+        let response = get(addr, Some("client"), "/v5/auth/session").await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains(r#""user_id":"admin""#), "{}", response);
+
+        // Same subject, but not issued by the configured CA.
+        assert_eq!(get(addr, Some("rogue"), "/v5/auth/session").await, None);
+        assert_eq!(get(addr, None, "/v5/auth/session").await, None);
+    }
+
+    #[test]
+    fn test_reload_keeps_the_previous_config_until_the_files_are_valid() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = fixture_config(dir.path());
+        let reloader = TlsReloader::load(config.clone()).unwrap();
+        assert!(!reloader.reload_if_changed().unwrap());
+
+        let modified = |path: &Path| {
+            let file = std::fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+        };
+
+        std::fs::write(&config.cert_path, "not a certificate").unwrap();
+        modified(&config.cert_path);
+        assert!(matches!(reloader.reload_if_changed(), Err(TlsError::NoCertificates { .. })));
+        let previous = reloader.acceptor();
+
+        std::fs::copy(fixture("server.pem"), &config.cert_path).unwrap();
+        modified(&config.cert_path);
+        assert!(reloader.reload_if_changed().unwrap());
+        assert!(!Arc::ptr_eq(previous.config(), reloader.acceptor().config()));
+
+        let missing_ca = TlsConfig { client_ca_path: None, ..config };
+        assert!(matches!(TlsReloader::load(missing_ca), Err(TlsError::MissingClientCa)));
+    }
+}
@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use serde_json::{Value, json};
 
 pub fn merge_json(base: &Value, overlay: &Value) -> Value {
@@ -21,35 +21,303 @@ pub fn merge_json(base: &Value, overlay: &Value) -> Value {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Parses a dotted path with optional array indices, e.g.
+/// `entities[3].stats.aggression` or `terrain.features[0][1]`.
+pub fn parse_path(path: &str) -> Result<Vec<PathSegment>, JsonError> {
+    let invalid = || JsonError::InvalidPath(path.to_string());
+    let mut segments = Vec::new();
+
+    for part in path.split('.') {
+        let (key, mut rest) = match part.find('[') {
+            Some(pos) => part.split_at(pos),
+            None => (part, ""),
+        };
+
+        if key.is_empty() && (segments.is_empty() || rest.is_empty()) {
+            return Err(invalid());
+        }
+        if !key.is_empty() {
+            segments.push(PathSegment::Key(key.to_string()));
+        }
+
+        while !rest.is_empty() {
+            let close = rest.find(']').ok_or_else(invalid)?;
+            let index = rest[1..close].parse::<usize>().map_err(|_| invalid())?;
+            segments.push(PathSegment::Index(index));
+            rest = &rest[close + 1..];
+            if !rest.is_empty() && !rest.starts_with('[') {
+                return Err(invalid());
+            }
+        }
+    }
+
+    Ok(segments)
+}
+
+fn step<'a>(current: &'a Value, segment: &PathSegment) -> Option<&'a Value> {
+    match segment {
+        PathSegment::Key(key) => current.get(key.as_str()),
+        PathSegment::Index(index) => current.get(*index),
+    }
+}
+
+fn step_mut<'a>(current: &'a mut Value, segment: &PathSegment) -> Option<&'a mut Value> {
+    match segment {
+        PathSegment::Key(key) => current.get_mut(key.as_str()),
+        PathSegment::Index(index) => current.get_mut(*index),
+    }
+}
+
 pub fn extract_field<T: for<'de> Deserialize<'de>>(json: &Value, path: &str) -> Option<T> {
-    let parts: Vec<&str> = path.split('.').collect();
+    let segments = parse_path(path).ok()?;
     let mut current = json;
-    
-    for part in parts {
-        current = current.get(part)?;
+
+    for segment in &segments {
+        current = step(current, segment)?;
     }
-    
+
     serde_json::from_value(current.clone()).ok()
 }
 
+/// Writes `value` at `path`, creating missing intermediate objects. Array
+/// elements must already exist; indices never grow an array implicitly.
 pub fn set_field(json: &mut Value, path: &str, value: Value) -> Result<(), JsonError> {
-    let parts: Vec<&str> = path.split('.').collect();
+    let segments = parse_path(path)?;
+    let (last, parents) = segments.split_last()
+        .ok_or_else(|| JsonError::InvalidPath(path.to_string()))?;
     let mut current = json;
-    
-    for (i, part) in parts.iter().enumerate() {
-        if i == parts.len() - 1 {
-            if let Value::Object(map) = current {
-                map.insert(part.to_string(), value);
-                return Ok(());
-            }
-            return Err(JsonError::InvalidPath(path.to_string()));
+
+    for segment in parents {
+        if let (Value::Object(map), PathSegment::Key(key)) = (&mut *current, segment) {
+            map.entry(key.clone()).or_insert_with(|| Value::Object(Default::default()));
         }
-        
-        current = current.get_mut(*part)
+        current = step_mut(current, segment)
+            .filter(|next| next.is_object() || next.is_array())
             .ok_or_else(|| JsonError::InvalidPath(path.to_string()))?;
     }
-    
-    Err(JsonError::InvalidPath(path.to_string()))
+
+    match (current, last) {
+        (Value::Object(map), PathSegment::Key(key)) => {
+            map.insert(key.clone(), value);
+            Ok(())
+        }
+        (Value::Array(items), PathSegment::Index(index)) if *index < items.len() => {
+            items[*index] = value;
+            Ok(())
+        }
+        _ => Err(JsonError::InvalidPath(path.to_string())),
+    }
+}
+
+/// Converts a dotted path (`entities[3].stats`) into a JSON Pointer
+/// (`/entities/3/stats`).
+pub fn path_to_pointer(path: &str) -> Result<String, JsonError> {
+    let mut pointer = String::new();
+    for segment in parse_path(path)? {
+        pointer.push('/');
+        match segment {
+            PathSegment::Key(key) => pointer.push_str(&key.replace('~', "~0").replace('/', "~1")),
+            PathSegment::Index(index) => pointer.push_str(&index.to_string()),
+        }
+    }
+    Ok(pointer)
+}
+
+/// Splits an RFC 6901 JSON Pointer into unescaped reference tokens.
+pub fn parse_pointer(pointer: &str) -> Result<Vec<String>, JsonError> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !pointer.starts_with('/') {
+        return Err(JsonError::InvalidPointer(pointer.to_string()));
+    }
+
+    pointer[1..]
+        .split('/')
+        .map(|token| {
+            let mut unescaped = String::with_capacity(token.len());
+            let mut chars = token.chars();
+            while let Some(c) = chars.next() {
+                if c != '~' {
+                    unescaped.push(c);
+                    continue;
+                }
+                // `~` only ever starts `~0` or `~1`.
+                match chars.next() {
+                    Some('0') => unescaped.push('~'),
+                    Some('1') => unescaped.push('/'),
+                    _ => return Err(JsonError::InvalidPointer(pointer.to_string())),
+                }
+            }
+            Ok(unescaped)
+        })
+        .collect()
+}
+
+/// Parses an array index token. Leading zeros are rejected as RFC 6901 requires.
+fn parse_index(token: &str, pointer: &str) -> Result<usize, JsonError> {
+    let valid = !token.is_empty()
+        && token.bytes().all(|b| b.is_ascii_digit())
+        && (token == "0" || !token.starts_with('0'));
+    if !valid {
+        return Err(JsonError::InvalidPointer(pointer.to_string()));
+    }
+    token.parse().map_err(|_| JsonError::InvalidPointer(pointer.to_string()))
+}
+
+pub fn resolve_pointer<'a>(json: &'a Value, pointer: &str) -> Result<&'a Value, JsonError> {
+    let mut current = json;
+    for token in parse_pointer(pointer)? {
+        current = match current {
+            Value::Object(map) => map.get(&token),
+            Value::Array(items) => items.get(parse_index(&token, pointer)?),
+            _ => None,
+        }
+        .ok_or_else(|| JsonError::PointerNotFound(pointer.to_string()))?;
+    }
+    Ok(current)
+}
+
+pub fn resolve_pointer_mut<'a>(json: &'a mut Value, pointer: &str) -> Result<&'a mut Value, JsonError> {
+    let mut current = json;
+    for token in parse_pointer(pointer)? {
+        current = match current {
+            Value::Object(map) => map.get_mut(&token),
+            Value::Array(items) => items.get_mut(parse_index(&token, pointer)?),
+            _ => None,
+        }
+        .ok_or_else(|| JsonError::PointerNotFound(pointer.to_string()))?;
+    }
+    Ok(current)
+}
+
+/// A single RFC 6902 JSON Patch operation.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// Applies an RFC 6902 patch. The patch is atomic: if any operation fails
+/// the document is left untouched and the failing operation is reported.
+pub fn apply_patch(json: &mut Value, operations: &[PatchOperation]) -> Result<(), JsonError> {
+    let mut doc = json.clone();
+    for (index, operation) in operations.iter().enumerate() {
+        apply_operation(&mut doc, operation).map_err(|source| JsonError::PatchFailed {
+            index,
+            source: Box::new(source),
+        })?;
+    }
+    *json = doc;
+    Ok(())
+}
+
+fn apply_operation(doc: &mut Value, operation: &PatchOperation) -> Result<(), JsonError> {
+    match operation {
+        PatchOperation::Add { path, value } => pointer_add(doc, path, value.clone()),
+        PatchOperation::Remove { path } => pointer_remove(doc, path).map(|_| ()),
+        PatchOperation::Replace { path, value } => {
+            *resolve_pointer_mut(doc, path)? = value.clone();
+            Ok(())
+        }
+        PatchOperation::Move { from, path } => {
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(JsonError::InvalidPointer(path.clone()));
+            }
+            if from == path {
+                return resolve_pointer(doc, from).map(|_| ());
+            }
+            let value = pointer_remove(doc, from)?;
+            pointer_add(doc, path, value)
+        }
+        PatchOperation::Copy { from, path } => {
+            let value = resolve_pointer(doc, from)?.clone();
+            pointer_add(doc, path, value)
+        }
+        PatchOperation::Test { path, value } => {
+            if values_equal(resolve_pointer(doc, path)?, value) {
+                Ok(())
+            } else {
+                Err(JsonError::TestFailed(path.clone()))
+            }
+        }
+    }
+}
+
+fn split_parent(pointer: &str) -> Result<(&str, String), JsonError> {
+    let pos = pointer.rfind('/').ok_or_else(|| JsonError::InvalidPointer(pointer.to_string()))?;
+    let last = parse_pointer(&pointer[pos..])?.pop().unwrap_or_default();
+    Ok((&pointer[..pos], last))
+}
+
+fn pointer_add(doc: &mut Value, pointer: &str, value: Value) -> Result<(), JsonError> {
+    if pointer.is_empty() {
+        *doc = value;
+        return Ok(());
+    }
+
+    let (parent, token) = split_parent(pointer)?;
+    match resolve_pointer_mut(doc, parent)? {
+        Value::Object(map) => {
+            map.insert(token, value);
+            Ok(())
+        }
+        Value::Array(items) => {
+            let index = if token == "-" { items.len() } else { parse_index(&token, pointer)? };
+            if index > items.len() {
+                return Err(JsonError::IndexOutOfBounds { pointer: pointer.to_string(), index });
+            }
+            items.insert(index, value);
+            Ok(())
+        }
+        _ => Err(JsonError::TypeMismatch(parent.to_string())),
+    }
+}
+
+fn pointer_remove(doc: &mut Value, pointer: &str) -> Result<Value, JsonError> {
+    if pointer.is_empty() {
+        return Err(JsonError::InvalidPointer(pointer.to_string()));
+    }
+
+    let (parent, token) = split_parent(pointer)?;
+    match resolve_pointer_mut(doc, parent)? {
+        Value::Object(map) => map.remove(&token)
+            .ok_or_else(|| JsonError::PointerNotFound(pointer.to_string())),
+        Value::Array(items) => {
+            let index = parse_index(&token, pointer)?;
+            if index >= items.len() {
+                return Err(JsonError::IndexOutOfBounds { pointer: pointer.to_string(), index });
+            }
+            Ok(items.remove(index))
+        }
+        _ => Err(JsonError::PointerNotFound(pointer.to_string())),
+    }
+}
+
+/// JSON equality as defined for the `test` operation: numbers compare by
+/// value, so `1` and `1.0` are equal.
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x == y || x.as_f64() == y.as_f64(),
+        (Value::Array(x), Value::Array(y)) => {
+            x.len() == y.len() && x.iter().zip(y).all(|(a, b)| values_equal(a, b))
+        }
+        (Value::Object(x), Value::Object(y)) => {
+            x.len() == y.len() && x.iter().all(|(k, v)| y.get(k).is_some_and(|w| values_equal(v, w)))
+        }
+        _ => a == b,
+    }
 }
 
 pub fn validate_schema(json: &Value, required_fields: &[&str]) -> Result<(), JsonError> {
@@ -61,6 +329,27 @@ pub fn validate_schema(json: &Value, required_fields: &[&str]) -> Result<(), Jso
     Ok(())
 }
 
+/// Serializes `value` with object keys sorted at every level and no
+/// whitespace, so equal documents always hash the same.
+pub fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let fields: Vec<String> = keys
+                .into_iter()
+                .map(|k| format!("{}:{}", Value::String(k.clone()), canonical_json(&map[k])))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
 pub fn create_world_json(
     id: &str,
     name: &str,
@@ -97,4 +386,118 @@ pub enum JsonError {
     
     #[error("Serialization error: {0}")]
     SerializationError(String),
+
+    #[error("Invalid JSON pointer: {0}")]
+    InvalidPointer(String),
+
+    #[error("JSON pointer not found: {0}")]
+    PointerNotFound(String),
+
+    #[error("Array index {index} out of bounds at {pointer}")]
+    IndexOutOfBounds { pointer: String, index: usize },
+
+    #[error("Patch test failed at {0}")]
+    TestFailed(String),
+
+    #[error("Patch operation {index} failed: {source}")]
+    PatchFailed {
+        index: usize,
+        #[source]
+        source: Box<JsonError>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path_with_indices() {
+        assert_eq!(parse_path("entities[3].stats.aggression").unwrap(), vec![
+            PathSegment::Key("entities".into()),
+            PathSegment::Index(3),
+            PathSegment::Key("stats".into()),
+            PathSegment::Key("aggression".into()),
+        ]);
+        assert!(parse_path("entities[x]").is_err());
+        assert!(parse_path("entities.").is_err());
+        assert!(parse_path("[0]").is_err());
+    }
+
+    #[test]
+    fn test_set_and_extract_array_element() {
+        let mut doc = json!({ "entities": [{ "stats": { "aggression": 0.1 } }] });
+        set_field(&mut doc, "entities[0].stats.aggression", json!(0.9)).unwrap();
+        assert_eq!(extract_field::<f64>(&doc, "entities[0].stats.aggression"), Some(0.9));
+        assert!(set_field(&mut doc, "entities[5].stats", json!({})).is_err());
+    }
+
+    #[test]
+    fn test_set_field_creates_intermediate_objects() {
+        let mut doc = json!({ "metadata": {} });
+        set_field(&mut doc, "metadata.pack.author", json!("ops")).unwrap();
+        assert_eq!(doc["metadata"]["pack"]["author"], json!("ops"));
+        assert!(set_field(&mut doc, "metadata.pack.author.name", json!("x")).is_err());
+    }
+
+    #[test]
+    fn test_pointer_escaping() {
+        let doc = json!({ "a/b": { "m~n": [10, 20] } });
+        assert_eq!(resolve_pointer(&doc, "/a~1b/m~0n/1").unwrap(), &json!(20));
+        assert_eq!(resolve_pointer(&doc, "").unwrap(), &doc);
+        assert!(matches!(resolve_pointer(&doc, "a"), Err(JsonError::InvalidPointer(_))));
+        assert!(matches!(resolve_pointer(&doc, "/a~1b/m~0n/01"), Err(JsonError::InvalidPointer(_))));
+        for escaped in ["/~~01", "/a~", "/a~1b/m~", "/~2"] {
+            assert!(matches!(parse_pointer(escaped), Err(JsonError::InvalidPointer(_))), "{escaped}");
+        }
+        assert_eq!(parse_pointer("/~01/~10").unwrap(), ["~1", "/0"]);
+        assert!(matches!(resolve_pointer(&doc, "/missing"), Err(JsonError::PointerNotFound(_))));
+        assert_eq!(path_to_pointer("entities[3].stats").unwrap(), "/entities/3/stats");
+    }
+
+    #[test]
+    fn test_apply_patch_operations() {
+        let mut doc = json!({ "entities": [{ "id": "a" }, { "id": "b" }], "terrain": { "biome": "arctic" } });
+        let ops: Vec<PatchOperation> = serde_json::from_value(json!([
+            { "op": "test", "path": "/terrain/biome", "value": "arctic" },
+            { "op": "add", "path": "/entities/-", "value": { "id": "c" } },
+            { "op": "remove", "path": "/entities/0" },
+            { "op": "replace", "path": "/terrain/biome", "value": "desert" },
+            { "op": "copy", "from": "/entities/0", "path": "/leader" },
+            { "op": "move", "from": "/leader", "path": "/terrain/leader" }
+        ])).unwrap();
+
+        apply_patch(&mut doc, &ops).unwrap();
+        assert_eq!(doc, json!({
+            "entities": [{ "id": "b" }, { "id": "c" }],
+            "terrain": { "biome": "desert", "leader": { "id": "b" } }
+        }));
+    }
+
+    #[test]
+    fn test_failed_patch_leaves_document_untouched() {
+        let mut doc = json!({ "count": 1 });
+        let ops = vec![
+            PatchOperation::Replace { path: "/count".into(), value: json!(2) },
+            PatchOperation::Test { path: "/count".into(), value: json!(3) },
+        ];
+
+        match apply_patch(&mut doc, &ops) {
+            Err(JsonError::PatchFailed { index, source }) => {
+                assert_eq!(index, 1);
+                assert!(matches!(*source, JsonError::TestFailed(_)));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(doc, json!({ "count": 1 }));
+        assert!(apply_patch(&mut doc, &[PatchOperation::Test { path: "/count".into(), value: json!(1.0) }]).is_ok());
+    }
+
+    #[test]
+    fn test_canonical_json_sorts_keys() {
+        let a = json!({ "b": 1, "a": { "d": [1, { "z": null, "y": "x" }], "c": true } });
+        let b = json!({ "a": { "c": true, "d": [1, { "y": "x", "z": null }] }, "b": 1 });
+        assert_eq!(canonical_json(&a), canonical_json(&b));
+        assert_eq!(canonical_json(&b), r#"{"a":{"c":true,"d":[1,{"y":"x","z":null}]},"b":1}"#);
+    }
 }
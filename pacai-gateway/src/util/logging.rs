@@ -1,11 +1,11 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
-use std::sync::atomic::{AtomicU64, Ordering};
+use crate::util::json::canonical_json;
 
-static AUDIT_COUNTER: AtomicU64 = AtomicU64::new(0);
-static mut PREV_HASH: Option<String> = None;
+/// `prev_hash` of the first entry in a chain.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditEntry {
     pub sequence: u64,
     pub timestamp: String,
@@ -18,7 +18,7 @@ pub struct AuditEntry {
     pub hash: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum AuditEventType {
     Auth,
@@ -26,11 +26,17 @@ pub enum AuditEventType {
     Override,
     Export,
     License,
+    Project,
+    Audit,
+    Checkpoint,
+    User,
     System,
     Error,
 }
 
 impl AuditEntry {
+    /// Builds an entry that is not yet part of a chain. Sequence, timestamp
+    /// and hashes are assigned when the audit log appends it.
     pub fn new(
         event_type: AuditEventType,
         actor: &str,
@@ -38,42 +44,42 @@ impl AuditEntry {
         resource: &str,
         details: Option<serde_json::Value>,
     ) -> Self {
-        let sequence = AUDIT_COUNTER.fetch_add(1, Ordering::SeqCst);
-        let timestamp = chrono::Utc::now().to_rfc3339();
-        
-        let prev_hash = unsafe {
-            PREV_HASH.clone().unwrap_or_else(|| "0".repeat(64))
-        };
-        
-        let hash_input = format!(
-            "{}:{}:{}:{}:{}:{}:{}",
-            sequence,
-            timestamp,
-            format!("{:?}", event_type),
-            actor,
-            action,
-            resource,
-            prev_hash
-        );
-        
-        let hash = hex::encode(Sha256::digest(hash_input.as_bytes()));
-        
-        unsafe {
-            PREV_HASH = Some(hash.clone());
-        }
-        
         Self {
-            sequence,
-            timestamp,
+            sequence: 0,
+            timestamp: chrono::Utc::now().to_rfc3339(),
             event_type,
             actor: actor.to_string(),
             action: action.to_string(),
             resource: resource.to_string(),
             details,
-            prev_hash,
-            hash,
+            prev_hash: String::new(),
+            hash: String::new(),
         }
     }
+
+    /// Links the entry after `prev_hash` at `sequence` and computes its hash.
+    pub fn seal(mut self, sequence: u64, prev_hash: &str) -> Self {
+        self.sequence = sequence;
+        self.timestamp = chrono::Utc::now().to_rfc3339();
+        self.prev_hash = prev_hash.to_string();
+        self.hash = self.compute_hash();
+        self
+    }
+
+    /// SHA-256 over the canonical JSON of every field except `hash` itself.
+    pub fn compute_hash(&self) -> String {
+        let canonical = canonical_json(&serde_json::json!({
+            "sequence": self.sequence,
+            "timestamp": self.timestamp,
+            "event_type": self.event_type,
+            "actor": self.actor,
+            "action": self.action,
+            "resource": self.resource,
+            "details": self.details,
+            "prev_hash": self.prev_hash,
+        }));
+        hex::encode(Sha256::digest(canonical.as_bytes()))
+    }
 }
 
 pub fn log_auth(actor: &str, action: &str, success: bool) -> AuditEntry {
@@ -102,9 +108,9 @@ pub fn log_override(actor: &str, project_id: &str, target: &str, behavior: &str)
         actor,
         "override",
         project_id,
-        Some(serde_json::json!({ 
+        Some(serde_json::json!({
             "target": target,
-            "behavior": behavior 
+            "behavior": behavior
         })),
     )
 }
@@ -149,24 +155,56 @@ pub fn log_error(actor: &str, action: &str, error: &str) -> AuditEntry {
     )
 }
 
-pub fn verify_chain(entries: &[AuditEntry]) -> bool {
-    if entries.is_empty() {
-        return true;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainFault {
+    /// The stored hash does not match the entry's contents.
+    HashMismatch,
+    /// `prev_hash` does not point at the previous entry.
+    BrokenLink,
+    /// Sequence numbers are not consecutive.
+    SequenceGap,
+    /// A signed checkpoint does not verify or attests a different hash.
+    BadCheckpoint,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TamperReport {
+    pub sequence: u64,
+    pub fault: ChainFault,
+}
+
+/// Recomputes every hash and checks every link. A chain starting at
+/// sequence 0 must also start from `GENESIS_HASH`.
+pub fn verify_chain(entries: &[AuditEntry]) -> Result<(), TamperReport> {
+    let Some(first) = entries.first() else {
+        return Ok(());
+    };
+    if first.sequence == 0 && first.prev_hash != GENESIS_HASH {
+        return Err(tampered(first, ChainFault::BrokenLink));
     }
-    
-    for i in 1..entries.len() {
-        if entries[i].prev_hash != entries[i - 1].hash {
-            tracing::error!(
-                "Audit chain broken at sequence {}: expected {}, got {}",
-                entries[i].sequence,
-                entries[i - 1].hash,
-                entries[i].prev_hash
-            );
-            return false;
+
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.compute_hash() != entry.hash {
+            return Err(tampered(entry, ChainFault::HashMismatch));
+        }
+        let Some(prev) = i.checked_sub(1).map(|p| &entries[p]) else {
+            continue;
+        };
+        if entry.sequence != prev.sequence + 1 {
+            return Err(tampered(entry, ChainFault::SequenceGap));
+        }
+        if entry.prev_hash != prev.hash {
+            return Err(tampered(entry, ChainFault::BrokenLink));
         }
     }
-    
-    true
+
+    Ok(())
+}
+
+fn tampered(entry: &AuditEntry, fault: ChainFault) -> TamperReport {
+    tracing::error!("Audit chain fails at sequence {}: {:?}", entry.sequence, fault);
+    TamperReport { sequence: entry.sequence, fault }
 }
 
 pub fn format_log_line(entry: &AuditEntry) -> String {
@@ -178,6 +216,47 @@ pub fn format_log_line(entry: &AuditEntry) -> String {
         entry.actor,
         entry.action,
         entry.resource,
-        &entry.hash[..16]
+        entry.hash.get(..16).unwrap_or(&entry.hash)
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(len: u64) -> Vec<AuditEntry> {
+        let mut prev = GENESIS_HASH.to_string();
+        (0..len)
+            .map(|seq| {
+                let entry = log_generate("alice", "p1", seq).seal(seq, &prev);
+                prev = entry.hash.clone();
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_chain_verifies() {
+        assert_eq!(verify_chain(&chain(5)), Ok(()));
+        assert_eq!(verify_chain(&chain(5)[2..]), Ok(()));
+    }
+
+    #[test]
+    fn test_tampered_details_are_detected() {
+        let mut entries = chain(5);
+        entries[3].details = Some(serde_json::json!({ "seed": 999 }));
+        assert_eq!(verify_chain(&entries), Err(TamperReport { sequence: 3, fault: ChainFault::HashMismatch }));
+
+        let mut entries = chain(5);
+        entries.remove(2);
+        assert_eq!(verify_chain(&entries), Err(TamperReport { sequence: 3, fault: ChainFault::SequenceGap }));
+    }
+
+    #[test]
+    fn test_rehashed_entry_breaks_next_link() {
+        let mut entries = chain(4);
+        entries[1].actor = "mallory".into();
+        entries[1].hash = entries[1].compute_hash();
+        assert_eq!(verify_chain(&entries), Err(TamperReport { sequence: 2, fault: ChainFault::BrokenLink }));
+    }
+}
@@ -109,5 +109,5 @@ impl Drop for Timer {
 }
 
 pub fn generate_request_id() -> String {
-    format!("req-{}", uuid::Uuid::new_v4().to_string()[..8].to_string())
+    format!("req-{}", &uuid::Uuid::new_v4().to_string()[..8])
 }
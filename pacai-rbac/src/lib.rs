@@ -0,0 +1,118 @@
+//! Roles, permissions and the policy engine shared by the v4 and v5
+//! gateways. `V4_RBAC_POLICY.json` is the built-in policy for both.
+
+pub mod permissions;
+pub mod policy;
+pub mod roles;
+
+pub use policy::{covers, Decision, PolicyError, PolicyFile, RbacPolicy, RolePolicy, TenantPolicy};
+pub use roles::{Role, LEGACY_OPERATOR, LEGACY_ROLES};
+
+#[cfg(test)]
+mod tests {
+    use super::permissions::*;
+    use super::*;
+
+    /// What `V4_RBAC_POLICY.json` grants, read off the file by hand. Columns
+    /// follow `Role::ALL`: admin, instructor, operator, auditor, integrator.
+    const MATRIX: &[(&str, [bool; 5])] = &[
+        (GENERATE, [true, true, false, false, false]),
+        (CONTROL_APPLY, [true, true, true, false, false]),
+        // Integrators hold `export:build`, which reaches the route but no format.
+        (EXPORT, [true, true, false, false, true]),
+        ("export:ue5", [true, true, false, false, false]),
+        ("export:unity", [true, true, false, false, false]),
+        ("export:godot", [true, true, false, false, false]),
+        ("export:vbs4", [true, false, false, false, false]),
+        ("export:onetess", [true, false, false, false, false]),
+        ("export:roblox", [true, false, false, false, false]),
+        ("export:blender", [true, false, false, false, false]),
+        ("export:cryengine", [true, false, false, false, false]),
+        ("export:source2", [true, false, false, false, false]),
+        ("export:webgpu", [true, false, false, false, false]),
+        ("export:visionos", [true, false, false, false, false]),
+        (AUDIT_READ, [true, true, true, true, true]),
+        (PROJECTS_CREATE, [true, true, false, false, false]),
+        (PROJECTS_READ, [true, true, false, false, false]),
+        (PROJECTS_UPDATE, [true, true, false, false, false]),
+        (PROJECTS_DELETE, [true, false, false, false, false]),
+        (SCENARIOS_READ, [true, true, true, true, true]),
+        (USERS_LIST, [true, true, false, true, false]),
+        (USERS_CREATE, [true, false, false, false, false]),
+        (USERS_MODIFY, [true, false, false, false, false]),
+        (USERS_DELETE, [true, false, false, false, false]),
+        (ROLES_ASSIGN, [true, false, false, false, false]),
+        (KEYS_CREATE, [true, false, false, false, false]),
+        (KEYS_DELETE, [true, false, false, false, false]),
+    ];
+
+    fn vocabulary() -> Vec<String> {
+        ALL.iter()
+            .map(|p| p.to_string())
+            .chain(EXPORT_FORMATS.iter().map(|f| export(f)))
+            .collect()
+    }
+
+    #[test]
+    fn test_builtin_policy_matches_the_v4_matrix() {
+        let policy = RbacPolicy::builtin();
+        assert_eq!(
+            policy.roles.keys().map(String::as_str).collect::<Vec<_>>(),
+            ["admin", "auditor", "creator", "demo", "instructor", "integrator", "legacy_operator", "lifetime", "operator"]
+        );
+
+        let vocabulary = vocabulary();
+        assert_eq!(MATRIX.len(), vocabulary.len());
+        for permission in &vocabulary {
+            let (_, expected) = MATRIX
+                .iter()
+                .find(|(p, _)| p == permission)
+                .unwrap_or_else(|| panic!("{} is missing from the matrix", permission));
+            for (role, expected) in Role::ALL.iter().zip(expected) {
+                let decision = policy.decide(role.as_str(), None, permission);
+                assert_eq!(decision.allowed, *expected, "{} / {}: {}", role, permission, decision.reason);
+            }
+        }
+    }
+
+    /// What the v5 gateway's own policy granted each legacy role, in the v4
+    /// vocabulary. Columns follow `LEGACY_ROLES`: lifetime, creator, demo,
+    /// legacy_operator. Its `project:read` covered zones, hence
+    /// `scenarios:read`.
+    const LEGACY_MATRIX: &[(&str, [bool; 4])] = &[
+        (GENERATE, [true, true, true, true]),
+        (CONTROL_APPLY, [true, false, false, true]),
+        (EXPORT, [true, true, false, true]),
+        (AUDIT_READ, [true, false, false, true]),
+        (PROJECTS_CREATE, [true, true, false, false]),
+        (PROJECTS_READ, [true, true, true, true]),
+        (PROJECTS_UPDATE, [true, true, false, true]),
+        (SCENARIOS_READ, [true, true, true, true]),
+    ];
+
+    #[test]
+    fn test_legacy_roles_keep_their_v5_permissions() {
+        let policy = RbacPolicy::builtin();
+        for permission in vocabulary() {
+            // v5 `export` reached every engine.
+            let granted = match permission.strip_prefix("export:") {
+                Some(_) => LEGACY_MATRIX.iter().find(|(p, _)| *p == EXPORT),
+                None => LEGACY_MATRIX.iter().find(|(p, _)| *p == permission),
+            };
+            for (index, legacy) in LEGACY_ROLES.iter().enumerate() {
+                let expected = granted.is_some_and(|(_, roles)| roles[index]);
+                let decision = policy.decide(legacy, None, &permission);
+                assert_eq!(decision.allowed, expected, "{} / {}: {}", legacy, permission, decision.reason);
+                assert_eq!(decision.effective_role.as_deref(), Some(*legacy));
+            }
+        }
+        for legacy in LEGACY_ROLES {
+            assert_eq!(Role::parse(legacy), None);
+        }
+        for role in Role::ALL {
+            assert_eq!(Role::parse(role.as_str()), Some(role));
+        }
+        assert_eq!(Role::parse("root"), None);
+        assert_eq!(policy.decide("root", None, AUDIT_READ).effective_role, None);
+    }
+}
@@ -0,0 +1,59 @@
+//! The permissions the gateways check, named as `V4_RBAC_POLICY.json`
+//! grants them. Routes ask for these, never for ad-hoc strings, so a
+//! policy file means the same thing to either gateway.
+
+pub const GENERATE: &str = "generate";
+
+pub const CONTROL_APPLY: &str = "control:apply";
+
+/// Reaching the export route. Each format is then checked as `export:<format>`.
+pub const EXPORT: &str = "export";
+
+pub const AUDIT_READ: &str = "audit:read";
+
+pub const PROJECTS_CREATE: &str = "projects:create";
+pub const PROJECTS_READ: &str = "projects:read";
+pub const PROJECTS_UPDATE: &str = "projects:update";
+pub const PROJECTS_DELETE: &str = "projects:delete";
+
+pub const SCENARIOS_READ: &str = "scenarios:read";
+
+pub const USERS_LIST: &str = "users:list";
+pub const USERS_CREATE: &str = "users:create";
+pub const USERS_MODIFY: &str = "users:modify";
+pub const USERS_DELETE: &str = "users:delete";
+pub const ROLES_ASSIGN: &str = "roles:assign";
+
+pub const KEYS_CREATE: &str = "keys:create";
+pub const KEYS_DELETE: &str = "keys:delete";
+
+/// Engines the policy can list under `export_formats`: the simulator
+/// formats of v4 and the game engines the v5 packager builds.
+pub const EXPORT_FORMATS: &[&str] = &[
+    "ue5", "unity", "godot", "vbs4", "onetess", "roblox", "blender", "cryengine", "source2", "webgpu", "visionos",
+];
+
+/// Every fixed permission above.
+pub const ALL: &[&str] = &[
+    GENERATE,
+    CONTROL_APPLY,
+    EXPORT,
+    AUDIT_READ,
+    PROJECTS_CREATE,
+    PROJECTS_READ,
+    PROJECTS_UPDATE,
+    PROJECTS_DELETE,
+    SCENARIOS_READ,
+    USERS_LIST,
+    USERS_CREATE,
+    USERS_MODIFY,
+    USERS_DELETE,
+    ROLES_ASSIGN,
+    KEYS_CREATE,
+    KEYS_DELETE,
+];
+
+/// The permission to export to one engine.
+pub fn export(format: &str) -> String {
+    format!("{}:{}", EXPORT, format)
+}
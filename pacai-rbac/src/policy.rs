@@ -0,0 +1,355 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+
+/// Actions whose resources are limited by a list in the policy, as
+/// `(action, role resource limit, tenant override)`. `export:ue5` is only
+/// granted if `ue5` is in both lists, where present.
+const SCOPED_ACTIONS: &[(&str, &str, &str)] = &[("export", "export_formats", "export_formats_allowed")];
+
+const BUILTIN_POLICY: &str = include_str!("../../V4_RBAC_POLICY.json");
+
+/// An RBAC policy in the `V4_RBAC_POLICY.json` format. Access is denied
+/// unless a capability of the role covers the permission.
+///
+/// Capabilities are `action` or `action:resource`. `*` on its own covers
+/// everything; `action:*` and a bare `action` both cover the action on any
+/// resource.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RbacPolicy {
+    #[serde(default)]
+    pub version: String,
+    pub roles: BTreeMap<String, RolePolicy>,
+    #[serde(default)]
+    pub per_tenant_overrides: BTreeMap<String, TenantPolicy>,
+    #[serde(default)]
+    validation_rules: ValidationRules,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RolePolicy {
+    #[serde(default)]
+    pub description: String,
+    pub capabilities: Vec<String>,
+    #[serde(default)]
+    pub resource_limits: BTreeMap<String, serde_json::Value>,
+    /// Documented restrictions; reported by `explain`, not enforced.
+    #[serde(default)]
+    pub constraints: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantPolicy {
+    #[serde(default)]
+    pub roles_enabled: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub settings: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ValidationRules {
+    #[serde(default)]
+    policy_linter: PolicyLinter,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct PolicyLinter {
+    max_capabilities_per_role: Option<usize>,
+}
+
+/// The outcome of a check and the part of the policy that decided it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Decision {
+    pub allowed: bool,
+    /// The policy role the request was checked as; differs from the
+    /// requested role for legacy names. `None` if the role is unknown.
+    pub effective_role: Option<String>,
+    /// Path into the policy document, e.g. `roles.admin.capabilities[2]`,
+    /// or `default_deny` when nothing matched.
+    pub rule: String,
+    pub capability: Option<String>,
+    pub reason: String,
+}
+
+impl Decision {
+    fn allow(role: &str, rule: String, capability: &str, reason: String) -> Self {
+        Self {
+            allowed: true,
+            effective_role: Some(role.to_string()),
+            rule,
+            capability: Some(capability.to_string()),
+            reason,
+        }
+    }
+
+    fn deny(role: Option<&str>, rule: String, reason: String) -> Self {
+        Self { allowed: false, effective_role: role.map(str::to_string), rule, capability: None, reason }
+    }
+}
+
+/// Whether `capability` grants `permission`.
+pub fn covers(capability: &str, permission: &str) -> bool {
+    if capability == "*" {
+        return true;
+    }
+    let base = capability.strip_suffix(":*").unwrap_or(capability);
+    permission == base || permission.strip_prefix(base).is_some_and(|rest| rest.starts_with(':'))
+}
+
+impl RbacPolicy {
+    pub fn from_json(bytes: &[u8]) -> Result<Self, PolicyError> {
+        let policy: RbacPolicy = serde_json::from_slice(bytes)?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PolicyError> {
+        let bytes = std::fs::read(path).map_err(|e| PolicyError::Io { path: path.to_path_buf(), source: e })?;
+        Self::from_json(&bytes)
+    }
+
+    /// `V4_RBAC_POLICY.json` as shipped with this crate.
+    pub fn builtin() -> Self {
+        Self::from_json(BUILTIN_POLICY.as_bytes()).expect("built-in RBAC policy is valid")
+    }
+
+    fn validate(&self) -> Result<(), PolicyError> {
+        let invalid = |reason: String| Err(PolicyError::Invalid { reason });
+        if self.roles.is_empty() {
+            return invalid("the policy defines no roles".into());
+        }
+        for (name, role) in &self.roles {
+            if role.capabilities.is_empty() {
+                return invalid(format!("role '{}' has no capabilities", name));
+            }
+            if let Some(max) = self.validation_rules.policy_linter.max_capabilities_per_role {
+                if role.capabilities.len() > max {
+                    return invalid(format!("role '{}' has more than {} capabilities", name, max));
+                }
+            }
+            let malformed = role.capabilities.iter().find(|c| {
+                c.is_empty()
+                    || c.contains(char::is_whitespace)
+                    || c.split(':').any(str::is_empty)
+                    || c.split(':').rev().skip(1).any(|segment| segment.contains('*'))
+            });
+            if let Some(capability) = malformed {
+                return invalid(format!("role '{}' has malformed capability '{}'", name, capability));
+            }
+        }
+        for (tenant, policy) in &self.per_tenant_overrides {
+            let unknown = policy.roles_enabled.iter().flatten().find(|r| !self.roles.contains_key(*r));
+            if let Some(role) = unknown {
+                return invalid(format!("tenant '{}' enables undefined role '{}'", tenant, role));
+            }
+        }
+        Ok(())
+    }
+
+    /// The policy role `name` is checked as, if the policy defines it.
+    pub fn resolve_role(&self, name: &str) -> Option<&str> {
+        self.roles.get_key_value(name).map(|(defined, _)| defined.as_str())
+    }
+
+    /// Decides whether `role`, acting for `tenant`, holds `permission`.
+    pub fn decide(&self, role: &str, tenant: Option<&str>, permission: &str) -> Decision {
+        let Some(role) = self.resolve_role(role) else {
+            return Decision::deny(None, "default_deny".into(), format!("Role '{}' is not defined in the policy", role));
+        };
+        let policy = &self.roles[role];
+        let overrides = tenant.and_then(|t| self.per_tenant_overrides.get(t).map(|o| (t, o)));
+        if let Some((tenant, enabled)) = overrides.and_then(|(t, o)| o.roles_enabled.as_ref().map(|r| (t, r))) {
+            if !enabled.iter().any(|r| r == role) {
+                return Decision::deny(
+                    Some(role),
+                    format!("per_tenant_overrides.{}.roles_enabled", tenant),
+                    format!("Role '{}' is not enabled for tenant '{}'", role, tenant),
+                );
+            }
+        }
+
+        let (action, resource) = match permission.split_once(':') {
+            Some((action, resource)) => (action, Some(resource)),
+            None => (permission, None),
+        };
+        let scope = SCOPED_ACTIONS.iter().find(|(scoped, _, _)| *scoped == action);
+
+        let matched = policy.capabilities.iter().enumerate().find(|(_, c)| covers(c, permission));
+        let Some((index, capability)) = matched else {
+            // Holding the action for some resources is enough to reach the
+            // route; each resource is checked when the request names it.
+            let partial = scope.filter(|_| resource.is_none()).and_then(|_| {
+                policy
+                    .capabilities
+                    .iter()
+                    .enumerate()
+                    .find(|(_, c)| c.strip_prefix(action).is_some_and(|rest| rest.starts_with(':')))
+            });
+            return match partial {
+                Some((index, capability)) => Decision::allow(
+                    role,
+                    format!("roles.{}.capabilities[{}]", role, index),
+                    capability,
+                    format!("'{}' grants '{}' for some resources; each is checked per request", capability, permission),
+                ),
+                None => Decision::deny(
+                    Some(role),
+                    "default_deny".into(),
+                    format!("No capability of role '{}' covers '{}'", role, permission),
+                ),
+            };
+        };
+
+        if let (Some((_, role_limit, tenant_limit)), Some(resource)) = (scope, resource) {
+            if !listed(policy.resource_limits.get(*role_limit), resource) {
+                return Decision::deny(
+                    Some(role),
+                    format!("roles.{}.resource_limits.{}", role, role_limit),
+                    format!("'{}' is not among the {} of role '{}'", resource, role_limit, role),
+                );
+            }
+            if let Some((tenant, overrides)) = overrides {
+                if !listed(overrides.settings.get(*tenant_limit), resource) {
+                    return Decision::deny(
+                        Some(role),
+                        format!("per_tenant_overrides.{}.{}", tenant, tenant_limit),
+                        format!("'{}' is not among the {} of tenant '{}'", resource, tenant_limit, tenant),
+                    );
+                }
+            }
+        }
+
+        Decision::allow(
+            role,
+            format!("roles.{}.capabilities[{}]", role, index),
+            capability,
+            format!("'{}' grants '{}'", capability, permission),
+        )
+    }
+}
+
+/// True unless `list` is a JSON array that leaves `resource` out.
+fn listed(list: Option<&serde_json::Value>, resource: &str) -> bool {
+    match list.and_then(|v| v.as_array()) {
+        Some(items) => items.iter().any(|item| item.as_str() == Some(resource)),
+        None => true,
+    }
+}
+
+/// A policy file and the modification time of the version last read.
+pub struct PolicyFile {
+    path: PathBuf,
+    loaded: Option<SystemTime>,
+}
+
+impl PolicyFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), loaded: None }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The policy, if the file changed since the last successful read.
+    pub fn poll(&mut self) -> Result<Option<RbacPolicy>, PolicyError> {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .map_err(|e| PolicyError::Io { path: self.path.clone(), source: e })?;
+        if self.loaded == Some(modified) {
+            return Ok(None);
+        }
+        let policy = RbacPolicy::load(&self.path)?;
+        self.loaded = Some(modified);
+        Ok(Some(policy))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyError {
+    #[error("{path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+
+    #[error("RBAC policy is not valid JSON: {0}")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("RBAC policy rejected: {reason}")]
+    Invalid { reason: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_wildcards_cover_the_action_and_its_resources() {
+        assert!(covers("*", "users:manage"));
+        assert!(covers("generate:*", "generate"));
+        assert!(covers("generate:*", "generate:npc"));
+        assert!(covers("export", "export:ue5"));
+        assert!(covers("export:ue5", "export:ue5"));
+        assert!(!covers("export:ue5", "export:unity"));
+        assert!(!covers("export:ue5", "export"));
+        assert!(!covers("audit:read", "audit:readwrite"));
+        assert!(!covers("gen", "generate"));
+    }
+
+    #[test]
+    fn test_v4_policy_scopes_resources_by_role_and_tenant() {
+        let policy = RbacPolicy::builtin();
+        let decide = |role, tenant, permission| policy.decide(role, tenant, permission);
+
+        let granted = decide("admin", None, "generate:npc");
+        assert!(granted.allowed);
+        assert_eq!(granted.capability.as_deref(), Some("generate:*"));
+        assert_eq!(granted.rule, "roles.admin.capabilities[3]");
+
+        assert!(decide("instructor", None, "export:ue5").allowed);
+        assert_eq!(decide("instructor", None, "export:vbs4").rule, "default_deny");
+        // Enough to reach the export route; the formats are checked per request.
+        assert!(decide("instructor", None, "export").allowed);
+        assert!(!decide("operator", None, "export").allowed);
+
+        assert_eq!(decide("admin", None, "export:fbx").rule, "roles.admin.resource_limits.export_formats");
+        assert_eq!(
+            decide("instructor", Some("police_dept_training"), "export:godot").rule,
+            "per_tenant_overrides.police_dept_training.export_formats_allowed"
+        );
+        assert_eq!(
+            decide("admin", Some("police_dept_training"), "audit:read").rule,
+            "per_tenant_overrides.police_dept_training.roles_enabled"
+        );
+        assert!(decide("admin", Some("military_sim"), "export:vbs4").allowed);
+        assert!(decide("operator", Some("unlisted"), "control:apply").allowed);
+        assert!(!decide("ghost", None, "audit:read").allowed);
+    }
+
+    #[test]
+    fn test_policy_file_is_reread_only_when_it_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.json");
+        std::fs::write(&path, BUILTIN_POLICY).unwrap();
+        let mut file = PolicyFile::new(&path);
+
+        assert!(file.poll().unwrap().is_some());
+        assert!(file.poll().unwrap().is_none());
+
+        let touch = |offset: u64| {
+            let handle = std::fs::File::options().write(true).open(&path).unwrap();
+            handle.set_modified(SystemTime::now() + Duration::from_secs(offset)).unwrap();
+        };
+        std::fs::write(&path, r#"{"roles": {"demo": {"capabilities": []}}}"#).unwrap();
+        touch(60);
+        assert!(matches!(file.poll(), Err(PolicyError::Invalid { .. })));
+
+        std::fs::write(&path, r#"{"roles": {"demo": {"capabilities": ["generate:*"]}}}"#).unwrap();
+        touch(120);
+        let policy = file.poll().unwrap().unwrap();
+        assert!(policy.decide("demo", None, "generate:zone").allowed);
+        assert!(!policy.decide("admin", None, "generate").allowed);
+    }
+}
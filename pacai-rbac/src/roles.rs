@@ -0,0 +1,41 @@
+/// The roles defined by `V4_RBAC_POLICY.json`, which both gateways use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Admin,
+    Instructor,
+    Operator,
+    Auditor,
+    Integrator,
+}
+
+/// Policy roles that keep the capability sets of the v5 gateway's own
+/// roles, so accounts created before the move gain nothing. `lifetime`,
+/// `creator` and `demo` kept their names; a v5 `operator` is stored as
+/// `LEGACY_OPERATOR`, since v4 uses `operator` for a narrower role.
+pub const LEGACY_ROLES: &[&str] = &["lifetime", "creator", "demo", LEGACY_OPERATOR];
+
+pub const LEGACY_OPERATOR: &str = "legacy_operator";
+
+impl Role {
+    pub const ALL: [Role; 5] = [Role::Admin, Role::Instructor, Role::Operator, Role::Auditor, Role::Integrator];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|role| role.as_str() == name)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Instructor => "instructor",
+            Role::Operator => "operator",
+            Role::Auditor => "auditor",
+            Role::Integrator => "integrator",
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
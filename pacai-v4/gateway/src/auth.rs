@@ -1,32 +1,210 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::rbac::{covers, RbacMiddleware, Role};
+use pacai_rbac::RbacPolicy;
+
+pub mod api_keys;
+pub mod oidc;
+pub mod x509;
+
+use api_keys::ApiKeyStore;
+use oidc::OidcVerifier;
+use x509::TrustStore;
+
+/// Where each identity method finds what it validates against. A method
+/// whose source is not configured rejects every credential.
+#[derive(Debug, Clone, Default)]
+pub struct IdentityConfig {
+    pub api_keys_path: Option<PathBuf>,
+    pub ca_bundle_path: Option<PathBuf>,
+    pub jwks_path: Option<PathBuf>,
+    pub oidc_issuer: Option<String>,
+    pub oidc_audience: Option<String>,
+    pub jwks_max_age: Option<Duration>,
+}
+
+impl IdentityConfig {
+    pub fn from_env() -> Self {
+        let path = |name: &str| std::env::var(name).ok().map(PathBuf::from);
+        Self {
+            api_keys_path: path("PACAI_API_KEYS").or_else(|| Some(PathBuf::from("data/api_keys.json"))),
+            ca_bundle_path: path("PACAI_CA_BUNDLE"),
+            jwks_path: path("PACAI_OIDC_JWKS"),
+            oidc_issuer: std::env::var("PACAI_OIDC_ISSUER").ok(),
+            oidc_audience: std::env::var("PACAI_OIDC_AUDIENCE").ok(),
+            jwks_max_age: std::env::var("PACAI_OIDC_JWKS_MAX_AGE_HOURS")
+                .ok()
+                .and_then(|h| h.parse::<u64>().ok())
+                .map(|h| Duration::from_secs(h * 3600)),
+        }
+    }
+}
+
+/// The validators built from an `IdentityConfig`.
+pub struct IdentityProviders {
+    pub api_keys: Option<ApiKeyStore>,
+    pub trust: Option<TrustStore>,
+    pub oidc: Option<OidcVerifier>,
+}
+
+impl IdentityProviders {
+    pub fn load(config: &IdentityConfig) -> Result<Self> {
+        let api_keys = config.api_keys_path.as_ref().map(ApiKeyStore::open).transpose()?;
+        let trust = config.ca_bundle_path.as_deref().map(TrustStore::load).transpose()?;
+        let oidc = match (&config.jwks_path, &config.oidc_issuer, &config.oidc_audience) {
+            (Some(jwks), Some(issuer), Some(audience)) => Some(OidcVerifier::new(
+                jwks,
+                issuer,
+                audience,
+                config.jwks_max_age.unwrap_or(Duration::from_secs(24 * 3600)),
+            )),
+            (None, None, None) => None,
+            _ => anyhow::bail!("OIDC needs PACAI_OIDC_JWKS, PACAI_OIDC_ISSUER and PACAI_OIDC_AUDIENCE together"),
+        };
+        Ok(Self { api_keys, trust, oidc })
+    }
+}
+
+/// Who a credential belongs to and what it may do.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub user: String,
+    pub role: Role,
+    pub method: &'static str,
+    /// Capabilities an API key is limited to; `None` for other methods.
+    pub scopes: Option<Vec<String>>,
+}
+
+impl Identity {
+    /// Role check plus, for API keys, the key's own scopes.
+    pub fn can(&self, policy: &RbacPolicy, permission: &str) -> bool {
+        let in_scope = self
+            .scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.iter().any(|s| covers(s, permission)));
+        in_scope && RbacMiddleware::enforce(policy, self.role, permission).is_ok()
+    }
+}
 
 pub async fn validate_identity(
+    providers: &IdentityProviders,
     method: &str,
     credential: &str,
     offline_mode: bool,
-) -> Result<String> {
+) -> Result<Identity> {
+    let now = Utc::now();
     match method {
         "api_key" => {
-            if credential.starts_with("sk_") {
-                Ok(format!("user_from_api_key"))
-            } else {
-                Err(anyhow::anyhow!("Invalid API key format"))
-            }
+            let store = providers.api_keys.as_ref().ok_or_else(|| anyhow!("API keys are not configured"))?;
+            let record = store.verify(credential.trim(), now)?;
+            Ok(Identity {
+                role: parse_role(&record.role)?,
+                user: record.user,
+                method: "api_key",
+                scopes: Some(record.scopes),
+            })
         }
         "sso" => {
-            if offline_mode {
-                Err(anyhow::anyhow!("SSO unavailable in offline mode"))
-            } else {
-                Ok(format!("user_from_sso"))
-            }
+            let verifier = providers.oidc.as_ref().ok_or_else(|| anyhow!("SSO is not configured"))?;
+            let claims = verifier.validate(credential.trim(), offline_mode)?;
+            let role = claims
+                .role_names()
+                .find_map(Role::parse)
+                .ok_or_else(|| anyhow!("ID token for {} carries no recognised role", claims.user()))?;
+            Ok(Identity {
+                user: claims.user().to_string(),
+                role,
+                method: "sso",
+                scopes: None,
+            })
         }
         "x509" => {
-            if credential.contains("BEGIN CERTIFICATE") {
-                Ok(format!("user_from_x509"))
-            } else {
-                Err(anyhow::anyhow!("Invalid X.509 certificate"))
-            }
+            let trust = providers.trust.as_ref().ok_or_else(|| anyhow!("No CA bundle configured"))?;
+            let chain = x509::parse_pem_chain(credential).context("Invalid X.509 certificate")?;
+            let leaf = trust.verify_chain(&chain, now)?;
+            let user = leaf.common_name.clone().ok_or_else(|| anyhow!("Client certificate has no common name"))?;
+            let role = leaf
+                .organizational_unit
+                .as_deref()
+                .and_then(Role::parse)
+                .ok_or_else(|| anyhow!("Client certificate for {} carries no recognised role (OU)", user))?;
+            Ok(Identity { user, role, method: "x509", scopes: None })
         }
-        _ => Err(anyhow::anyhow!("Unknown identity method")),
+        _ => Err(anyhow!("Unknown identity method")),
+    }
+}
+
+fn parse_role(name: &str) -> Result<Role> {
+    Role::parse(name).ok_or_else(|| anyhow!("Unknown role {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rbac::permissions;
+    use std::path::Path;
+
+    fn providers(dir: &Path) -> IdentityProviders {
+        let testdata = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        IdentityProviders::load(&IdentityConfig {
+            api_keys_path: Some(dir.join("api_keys.json")),
+            ca_bundle_path: Some(testdata.join("x509/ca.pem")),
+            ..IdentityConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_api_key_scopes_narrow_the_role() {
+        let dir = tempfile::tempdir().unwrap();
+        let providers = providers(dir.path());
+        let policy = RbacPolicy::builtin();
+        let store = providers.api_keys.as_ref().unwrap();
+
+        let (key, _) = store.issue("dana", "instructor", vec!["export:*".into()], None).unwrap();
+        let identity = validate_identity(&providers, "api_key", &key, false).await.unwrap();
+        assert_eq!((identity.user.as_str(), identity.role), ("dana", Role::Instructor));
+        assert!(identity.can(&policy, "export:ue5"));
+        // The role may generate, but the key was not scoped for it.
+        assert!(!identity.can(&policy, permissions::GENERATE));
+
+        // A wildcard scope never grants more than the role holds.
+        let (key, _) = store.issue("erin", "operator", vec!["*".into()], None).unwrap();
+        let identity = validate_identity(&providers, "api_key", &key, false).await.unwrap();
+        assert!(identity.can(&policy, permissions::CONTROL_APPLY));
+        assert!(!identity.can(&policy, permissions::GENERATE));
+
+        let (key, _) = store.issue("frank", "superuser", vec!["*".into()], None).unwrap();
+        assert!(validate_identity(&providers, "api_key", &key, false).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_x509_credentials_map_common_name_and_unit() {
+        let dir = tempfile::tempdir().unwrap();
+        let providers = providers(dir.path());
+        let testdata = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/x509");
+        let read = |name: &str| std::fs::read_to_string(testdata.join(name)).unwrap();
+
+        let chain = read("leaf.pem") + &read("intermediate.pem");
+        let identity = validate_identity(&providers, "x509", &chain, false).await.unwrap();
+        assert_eq!((identity.user.as_str(), identity.role, identity.method), ("alice", Role::Instructor, "x509"));
+        assert!(identity.scopes.is_none());
+
+        let expired = read("expired.pem") + &read("intermediate.pem");
+        assert!(validate_identity(&providers, "x509", &expired, false).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unconfigured_and_unknown_methods_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let providers = providers(dir.path());
+        assert!(validate_identity(&providers, "sso", "token", false).await.is_err());
+        assert!(validate_identity(&providers, "password", "hunter2", false).await.is_err());
+
+        let incomplete = IdentityConfig { jwks_path: Some(dir.path().join("jwks.json")), ..IdentityConfig::default() };
+        assert!(IdentityProviders::load(&incomplete).is_err());
     }
 }
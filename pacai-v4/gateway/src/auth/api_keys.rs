@@ -0,0 +1,228 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use subtle::ConstantTimeEq;
+
+/// A stored API key. Only the SHA-256 of the key is kept; the key itself is
+/// shown once, when it is issued. Keys look like `sk_<id>_<secret>`, so the
+/// record is found by id without scanning every hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyRecord {
+    pub id: String,
+    pub user: String,
+    pub role: String,
+    /// Capabilities the key is limited to, in the policy's own syntax
+    /// (`export:*`, `keys:create`); `*` allows everything the role does.
+    pub scopes: Vec<String>,
+    pub key_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// API keys persisted as one JSON file.
+pub struct ApiKeyStore {
+    path: PathBuf,
+    keys: RwLock<HashMap<String, ApiKeyRecord>>,
+}
+
+impl ApiKeyStore {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let records: Vec<ApiKeyRecord> = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).with_context(|| format!("parsing {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        let keys = records.into_iter().map(|r| (r.id.clone(), r)).collect();
+        Ok(Self { path, keys: RwLock::new(keys) })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.read().unwrap().is_empty()
+    }
+
+    /// Creates a key and returns it in plain text along with its record.
+    pub fn issue(
+        &self,
+        user: &str,
+        role: &str,
+        scopes: Vec<String>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(String, ApiKeyRecord)> {
+        let rng = SystemRandom::new();
+        let mut id = [0u8; 6];
+        let mut secret = [0u8; 32];
+        rng.fill(&mut id).map_err(|_| anyhow!("system RNG unavailable"))?;
+        rng.fill(&mut secret).map_err(|_| anyhow!("system RNG unavailable"))?;
+
+        let id = to_hex(&id);
+        let key = format!("sk_{}_{}", id, to_hex(&secret));
+        let record = ApiKeyRecord {
+            id: id.clone(),
+            user: user.to_string(),
+            role: role.to_string(),
+            scopes,
+            key_hash: hash_key(&key),
+            created_at: Utc::now(),
+            expires_at,
+            revoked_at: None,
+        };
+
+        let mut keys = self.keys.write().unwrap();
+        keys.insert(id.clone(), record.clone());
+        if let Err(e) = self.persist(&keys) {
+            keys.remove(&id);
+            return Err(e);
+        }
+        Ok((key, record))
+    }
+
+    /// On an empty store, issues a key for `admin` and writes it to
+    /// `bootstrap_admin_key` beside the store, readable by the owner only,
+    /// so it never has to pass through the log. Returns the record and
+    /// where the key went.
+    pub fn bootstrap_admin(&self) -> Result<Option<(ApiKeyRecord, PathBuf)>> {
+        if !self.is_empty() {
+            return Ok(None);
+        }
+        let (key, record) = self.issue("admin", "admin", vec!["*".to_string()], None)?;
+        let path = self.path.with_file_name("bootstrap_admin_key");
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(&mut options.open(&path)?, key.as_bytes())
+            .with_context(|| format!("writing {}", path.display()))?;
+        Ok(Some((record, path)))
+    }
+
+    pub fn revoke(&self, id: &str) -> Result<ApiKeyRecord> {
+        let mut keys = self.keys.write().unwrap();
+        let record = keys.get_mut(id).ok_or_else(|| anyhow!("API key {} not found", id))?;
+        if record.revoked_at.is_none() {
+            record.revoked_at = Some(Utc::now());
+        }
+        let record = record.clone();
+        self.persist(&keys)?;
+        Ok(record)
+    }
+
+    /// The record for `key` if it exists, matches, and is neither revoked
+    /// nor expired.
+    pub fn verify(&self, key: &str, now: DateTime<Utc>) -> Result<ApiKeyRecord> {
+        let (id, _) = key
+            .strip_prefix("sk_")
+            .and_then(|rest| rest.split_once('_'))
+            .ok_or_else(|| anyhow!("Invalid API key format"))?;
+
+        let keys = self.keys.read().unwrap();
+        let record = keys.get(id).ok_or_else(|| anyhow!("Unknown API key"))?;
+        if !bool::from(hash_key(key).as_bytes().ct_eq(record.key_hash.as_bytes())) {
+            bail!("Unknown API key");
+        }
+        if record.revoked_at.is_some() {
+            bail!("API key {} has been revoked", id);
+        }
+        if record.expires_at.is_some_and(|at| at <= now) {
+            bail!("API key {} has expired", id);
+        }
+        Ok(record.clone())
+    }
+
+    fn persist(&self, keys: &HashMap<String, ApiKeyRecord>) -> Result<()> {
+        let mut records: Vec<&ApiKeyRecord> = keys.values().collect();
+        records.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&records)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn hash_key(key: &str) -> String {
+    to_hex(&Sha256::digest(key.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_keys_are_stored_hashed_and_found_again_after_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api_keys.json");
+        let store = ApiKeyStore::open(&path).unwrap();
+        assert!(store.is_empty());
+
+        let (key, record) = store.issue("alice", "instructor", vec!["*".into()], None).unwrap();
+        assert!(key.starts_with(&format!("sk_{}_", record.id)));
+        assert_eq!(record.key_hash, hash_key(&key));
+        let stored = std::fs::read_to_string(&path).unwrap();
+        assert!(stored.contains(&record.key_hash));
+        assert!(!stored.contains(&key));
+
+        let reopened = ApiKeyStore::open(&path).unwrap();
+        assert_eq!(reopened.verify(&key, Utc::now()).unwrap().user, "alice");
+
+        // Right id, wrong secret.
+        let forged = format!("sk_{}_{}", record.id, "0".repeat(64));
+        assert!(reopened.verify(&forged, Utc::now()).is_err());
+        assert!(reopened.verify("not-a-key", Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_bootstrap_key_is_written_to_a_private_file_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ApiKeyStore::open(dir.path().join("api_keys.json")).unwrap();
+        let (record, path) = store.bootstrap_admin().unwrap().unwrap();
+        let key = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(store.verify(&key, Utc::now()).unwrap().id, record.id);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        assert!(store.bootstrap_admin().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_revoked_and_expired_keys_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ApiKeyStore::open(dir.path().join("api_keys.json")).unwrap();
+        let now = Utc::now();
+
+        let (expiring, _) = store.issue("bob", "operator", vec!["*".into()], Some(now + Duration::days(1))).unwrap();
+        assert!(store.verify(&expiring, now).is_ok());
+        let err = store.verify(&expiring, now + Duration::days(2)).unwrap_err();
+        assert!(err.to_string().contains("expired"), "{}", err);
+
+        let (revoked, record) = store.issue("bob", "operator", vec!["*".into()], None).unwrap();
+        store.revoke(&record.id).unwrap();
+        let err = store.verify(&revoked, now).unwrap_err();
+        assert!(err.to_string().contains("revoked"), "{}", err);
+
+        // Revocation is persisted.
+        let reopened = ApiKeyStore::open(dir.path().join("api_keys.json")).unwrap();
+        assert!(reopened.verify(&revoked, now).is_err());
+        assert!(store.revoke("missing").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{anyhow, bail, Context, Result};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    #[serde(default)]
+    pub preferred_username: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub role: Option<String>,
+}
+
+impl IdTokenClaims {
+    pub fn user(&self) -> &str {
+        self.preferred_username
+            .as_deref()
+            .or(self.email.as_deref())
+            .unwrap_or(&self.sub)
+    }
+
+    /// Role names claimed by the provider, single `role` claim first.
+    pub fn role_names(&self) -> impl Iterator<Item = &str> {
+        self.role.as_deref().into_iter().chain(self.roles.iter().map(String::as_str))
+    }
+}
+
+/// Validates ID tokens against a JWKS file that is kept up to date out of
+/// band (the gateway never reaches out to the identity provider). The file
+/// is re-read whenever it changes on disk.
+pub struct OidcVerifier {
+    jwks_path: PathBuf,
+    issuer: String,
+    audience: String,
+    /// Outside offline mode a JWKS older than this is refused, so a stalled
+    /// sync job cannot keep a rotated-out key trusted forever.
+    max_age: Duration,
+    cache: RwLock<Option<(SystemTime, JwkSet)>>,
+}
+
+impl OidcVerifier {
+    pub fn new(jwks_path: impl Into<PathBuf>, issuer: &str, audience: &str, max_age: Duration) -> Self {
+        Self {
+            jwks_path: jwks_path.into(),
+            issuer: issuer.to_string(),
+            audience: audience.to_string(),
+            max_age,
+            cache: RwLock::new(None),
+        }
+    }
+
+    pub fn validate(&self, token: &str, offline_mode: bool) -> Result<IdTokenClaims> {
+        let header = jsonwebtoken::decode_header(token).context("malformed ID token")?;
+        if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            bail!("ID tokens must be signed with an asymmetric key");
+        }
+        let kid = header.kid.ok_or_else(|| anyhow!("ID token has no key id"))?;
+
+        let jwks = self.jwks(offline_mode)?;
+        let jwk = jwks.find(&kid).ok_or_else(|| anyhow!("ID token key {} is not in the cached JWKS", kid))?;
+        let key = DecodingKey::from_jwk(jwk).context("unusable JWKS key")?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let data = jsonwebtoken::decode::<IdTokenClaims>(token, &key, &validation).context("ID token rejected")?;
+        Ok(data.claims)
+    }
+
+    fn jwks(&self, offline_mode: bool) -> Result<JwkSet> {
+        let modified = std::fs::metadata(&self.jwks_path)
+            .and_then(|m| m.modified())
+            .with_context(|| format!("JWKS cache {} unavailable", self.jwks_path.display()))?;
+        if !offline_mode {
+            let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+            if age > self.max_age {
+                bail!("JWKS cache is {}s old; refresh it or run in offline mode", age.as_secs());
+            }
+        }
+
+        if let Some((loaded, jwks)) = self.cache.read().unwrap().as_ref() {
+            if *loaded == modified {
+                return Ok(jwks.clone());
+            }
+        }
+        let bytes = std::fs::read(&self.jwks_path)?;
+        let jwks: JwkSet = serde_json::from_slice(&bytes).context("JWKS cache is not a JWK set")?;
+        *self.cache.write().unwrap() = Some((modified, jwks.clone()));
+        tracing::info!("Loaded {} keys from {}", jwks.keys.len(), self.jwks_path.display());
+        Ok(jwks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+    use std::path::Path;
+
+    const ISSUER: &str = "https://idp.example";
+    const AUDIENCE: &str = "pacai";
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/oidc").join(name)
+    }
+
+    /// An ID token for `claims`, signed with the fixture key `key_name`.
+    fn token(key_name: &str, claims: serde_json::Value) -> String {
+        let key = EncodingKey::from_ed_pem(&std::fs::read(fixture(key_name)).unwrap()).unwrap();
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some("test-1".into());
+        jsonwebtoken::encode(&header, &claims, &key).unwrap()
+    }
+
+    fn claims() -> serde_json::Value {
+        json!({
+            "sub": "u-1",
+            "preferred_username": "carol",
+            "roles": ["instructor"],
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "exp": chrono::Utc::now().timestamp() + 600,
+        })
+    }
+
+    /// A verifier over a copy of the fixture JWKS in `dir`.
+    fn verifier(dir: &Path) -> OidcVerifier {
+        let jwks = dir.join("jwks.json");
+        std::fs::copy(fixture("jwks.json"), &jwks).unwrap();
+        OidcVerifier::new(jwks, ISSUER, AUDIENCE, Duration::from_secs(3600))
+    }
+
+    #[test]
+    fn test_valid_token_yields_its_claims() {
+        let dir = tempfile::tempdir().unwrap();
+        let claims = verifier(dir.path()).validate(&token("signing.key", claims()), false).unwrap();
+        assert_eq!(claims.user(), "carol");
+        assert_eq!(claims.role_names().collect::<Vec<_>>(), ["instructor"]);
+    }
+
+    #[test]
+    fn test_signature_audience_issuer_and_expiry_are_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let verifier = verifier(dir.path());
+        let with = |field: &str, value: serde_json::Value| {
+            let mut claims = claims();
+            claims[field] = value;
+            token("signing.key", claims)
+        };
+
+        // Same key id, different key.
+        assert!(verifier.validate(&token("untrusted.key", claims()), false).is_err());
+        assert!(verifier.validate(&with("aud", json!("someone-else")), false).is_err());
+        assert!(verifier.validate(&with("iss", json!("https://other.example")), false).is_err());
+        let expired = with("exp", json!(chrono::Utc::now().timestamp() - 3600));
+        assert!(verifier.validate(&expired, false).is_err());
+    }
+
+    #[test]
+    fn test_stale_jwks_is_refused_outside_offline_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let verifier = verifier(dir.path());
+        let file = std::fs::File::options().write(true).open(dir.path().join("jwks.json")).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(2 * 3600)).unwrap();
+
+        let token = token("signing.key", claims());
+        let err = verifier.validate(&token, false).unwrap_err();
+        assert!(err.to_string().contains("old"), "{}", err);
+        assert!(verifier.validate(&token, true).is_ok());
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use std::path::Path;
+use std::time::Duration;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::{CertificateDer, TrustAnchor, UnixTime};
+use webpki::{EndEntityCert, KeyUsage};
+use x509_parser::x509::AttributeTypeAndValue;
+
+/// A client certificate as presented, with the subject attributes identity
+/// mapping uses. Validation itself is left to `webpki`.
+#[derive(Debug, Clone)]
+pub struct Certificate {
+    der: CertificateDer<'static>,
+    pub common_name: Option<String>,
+    pub organizational_unit: Option<String>,
+}
+
+impl Certificate {
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).map_err(|e| anyhow!("invalid certificate: {}", e))?;
+        let subject = cert.subject();
+        Ok(Self {
+            der: CertificateDer::from(der.to_vec()),
+            common_name: first(subject.iter_common_name()),
+            organizational_unit: first(subject.iter_organizational_unit()),
+        })
+    }
+
+    pub fn subject_name(&self) -> String {
+        self.common_name.clone().unwrap_or_else(|| "<no common name>".into())
+    }
+}
+
+/// The certificates a client chain must lead back to.
+pub struct TrustStore {
+    anchors: Vec<TrustAnchor<'static>>,
+}
+
+impl TrustStore {
+    pub fn load(path: &Path) -> Result<Self> {
+        let pem = std::fs::read_to_string(path).with_context(|| format!("reading CA bundle {}", path.display()))?;
+        let anchors = parse_pem_chain(&pem)?
+            .iter()
+            .map(|cert| webpki::anchor_from_trusted_cert(&cert.der).map(|anchor| anchor.to_owned()))
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("unusable certificate in CA bundle {}", path.display()))?;
+        if anchors.is_empty() {
+            bail!("CA bundle {} contains no certificates", path.display());
+        }
+        Ok(Self { anchors })
+    }
+
+    /// Builds a path from the leaf (the first certificate in `chain`)
+    /// through any intermediates in `chain` to one of the trust anchors, the
+    /// same way the mutual TLS listener does: signatures, validity periods,
+    /// CA and path length constraints, name constraints, client-auth EKU and
+    /// critical extensions are all checked. Returns the leaf on success.
+    pub fn verify_chain<'a>(&self, chain: &'a [Certificate], now: DateTime<Utc>) -> Result<&'a Certificate> {
+        let leaf = chain.first().ok_or_else(|| anyhow!("no client certificate presented"))?;
+        let intermediates: Vec<CertificateDer<'_>> = chain[1..].iter().map(|cert| cert.der.clone()).collect();
+        let time = UnixTime::since_unix_epoch(Duration::from_secs(now.timestamp().max(0) as u64));
+
+        EndEntityCert::try_from(&leaf.der)
+            .and_then(|end_entity| {
+                end_entity
+                    .verify_for_usage(
+                        default_provider().signature_verification_algorithms.all,
+                        &self.anchors,
+                        &intermediates,
+                        time,
+                        KeyUsage::client_auth(),
+                        None,
+                        None,
+                    )
+                    .map(|_| ())
+            })
+            .with_context(|| format!("certificate for {} rejected", leaf.subject_name()))?;
+        Ok(leaf)
+    }
+}
+
+/// The first value of a subject attribute, if it is a string.
+fn first<'a>(mut values: impl Iterator<Item = &'a AttributeTypeAndValue<'a>>) -> Option<String> {
+    values.next().and_then(|value| value.as_str().ok()).map(str::to_string)
+}
+
+/// Every `CERTIFICATE` block in `pem`, in order.
+pub fn parse_pem_chain(pem: &str) -> Result<Vec<Certificate>> {
+    pem::parse_many(pem)
+        .context("invalid PEM")?
+        .iter()
+        .filter(|block| block.tag() == "CERTIFICATE")
+        .map(|block| Certificate::from_der(block.contents()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/x509").join(name)
+    }
+
+    /// `names` concatenated into one PEM chain, leaf first.
+    fn chain(names: &[&str]) -> Vec<Certificate> {
+        let pem: String = names
+            .iter()
+            .map(|name| std::fs::read_to_string(fixture(&format!("{}.pem", name))).unwrap())
+            .collect();
+        parse_pem_chain(&pem).unwrap()
+    }
+
+    fn rejection(names: &[&str]) -> webpki::Error {
+        let trust = TrustStore::load(&fixture("ca.pem")).unwrap();
+        let err = trust.verify_chain(&chain(names), now()).unwrap_err();
+        err.downcast_ref::<webpki::Error>().unwrap_or_else(|| panic!("{:#}", err)).clone()
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 6, 1, 0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_valid_chain_yields_the_leaf_identity() {
+        let trust = TrustStore::load(&fixture("ca.pem")).unwrap();
+        let chain = chain(&["leaf", "intermediate"]);
+        let leaf = trust.verify_chain(&chain, now()).unwrap();
+        assert_eq!(leaf.common_name.as_deref(), Some("alice"));
+        assert_eq!(leaf.organizational_unit.as_deref(), Some("instructor"));
+
+        // Without the intermediate there is no path to the root.
+        let err = trust.verify_chain(&chain[..1], now()).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&webpki::Error::UnknownIssuer));
+    }
+
+    #[test]
+    fn test_expired_leaf_is_rejected() {
+        assert!(matches!(rejection(&["expired", "intermediate"]), webpki::Error::CertExpired { .. }));
+    }
+
+    #[test]
+    fn test_leaf_signed_by_a_different_key_is_rejected() {
+        // Names the real intermediate as issuer but was signed by an impostor.
+        assert_eq!(rejection(&["wrong-issuer", "intermediate"]), webpki::Error::InvalidSignatureForPublicKey);
+    }
+
+    #[test]
+    fn test_non_ca_intermediate_is_rejected() {
+        assert_eq!(rejection(&["under-not-ca", "not-ca"]), webpki::Error::EndEntityUsedAsCa);
+    }
+
+    #[test]
+    fn test_unknown_critical_extension_is_rejected() {
+        assert_eq!(rejection(&["unknown-critical", "intermediate"]), webpki::Error::UnsupportedCriticalExtension);
+    }
+}
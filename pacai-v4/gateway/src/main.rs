@@ -1,16 +1,21 @@
 use axum::{
-    extract::{State, Json},
-    routing::post,
+    extract::{Extension, Path, State, Json},
+    routing::{delete, post},
     Router,
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use sha2::{Sha256, Digest};
 use uuid::Uuid;
 
 pub mod pb;
+mod auth;
+mod overrides;
+mod rbac;
+mod tls;
 
 // ===== Data Structures =====
 
@@ -20,6 +25,10 @@ struct AppState {
     hsm_primary_active: Arc<Mutex<bool>>,
     nitro_fallback_active: Arc<Mutex<bool>>,
     kms_keys: Arc<Vec<[u8; 32]>>,
+    scenarios: Arc<Mutex<HashMap<String, serde_json::Value>>>, // project_id -> live scenario
+    identity: Arc<auth::IdentityProviders>,
+    rbac: Arc<pacai_rbac::RbacPolicy>,
+    offline_mode: bool,
 }
 
 #[derive(Deserialize)]
@@ -28,6 +37,8 @@ struct GenerateRequest {
     seed: u64,
     #[serde(default)]
     stream: bool,
+    #[serde(default)]
+    project_id: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -53,12 +64,16 @@ struct OverrideRequest {
     count: i32,
     #[serde(default)]
     position: Vec<f32>,
+    #[serde(default)]
+    priority: u32,
 }
 
 #[derive(Serialize)]
 struct OverrideResponse {
     success: bool,
     entities_affected: i32,
+    affected_ids: Vec<String>,
+    skipped_ids: Vec<String>,
     audit_event_id: String,
 }
 
@@ -71,9 +86,90 @@ struct HealthResponse {
     offline_mode: bool,
 }
 
+/// `/auth/handshake` request, per V4_SPEC. The spec's `offline_mode` flag
+/// is ignored: only the operator's PACAI_OFFLINE relaxes the JWKS age check.
+#[derive(Deserialize)]
+struct HandshakeRequest {
+    identity_method: String,
+    credential: String,
+    #[serde(default)]
+    client_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HandshakeUser {
+    id: String,
+    roles: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct HandshakeResponse {
+    user: HandshakeUser,
+    identity_method: String,
+    scopes: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct IssueKeyRequest {
+    user: String,
+    role: String,
+    #[serde(default)]
+    scopes: Vec<String>,
+    #[serde(default)]
+    expires_in_days: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct IssueKeyResponse {
+    /// Shown once; only its hash is stored.
+    key: String,
+    id: String,
+    user: String,
+    role: String,
+    scopes: Vec<String>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// ===== Identity =====
+
+/// Resolves the caller from an `Authorization: Bearer` API key or ID token,
+/// or failing that the mTLS client certificate, and checks it holds
+/// `permission`.
+async fn authorize(
+    state: &AppState,
+    client: Option<Extension<auth::Identity>>,
+    headers: &HeaderMap,
+    permission: &str,
+) -> Result<auth::Identity, StatusCode> {
+    // A bearer credential wins; otherwise the mTLS client certificate, if the
+    // connection presented one, identifies the caller.
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let identity = match (bearer, client) {
+        (Some(credential), _) => {
+            let method = if credential.starts_with("sk_") { "api_key" } else { "sso" };
+            auth::validate_identity(&state.identity, method, credential, state.offline_mode)
+                .await
+                .map_err(|e| {
+                    tracing::warn!("{} authentication failed: {}", method, e);
+                    StatusCode::UNAUTHORIZED
+                })?
+        }
+        (None, Some(Extension(identity))) => identity,
+        (None, None) => return Err(StatusCode::UNAUTHORIZED),
+    };
+    if !identity.can(&state.rbac, permission) {
+        tracing::warn!("{} ({}) denied {}", identity.user, identity.role.as_str(), permission);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(identity)
+}
+
 // ===== HSM License Check =====
 
-async fn hsm_license_check(state: &State<AppState>) -> Result<(), StatusCode> {
+async fn hsm_license_check(state: &AppState) -> Result<(), StatusCode> {
     let hsm_primary = state.hsm_primary_active.lock().await;
     let hsm_fallback = state.nitro_fallback_active.lock().await;
 
@@ -104,6 +200,7 @@ fn generate_deterministic_json(prompt: &str, seed: u64) -> serde_json::Value {
                 {
                     "id": "npc_001",
                     "type": "civilian",
+                    "faction": "neutral",
                     "position": [0.0, 0.0, 0.0],
                     "behavior_tree": "base::idle",
                     "initial_state": {}
@@ -130,7 +227,7 @@ async fn health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<HealthR
         version: "4.0.0-alpha".to_string(),
         hsm_primary: if *hsm_primary { "active".to_string() } else { "inactive".to_string() },
         hsm_fallback: if *hsm_fallback { "active".to_string() } else { "inactive".to_string() },
-        offline_mode: true,
+        offline_mode: state.offline_mode,
     };
 
     (StatusCode::OK, Json(response))
@@ -138,8 +235,12 @@ async fn health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<HealthR
 
 async fn generate_zone(
     State(state): State<Arc<AppState>>,
+    client: Option<Extension<auth::Identity>>,
+    headers: HeaderMap,
     Json(payload): Json<GenerateRequest>,
 ) -> Result<(StatusCode, Json<GenerateResponse>), StatusCode> {
+    let caller = authorize(&state, client, &headers, rbac::permissions::GENERATE).await?;
+
     // License gate via HSM
     hsm_license_check(&state).await?;
 
@@ -166,38 +267,149 @@ async fn generate_zone(
         },
     };
 
+    // Keep the scenario so later overrides can mutate it
+    let project_id = payload.project_id.unwrap_or_else(|| response.zone_id.clone());
+    state.scenarios.lock().await.insert(project_id, json);
+
     // Audit log (mock)
     let _pool = state.pool.lock().await;
-    tracing::info!("generate_zone: zone_id={}, seed={}", response.zone_id, payload.seed);
+    tracing::info!("generate_zone: zone_id={}, seed={}, by={}", response.zone_id, payload.seed, caller.user);
 
     Ok((StatusCode::OK, Json(response)))
 }
 
 async fn apply_override(
     State(state): State<Arc<AppState>>,
+    client: Option<Extension<auth::Identity>>,
+    headers: HeaderMap,
     Json(payload): Json<OverrideRequest>,
 ) -> Result<(StatusCode, Json<OverrideResponse>), StatusCode> {
+    let caller = authorize(&state, client, &headers, rbac::permissions::CONTROL_APPLY).await?;
+
     // License gate
     hsm_license_check(&state).await?;
 
     let audit_event_id = Uuid::new_v4().to_string();
+    let target = overrides::Target::parse(&payload.target).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let mut scenarios = state.scenarios.lock().await;
+    let scenario = scenarios.get_mut(&payload.project_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let outcome = overrides::apply(
+        scenario,
+        &target,
+        &payload.event,
+        payload.count,
+        &payload.position,
+        payload.priority,
+        &audit_event_id,
+    )
+    .map_err(|e| {
+        tracing::warn!("apply_override rejected: {}", e);
+        StatusCode::UNPROCESSABLE_ENTITY
+    })?;
 
     let response = OverrideResponse {
-        success: true,
-        entities_affected: payload.count,
+        success: !outcome.affected_ids.is_empty(),
+        entities_affected: outcome.affected_ids.len() as i32,
+        affected_ids: outcome.affected_ids,
+        skipped_ids: outcome.skipped_ids,
         audit_event_id,
     };
 
     tracing::info!(
-        "apply_override: project={}, target={}, count={}",
+        "apply_override: project={}, target={}, affected={}, by={}",
         payload.project_id,
         payload.target,
-        payload.count
+        response.entities_affected,
+        caller.user
     );
 
     Ok((StatusCode::OK, Json(response)))
 }
 
+async fn handshake(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<HandshakeRequest>,
+) -> Result<Json<HandshakeResponse>, StatusCode> {
+    let identity = auth::validate_identity(&state.identity, &payload.identity_method, &payload.credential, state.offline_mode)
+        .await
+        .map_err(|e| {
+            tracing::warn!("{} handshake rejected: {}", payload.identity_method, e);
+            StatusCode::UNAUTHORIZED
+        })?;
+    tracing::info!(
+        "handshake: user={}, role={}, method={}, client={}",
+        identity.user,
+        identity.role.as_str(),
+        identity.method,
+        payload.client_id.as_deref().unwrap_or("-")
+    );
+
+    Ok(Json(HandshakeResponse {
+        user: HandshakeUser { id: identity.user, roles: vec![identity.role.as_str().to_string()] },
+        identity_method: identity.method.to_string(),
+        scopes: identity.scopes,
+    }))
+}
+
+async fn issue_api_key(
+    State(state): State<Arc<AppState>>,
+    client: Option<Extension<auth::Identity>>,
+    headers: HeaderMap,
+    Json(payload): Json<IssueKeyRequest>,
+) -> Result<(StatusCode, Json<IssueKeyResponse>), StatusCode> {
+    let caller = authorize(&state, client, &headers, rbac::permissions::KEYS_CREATE).await?;
+    let store = state.identity.api_keys.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let role = rbac::Role::parse(&payload.role).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let expires_at = payload.expires_in_days.map(|days| chrono::Utc::now() + chrono::Duration::days(days));
+    let scopes = if payload.scopes.is_empty() { vec!["*".to_string()] } else { payload.scopes };
+    let (key, record) = store
+        .issue(&payload.user, role.as_str(), scopes, expires_at)
+        .map_err(|e| {
+            tracing::error!("issue_api_key failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    tracing::info!("issue_api_key: id={}, user={}, by={}", record.id, record.user, caller.user);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(IssueKeyResponse {
+            key,
+            id: record.id,
+            user: record.user,
+            role: record.role,
+            scopes: record.scopes,
+            expires_at: record.expires_at,
+        }),
+    ))
+}
+
+async fn revoke_api_key(
+    State(state): State<Arc<AppState>>,
+    client: Option<Extension<auth::Identity>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let caller = authorize(&state, client, &headers, rbac::permissions::KEYS_DELETE).await?;
+    let store = state.identity.api_keys.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    store.revoke(&id).map_err(|_| StatusCode::NOT_FOUND)?;
+    tracing::info!("revoke_api_key: id={}, by={}", id, caller.user);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/health", axum::routing::get(health))
+        .route("/generate", post(generate_zone))
+        .route("/override", post(apply_override))
+        .route("/auth/handshake", post(handshake))
+        .route("/admin/keys", post(issue_api_key))
+        .route("/admin/keys/:id", delete(revoke_api_key))
+        .with_state(state)
+}
+
 // ===== Main =====
 
 #[tokio::main]
@@ -209,29 +421,112 @@ async fn main() {
     let hsm_fallback = Arc::new(Mutex::new(true)); // Nitrokey3 fallback
     let pool = Arc::new(Mutex::new("mock_db".to_string()));
     let kms_keys = Arc::new(vec![[0u8; 32]; 10]);
+    let identity = auth::IdentityProviders::load(&auth::IdentityConfig::from_env())
+        .expect("Failed to load identity providers");
+    if let Some(store) = &identity.api_keys {
+        if let Some((record, path)) = store.bootstrap_admin().expect("Failed to issue bootstrap API key") {
+            tracing::warn!(
+                "No API keys configured; issued admin key {} and saved it to {}. Store it and delete the file.",
+                record.id,
+                path.display()
+            );
+        }
+    }
 
     let app_state = Arc::new(AppState {
         pool,
         hsm_primary_active: hsm_primary,
         nitro_fallback_active: hsm_fallback,
         kms_keys,
+        scenarios: Arc::new(Mutex::new(HashMap::new())),
+        identity: Arc::new(identity),
+        rbac: Arc::new(rbac::load_policy().expect("Failed to load RBAC policy")),
+        offline_mode: std::env::var("PACAI_OFFLINE").is_ok_and(|v| v == "1" || v == "true"),
     });
 
-    let app = Router::new()
-        .route("/health", axum::routing::get(health))
-        .route("/generate", post(generate_zone))
-        .route("/override", post(apply_override))
-        .with_state(app_state);
+    let app = router(app_state);
+
+    let tls = tls::TlsConfig::from_env()
+        .and_then(|config| config.map(tls::TlsReloader::load).transpose())
+        .expect("Failed to load TLS configuration");
 
     let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
         .await
         .expect("Failed to bind to port 3000");
 
+    if let Some(reloader) = tls {
+        tracing::info!("v4 Gateway listening on https://127.0.0.1:3000 (mutual TLS)");
+        tls::serve(listener, app, Arc::new(reloader)).await.expect("Server error");
+        return;
+    }
+
     tracing::info!("v4 Gateway listening on http://127.0.0.1:3000");
     tracing::info!("Health check: curl http://127.0.0.1:3000/health");
-    tracing::info!("Generate: curl -X POST http://127.0.0.1:3000/generate -H 'Content-Type: application/json' -d '{{\"prompt\":\"test\",\"seed\":12345}}'");
+    tracing::info!("Generate: curl -X POST http://127.0.0.1:3000/generate -H \"Authorization: Bearer $PACAI_API_KEY\" -H 'Content-Type: application/json' -d '{{\"prompt\":\"test\",\"seed\":12345}}'");
 
     axum::serve(listener, app)
         .await
         .expect("Server error");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    pub(crate) fn test_state(dir: &std::path::Path) -> Arc<AppState> {
+        let config = auth::IdentityConfig {
+            api_keys_path: Some(dir.join("api_keys.json")),
+            ..auth::IdentityConfig::default()
+        };
+        Arc::new(AppState {
+            pool: Arc::new(Mutex::new(String::new())),
+            hsm_primary_active: Arc::new(Mutex::new(true)),
+            nitro_fallback_active: Arc::new(Mutex::new(false)),
+            kms_keys: Arc::new(Vec::new()),
+            scenarios: Arc::new(Mutex::new(HashMap::new())),
+            identity: Arc::new(auth::IdentityProviders::load(&config).unwrap()),
+            rbac: Arc::new(pacai_rbac::RbacPolicy::builtin()),
+            offline_mode: false,
+        })
+    }
+
+    fn key_for(state: &AppState, role: &str) -> String {
+        let store = state.identity.api_keys.as_ref().unwrap();
+        store.issue(role, role, vec!["*".to_string()], None).unwrap().0
+    }
+
+    async fn post_json(app: &Router, uri: &str, key: Option<&str>, body: serde_json::Value) -> StatusCode {
+        let mut request = Request::post(uri).header(header::CONTENT_TYPE, "application/json");
+        if let Some(key) = key {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", key));
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_work_routes_are_governed_by_the_rbac_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let app = router(state.clone());
+        let generate = serde_json::json!({ "prompt": "checkpoint", "seed": 7, "project_id": "p1" });
+        let apply = serde_json::json!({ "project_id": "p1", "target": "entity:npc_001", "event": "combat::flee", "count": 1 });
+
+        assert_eq!(post_json(&app, "/generate", None, generate.clone()).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(post_json(&app, "/override", None, apply.clone()).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(post_json(&app, "/generate", Some("sk_bogus_key"), generate.clone()).await, StatusCode::UNAUTHORIZED);
+
+        // Operators drive live scenarios but may not generate them; auditors do neither.
+        let operator = key_for(&state, "operator");
+        let auditor = key_for(&state, "auditor");
+        assert_eq!(post_json(&app, "/generate", Some(&operator), generate.clone()).await, StatusCode::FORBIDDEN);
+        assert_eq!(post_json(&app, "/override", Some(&auditor), apply.clone()).await, StatusCode::FORBIDDEN);
+
+        let instructor = key_for(&state, "instructor");
+        assert_eq!(post_json(&app, "/generate", Some(&instructor), generate).await, StatusCode::OK);
+        assert_eq!(post_json(&app, "/override", Some(&operator), apply).await, StatusCode::OK);
+    }
+}
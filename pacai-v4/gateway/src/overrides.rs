@@ -0,0 +1,218 @@
+use serde_json::Value;
+
+/// Result of resolving and applying an override against a stored scenario.
+#[derive(Debug, Default)]
+pub struct OverrideOutcome {
+    pub affected_ids: Vec<String>,
+    pub skipped_ids: Vec<String>,
+}
+
+/// Target selectors understood by `/override`:
+/// `all`, `entity:<id>`, `type:<entity type>`, `faction:<name>` and
+/// `radius:<meters>` (centered on the request `position`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    All,
+    Entity(String),
+    Type(String),
+    Faction(String),
+    Radius(f32),
+}
+
+impl Target {
+    pub fn parse(target: &str) -> Result<Self, String> {
+        match target.split_once(':') {
+            None if target == "all" => Ok(Target::All),
+            Some(("entity", id)) if !id.is_empty() => Ok(Target::Entity(id.to_string())),
+            Some(("type", kind)) if !kind.is_empty() => Ok(Target::Type(kind.to_string())),
+            Some(("faction", name)) if !name.is_empty() => Ok(Target::Faction(name.to_string())),
+            Some(("radius", r)) => r
+                .parse::<f32>()
+                .ok()
+                .filter(|r| *r >= 0.0)
+                .map(Target::Radius)
+                .ok_or_else(|| format!("Invalid radius in target '{}'", target)),
+            _ => Err(format!("Unknown override target '{}'", target)),
+        }
+    }
+}
+
+fn entity_position(entity: &Value) -> Option<[f32; 3]> {
+    let coords = entity["position"].as_array()?;
+    let get = |i: usize| coords.get(i).and_then(|v| v.as_f64()).map(|v| v as f32);
+    Some([get(0)?, get(1)?, get(2).unwrap_or(0.0)])
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+fn center(position: &[f32]) -> Option<[f32; 3]> {
+    match position {
+        [x, y] => Some([*x, *y, 0.0]),
+        [x, y, z, ..] => Some([*x, *y, *z]),
+        _ => None,
+    }
+}
+
+/// Applies `event` as the behavior tree of every entity matched by `target`.
+///
+/// When `position` is given, matches are ordered nearest first; a positive
+/// `count` caps how many entities are affected. An entity already held by an
+/// override of higher `priority` is skipped rather than overwritten.
+pub fn apply(
+    scenario: &mut Value,
+    target: &Target,
+    event: &str,
+    count: i32,
+    position: &[f32],
+    priority: u32,
+    override_id: &str,
+) -> Result<OverrideOutcome, String> {
+    let origin = center(position);
+    if matches!(target, Target::Radius(_)) && origin.is_none() {
+        return Err("Radius targets require a position".to_string());
+    }
+
+    let entities = scenario["zone"]["entities"]
+        .as_array_mut()
+        .ok_or_else(|| "Scenario has no entities".to_string())?;
+
+    let mut matched: Vec<(usize, f32)> = entities
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entity)| {
+            let dist = match (origin, entity_position(entity)) {
+                (Some(o), Some(p)) => distance(o, p),
+                _ => 0.0,
+            };
+            let hit = match target {
+                Target::All => true,
+                Target::Entity(id) => entity["id"].as_str() == Some(id.as_str()),
+                Target::Type(kind) => entity["type"].as_str() == Some(kind.as_str()),
+                Target::Faction(name) => entity["faction"].as_str() == Some(name.as_str()),
+                Target::Radius(r) => entity_position(entity).is_some() && dist <= *r,
+            };
+            hit.then_some((i, dist))
+        })
+        .collect();
+
+    if let Target::Entity(id) = target {
+        if matched.is_empty() {
+            return Err(format!("Entity not found: {}", id));
+        }
+    }
+
+    if origin.is_some() {
+        matched.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+    }
+
+    let mut outcome = OverrideOutcome::default();
+    for (index, _) in matched {
+        let entity = &mut entities[index];
+        let id = entity["id"].as_str().unwrap_or_default().to_string();
+
+        if count > 0 && outcome.affected_ids.len() >= count as usize {
+            break;
+        }
+
+        let held_by = entity["override"]["priority"].as_u64().unwrap_or(0) as u32;
+        if entity["override"].is_object() && held_by > priority {
+            outcome.skipped_ids.push(id);
+            continue;
+        }
+
+        entity["behavior_tree"] = Value::String(event.to_string());
+        entity["override"] = serde_json::json!({
+            "id": override_id,
+            "event": event,
+            "priority": priority,
+        });
+        outcome.affected_ids.push(id);
+    }
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scenario() -> Value {
+        let entity = |id: &str, kind: &str, faction: &str, x: f32| {
+            json!({ "id": id, "type": kind, "faction": faction, "position": [x, 0.0, 0.0], "behavior_tree": "base::idle" })
+        };
+        json!({ "zone": { "entities": [
+            entity("npc_001", "civilian", "neutral", 0.0),
+            entity("npc_002", "suspect", "cartel", 10.0),
+            entity("npc_003", "suspect", "cartel", 3.0),
+            entity("npc_004", "officer", "police", 20.0),
+        ] } })
+    }
+
+    fn behavior(scenario: &Value, index: usize) -> &str {
+        scenario["zone"]["entities"][index]["behavior_tree"].as_str().unwrap()
+    }
+
+    #[test]
+    fn test_parse_targets() {
+        assert_eq!(Target::parse("all"), Ok(Target::All));
+        assert_eq!(Target::parse("entity:npc_001"), Ok(Target::Entity("npc_001".into())));
+        assert_eq!(Target::parse("type:suspect"), Ok(Target::Type("suspect".into())));
+        assert_eq!(Target::parse("faction:cartel"), Ok(Target::Faction("cartel".into())));
+        assert_eq!(Target::parse("radius:5"), Ok(Target::Radius(5.0)));
+        for bad in ["faction:", "entity:", "radius:-1", "radius:far", "squad:a", "everyone"] {
+            assert!(Target::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_selectors_report_exactly_the_matched_entities() {
+        let cases = [
+            (Target::All, vec!["npc_001", "npc_002", "npc_003", "npc_004"]),
+            (Target::Entity("npc_004".into()), vec!["npc_004"]),
+            (Target::Type("suspect".into()), vec!["npc_002", "npc_003"]),
+            (Target::Faction("cartel".into()), vec!["npc_002", "npc_003"]),
+            (Target::Faction("army".into()), vec![]),
+        ];
+        for (target, expected) in cases {
+            let mut scenario = scenario();
+            let outcome = apply(&mut scenario, &target, "combat::flee", 0, &[], 1, "o1").unwrap();
+            assert_eq!(outcome.affected_ids, expected, "{:?}", target);
+            assert!(outcome.skipped_ids.is_empty());
+        }
+
+        let mut scenario = scenario();
+        let missing = apply(&mut scenario, &Target::Entity("npc_404".into()), "combat::flee", 0, &[], 1, "o1");
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_radius_orders_nearest_first_and_honors_count() {
+        let mut scenario = scenario();
+        assert!(apply(&mut scenario, &Target::Radius(5.0), "combat::flee", 0, &[], 1, "o1").is_err());
+
+        let outcome = apply(&mut scenario, &Target::Radius(12.0), "combat::flee", 2, &[9.0, 0.0], 1, "o1").unwrap();
+        assert_eq!(outcome.affected_ids, ["npc_002", "npc_003"]);
+        assert_eq!(behavior(&scenario, 0), "base::idle");
+        assert_eq!(behavior(&scenario, 1), "combat::flee");
+    }
+
+    #[test]
+    fn test_higher_priority_override_is_not_overwritten() {
+        let mut scenario = scenario();
+        apply(&mut scenario, &Target::Entity("npc_002".into()), "combat::engage", 0, &[], 10, "high").unwrap();
+
+        let outcome = apply(&mut scenario, &Target::Faction("cartel".into()), "combat::flee", 0, &[], 1, "low").unwrap();
+        assert_eq!(outcome.affected_ids, ["npc_003"]);
+        assert_eq!(outcome.skipped_ids, ["npc_002"]);
+        assert_eq!(behavior(&scenario, 1), "combat::engage");
+        assert_eq!(scenario["zone"]["entities"][1]["override"]["id"], "high");
+
+        // Equal or higher priority takes the entity over.
+        let outcome = apply(&mut scenario, &Target::Entity("npc_002".into()), "combat::flee", 0, &[], 10, "again").unwrap();
+        assert_eq!(outcome.affected_ids, ["npc_002"]);
+        assert_eq!(scenario["zone"]["entities"][1]["override"]["id"], "again");
+    }
+}
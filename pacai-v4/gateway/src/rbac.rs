@@ -1,48 +1,25 @@
-#[derive(Debug, Clone, PartialEq)]
-pub enum Role {
-    Admin,
-    Instructor,
-    Operator,
-    Auditor,
-    Integrator,
-}
+use anyhow::{Context, Result};
+use pacai_rbac::RbacPolicy;
 
-impl Role {
-    pub fn from_str(s: &str) -> Option<Self> {
-        match s {
-            "admin" => Some(Role::Admin),
-            "instructor" => Some(Role::Instructor),
-            "operator" => Some(Role::Operator),
-            "auditor" => Some(Role::Auditor),
-            "integrator" => Some(Role::Integrator),
-            _ => None,
-        }
-    }
+pub use pacai_rbac::{covers, permissions, Role};
 
-    pub fn can_access(&self, resource: &str) -> bool {
-        match self {
-            Role::Admin => true,
-            Role::Instructor => {
-                !resource.starts_with("/admin/keys")
-                    && !resource.starts_with("/admin/license")
-            }
-            Role::Operator => {
-                !resource.starts_with("/admin") && !resource.starts_with("/generate")
-            }
-            Role::Auditor => resource.starts_with("/audit"),
-            Role::Integrator => resource.starts_with("/models") || resource.starts_with("/exporters"),
-        }
+/// The policy at PACAI_RBAC_POLICY, or `V4_RBAC_POLICY.json` as built in.
+pub fn load_policy() -> Result<RbacPolicy> {
+    match std::env::var("PACAI_RBAC_POLICY") {
+        Ok(path) => RbacPolicy::load(path.as_ref()).with_context(|| format!("loading RBAC policy {}", path)),
+        Err(_) => Ok(RbacPolicy::builtin()),
     }
 }
 
 pub struct RbacMiddleware;
 
 impl RbacMiddleware {
-    pub fn enforce(user_role: Role, resource: &str) -> Result<(), String> {
-        if user_role.can_access(resource) {
+    pub fn enforce(policy: &RbacPolicy, user_role: Role, permission: &str) -> Result<(), String> {
+        let decision = policy.decide(user_role.as_str(), None, permission);
+        if decision.allowed {
             Ok(())
         } else {
-            Err(format!("Role {:?} cannot access {}", user_role, resource))
+            Err(format!("Role {} cannot {}: {}", user_role, permission, decision.reason))
         }
     }
 }
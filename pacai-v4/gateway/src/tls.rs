@@ -0,0 +1,301 @@
+use anyhow::{anyhow, bail, Context, Result};
+use axum::{extract::Request, Router};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::{self, pki_types::CertificateDer, server::WebPkiClientVerifier, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+
+use crate::auth::{x509::Certificate, Identity};
+use crate::rbac::Role;
+
+/// Mutual TLS settings. Client certificates are checked against the same
+/// kind of CA bundle as the `x509` handshake and map to an identity the
+/// same way: CN is the user, OU the role.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    pub client_ca_path: PathBuf,
+    pub reload_interval: Duration,
+}
+
+impl TlsConfig {
+    /// `None` unless both PACAI_TLS_CERT and PACAI_TLS_KEY are set. The
+    /// client CA defaults to PACAI_CA_BUNDLE.
+    pub fn from_env() -> Result<Option<Self>> {
+        let (Ok(cert), Ok(key)) = (std::env::var("PACAI_TLS_CERT"), std::env::var("PACAI_TLS_KEY")) else {
+            return Ok(None);
+        };
+        let client_ca = std::env::var("PACAI_TLS_CLIENT_CA")
+            .or_else(|_| std::env::var("PACAI_CA_BUNDLE"))
+            .map_err(|_| anyhow!("Mutual TLS needs PACAI_TLS_CLIENT_CA or PACAI_CA_BUNDLE"))?;
+        let seconds = std::env::var("PACAI_TLS_RELOAD_SECONDS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(30);
+        Ok(Some(Self {
+            cert_path: cert.into(),
+            key_path: key.into(),
+            client_ca_path: client_ca.into(),
+            reload_interval: Duration::from_secs(seconds.max(1)),
+        }))
+    }
+}
+
+/// Holds the server configuration and swaps it when the certificate, key or
+/// client CA file changes. A broken update leaves the old one in place.
+pub struct TlsReloader {
+    config: TlsConfig,
+    current: RwLock<(Vec<Option<SystemTime>>, Arc<ServerConfig>)>,
+}
+
+impl TlsReloader {
+    pub fn load(config: TlsConfig) -> Result<Self> {
+        let stamp = stamp(&config);
+        let server = server_config(&config)?;
+        Ok(Self { config, current: RwLock::new((stamp, server)) })
+    }
+
+    fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.read().unwrap().1.clone())
+    }
+
+    fn reload_if_changed(&self) -> Result<bool> {
+        let stamp = stamp(&self.config);
+        if self.current.read().unwrap().0 == stamp {
+            return Ok(false);
+        }
+        let server = server_config(&self.config)?;
+        *self.current.write().unwrap() = (stamp, server);
+        Ok(true)
+    }
+}
+
+fn stamp(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+    [&config.cert_path, &config.key_path, &config.client_ca_path]
+        .into_iter()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+fn server_config(config: &TlsConfig) -> Result<Arc<ServerConfig>> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let mut roots = RootCertStore::empty();
+    for cert in read_certs(&config.client_ca_path)? {
+        roots.add(cert).context("unusable client CA certificate")?;
+    }
+    let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build()?;
+
+    let key = rustls_pemfile::private_key(&mut open(&config.key_path)?)?
+        .ok_or_else(|| anyhow!("No private key in {}", config.key_path.display()))?;
+
+    let mut server = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(read_certs(&config.cert_path)?, key)?;
+    server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(server))
+}
+
+fn open(path: &Path) -> Result<std::io::BufReader<std::fs::File>> {
+    let file = std::fs::File::open(path).with_context(|| format!("reading {}", path.display()))?;
+    Ok(std::io::BufReader::new(file))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut open(path)?).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        bail!("No certificates in {}", path.display());
+    }
+    Ok(certs)
+}
+
+/// The identity a verified client certificate speaks for.
+fn client_identity(der: &[u8]) -> Result<Identity> {
+    let cert = Certificate::from_der(der)?;
+    let user = cert.common_name.ok_or_else(|| anyhow!("Client certificate has no common name"))?;
+    let role = cert
+        .organizational_unit
+        .as_deref()
+        .and_then(Role::parse)
+        .ok_or_else(|| anyhow!("Client certificate for {} carries no recognised role (OU)", user))?;
+    Ok(Identity { user, role, method: "mtls", scopes: None })
+}
+
+/// Accepts TLS connections until the listener fails, re-reading the
+/// certificate files every `reload_interval` in the background.
+pub async fn serve(listener: TcpListener, app: Router, reloader: Arc<TlsReloader>) -> std::io::Result<()> {
+    let watcher = reloader.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(watcher.config.reload_interval);
+        loop {
+            interval.tick().await;
+            match watcher.reload_if_changed() {
+                Ok(true) => tracing::info!("TLS certificates reloaded"),
+                Ok(false) => {}
+                Err(e) => tracing::error!("TLS reload failed, keeping previous certificates: {:#}", e),
+            }
+        }
+    });
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let acceptor = reloader.acceptor();
+        let app = app.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_connection(stream, acceptor, app).await {
+                tracing::debug!("TLS connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    acceptor: TlsAcceptor,
+    app: Router,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let stream = tokio::time::timeout(Duration::from_secs(10), acceptor.accept(stream)).await??;
+    let identity = match stream.get_ref().1.peer_certificates().and_then(|chain| chain.first()) {
+        Some(leaf) => client_identity(leaf)
+            .map_err(|e| tracing::warn!("Client certificate not mapped to an identity: {:#}", e))
+            .ok(),
+        None => None,
+    };
+
+    let service = hyper::service::service_fn(move |mut request: Request<hyper::body::Incoming>| {
+        if let Some(identity) = &identity {
+            request.extensions_mut().insert(identity.clone());
+        }
+        app.clone().call(request)
+    });
+    auto::Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(TokioIo::new(stream), service)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::{pki_types::ServerName, ClientConfig};
+    use tokio_rustls::TlsConnector;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tls").join(name)
+    }
+
+    fn fixture_config(dir: &Path) -> TlsConfig {
+        for name in ["server.pem", "server.key", "ca.pem"] {
+            std::fs::copy(fixture(name), dir.join(name)).unwrap();
+        }
+        TlsConfig {
+            cert_path: dir.join("server.pem"),
+            key_path: dir.join("server.key"),
+            client_ca_path: dir.join("ca.pem"),
+            reload_interval: Duration::from_secs(30),
+        }
+    }
+
+    fn connector(client: Option<&str>) -> TlsConnector {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut roots = RootCertStore::empty();
+        roots.add(read_certs(&fixture("ca.pem")).unwrap().remove(0)).unwrap();
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = match client {
+            Some(name) => {
+                let certs = read_certs(&fixture(&format!("{}.pem", name))).unwrap();
+                let key = rustls_pemfile::private_key(&mut open(&fixture(&format!("{}.key", name))).unwrap())
+                    .unwrap()
+                    .unwrap();
+                builder.with_client_auth_cert(certs, key).unwrap()
+            }
+            None => builder.with_no_client_auth(),
+        };
+        TlsConnector::from(Arc::new(config))
+    }
+
+    /// The raw HTTP/1.1 response to a JSON `POST path`, or `None` if the
+    /// server refused the connection.
+    async fn post(addr: std::net::SocketAddr, client: Option<&str>, path: &str, body: &str) -> Option<String> {
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let server_name = ServerName::try_from("localhost").unwrap();
+        let mut stream = connector(client).connect(server_name, tcp).await.ok()?;
+        let request = format!(
+            "POST {} HTTP/1.1\r\nhost: localhost\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            path,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.ok()?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.ok()?;
+        Some(response).filter(|r| !r.is_empty())
+    }
+
+    #[test]
+    fn test_client_identity_maps_common_name_and_unit() {
+        let der = read_certs(&fixture("instructor.pem")).unwrap().remove(0);
+        let identity = client_identity(&der).unwrap();
+        assert_eq!((identity.user.as_str(), identity.role, identity.method), ("alice", Role::Instructor, "mtls"));
+
+        // The server certificate has no OU, so no role.
+        assert!(client_identity(&read_certs(&fixture("server.pem")).unwrap()[0]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_certificates_drive_rbac_on_work_routes() {
+        let dir = tempfile::tempdir().unwrap();
+        let reloader = Arc::new(TlsReloader::load(fixture_config(dir.path())).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, crate::router(crate::tests::test_state(dir.path())), reloader));
+        let body = r#"{"prompt":"checkpoint","seed":7}"#;
+
+        let response = post(addr, Some("instructor"), "/generate", body).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        // Auditors authenticate but may not generate.
+        let response = post(addr, Some("auditor"), "/generate", body).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+
+        // Same subject, but not issued by the configured CA.
+        assert_eq!(post(addr, Some("rogue"), "/generate", body).await, None);
+        assert_eq!(post(addr, None, "/generate", body).await, None);
+    }
+
+    #[test]
+    fn test_reload_keeps_the_previous_config_until_the_files_are_valid() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = fixture_config(dir.path());
+        let reloader = TlsReloader::load(config.clone()).unwrap();
+        assert!(!reloader.reload_if_changed().unwrap());
+
+        let modified = |path: &Path| {
+            let file = std::fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+        };
+
+        std::fs::write(&config.cert_path, "not a certificate").unwrap();
+        modified(&config.cert_path);
+        let err = reloader.reload_if_changed().unwrap_err();
+        assert!(err.to_string().contains("No certificates"), "{:#}", err);
+        let previous = reloader.acceptor();
+
+        std::fs::copy(fixture("server.pem"), &config.cert_path).unwrap();
+        modified(&config.cert_path);
+        assert!(reloader.reload_if_changed().unwrap());
+        assert!(!Arc::ptr_eq(previous.config(), reloader.acceptor().config()));
+
+        let missing_ca = TlsConfig { client_ca_path: dir.path().join("missing.pem"), ..config };
+        assert!(TlsReloader::load(missing_ca).is_err());
+    }
+}